@@ -0,0 +1,171 @@
+//! An opt-in log of every payload sent to and received from nodes, written as
+//! [JSON Lines] for debugging incidents and tracing the protocol.
+//!
+//! Each line is an object with the `direction` of the payload - `"inbound"`
+//! or `"outbound"` - the websocket host of the `node`, the `payload` itself,
+//! and the Unix `timestamp` in milliseconds that it was recorded at. Payloads
+//! that aren't valid JSON are written as strings.
+//!
+//! An [`AuditLog`] is provided to nodes via [`NodeConfig::audit`]. Clones of
+//! it write to the same writer, so one log can be shared between nodes, and
+//! logging can be toggled at runtime via [`AuditLog::set_enabled`].
+//!
+//! # Examples
+//!
+//! Write the payloads of a node to a file:
+//!
+//! ```rust,no_run
+//! # extern crate lavalink_futures;
+//! #
+//! # use std::error::Error;
+//! #
+//! # fn try_main() -> Result<(), Box<Error>> {
+//! #
+//! use lavalink_futures::audit::AuditLog;
+//! use lavalink_futures::nodes::NodeConfigBuilder;
+//! use std::fs::File;
+//!
+//! let audit = AuditLog::new(File::create("lavalink.jsonl")?);
+//!
+//! let config = NodeConfigBuilder::new()
+//!     .password("youshallnotpass")
+//!     .user_id("1")
+//!     .audit(audit.clone())
+//!     .build()?;
+//!
+//! // Stop logging once the incident is resolved.
+//! audit.set_enabled(false);
+//! #     Ok(())
+//! # }
+//! #
+//! # fn main() {
+//! #     try_main().unwrap();
+//! # }
+//! ```
+//!
+//! [`AuditLog`]: struct.AuditLog.html
+//! [`AuditLog::set_enabled`]: struct.AuditLog.html#method.set_enabled
+//! [`NodeConfig::audit`]: ../nodes/struct.NodeConfig.html#method.audit
+//! [JSON Lines]: http://jsonlines.org
+
+use serde_json::{self, Value};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The direction that a payload was sent in.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The payload was received from the node.
+    Inbound,
+    /// The payload was sent to the node.
+    Outbound,
+}
+
+/// A cheaply cloneable log writing payloads as JSON Lines to a writer.
+///
+/// Refer to the [module-level documentation] for more information.
+///
+/// [module-level documentation]: index.html
+#[derive(Clone)]
+pub struct AuditLog {
+    enabled: Arc<AtomicBool>,
+    writer: Arc<Mutex<Box<Write + Send>>>,
+}
+
+impl AuditLog {
+    /// Creates a new log writing to the writer, which is enabled.
+    ///
+    /// Each line is flushed once it is written, so wrap the writer in a
+    /// `BufWriter` only if losing the last lines on a crash is acceptable.
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(true)),
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Whether payloads are being written.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether payloads are written, for every clone of the log.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Writes a payload sent to or received from the node with the websocket
+    /// host, if the log is enabled.
+    ///
+    /// This is called automatically for the payloads of nodes configured with
+    /// the log. Errors writing are logged rather than returned, so that a
+    /// broken writer can't interrupt the connection.
+    pub fn record(&self, node: &str, direction: Direction, payload: &[u8]) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let payload = serde_json::from_slice(payload).unwrap_or_else(|_| {
+            Value::String(String::from_utf8_lossy(payload).into_owned())
+        });
+
+        let entry = Entry {
+            direction,
+            node,
+            payload,
+            timestamp: now_millis(),
+        };
+
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(why) => {
+                event!(warn; "Err serializing audit entry: {:?}", why);
+
+                return;
+            },
+        };
+        line.push(b'\n');
+
+        let mut writer = match self.writer.lock() {
+            Ok(writer) => writer,
+            Err(why) => {
+                event!(warn; "Err locking audit writer: {:?}", why);
+
+                return;
+            },
+        };
+
+        if let Err(why) = writer.write_all(&line).and_then(|_| writer.flush()) {
+            event!(warn; "Err writing audit entry: {:?}", why);
+        }
+    }
+}
+
+impl Debug for AuditLog {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("AuditLog")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+/// A line of the log.
+#[derive(Serialize)]
+struct Entry<'a> {
+    direction: Direction,
+    node: &'a str,
+    payload: Value,
+    timestamp: u64,
+}
+
+fn now_millis() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    now.as_secs() * 1000 + u64::from(now.subsec_nanos() / 1_000_000)
+}
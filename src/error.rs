@@ -1,39 +1,277 @@
 use futures::sync::mpsc::SendError as SyncSendError;
-use lavalink::Error as LavalinkError;
+use futures::sync::oneshot::Canceled;
+use hyper::error::UriError;
+use hyper::Error as HyperError;
+use native_tls::Error as TlsError;
 use serde_json::Error as JsonError;
-use std::cell::BorrowMutError;
 use std::error::Error as StdError;
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::Error as IoError;
+use std::sync::PoisonError;
 use websocket::client::ParseError as WebSocketClientParseError;
 use websocket::{OwnedMessage, WebSocketError};
+use ::model::GuildId;
+
+/// Context about the node or guild that an [`Error`] occurred for.
+///
+/// [`Error`]: enum.Error.html
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ErrorContext {
+    /// The ID of the guild that the error occurred for, if known.
+    pub guild_id: Option<u64>,
+    /// The websocket host of the node that the error occurred for, if known.
+    pub node: Option<String>,
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match (self.node.as_ref(), self.guild_id) {
+            (Some(node), Some(guild_id)) => write!(f, "node={}, guild_id={}", node, guild_id),
+            (Some(node), None) => write!(f, "node={}", node),
+            (None, Some(guild_id)) => write!(f, "guild_id={}", guild_id),
+            (None, None) => f.write_str("no context"),
+        }
+    }
+}
+
+/// The broad category of an [`Error`], returned by [`Error::kind`].
+///
+/// [`Error`]: enum.Error.html
+/// [`Error::kind`]: enum.Error.html#method.kind
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ErrorKind {
+    /// The library is in a state it can not continue from, such as a poisoned
+    /// lock or a dropped tokio Core.
+    Internal,
+    /// A failure of the connection to a node, such as a closed websocket or a
+    /// timed out request. These are generally worth retrying.
+    Network,
+    /// A node sent something unexpected, such as an invalid payload, or did
+    /// not respond to a command as expected.
+    Protocol,
+    /// The library was used incorrectly, such as with an invalid
+    /// configuration or a command that is invalid for a player's state.
+    Usage,
+}
 
 /// An error enum wrapping all potential errors that could return from the
 /// library's functions.
 #[derive(Debug)]
 pub enum Error {
-    /// A RefCell's data couldn't be mutably borrowed.
-    BorrowMut(BorrowMutError),
+    /// A node closed the connection after connecting because it did not
+    /// accept the password.
+    AuthenticationFailed,
+    /// A future was canceled before it could resolve, such as when the tokio
+    /// Core running it was dropped.
+    Canceled(Canceled),
+    /// The connection to a node was closed, so a message could not be sent.
+    Closed,
+    /// A command sent to a node was not acknowledged within its timeout, such
+    /// as a play that never resulted in the track starting.
+    ///
+    /// Lavalink silently ignores some invalid commands, so the command may be
+    /// retried.
+    CommandUnacknowledged,
+    /// An error with context about the node or guild that it occurred for.
+    ///
+    /// Use [`Error::root`] to retrieve the underlying error.
+    ///
+    /// [`Error::root`]: enum.Error.html#method.root
+    Context(ErrorContext, Box<Error>),
+    /// A node responded to a REST request with an unsuccessful HTTP status,
+    /// such as a 404 for a player that does not exist.
+    ///
+    /// Contains the status code.
+    HttpStatus(u16),
+    /// An error from the `hyper` crate.
+    Hyper(Box<StdError + Send + Sync>),
+    /// A configuration was invalid, such as when building a [`NodeConfig`].
+    ///
+    /// Contains a description of why the configuration was invalid.
+    ///
+    /// [`NodeConfig`]: nodes/struct.NodeConfig.html
+    InvalidConfig(&'static str),
+    /// A volume was outside of the range of [`MIN_VOLUME`] to [`MAX_VOLUME`].
+    ///
+    /// Contains the invalid volume.
+    ///
+    /// [`MAX_VOLUME`]: player/constant.MAX_VOLUME.html
+    /// [`MIN_VOLUME`]: player/constant.MIN_VOLUME.html
+    InvalidVolume(i32),
+    /// An error from the standard library's `io` module, such as while
+    /// binding a socket.
+    Io(IoError),
     /// An error from the `serde_json` crate.
     Json(JsonError),
-    /// An error from the `lavalink` crate.
-    Lavalink(LavalinkError),
     /// An indicator that something that should have been present wasn't.
     ///
     /// This is basically a representation of `Option::None`.
     None,
+    /// A player's current track can not be seeked, such as a stream.
+    ///
+    /// This is only known once the track's information is set via
+    /// [`AudioPlayer::set_track_info`].
+    ///
+    /// [`AudioPlayer::set_track_info`]: player/struct.AudioPlayer.html#method.set_track_info
+    NotSeekable,
     /// A player already existed when one was attempted to be made.
     PlayerAlreadyExists,
+    /// A lock was poisoned due to a panic while it was held.
+    Poisoned,
+    /// Too many messages were queued to be sent for a player.
+    QueueFull,
+    /// A seek position was outside of a player's current track.
+    ///
+    /// Contains the invalid position. This is only known once the track's
+    /// length is set, such as via [`AudioPlayer::set_track_info`].
+    ///
+    /// [`AudioPlayer::set_track_info`]: player/struct.AudioPlayer.html#method.set_track_info
+    SeekOutOfBounds(i64),
     /// There was an error sending a message over the WebSocket sender.
-    SyncSend(SyncSendError<OwnedMessage>),
+    SyncSend(Box<StdError + Send + Sync>),
+    /// A request did not complete within its configured timeout.
+    Timeout,
+    /// An error from the `native-tls` crate while building a TLS connector.
+    Tls(Box<StdError + Send + Sync>),
+    /// There was an error while `hyper` was parsing a URI.
+    Uri(Box<StdError + Send + Sync>),
+    /// An outgoing payload failed validation, with a description of why.
+    ///
+    /// This is only returned for players with validation enabled.
+    Validation(String),
     /// An error from the `websocket` crate.
-    WebSocket(WebSocketError),
+    WebSocket(Box<StdError + Send + Sync>),
     /// There was an error while the `websocket` crate was parsing a URI.
-    WebSocketClientParse(WebSocketClientParseError),
+    WebSocketClientParse(Box<StdError + Send + Sync>),
+}
+
+impl Error {
+    /// The context of the error, if any.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match *self {
+            Error::Context(ref context, _) => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Whether the error can never succeed when retried, such as a node not
+    /// accepting the password or an invalid configuration.
+    ///
+    /// Reconnect loops should give up on a node when this is true.
+    pub fn is_fatal(&self) -> bool {
+        use self::Error::*;
+
+        match *self.root() {
+            AuthenticationFailed
+            | Canceled(_)
+            | InvalidConfig(_)
+            | Poisoned
+            | Tls(_)
+            | Uri(_)
+            | WebSocketClientParse(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the error is transient, so the same operation may succeed when
+    /// retried, such as after reconnecting to a node.
+    ///
+    /// This is true for [`ErrorKind::Network`] errors and unacknowledged
+    /// commands. Errors that are neither recoverable nor [fatal], such as an
+    /// invalid volume, will fail again unless the operation is changed.
+    ///
+    /// [`ErrorKind::Network`]: enum.ErrorKind.html#variant.Network
+    /// [fatal]: #method.is_fatal
+    pub fn is_recoverable(&self) -> bool {
+        match *self.root() {
+            Error::CommandUnacknowledged => true,
+            ref other => other.kind() == ErrorKind::Network,
+        }
+    }
+
+    /// The category of the error, ignoring its context.
+    pub fn kind(&self) -> ErrorKind {
+        use self::Error::*;
+
+        match *self.root() {
+            Canceled(_) | Poisoned | Tls(_) => ErrorKind::Internal,
+            Closed
+            | Hyper(_)
+            | Io(_)
+            | QueueFull
+            | SyncSend(_)
+            | Timeout
+            | WebSocket(_) => ErrorKind::Network,
+            HttpStatus(status) if status >= 500 => ErrorKind::Network,
+            CommandUnacknowledged
+            | HttpStatus(_)
+            | Json(_) => ErrorKind::Protocol,
+            AuthenticationFailed
+            | InvalidConfig(_)
+            | InvalidVolume(_)
+            | None
+            | NotSeekable
+            | PlayerAlreadyExists
+            | SeekOutOfBounds(_)
+            | Uri(_)
+            | Validation(_)
+            | WebSocketClientParse(_) => ErrorKind::Usage,
+            Context(_, ref inner) => inner.kind(),
+        }
+    }
+
+    /// The underlying error, without any context.
+    pub fn root(&self) -> &Error {
+        match *self {
+            Error::Context(_, ref inner) => inner.root(),
+            ref other => other,
+        }
+    }
+
+    /// Adds the ID of the guild that the error occurred for to its context,
+    /// unless one is already present.
+    pub fn with_guild<G: Into<GuildId>>(self, guild_id: G) -> Self {
+        let guild_id = guild_id.into().0;
+
+        self.with_context(|context| {
+            context.guild_id = context.guild_id.or(Some(guild_id));
+        })
+    }
+
+    /// Adds the websocket host of the node that the error occurred for to its
+    /// context, unless one is already present.
+    pub fn with_node<S: Into<String>>(self, node: S) -> Self {
+        self.with_context(|context| {
+            if context.node.is_none() {
+                context.node = Some(node.into());
+            }
+        })
+    }
+
+    fn with_context<F: FnOnce(&mut ErrorContext)>(self, f: F) -> Self {
+        match self {
+            Error::Context(mut context, inner) => {
+                f(&mut context);
+
+                Error::Context(context, inner)
+            },
+            other => {
+                let mut context = ErrorContext::default();
+                f(&mut context);
+
+                Error::Context(context, Box::new(other))
+            },
+        }
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        f.write_str(self.description())
+        match *self {
+            Error::Context(ref context, ref inner) => write!(f, "{} ({})", inner, context),
+            Error::HttpStatus(status) => write!(f, "{}: {}", self.description(), status),
+            _ => f.write_str(self.description()),
+        }
     }
 }
 
@@ -42,21 +280,64 @@ impl StdError for Error {
         use self::Error::*;
 
         match *self {
-            BorrowMut(ref inner) => inner.description(),
+            AuthenticationFailed => "The node did not accept the password",
+            Canceled(ref inner) => inner.description(),
+            Closed => "The connection to the node was closed",
+            CommandUnacknowledged => "The node did not acknowledge the command in time",
+            Context(_, ref inner) => inner.description(),
+            HttpStatus(_) => "The node responded with an unsuccessful HTTP status",
+            Hyper(ref inner) => inner.description(),
+            InvalidConfig(reason) => reason,
+            InvalidVolume(_) => "The volume must be between 0 and 1000",
+            Io(ref inner) => inner.description(),
             Json(ref inner) => inner.description(),
-            Lavalink(ref inner) => inner.description(),
             None => "No value found",
+            NotSeekable => "The track can not be seeked",
             PlayerAlreadyExists => "A player for that guild already exists",
+            Poisoned => "A lock was poisoned",
+            QueueFull => "Too many messages are queued for the player",
+            SeekOutOfBounds(_) => "The seek position is outside of the track",
             SyncSend(ref inner) => inner.description(),
+            Timeout => "The request timed out",
+            Tls(ref inner) => inner.description(),
+            Uri(ref inner) => inner.description(),
+            Validation(ref reason) => reason,
             WebSocket(ref inner) => inner.description(),
             WebSocketClientParse(ref inner) => inner.description(),
         }
     }
+
+    fn cause(&self) -> Option<&StdError> {
+        use self::Error::*;
+
+        match *self {
+            Context(_, ref inner) => Some(&**inner),
+            Hyper(ref inner)
+            | SyncSend(ref inner)
+            | Tls(ref inner)
+            | Uri(ref inner)
+            | WebSocket(ref inner)
+            | WebSocketClientParse(ref inner) => Some(&**inner),
+            _ => None,
+        }
+    }
+}
+
+impl From<Canceled> for Error {
+    fn from(err: Canceled) -> Self {
+        Error::Canceled(err)
+    }
 }
 
-impl From<BorrowMutError> for Error {
-    fn from(err: BorrowMutError) -> Self {
-        Error::BorrowMut(err)
+impl From<HyperError> for Error {
+    fn from(err: HyperError) -> Self {
+        Error::Hyper(Box::new(err))
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        Error::Io(err)
     }
 }
 
@@ -66,26 +347,38 @@ impl From<JsonError> for Error {
     }
 }
 
-impl From<LavalinkError> for Error {
-    fn from(err: LavalinkError) -> Self {
-        Error::Lavalink(err)
+impl<T> From<PoisonError<T>> for Error {
+    fn from(_: PoisonError<T>) -> Self {
+        Error::Poisoned
     }
 }
 
 impl From<SyncSendError<OwnedMessage>> for Error {
     fn from(err: SyncSendError<OwnedMessage>) -> Self {
-        Error::SyncSend(err)
+        Error::SyncSend(Box::new(err))
+    }
+}
+
+impl From<TlsError> for Error {
+    fn from(err: TlsError) -> Self {
+        Error::Tls(Box::new(err))
+    }
+}
+
+impl From<UriError> for Error {
+    fn from(err: UriError) -> Self {
+        Error::Uri(Box::new(err))
     }
 }
 
 impl From<WebSocketError> for Error {
     fn from(err: WebSocketError) -> Self {
-        Error::WebSocket(err)
+        Error::WebSocket(Box::new(err))
     }
 }
 
 impl From<WebSocketClientParseError> for Error {
     fn from(err: WebSocketClientParseError) -> Self {
-        Error::WebSocketClientParse(err)
+        Error::WebSocketClientParse(Box::new(err))
     }
 }
@@ -1,35 +1,647 @@
-use futures::Future;
+use futures::{Future, future};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::{Arc, Mutex};
 use websocket::OwnedMessage;
+use ::model::{GuildId, NodeStats, TrackEndReason, TrackException};
+use ::nodes::CloseData;
+use ::player::{AudioPlayer, TrackData};
+#[cfg(feature = "lyrics")]
+use ::plugins::lyrics::LyricsLine;
+#[cfg(feature = "sponsorblock")]
+use ::plugins::sponsorblock::Segment;
+use ::Error;
+
+type SharedHandler = Arc<Mutex<Box<EventHandler>>>;
 
 /// Trait that must be implemented determining what to do on certain events from
 /// lavalink, some that will be used to reply to Lavalink with.
-pub trait EventHandler {
+///
+/// Implementations must be `Send`, so that the [`NodeManager`] holding the
+/// handler can be used across threads.
+///
+/// Errors resolved by the futures of tymethods called for events from nodes
+/// are passed to [`handler_error`] rather than being discarded.
+///
+/// [`NodeManager`]: nodes/struct.NodeManager.html
+/// [`handler_error`]: #method.handler_error
+pub trait EventHandler: Send {
     /// Tymethod called for forwarding a WebSocket message to Discord.
     fn forward(&mut self, shard_id: u64, message: &str)
-        -> Box<Future<Item = Option<OwnedMessage>, Error = ()>>;
+        -> Box<Future<Item = Option<OwnedMessage>, Error = Error>>;
 
     /// Tymethod called for checking if a shard is connected.
     fn is_connected(&mut self, shard_id: u64)
-        -> Box<Future<Item = bool, Error = ()>>;
+        -> Box<Future<Item = bool, Error = Error>>;
 
     /// Tymethod called for checking if a guild - and optionally voice channel
     /// - combination is valid.
     fn is_valid(&mut self, guild_id: &str, channel_id: Option<String>)
-        -> Box<Future<Item = bool, Error = ()>>;
+        -> Box<Future<Item = bool, Error = Error>>;
 
     /// Tymethod called when a track ends. This is useful for then subsequently
     /// playing a new track.
-    fn track_end(&mut self, track: String, reason: String)
-        -> Box<Future<Item = (), Error = ()>>;
+    ///
+    /// Use [`TrackEndReason::may_start_next`] to determine whether a new track
+    /// should be played.
+    ///
+    /// [`TrackEndReason::may_start_next`]: model/enum.TrackEndReason.html#method.may_start_next
+    fn track_end(&mut self, track: String, reason: TrackEndReason)
+        -> Box<Future<Item = (), Error = Error>>;
 
     /// Tymethod called when an exception occurs during a track playing.
-    fn track_exception(&mut self, track: String, error: String)
-        -> Box<Future<Item = (), Error = ()>>;
+    ///
+    /// Use [`Severity::may_retry`] on the exception's severity to determine
+    /// whether the track may be played again.
+    ///
+    /// [`Severity::may_retry`]: model/enum.Severity.html#method.may_retry
+    fn track_exception(&mut self, track: String, exception: TrackException)
+        -> Box<Future<Item = (), Error = Error>>;
 
     /// Tymethod called when a track is determined as being "stuck" in playing.
     ///
     /// Includes the threshold in milliseconds before a track is detected as
     /// being stuck.
     fn track_stuck(&mut self, track: String, threshold_ms: i64)
-        -> Box<Future<Item = (), Error = ()>>;
+        -> Box<Future<Item = (), Error = Error>>;
+
+    /// Tymethod called when a node sends updated statistics about its load
+    /// and players.
+    ///
+    /// This is useful for exporting node statistics to monitoring. The stats
+    /// are also stored in the node's [`State`].
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`State`]: nodes/struct.State.html
+    fn stats_update(&mut self, node_host: &str, stats: NodeStats)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (node_host, stats);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when the node confirms that a track started playing.
+    ///
+    /// This is useful for updating now-playing messages at the correct time.
+    /// For versions of Lavalink that don't send a `TrackStartEvent`, this is
+    /// called upon the first player update after a track is played.
+    ///
+    /// By default this does nothing.
+    fn track_start(&mut self, player: AudioPlayer, track: String)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (player, track);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when a payload received from a node could not be
+    /// parsed, such as when it is malformed or is missing fields.
+    ///
+    /// The payload is the raw text that was received. The payload is otherwise
+    /// ignored.
+    ///
+    /// By default this does nothing.
+    fn parse_error(&mut self, node_host: &str, payload: String, error: Error)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (node_host, payload, error);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when nothing was received from a node within its
+    /// heartbeat timeout, so its connection is considered stale.
+    ///
+    /// This is called once each time the connection becomes stale. Refer to
+    /// [`HeartbeatConfig`] for more information.
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`HeartbeatConfig`]: nodes/struct.HeartbeatConfig.html
+    fn ws_stale(&mut self, node_host: &str) -> Box<Future<Item = (), Error = Error>> {
+        let _ = node_host;
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when a node sends an update of a player's progress.
+    ///
+    /// The `time` is the node's Unix timestamp of the update in milliseconds,
+    /// and the `position` is the position of the track in milliseconds. This
+    /// is useful for progress displays driven by the node's real position.
+    /// The player's [`time`] and [`position`] are updated before this is
+    /// called.
+    ///
+    /// This is only called for guilds with a player.
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`position`]: player/struct.AudioPlayer.html#structfield.position
+    /// [`time`]: player/struct.AudioPlayer.html#structfield.time
+    fn player_update(&mut self, guild_id: GuildId, time: i64, position: i64)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (guild_id, time, position);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when a track ends, with the data attached to it via
+    /// [`AudioPlayer::play_with_data`].
+    ///
+    /// By default this calls [`track_end`], discarding the data.
+    ///
+    /// [`AudioPlayer::play_with_data`]: player/struct.AudioPlayer.html#method.play_with_data
+    /// [`track_end`]: #tymethod.track_end
+    fn track_end_with_data(
+        &mut self,
+        track: String,
+        reason: TrackEndReason,
+        data: Option<TrackData>,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        let _ = data;
+
+        self.track_end(track, reason)
+    }
+
+    /// Tymethod called when an exception occurs during a track playing, with
+    /// the data attached to it via [`AudioPlayer::play_with_data`].
+    ///
+    /// By default this calls [`track_exception`], discarding the data.
+    ///
+    /// [`AudioPlayer::play_with_data`]: player/struct.AudioPlayer.html#method.play_with_data
+    /// [`track_exception`]: #tymethod.track_exception
+    fn track_exception_with_data(
+        &mut self,
+        track: String,
+        exception: TrackException,
+        data: Option<TrackData>,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        let _ = data;
+
+        self.track_exception(track, exception)
+    }
+
+    /// Tymethod called when the connection to a node is closed, whether by
+    /// the node or because the connection was lost.
+    ///
+    /// Use [`CloseData::is_normal`] to distinguish expected closes from
+    /// abnormal ones. The data is also stored as the node's
+    /// [`State::last_close`].
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`CloseData::is_normal`]: nodes/struct.CloseData.html#method.is_normal
+    /// [`State::last_close`]: nodes/struct.State.html#structfield.last_close
+    fn ws_close(&mut self, node_host: &str, data: CloseData)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (node_host, data);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called with the error resolved by the future of another
+    /// tymethod called for an event from a node, such as [`track_end`].
+    ///
+    /// By default this logs the error.
+    ///
+    /// [`track_end`]: #tymethod.track_end
+    fn handler_error(&mut self, error: Error) {
+        event!(warn; "Err in event handler: {:?}", error);
+    }
+
+    /// Tymethod called when Discord's voice WebSocket connection for a
+    /// player is closed, with the close code, the reason, and whether it was
+    /// closed by Discord.
+    ///
+    /// This is useful for rejoining the voice channel after the connection
+    /// was closed with the code `4006` or `4014`.
+    ///
+    /// By default this does nothing.
+    fn websocket_closed(
+        &mut self,
+        player: AudioPlayer,
+        code: i64,
+        reason: String,
+        by_remote: bool,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        let _ = (player, code, reason, by_remote);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called for each player on a node when the connection to the
+    /// node is closed, with how it was closed.
+    ///
+    /// The player is marked as [`node_disconnected`] beforehand, and its
+    /// [estimated position] is frozen until the node is reconnected to. This
+    /// is useful for showing that the connection was lost rather than a
+    /// frozen progress bar.
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`node_disconnected`]: player/struct.AudioPlayer.html#structfield.node_disconnected
+    /// [estimated position]: player/struct.AudioPlayer.html#method.estimated_position
+    fn player_disconnected(&mut self, player: AudioPlayer, data: CloseData)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (player, data);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when a node sends a payload that the library doesn't
+    /// handle, with the payload's op.
+    ///
+    /// This is useful for consuming the ops of Lavalink plugins, such as
+    /// SponsorBlock's segment events. Events of unknown types are passed with
+    /// the `event` op, so check the payload's `type` to distinguish them.
+    /// Use [`Node::send_raw`] to send ops to plugins.
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`Node::send_raw`]: nodes/struct.Node.html#method.send_raw
+    fn unknown_op(&mut self, node_host: &str, op: &str, json: Value)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (node_host, op, json);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when a node sends its `ready` op once connected to,
+    /// with whether the previous session was resumed and the ID of the
+    /// session.
+    ///
+    /// This is only sent by Lavalink v3.7 and newer. The session ID is also
+    /// stored in the node's [`State`].
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`State`]: nodes/struct.State.html
+    fn ready(&mut self, node_host: &str, resumed: bool, session_id: &str)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (node_host, resumed, session_id);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when a player subscribed to lyrics reaches a line of
+    /// its track's lyrics, with whether the line was skipped over, such as by
+    /// seeking.
+    ///
+    /// This requires the LavaLyrics plugin on the node and the `lyrics`
+    /// feature. Refer to the [`plugins::lyrics`] module for more information.
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`plugins::lyrics`]: plugins/lyrics/index.html
+    #[cfg(feature = "lyrics")]
+    fn lyrics_line(&mut self, player: AudioPlayer, line: LyricsLine, skipped: bool)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (player, line, skipped);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when a segment of a player's track was skipped, such
+    /// as a sponsored section of a video.
+    ///
+    /// This requires the SponsorBlock plugin on the node and the
+    /// `sponsorblock` feature. Refer to the [`plugins::sponsorblock`] module
+    /// for more information.
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`plugins::sponsorblock`]: plugins/sponsorblock/index.html
+    #[cfg(feature = "sponsorblock")]
+    fn segment_skipped(&mut self, player: AudioPlayer, segment: Segment)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (player, segment);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Tymethod called when a player's position reaches one of its markers,
+    /// with the name of the marker.
+    ///
+    /// Refer to [`AudioPlayer::set_marker`] for more information.
+    ///
+    /// By default this does nothing.
+    ///
+    /// [`AudioPlayer::set_marker`]: player/struct.AudioPlayer.html#method.set_marker
+    fn marker_reached(&mut self, player: AudioPlayer, name: String)
+        -> Box<Future<Item = (), Error = Error>> {
+        let _ = (player, name);
+
+        Box::new(future::ok(()))
+    }
+
+    /// Method returning the handler as a [`HandlerChain`] if it is one, so
+    /// that [`NodeManager::add_handler`] can add to it rather than nesting
+    /// chains.
+    ///
+    /// This should not be implemented by other handlers.
+    ///
+    /// [`HandlerChain`]: struct.HandlerChain.html
+    /// [`NodeManager::add_handler`]: nodes/struct.NodeManager.html#method.add_handler
+    fn as_handler_chain(&mut self) -> Option<&mut HandlerChain> {
+        None
+    }
+}
+
+/// An [`EventHandler`] calling a chain of handlers in order, so that a
+/// library can install a handler of its own - such as for queueing - while
+/// the application installs another.
+///
+/// Tymethods for events are called on every handler, resolving once all of
+/// their futures have resolved. Errors can't be cloned, so [`handler_error`]
+/// and [`parse_error`] are only called on the first handler.
+///
+/// When short-circuiting, the handlers are called one after another for
+/// [`forward`] until one resolves to a message, and for [`is_connected`] and
+/// [`is_valid`] until one resolves to `false`. Otherwise every handler is
+/// called, resolving to the first message or whether every handler resolved
+/// to `true`. Handlers that don't know about shards or guilds should
+/// resolve to `true`.
+///
+/// A chain is created automatically by [`NodeManager::add_handler`].
+///
+/// [`EventHandler`]: trait.EventHandler.html
+/// [`NodeManager::add_handler`]: nodes/struct.NodeManager.html#method.add_handler
+/// [`forward`]: trait.EventHandler.html#tymethod.forward
+/// [`handler_error`]: trait.EventHandler.html#method.handler_error
+/// [`is_connected`]: trait.EventHandler.html#tymethod.is_connected
+/// [`is_valid`]: trait.EventHandler.html#tymethod.is_valid
+/// [`parse_error`]: trait.EventHandler.html#method.parse_error
+pub struct HandlerChain {
+    handlers: Vec<SharedHandler>,
+    short_circuit: bool,
+}
+
+impl HandlerChain {
+    /// Creates a new, empty chain, which short-circuits if `short_circuit`
+    /// is set.
+    pub fn new(short_circuit: bool) -> Self {
+        Self {
+            handlers: Vec::new(),
+            short_circuit,
+        }
+    }
+
+    /// Whether there are no handlers in the chain.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// The number of handlers in the chain.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// Adds a handler to the end of the chain.
+    pub fn push(&mut self, handler: Box<EventHandler>) {
+        self.handlers.push(Arc::new(Mutex::new(handler)));
+    }
+
+    /// Whether the chain short-circuits.
+    #[inline]
+    pub fn short_circuit(&self) -> bool {
+        self.short_circuit
+    }
+
+    fn call_all<T, F>(&self, mut f: F) -> Box<Future<Item = Vec<T>, Error = Error>>
+        where T: 'static,
+              F: FnMut(&mut Box<EventHandler>) -> Box<Future<Item = T, Error = Error>> {
+        let called = self.handlers.iter().map(|handler| -> Box<Future<Item = T, Error = Error>> {
+            match handler.lock() {
+                Ok(mut handler) => f(&mut handler),
+                Err(why) => Box::new(future::err(Error::from(why))),
+            }
+        }).collect::<Vec<_>>();
+
+        Box::new(future::join_all(called))
+    }
+
+    fn call_each<F>(&self, f: F) -> Box<Future<Item = (), Error = Error>>
+        where F: FnMut(&mut Box<EventHandler>) -> Box<Future<Item = (), Error = Error>> {
+        Box::new(self.call_all(f).map(|_| ()))
+    }
+
+    fn call_first<F: FnOnce(&mut Box<EventHandler>)>(&self, f: F) {
+        let handler = match self.handlers.first() {
+            Some(handler) => handler,
+            None => return,
+        };
+
+        match handler.lock() {
+            Ok(mut handler) => f(&mut handler),
+            Err(why) => event!(warn; "Err locking chained event handler: {:?}", why),
+        }
+    }
+}
+
+impl Debug for HandlerChain {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("HandlerChain")
+            .field("handlers", &self.handlers.len())
+            .field("short_circuit", &self.short_circuit)
+            .finish()
+    }
+}
+
+impl EventHandler for HandlerChain {
+    fn as_handler_chain(&mut self) -> Option<&mut HandlerChain> {
+        Some(self)
+    }
+
+    fn forward(&mut self, shard_id: u64, message: &str)
+        -> Box<Future<Item = Option<OwnedMessage>, Error = Error>> {
+        if self.short_circuit {
+            let handlers = self.handlers.iter().cloned().collect();
+            let message = message.to_owned();
+
+            return call_until(handlers, None, move |handler| {
+                handler.forward(shard_id, &message)
+            }, Option::is_some);
+        }
+
+        Box::new(self.call_all(|handler| handler.forward(shard_id, message)).map(|replies| {
+            replies.into_iter().flatten().next()
+        }))
+    }
+
+    fn is_connected(&mut self, shard_id: u64)
+        -> Box<Future<Item = bool, Error = Error>> {
+        if self.short_circuit {
+            let handlers = self.handlers.iter().cloned().collect();
+
+            return call_until(handlers, true, move |handler| {
+                handler.is_connected(shard_id)
+            }, |connected| !connected);
+        }
+
+        let connected = self.call_all(|handler| handler.is_connected(shard_id));
+
+        Box::new(connected.map(|connected| connected.into_iter().all(|connected| connected)))
+    }
+
+    fn is_valid(&mut self, guild_id: &str, channel_id: Option<String>)
+        -> Box<Future<Item = bool, Error = Error>> {
+        if self.short_circuit {
+            let handlers = self.handlers.iter().cloned().collect();
+            let guild_id = guild_id.to_owned();
+
+            return call_until(handlers, true, move |handler| {
+                handler.is_valid(&guild_id, channel_id.clone())
+            }, |valid| !valid);
+        }
+
+        let valid = self.call_all(|handler| handler.is_valid(guild_id, channel_id.clone()));
+
+        Box::new(valid.map(|valid| valid.into_iter().all(|valid| valid)))
+    }
+
+    fn track_end(&mut self, track: String, reason: TrackEndReason)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.track_end(track.clone(), reason))
+    }
+
+    fn track_exception(&mut self, track: String, exception: TrackException)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.track_exception(track.clone(), exception.clone()))
+    }
+
+    fn track_stuck(&mut self, track: String, threshold_ms: i64)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.track_stuck(track.clone(), threshold_ms))
+    }
+
+    fn stats_update(&mut self, node_host: &str, stats: NodeStats)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.stats_update(node_host, stats.clone()))
+    }
+
+    fn track_start(&mut self, player: AudioPlayer, track: String)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.track_start(player.clone(), track.clone()))
+    }
+
+    fn parse_error(&mut self, node_host: &str, payload: String, error: Error)
+        -> Box<Future<Item = (), Error = Error>> {
+        let mut parsed: Box<Future<Item = (), Error = Error>> = Box::new(future::ok(()));
+
+        self.call_first(|handler| parsed = handler.parse_error(node_host, payload, error));
+
+        parsed
+    }
+
+    fn ws_stale(&mut self, node_host: &str) -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.ws_stale(node_host))
+    }
+
+    fn player_update(&mut self, guild_id: GuildId, time: i64, position: i64)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.player_update(guild_id, time, position))
+    }
+
+    fn track_end_with_data(
+        &mut self,
+        track: String,
+        reason: TrackEndReason,
+        data: Option<TrackData>,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.track_end_with_data(track.clone(), reason, data.clone()))
+    }
+
+    fn track_exception_with_data(
+        &mut self,
+        track: String,
+        exception: TrackException,
+        data: Option<TrackData>,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| {
+            handler.track_exception_with_data(track.clone(), exception.clone(), data.clone())
+        })
+    }
+
+    fn ws_close(&mut self, node_host: &str, data: CloseData)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.ws_close(node_host, data.clone()))
+    }
+
+    fn handler_error(&mut self, error: Error) {
+        self.call_first(|handler| handler.handler_error(error));
+    }
+
+    fn websocket_closed(
+        &mut self,
+        player: AudioPlayer,
+        code: i64,
+        reason: String,
+        by_remote: bool,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| {
+            handler.websocket_closed(player.clone(), code, reason.clone(), by_remote)
+        })
+    }
+
+    fn player_disconnected(&mut self, player: AudioPlayer, data: CloseData)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.player_disconnected(player.clone(), data.clone()))
+    }
+
+    fn unknown_op(&mut self, node_host: &str, op: &str, json: Value)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.unknown_op(node_host, op, json.clone()))
+    }
+
+    fn ready(&mut self, node_host: &str, resumed: bool, session_id: &str)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.ready(node_host, resumed, session_id))
+    }
+
+    #[cfg(feature = "lyrics")]
+    fn lyrics_line(&mut self, player: AudioPlayer, line: LyricsLine, skipped: bool)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.lyrics_line(player.clone(), line.clone(), skipped))
+    }
+
+    #[cfg(feature = "sponsorblock")]
+    fn segment_skipped(&mut self, player: AudioPlayer, segment: Segment)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.segment_skipped(player.clone(), segment.clone()))
+    }
+
+    fn marker_reached(&mut self, player: AudioPlayer, name: String)
+        -> Box<Future<Item = (), Error = Error>> {
+        self.call_each(|handler| handler.marker_reached(player.clone(), name.clone()))
+    }
+}
+
+/// Calls the handlers one after another until the value resolved by one of
+/// them satisfies `stop`, resolving to the last value, or to the default if
+/// there are no handlers.
+fn call_until<T, F, P>(mut handlers: VecDeque<SharedHandler>, default: T, mut f: F, stop: P)
+    -> Box<Future<Item = T, Error = Error>>
+    where T: 'static,
+          F: FnMut(&mut Box<EventHandler>) -> Box<Future<Item = T, Error = Error>> + 'static,
+          P: Fn(&T) -> bool + 'static {
+    let handler = match handlers.pop_front() {
+        Some(handler) => handler,
+        None => return Box::new(future::ok(default)),
+    };
+
+    let called = match handler.lock() {
+        Ok(mut handler) => f(&mut handler),
+        Err(why) => return Box::new(future::err(Error::from(why))),
+    };
+
+    Box::new(called.and_then(move |value| -> Box<Future<Item = T, Error = Error>> {
+        if stop(&value) || handlers.is_empty() {
+            Box::new(future::ok(value))
+        } else {
+            call_until(handlers, default, f, stop)
+        }
+    }))
 }
@@ -0,0 +1,108 @@
+//! Executors calling the [`EventHandler`] for events received from nodes.
+//!
+//! By default the handler is called - and the futures it returns are run - on
+//! the tokio Core that the [`NodeManager`] was created with, which also reads
+//! from the nodes. Heavy handler work can be moved elsewhere by providing an
+//! [`Executor`] via [`NodeManager::set_executor`], such as a
+//! [`RemoteExecutor`] running on a dedicated event thread.
+//!
+//! [`EventHandler`]: ../trait.EventHandler.html
+//! [`Executor`]: trait.Executor.html
+//! [`NodeManager`]: ../nodes/struct.NodeManager.html
+//! [`NodeManager::set_executor`]: ../nodes/struct.NodeManager.html#method.set_executor
+//! [`RemoteExecutor`]: struct.RemoteExecutor.html
+
+use futures::sync::oneshot;
+use futures::Future;
+use std::sync::mpsc;
+use std::thread;
+use tokio_core::reactor::{Core, Remote};
+use ::Error;
+
+/// A call of the handler for an event, which returns the future to run to
+/// completion.
+///
+/// This is implemented for closures.
+pub trait Task: Send {
+    /// Calls the handler, returning the future that it resolved.
+    fn run(self: Box<Self>) -> Box<Future<Item = (), Error = ()>>;
+}
+
+impl<F> Task for F where F: FnOnce() -> Box<Future<Item = (), Error = ()>> + Send {
+    fn run(self: Box<Self>) -> Box<Future<Item = (), Error = ()>> {
+        (*self)()
+    }
+}
+
+/// A spawner of the tasks calling the handler for events from nodes.
+///
+/// The future returned by a task is not `Send`, so it must be run on the
+/// thread that ran the task. Tasks should be run in the order that they are
+/// given, so that the handler receives events in the order that they were
+/// received.
+///
+/// For example, an executor over a `futures-cpupool` can run each task to
+/// completion on the pool via `pool.spawn_fn(move || task.run().wait())`.
+pub trait Executor: Send + Sync {
+    /// Runs a task, driving its future to completion.
+    fn execute(&self, task: Box<Task>);
+}
+
+/// An executor running tasks on a tokio Core via its [`Remote`].
+///
+/// [`Remote`]: https://docs.rs/tokio-core/0.1/tokio_core/reactor/struct.Remote.html
+pub struct RemoteExecutor {
+    remote: Remote,
+    // Stops the core of a spawned thread when dropped.
+    _stop: Option<oneshot::Sender<()>>,
+}
+
+impl RemoteExecutor {
+    /// Creates a new executor running tasks on the core of the remote.
+    pub fn new(remote: Remote) -> Self {
+        Self {
+            remote,
+            _stop: None,
+        }
+    }
+
+    /// Creates a new executor running tasks on a new core on a dedicated
+    /// thread with the given name.
+    ///
+    /// The thread runs until the executor is dropped.
+    pub fn spawn_thread(name: &str) -> Result<Self, Error> {
+        let (remote_tx, remote_rx) = mpsc::channel();
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+
+        thread::Builder::new().name(name.to_owned()).spawn(move || {
+            let mut core = match Core::new() {
+                Ok(core) => core,
+                Err(why) => {
+                    let _ = remote_tx.send(Err(why));
+
+                    return;
+                },
+            };
+
+            let _ = remote_tx.send(Ok(core.remote()));
+
+            // Resolves to an error once the executor is dropped.
+            let _ = core.run(stop_rx);
+        })?;
+
+        match remote_rx.recv() {
+            Ok(Ok(remote)) => Ok(Self {
+                remote,
+                _stop: Some(stop_tx),
+            }),
+            Ok(Err(why)) => Err(Error::Io(why)),
+            Err(_) => Err(Error::Closed),
+        }
+    }
+}
+
+impl Executor for RemoteExecutor {
+    fn execute(&self, task: Box<Task>) {
+        self.remote.spawn(move |_| task.run());
+    }
+}
@@ -0,0 +1,9 @@
+//! Integrations with Discord libraries, each behind a cargo feature of the
+//! same name.
+//!
+//! - `serenity`: [`interop::serenity`]
+//!
+//! [`interop::serenity`]: serenity/index.html
+
+#[cfg(feature = "serenity")]
+pub mod serenity;
@@ -0,0 +1,394 @@
+//! Integration with [serenity], enabled via the `serenity` feature.
+//!
+//! [`SerenityHandler`] is a ready-made [`EventHandler`] which forwards
+//! payloads to Discord over serenity's shards, and [`VoiceUpdates`] maps
+//! serenity's voice events into [`AudioPlayer::voice_update`].
+//!
+//! Create the handler from the shard runners of serenity's client, such as
+//! via `client.shard_manager.lock().runners.clone()`, and call the methods of
+//! [`VoiceUpdates`] from the `voice_state_update` and `voice_server_update`
+//! methods of serenity's event handler.
+//!
+//! [`AudioPlayer::voice_update`]: ../../player/struct.AudioPlayer.html#method.voice_update
+//! [`EventHandler`]: ../../trait.EventHandler.html
+//! [`SerenityHandler`]: struct.SerenityHandler.html
+//! [`VoiceUpdates`]: struct.VoiceUpdates.html
+//! [serenity]: https://github.com/serenity-rs/serenity
+
+use futures::{Future, future};
+use serde_json::{self, Value};
+use serenity::client::bridge::gateway::{ShardId, ShardRunnerInfo};
+use serenity::gateway::{ConnectionStage, InterMessage};
+use serenity::model::event::VoiceServerUpdateEvent;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::model::voice::VoiceState;
+use serenity::prelude::Mutex as SerenityMutex;
+use serenity::CACHE;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use websocket::OwnedMessage;
+use ::model::{GuildId as LavalinkGuildId, NodeStats, TrackEndReason, TrackException};
+use ::nodes::CloseData;
+use ::player::{AudioPlayer, AudioPlayerManager, TrackData, lock_players};
+#[cfg(feature = "lyrics")]
+use ::plugins::lyrics::LyricsLine;
+#[cfg(feature = "sponsorblock")]
+use ::plugins::sponsorblock::Segment;
+use ::{Error, EventHandler};
+
+/// The shard runners of serenity's shard manager, keyed by shard ID.
+pub type ShardRunners = Arc<SerenityMutex<HashMap<ShardId, ShardRunnerInfo>>>;
+
+/// An [`EventHandler`] which forwards payloads to Discord over serenity's
+/// shards, and checks the validity of guilds and channels via serenity's
+/// cache.
+///
+/// Events from nodes - such as track ends - are passed to the optional
+/// handler given via [`with_events`], and are otherwise ignored. The
+/// [`forward`], [`is_connected`], and [`is_valid`] tymethods of that handler
+/// are never called.
+///
+/// [`EventHandler`]: ../../trait.EventHandler.html
+/// [`forward`]: ../../trait.EventHandler.html#tymethod.forward
+/// [`is_connected`]: ../../trait.EventHandler.html#tymethod.is_connected
+/// [`is_valid`]: ../../trait.EventHandler.html#tymethod.is_valid
+/// [`with_events`]: #method.with_events
+pub struct SerenityHandler {
+    events: Option<Box<EventHandler>>,
+    runners: ShardRunners,
+}
+
+impl SerenityHandler {
+    /// Creates a new handler forwarding payloads over the given shard runners,
+    /// ignoring events from nodes.
+    pub fn new(runners: ShardRunners) -> Self {
+        Self {
+            events: None,
+            runners,
+        }
+    }
+
+    /// Creates a new handler forwarding payloads over the given shard runners,
+    /// passing events from nodes to another handler.
+    pub fn with_events(runners: ShardRunners, events: Box<EventHandler>) -> Self {
+        Self {
+            events: Some(events),
+            runners,
+        }
+    }
+}
+
+impl EventHandler for SerenityHandler {
+    fn forward(&mut self, shard_id: u64, message: &str)
+        -> Box<Future<Item = Option<OwnedMessage>, Error = Error>> {
+        let value = match serde_json::from_str::<Value>(message) {
+            Ok(value) => value,
+            Err(why) => return Box::new(future::err(Error::Json(why))),
+        };
+
+        let runners = self.runners.lock();
+
+        let info = match runners.get(&ShardId(shard_id)) {
+            Some(info) => info,
+            None => {
+                event!(warn, shard = shard_id; "No shard runner");
+
+                return Box::new(future::err(Error::None));
+            },
+        };
+
+        if let Err(why) = info.runner_tx.send(InterMessage::Json(value)) {
+            event!(warn, shard = shard_id; "Err forwarding payload: {:?}", why);
+
+            return Box::new(future::err(Error::Closed));
+        }
+
+        Box::new(future::ok(None))
+    }
+
+    fn is_connected(&mut self, shard_id: u64)
+        -> Box<Future<Item = bool, Error = Error>> {
+        let connected = self.runners
+            .lock()
+            .get(&ShardId(shard_id))
+            .map_or(false, |info| info.stage == ConnectionStage::Connected);
+
+        Box::new(future::ok(connected))
+    }
+
+    fn is_valid(&mut self, guild_id: &str, channel_id: Option<String>)
+        -> Box<Future<Item = bool, Error = Error>> {
+        let guild_id = match guild_id.parse::<u64>() {
+            Ok(guild_id) => GuildId(guild_id),
+            Err(_) => return Box::new(future::ok(false)),
+        };
+
+        let guild = match CACHE.read().guild(guild_id) {
+            Some(guild) => guild,
+            None => return Box::new(future::ok(false)),
+        };
+
+        let valid = match channel_id {
+            Some(channel_id) => match channel_id.parse::<u64>() {
+                Ok(channel_id) => {
+                    guild.read().channels.contains_key(&ChannelId(channel_id))
+                },
+                Err(_) => false,
+            },
+            None => true,
+        };
+
+        Box::new(future::ok(valid))
+    }
+
+    fn track_end(&mut self, track: String, reason: TrackEndReason)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.track_end(track, reason),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn track_exception(&mut self, track: String, exception: TrackException)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.track_exception(track, exception),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn track_stuck(&mut self, track: String, threshold_ms: i64)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.track_stuck(track, threshold_ms),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn stats_update(&mut self, node_host: &str, stats: NodeStats)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.stats_update(node_host, stats),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn track_start(&mut self, player: AudioPlayer, track: String)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.track_start(player, track),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn parse_error(&mut self, node_host: &str, payload: String, error: Error)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.parse_error(node_host, payload, error),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn ws_stale(&mut self, node_host: &str) -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.ws_stale(node_host),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn player_update(&mut self, guild_id: LavalinkGuildId, time: i64, position: i64)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.player_update(guild_id, time, position),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn track_end_with_data(
+        &mut self,
+        track: String,
+        reason: TrackEndReason,
+        data: Option<TrackData>,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.track_end_with_data(track, reason, data),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn track_exception_with_data(
+        &mut self,
+        track: String,
+        exception: TrackException,
+        data: Option<TrackData>,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.track_exception_with_data(track, exception, data),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn ws_close(&mut self, node_host: &str, data: CloseData)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.ws_close(node_host, data),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn handler_error(&mut self, error: Error) {
+        match self.events {
+            Some(ref mut events) => events.handler_error(error),
+            None => event!(warn; "Err in event handler: {:?}", error),
+        }
+    }
+
+    fn websocket_closed(
+        &mut self,
+        player: AudioPlayer,
+        code: i64,
+        reason: String,
+        by_remote: bool,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.websocket_closed(player, code, reason, by_remote),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn player_disconnected(&mut self, player: AudioPlayer, data: CloseData)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.player_disconnected(player, data),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn unknown_op(&mut self, node_host: &str, op: &str, json: Value)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.unknown_op(node_host, op, json),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn ready(&mut self, node_host: &str, resumed: bool, session_id: &str)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.ready(node_host, resumed, session_id),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    #[cfg(feature = "lyrics")]
+    fn lyrics_line(&mut self, player: AudioPlayer, line: LyricsLine, skipped: bool)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.lyrics_line(player, line, skipped),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    #[cfg(feature = "sponsorblock")]
+    fn segment_skipped(&mut self, player: AudioPlayer, segment: Segment)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.segment_skipped(player, segment),
+            None => Box::new(future::ok(())),
+        }
+    }
+
+    fn marker_reached(&mut self, player: AudioPlayer, name: String)
+        -> Box<Future<Item = (), Error = Error>> {
+        match self.events {
+            Some(ref mut events) => events.marker_reached(player, name),
+            None => Box::new(future::ok(())),
+        }
+    }
+}
+
+/// A mapper of serenity's voice events into [`AudioPlayer::voice_update`].
+///
+/// Discord sends the session ID of the bot user's voice connection in a
+/// voice state update, and the token and endpoint in a separate voice server
+/// update. The session ID is stored until the voice server update is
+/// received, at which point the guild's player is sent the voice update.
+///
+/// [`AudioPlayer::voice_update`]: ../../player/struct.AudioPlayer.html#method.voice_update
+#[derive(Debug, Default)]
+pub struct VoiceUpdates {
+    sessions: Mutex<HashMap<u64, String>>,
+}
+
+impl VoiceUpdates {
+    /// Creates a new default `VoiceUpdates`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores the session ID of the bot user's voice connection in a guild.
+    ///
+    /// Voice states of other users are ignored. The session is forgotten when
+    /// the bot user leaves the voice channel.
+    pub fn voice_state_update(&self, guild_id: Option<GuildId>, state: &VoiceState) {
+        let guild_id = match guild_id {
+            Some(guild_id) => guild_id.0,
+            None => return,
+        };
+
+        if state.user_id != CACHE.read().user.id {
+            return;
+        }
+
+        let mut sessions = match self.sessions.lock() {
+            Ok(sessions) => sessions,
+            Err(why) => {
+                event!(warn; "Err locking voice sessions: {:?}", why);
+
+                return;
+            },
+        };
+
+        if state.channel_id.is_some() {
+            sessions.insert(guild_id, state.session_id.clone());
+        } else {
+            sessions.remove(&guild_id);
+        }
+    }
+
+    /// Sends a voice update to the player of the event's guild, using the
+    /// session ID stored from the last voice state update.
+    ///
+    /// Returns whether a voice update was sent. None is sent if the guild has
+    /// no player or session ID, or if the event has no endpoint.
+    pub fn voice_server_update(
+        &self,
+        manager: &Arc<Mutex<AudioPlayerManager>>,
+        event: &VoiceServerUpdateEvent,
+    ) -> Result<bool, Error> {
+        let guild_id = match event.guild_id {
+            Some(guild_id) => guild_id.0,
+            None => return Ok(false),
+        };
+        let endpoint = match event.endpoint {
+            Some(ref endpoint) => endpoint,
+            None => return Ok(false),
+        };
+        let session_id = match self.sessions.lock()?.get(&guild_id) {
+            Some(session_id) => session_id.clone(),
+            None => return Ok(false),
+        };
+
+        let mut manager = lock_players(manager);
+
+        let player = match manager.get_mut(&guild_id) {
+            Some(player) => player,
+            None => return Ok(false),
+        };
+
+        player.voice_update(&session_id, &event.token, endpoint)?;
+
+        Ok(true)
+    }
+}
@@ -1,7 +1,7 @@
 //! # lavalink-futures
 //!
 //! `lavalink-futures` is an asynchronous, Futures-based client implementation for
-//! [Lavalink], built on top of [lavalink.rs].
+//! [Lavalink].
 //!
 //! ### Installation
 //!
@@ -25,20 +25,24 @@
 //!
 //! ```rust,no_run
 //! # extern crate futures;
+extern crate futures_cpupool;
 //! #
 //! extern crate lavalink_futures;
 //! extern crate tokio_core;
 //!
 //! # use futures::{Future, future};
+//! # use lavalink_futures::model::{TrackEndReason, TrackException};
 //! # use lavalink_futures::reexports::OwnedMessage;
-//! # use std::error::Error;
+//! # use lavalink_futures::Error;
+//! # use std::error::Error as StdError;
 //! #
-//! # fn try_main() -> Result<(), Box<Error>> {
+//! # fn try_main() -> Result<(), Box<StdError>> {
 //! #
-//! use lavalink_futures::nodes::{NodeConfig, NodeManager};
+//! use lavalink_futures::nodes::{NodeConfigBuilder, NodeManager};
 //! use lavalink_futures::EventHandler;
-//! use std::cell::RefCell;
 //! use std::env;
+//! use std::sync::Mutex;
+//! use std::time::Duration;
 //! use tokio_core::reactor::Core;
 //!
 //! struct Handler {
@@ -56,48 +60,52 @@
 //! impl EventHandler for Handler {
 //!     // Implement EventHandler tymethods here...
 //! #     fn forward(&mut self, _: u64, _: &str)
-//! #         -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+//! #         -> Box<Future<Item = Option<OwnedMessage>, Error = Error>> {
 //! #         Box::new(future::ok(None))
 //! #     }
 //! #
 //! #     fn is_connected(&mut self, _: u64)
-//! #         -> Box<Future<Item = bool, Error = ()>> {
+//! #         -> Box<Future<Item = bool, Error = Error>> {
 //! #         Box::new(future::ok(true))
 //! #     }
 //! #
 //! #     fn is_valid(&mut self, _: &str, _: Option<String>)
-//! #         -> Box<Future<Item = bool, Error = ()>> {
+//! #         -> Box<Future<Item = bool, Error = Error>> {
 //! #         Box::new(future::ok(true))
 //! #     }
 //! #
-//! #     fn track_end(&mut self, _: String, _: String)
-//! #         -> Box<Future<Item = (), Error = ()>> {
+//! #     fn track_end(&mut self, _: String, _: TrackEndReason)
+//! #         -> Box<Future<Item = (), Error = Error>> {
 //! #         Box::new(future::ok(()))
 //! #     }
 //! #
-//! #     fn track_exception(&mut self, _: String, _: String)
-//! #         -> Box<Future<Item = (), Error = ()>> {
+//! #     fn track_exception(&mut self, _: String, _: TrackException)
+//! #         -> Box<Future<Item = (), Error = Error>> {
 //! #         Box::new(future::ok(()))
 //! #     }
 //! #
 //! #     fn track_stuck(&mut self, _: String, _: i64)
-//! #         -> Box<Future<Item = (), Error = ()>> {
+//! #         -> Box<Future<Item = (), Error = Error>> {
 //! #         Box::new(future::ok(()))
 //! #     }
 //! }
 //!
 //! let mut core = Core::new()?;
 //!
-//! let handler = RefCell::new(Box::new(Handler::new()));
+//! let handler: Box<EventHandler> = Box::new(Handler::new());
+//! let handler = Mutex::new(handler);
 //! let mut manager = NodeManager::new(core.handle(), handler);
 //!
-//! let done = manager.add_node(NodeConfig {
-//!     http_host: env::var("LAVALINK_HTTP_HOST")?,
-//!     num_shards: env::var("DISCORD_SHARD_COUNT")?.parse()?,
-//!     password: env::var("LAVALINK_PASSWORD")?,
-//!     user_id: env::var("DISCORD_USER_ID")?.parse()?,
-//!     websocket_host: env::var("LAVALINK_WS_HOST")?,
-//! }).map(|manager| {
+//! let config = NodeConfigBuilder::new()
+//!     .http_host(env::var("LAVALINK_HTTP_HOST")?)
+//!     .websocket_host(env::var("LAVALINK_WS_HOST")?)
+//!     .password(env::var("LAVALINK_PASSWORD")?)
+//!     .user_id(env::var("DISCORD_USER_ID")?)
+//!     .num_shards(env::var("DISCORD_SHARD_COUNT")?.parse()?)
+//!     .ready_timeout(Some(Duration::from_secs(10)))
+//!     .build()?;
+//!
+//! let done = manager.add_node(config).map(|(manager, result)| {
 //!     // Do more with the manager here, such as connecting to more nodes, creating
 //!     // audio players, and/or attaching the node manager to some structure's
 //!     // state.
@@ -113,25 +121,54 @@
 //! ```
 //!
 //! [Lavalink]: https://github.com/Frederikam/Lavalink
-//! [lavalink.rs]: https://github.com/serenity-rs/lavalink.rs
 
 #![deny(missing_docs)]
 
-#[macro_use] extern crate log;
+#[cfg_attr(not(feature = "tracing"), macro_use)] extern crate log;
+#[macro_use] extern crate percent_encoding;
+#[macro_use] extern crate serde_derive;
+#[macro_use] extern crate serde_json;
 
 extern crate evzht9h3nznqzwl as websocket;
 extern crate futures;
+extern crate futures_cpupool;
 extern crate hyper;
-extern crate lavalink;
+extern crate hyper_tls;
+extern crate native_tls;
 extern crate serde;
-extern crate serde_json;
+extern crate tokio_core;
+extern crate tokio_tls;
+
+#[cfg(feature = "serenity")]
+extern crate serenity;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+#[macro_use]
+mod macros;
 
+pub mod audit;
+pub mod executor;
+pub mod interop;
+pub mod metrics;
+pub mod middleware;
+pub mod model;
 pub mod nodes;
 pub mod player;
+pub mod playlist;
+pub mod plugins;
 pub mod reexports;
+pub mod rest;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod validation;
+pub mod voice;
 
 mod error;
 mod event_handler;
+mod remote;
+mod resolver;
+mod tls;
 
-pub use self::error::Error;
-pub use self::event_handler::EventHandler;
+pub use self::error::{Error, ErrorContext, ErrorKind};
+pub use self::event_handler::{EventHandler, HandlerChain};
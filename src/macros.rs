@@ -0,0 +1,58 @@
+//! Macros for emitting structured diagnostics.
+//!
+//! With the `tracing` feature, events and spans are emitted via the `tracing`
+//! crate with their fields attached. Otherwise, events are logged via the
+//! `log` crate with their fields appended to the message as `key=value`
+//! pairs, and spans do nothing.
+
+/// Emits an event at a level, optionally with fields, such as:
+///
+/// ```rust,ignore
+/// event!(warn, node = host, guild_id = guild_id; "Unknown guild");
+/// event!(trace; "Heartbeat stopped");
+/// ```
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:ident; $($arg:tt)+) => {
+        ::tracing::$level!($($arg)+)
+    };
+    ($level:ident, $($field:ident = $value:expr),+; $($arg:tt)+) => {
+        ::tracing::$level!($($field = %$value),+, $($arg)+)
+    };
+}
+
+/// Emits an event at a level, optionally with fields, such as:
+///
+/// ```rust,ignore
+/// event!(warn, node = host, guild_id = guild_id; "Unknown guild");
+/// event!(trace; "Heartbeat stopped");
+/// ```
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($level:ident; $($arg:tt)+) => {
+        $level!($($arg)+)
+    };
+    ($level:ident, $($field:ident = $value:expr),+; $($arg:tt)+) => {
+        $level!("{}{}", format_args!($($arg)+), {
+            let mut fields = String::new();
+            $(fields.push_str(&format!(" {}={}", stringify!($field), $value));)+
+            fields
+        })
+    };
+}
+
+/// Enters a span with fields until the returned guard is dropped.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($name:expr, $($field:ident = $value:expr),+) => {
+        ::tracing::debug_span!($name, $($field = %$value),+).entered()
+    };
+}
+
+/// Enters a span with fields until the returned guard is dropped.
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($name:expr, $($field:ident = $value:expr),+) => {{
+        let _ = ($name, $(&$value),+);
+    }};
+}
@@ -0,0 +1,325 @@
+//! Metrics about nodes, such as the number of messages received, emitted to a
+//! pluggable [`MetricsSink`].
+//!
+//! Implement [`MetricsSink`] to export the metrics to a system such as
+//! Prometheus or statsd, and provide it via [`NodeManager::with_metrics`].
+//!
+//! Every metric is labelled with the websocket host of the node that it is
+//! for, under the label `"node"`.
+//!
+//! The metrics are also kept by the library, and can be rendered in the
+//! Prometheus text exposition format - along with the stats of each node -
+//! via [`NodeManager::render_metrics`].
+//!
+//! [`MetricsSink`]: trait.MetricsSink.html
+//! [`NodeManager::render_metrics`]: ../nodes/struct.NodeManager.html#method.render_metrics
+//! [`NodeManager::with_metrics`]: ../nodes/struct.NodeManager.html#method.with_metrics
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The counter incremented when a payload is received from a node, labelled
+/// with the `"op"` of the payload.
+pub const MESSAGES_RECEIVED: &str = "lavalink_messages_received";
+/// The counter incremented when an event is dispatched to the handler,
+/// labelled with the `"event"` type.
+pub const EVENTS_DISPATCHED: &str = "lavalink_events_dispatched";
+/// The gauge set to the penalty of a node when its stats are received.
+pub const NODE_PENALTY: &str = "lavalink_node_penalty";
+/// The counter incremented when a node is reconnected to.
+pub const RECONNECTS: &str = "lavalink_reconnects";
+/// The timing of REST requests made to a node, labelled with the `"request"`
+/// made.
+pub const REST_LATENCY: &str = "lavalink_rest_latency";
+
+/// A receiver of metrics, which can export them to another system.
+///
+/// All of the methods do nothing by default.
+pub trait MetricsSink: Send + Sync {
+    /// Called to increment a counter by a value.
+    fn counter(&self, name: &str, value: u64, labels: &[(&str, &str)]) {
+        let _ = (name, value, labels);
+    }
+
+    /// Called to set a gauge to a value.
+    fn gauge(&self, name: &str, value: f64, labels: &[(&str, &str)]) {
+        let _ = (name, value, labels);
+    }
+
+    /// Called to record how long something took.
+    fn timing(&self, name: &str, duration: Duration, labels: &[(&str, &str)]) {
+        let _ = (name, duration, labels);
+    }
+}
+
+/// A sink which discards all metrics.
+///
+/// This is used when no sink is provided.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopSink;
+
+impl MetricsSink for NoopSink {}
+
+/// A cheaply cloneable handle to a [`MetricsSink`], which emits the metrics
+/// of nodes into it.
+///
+/// The current value of each metric is also kept, so that they can be
+/// [rendered].
+///
+/// [`MetricsSink`]: trait.MetricsSink.html
+/// [rendered]: #method.render
+#[derive(Clone)]
+pub struct Metrics {
+    recorded: Arc<Mutex<Recorded>>,
+    sink: Arc<MetricsSink>,
+}
+
+impl Metrics {
+    /// Creates a new handle emitting into the given sink.
+    pub fn new(sink: Box<MetricsSink>) -> Self {
+        Self {
+            recorded: Arc::new(Mutex::new(Recorded::default())),
+            sink: Arc::from(sink),
+        }
+    }
+
+    /// Increments the [`EVENTS_DISPATCHED`] counter.
+    ///
+    /// [`EVENTS_DISPATCHED`]: constant.EVENTS_DISPATCHED.html
+    pub fn event_dispatched(&self, node: &str, event: &str) {
+        self.counter(EVENTS_DISPATCHED, 1, &[("node", node), ("event", event)]);
+    }
+
+    /// Increments the [`MESSAGES_RECEIVED`] counter.
+    ///
+    /// [`MESSAGES_RECEIVED`]: constant.MESSAGES_RECEIVED.html
+    pub fn message_received(&self, node: &str, op: &str) {
+        self.counter(MESSAGES_RECEIVED, 1, &[("node", node), ("op", op)]);
+    }
+
+    /// Sets the [`NODE_PENALTY`] gauge.
+    ///
+    /// [`NODE_PENALTY`]: constant.NODE_PENALTY.html
+    pub fn penalty(&self, node: &str, penalty: i32) {
+        self.gauge(NODE_PENALTY, f64::from(penalty), &[("node", node)]);
+    }
+
+    /// Increments the [`RECONNECTS`] counter.
+    ///
+    /// [`RECONNECTS`]: constant.RECONNECTS.html
+    pub fn reconnect(&self, node: &str) {
+        self.counter(RECONNECTS, 1, &[("node", node)]);
+    }
+
+    /// Records the [`REST_LATENCY`] of a request.
+    ///
+    /// [`REST_LATENCY`]: constant.REST_LATENCY.html
+    pub fn rest_latency(&self, node: &str, request: &str, duration: Duration) {
+        self.timing(REST_LATENCY, duration, &[("node", node), ("request", request)]);
+    }
+
+    /// Renders the current value of every metric emitted so far into an
+    /// exposition.
+    ///
+    /// Timings are rendered as summaries of their total seconds and count.
+    pub fn render(&self, exposition: &mut Exposition) {
+        let recorded = match self.recorded.lock() {
+            Ok(recorded) => recorded,
+            Err(why) => {
+                event!(warn; "Err locking recorded metrics: {:?}", why);
+
+                return;
+            },
+        };
+
+        render_family(exposition, "counter", &recorded.counters, |out, name, labels, value| {
+            out.sample(name, labels, *value as f64);
+        });
+
+        render_family(exposition, "gauge", &recorded.gauges, |out, name, labels, value| {
+            out.sample(name, labels, *value);
+        });
+
+        render_family(exposition, "summary", &recorded.timings, |out, name, labels, value| {
+            let &(count, sum) = value;
+
+            out.sample(&format!("{}_sum", name), labels, sum);
+            out.sample(&format!("{}_count", name), labels, count as f64);
+        });
+    }
+
+    fn counter(&self, name: &'static str, value: u64, labels: &[(&str, &str)]) {
+        if let Ok(mut recorded) = self.recorded.lock() {
+            *recorded.counters.entry(key(name, labels)).or_insert(0) += value;
+        }
+
+        self.sink.counter(name, value, labels);
+    }
+
+    fn gauge(&self, name: &'static str, value: f64, labels: &[(&str, &str)]) {
+        if let Ok(mut recorded) = self.recorded.lock() {
+            recorded.gauges.insert(key(name, labels), value);
+        }
+
+        self.sink.gauge(name, value, labels);
+    }
+
+    fn timing(&self, name: &'static str, duration: Duration, labels: &[(&str, &str)]) {
+        if let Ok(mut recorded) = self.recorded.lock() {
+            let seconds = duration.as_secs() as f64
+                + f64::from(duration.subsec_nanos()) / 1_000_000_000.0;
+            let timing = recorded.timings.entry(key(name, labels)).or_insert((0, 0.0));
+
+            timing.0 += 1;
+            timing.1 += seconds;
+        }
+
+        self.sink.timing(name, duration, labels);
+    }
+}
+
+impl Debug for Metrics {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("Metrics").finish()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new(Box::new(NoopSink))
+    }
+}
+
+/// A builder of metrics in the Prometheus text exposition format.
+///
+/// This is used by [`NodeManager::render_metrics`], and can be used to add
+/// metrics of your own to its output.
+///
+/// [`NodeManager::render_metrics`]: ../nodes/struct.NodeManager.html#method.render_metrics
+#[derive(Clone, Debug, Default)]
+pub struct Exposition {
+    output: String,
+}
+
+impl Exposition {
+    /// Creates a new, empty exposition.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the help and type of a metric, which should precede all of its
+    /// samples.
+    ///
+    /// The `kind` is the metric's type, such as `counter` or `gauge`.
+    pub fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.output, "# HELP {} {}", name, escape(help, false));
+        let _ = writeln!(self.output, "# TYPE {} {}", name, kind);
+    }
+
+    /// Writes a sample of a metric with the given labels, as pairs of names
+    /// and values.
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.output.push_str(name);
+
+        if !labels.is_empty() {
+            self.output.push('{');
+
+            for (idx, &(label, label_value)) in labels.iter().enumerate() {
+                if idx > 0 {
+                    self.output.push(',');
+                }
+
+                let _ = write!(self.output, "{}=\"{}\"", label, escape(label_value, true));
+            }
+
+            self.output.push('}');
+        }
+
+        let _ = writeln!(self.output, " {}", value);
+    }
+
+    /// The rendered exposition.
+    #[inline]
+    pub fn into_string(self) -> String {
+        self.output
+    }
+}
+
+/// The name and labels identifying a metric's value.
+type Key = (&'static str, Vec<(String, String)>);
+
+/// The current values of the metrics emitted, ordered so that each metric's
+/// values are rendered together.
+#[derive(Default)]
+struct Recorded {
+    counters: BTreeMap<Key, u64>,
+    gauges: BTreeMap<Key, f64>,
+    // The number of timings and the sum of their seconds.
+    timings: BTreeMap<Key, (u64, f64)>,
+}
+
+fn key(name: &'static str, labels: &[(&str, &str)]) -> Key {
+    let labels = labels
+        .iter()
+        .map(|&(label, value)| (label.to_owned(), value.to_owned()))
+        .collect();
+
+    (name, labels)
+}
+
+/// Renders the values of one kind of metric, writing the header of each
+/// metric before its first value.
+fn render_family<T, F>(
+    exposition: &mut Exposition,
+    kind: &str,
+    values: &BTreeMap<Key, T>,
+    mut f: F,
+) where F: FnMut(&mut Exposition, &str, &[(&str, &str)], &T) {
+    let mut last = None;
+
+    for (&(name, ref labels), value) in values {
+        if last != Some(name) {
+            exposition.header(name, kind, help(name));
+            last = Some(name);
+        }
+
+        let labels = labels
+            .iter()
+            .map(|&(ref label, ref value)| (label.as_str(), value.as_str()))
+            .collect::<Vec<_>>();
+
+        f(exposition, name, &labels, value);
+    }
+}
+
+/// The help text of one of the library's metrics.
+fn help(name: &str) -> &'static str {
+    match name {
+        EVENTS_DISPATCHED => "Events dispatched to the handler.",
+        MESSAGES_RECEIVED => "Payloads received from the node.",
+        NODE_PENALTY => "The penalty of the node.",
+        RECONNECTS => "Reconnects to the node.",
+        REST_LATENCY => "The latency of REST requests to the node, in seconds.",
+        _ => "",
+    }
+}
+
+/// Escapes the backslashes and newlines of text - and the double quotes of
+/// label values - for the exposition format.
+fn escape(text: &str, quotes: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quotes => escaped.push_str("\\\""),
+            ch => escaped.push(ch),
+        }
+    }
+
+    escaped
+}
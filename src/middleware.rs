@@ -0,0 +1,244 @@
+//! Hooks which see every op sent to and received from nodes, and can change
+//! or drop them, such as to cap the volume of every player or to handle the
+//! ops of experimental server features.
+//!
+//! Outbound middleware is added via
+//! [`NodeManager::add_outbound_middleware`], and is applied to the ops sent
+//! to each of the manager's nodes. Each is called with the op as an
+//! [`OutboundOp`], and returns whether to continue sending it or to drop it.
+//!
+//! Inbound middleware is added via [`NodeManager::add_inbound_middleware`],
+//! and is applied to the payloads received from each of the manager's nodes
+//! before they are dispatched. Each is called with the raw payload, and
+//! returns whether to continue dispatching it or to consume it, so that it
+//! isn't dispatched. Changes made to the payload are dispatched instead of
+//! it, such as to patch around a bug of the node.
+//!
+//! Middleware applies to the nodes that are already connected as well as
+//! those added later, in the order that the middleware was added. Payloads
+//! which aren't JSON, such as pings, aren't passed to middleware.
+//!
+//! # Examples
+//!
+//! Cap the volume of every player at 100:
+//!
+//! ```rust,no_run
+//! # extern crate lavalink_futures;
+//! #
+//! use lavalink_futures::Error;
+//! use lavalink_futures::middleware::MiddlewareAction;
+//! use lavalink_futures::nodes::NodeManager;
+//! use lavalink_futures::player::OutboundOp;
+//!
+//! fn cap_volume(manager: &NodeManager) -> Result<(), Error> {
+//!     manager.add_outbound_middleware(Box::new(|op: &mut OutboundOp| {
+//!         if let OutboundOp::Volume { ref mut volume, .. } = *op {
+//!             *volume = (*volume).min(100);
+//!         }
+//!
+//!         MiddlewareAction::Continue
+//!     }))
+//! }
+//! #
+//! # fn main() {}
+//! ```
+//!
+//! [`NodeManager::add_inbound_middleware`]: ../nodes/struct.NodeManager.html#method.add_inbound_middleware
+//! [`NodeManager::add_outbound_middleware`]: ../nodes/struct.NodeManager.html#method.add_outbound_middleware
+//! [`OutboundOp`]: ../player/enum.OutboundOp.html
+
+use serde_json::{self, Value};
+use std::borrow::Cow;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::{Arc, RwLock};
+use websocket::OwnedMessage;
+use ::player::OutboundOp;
+use ::Error;
+
+/// A middleware called with every payload received from a node.
+///
+/// Refer to the [module-level documentation] for more information.
+///
+/// [module-level documentation]: index.html
+pub type InboundMiddleware = Box<Fn(&mut Value) -> MiddlewareAction + Send + Sync>;
+
+/// A middleware called with every op sent to a node.
+///
+/// Refer to the [module-level documentation] for more information.
+///
+/// [module-level documentation]: index.html
+pub type OutboundMiddleware = Box<Fn(&mut OutboundOp) -> MiddlewareAction + Send + Sync>;
+
+/// What to do with an op or payload once a middleware has been called with
+/// it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MiddlewareAction {
+    /// Continue sending or dispatching the op, including any changes made to
+    /// it, calling the next middleware with it.
+    Continue,
+    /// Drop the op, so that it isn't sent or dispatched. The next middleware
+    /// isn't called.
+    Drop,
+}
+
+/// A cheaply cloneable handle to the middleware of a [`NodeManager`], which
+/// is shared with its nodes.
+///
+/// Clones of it share the same middleware, so middleware added after a node
+/// connects is applied to it too.
+///
+/// [`NodeManager`]: ../nodes/struct.NodeManager.html
+#[derive(Clone, Default)]
+pub struct Middleware {
+    inbound: Arc<RwLock<Vec<InboundMiddleware>>>,
+    outbound: Arc<RwLock<Vec<OutboundMiddleware>>>,
+}
+
+impl Middleware {
+    /// Creates a new handle without any middleware.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an inbound middleware, called after the existing middleware.
+    pub fn add_inbound(&self, middleware: InboundMiddleware) -> Result<(), Error> {
+        self.inbound.write()?.push(middleware);
+
+        Ok(())
+    }
+
+    /// Adds an outbound middleware, called after the existing middleware.
+    pub fn add_outbound(&self, middleware: OutboundMiddleware) -> Result<(), Error> {
+        self.outbound.write()?.push(middleware);
+
+        Ok(())
+    }
+
+    /// Calls each inbound middleware with a payload in turn, returning the
+    /// payload if it should be dispatched or `None` if it was consumed.
+    pub fn apply_inbound(&self, mut payload: Value) -> Option<Value> {
+        let middleware = match self.inbound.read() {
+            Ok(middleware) => middleware,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        for middleware in middleware.iter() {
+            if middleware(&mut payload) == MiddlewareAction::Drop {
+                return None;
+            }
+        }
+
+        Some(payload)
+    }
+
+    /// Like [`apply_inbound`], but for the bytes of a payload received over
+    /// the WebSocket.
+    ///
+    /// Bytes which aren't JSON are returned as-is, so that the error parsing
+    /// them is dispatched, as are all bytes if there isn't any middleware.
+    ///
+    /// [`apply_inbound`]: #method.apply_inbound
+    pub fn apply_inbound_bytes<'a>(&self, bytes: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        if !self.has_inbound() {
+            return Some(Cow::Borrowed(bytes));
+        }
+
+        let payload = match serde_json::from_slice::<Value>(bytes) {
+            Ok(payload) => payload,
+            Err(_) => return Some(Cow::Borrowed(bytes)),
+        };
+
+        match serde_json::to_vec(&self.apply_inbound(payload)?) {
+            Ok(bytes) => Some(Cow::Owned(bytes)),
+            Err(why) => {
+                event!(warn; "Err serializing payload from middleware: {:?}", why);
+
+                None
+            },
+        }
+    }
+
+    /// Calls each outbound middleware with an op in turn, returning the op
+    /// if it should be sent or `None` if it was dropped.
+    pub fn apply_outbound(&self, mut op: OutboundOp) -> Option<OutboundOp> {
+        let middleware = match self.outbound.read() {
+            Ok(middleware) => middleware,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        for middleware in middleware.iter() {
+            if middleware(&mut op) == MiddlewareAction::Drop {
+                return None;
+            }
+        }
+
+        Some(op)
+    }
+
+    /// Like [`apply_outbound`], but for a message sent over the WebSocket.
+    ///
+    /// Messages which aren't JSON are returned as-is, as are all messages if
+    /// there isn't any middleware.
+    ///
+    /// [`apply_outbound`]: #method.apply_outbound
+    pub fn apply_outbound_message(&self, message: OwnedMessage) -> Option<OwnedMessage> {
+        if !self.has_outbound() {
+            return Some(message);
+        }
+
+        let payload = match message {
+            OwnedMessage::Binary(ref data) => serde_json::from_slice::<Value>(data).ok(),
+            OwnedMessage::Text(ref data) => serde_json::from_str::<Value>(data).ok(),
+            _ => None,
+        };
+
+        let op = match payload {
+            Some(payload) => OutboundOp::from_json(payload),
+            None => return Some(message),
+        };
+
+        match self.apply_outbound(op)?.to_message() {
+            Ok(message) => Some(message),
+            Err(why) => {
+                event!(warn; "Err serializing op from middleware: {:?}", why);
+
+                None
+            },
+        }
+    }
+
+    /// Whether there is any inbound middleware.
+    pub fn has_inbound(&self) -> bool {
+        match self.inbound.read() {
+            Ok(middleware) => !middleware.is_empty(),
+            Err(poisoned) => !poisoned.into_inner().is_empty(),
+        }
+    }
+
+    /// Whether there is any outbound middleware.
+    pub fn has_outbound(&self) -> bool {
+        match self.outbound.read() {
+            Ok(middleware) => !middleware.is_empty(),
+            Err(poisoned) => !poisoned.into_inner().is_empty(),
+        }
+    }
+}
+
+impl Debug for Middleware {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let inbound = match self.inbound.read() {
+            Ok(middleware) => middleware.len(),
+            Err(poisoned) => poisoned.into_inner().len(),
+        };
+        let outbound = match self.outbound.read() {
+            Ok(middleware) => middleware.len(),
+            Err(poisoned) => poisoned.into_inner().len(),
+        };
+
+        f.debug_struct("Middleware")
+            .field("inbound", &inbound)
+            .field("outbound", &outbound)
+            .finish()
+    }
+}
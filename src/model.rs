@@ -0,0 +1,443 @@
+//! Models for payloads received from Lavalink nodes.
+
+use serde::de::{Deserialize, Deserializer, Error as DeError, Unexpected, Visitor};
+use serde::ser::{Serialize, Serializer};
+use serde_json::Value;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// Statistics about the CPU of a node, within [`NodeStats`].
+///
+/// [`NodeStats`]: struct.NodeStats.html
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CpuStats {
+    /// The number of CPU cores available to the node.
+    pub cores: i64,
+    /// The load of the Lavalink process, from `0.0` to `1.0`.
+    pub lavalink_load: f64,
+    /// The load of the whole system, from `0.0` to `1.0`.
+    pub system_load: f64,
+}
+
+/// Statistics about the audio frames sent by a node in the last minute,
+/// within [`NodeStats`].
+///
+/// [`NodeStats`]: struct.NodeStats.html
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FrameStats {
+    /// The number of frames that were not sent in time, on average per
+    /// player.
+    pub deficit: i64,
+    /// The number of frames that were nulled, on average per player.
+    pub nulled: i64,
+    /// The number of frames that were sent, on average per player.
+    pub sent: i64,
+}
+
+/// The ID of a guild, distinguishing it from the IDs of channels and users.
+///
+/// Lavalink sends and receives guild IDs as strings, which is how this is
+/// serialized. Functions taking a guild ID accept anything convertible into
+/// this, so a raw `u64` can still be passed while migrating.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct GuildId(pub u64);
+
+impl GuildId {
+    /// Reads a guild ID from a JSON value, accepting either a string or a
+    /// number, as some forks and plugins of Lavalink send guild IDs as
+    /// numbers.
+    ///
+    /// Returns `None` if the value is neither, or is not a valid guild ID.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        Self::deserialize(value).ok()
+    }
+}
+
+impl Display for GuildId {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl From<u64> for GuildId {
+    fn from(guild_id: u64) -> Self {
+        GuildId(guild_id)
+    }
+}
+
+impl<'a> From<&'a u64> for GuildId {
+    fn from(guild_id: &'a u64) -> Self {
+        GuildId(*guild_id)
+    }
+}
+
+impl<'a> From<&'a GuildId> for GuildId {
+    fn from(guild_id: &'a GuildId) -> Self {
+        *guild_id
+    }
+}
+
+impl From<GuildId> for u64 {
+    fn from(guild_id: GuildId) -> Self {
+        guild_id.0
+    }
+}
+
+impl FromStr for GuildId {
+    type Err = ParseIntError;
+
+    fn from_str(guild_id: &str) -> Result<Self, ParseIntError> {
+        guild_id.parse().map(GuildId)
+    }
+}
+
+impl<'de> Deserialize<'de> for GuildId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(GuildIdVisitor)
+    }
+}
+
+/// Visits a guild ID as either a string or a number, as some forks and
+/// plugins of Lavalink send guild IDs as numbers.
+struct GuildIdVisitor;
+
+impl<'de> Visitor<'de> for GuildIdVisitor {
+    type Value = GuildId;
+
+    fn expecting(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str("a guild ID as a string or a number")
+    }
+
+    fn visit_i64<E: DeError>(self, guild_id: i64) -> Result<GuildId, E> {
+        if guild_id < 0 {
+            return Err(E::invalid_value(Unexpected::Signed(guild_id), &self));
+        }
+
+        Ok(GuildId(guild_id as u64))
+    }
+
+    fn visit_u64<E: DeError>(self, guild_id: u64) -> Result<GuildId, E> {
+        Ok(GuildId(guild_id))
+    }
+
+    fn visit_str<E: DeError>(self, guild_id: &str) -> Result<GuildId, E> {
+        guild_id.parse().map_err(|_| E::invalid_value(Unexpected::Str(guild_id), &self))
+    }
+}
+
+impl Serialize for GuildId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Statistics about the memory of a node in bytes, within [`NodeStats`].
+///
+/// [`NodeStats`]: struct.NodeStats.html
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MemoryStats {
+    /// The memory allocated by the JVM.
+    pub allocated: i64,
+    /// The memory that is free within the allocated memory.
+    pub free: i64,
+    /// The maximum memory that the JVM may allocate.
+    pub reservable: i64,
+    /// The memory in use.
+    pub used: i64,
+}
+
+/// Statistics about the load and players of a node, sent periodically via
+/// the `stats` op.
+///
+/// Every field is optional in the payload, and unknown fields are ignored, so
+/// that newer and older versions of Lavalink can be parsed.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NodeStats {
+    /// Statistics about the node's CPU.
+    pub cpu: CpuStats,
+    /// Statistics about the audio frames sent in the last minute, if any
+    /// players are playing.
+    pub frame_stats: Option<FrameStats>,
+    /// Statistics about the node's memory.
+    pub memory: MemoryStats,
+    /// The number of players on the node.
+    pub players: i64,
+    /// The number of players on the node that are playing a track.
+    pub playing_players: i64,
+    /// How long the node has been running for in milliseconds, if it was
+    /// sent.
+    pub uptime: Option<i64>,
+}
+
+/// An update about the state of a guild's player.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerUpdate {
+    /// The ID of the guild that the player is for.
+    pub guild_id: GuildId,
+    /// The state of the player.
+    pub state: PlayerUpdateState,
+}
+
+/// The state of a player within a [`PlayerUpdate`].
+///
+/// [`PlayerUpdate`]: struct.PlayerUpdate.html
+#[derive(Clone, Debug, Deserialize)]
+pub struct PlayerUpdateState {
+    /// The position of the player, in milliseconds.
+    ///
+    /// This is 0 if nothing is playing.
+    #[serde(default)]
+    pub position: i64,
+    /// The time of the node when the update was sent.
+    pub time: i64,
+}
+
+/// The payload sent by Lavalink v3.7 and newer once connected to.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ready {
+    /// Whether the previous session was resumed.
+    #[serde(default)]
+    pub resumed: bool,
+    /// The ID of the session, which is required to control players via the
+    /// node's REST API.
+    pub session_id: String,
+}
+
+/// The severity of a [`TrackException`], describing its likely cause.
+///
+///
+/// Lavalink v4 sends the severity in lowercase, which is accepted as well.
+///
+/// [`TrackException`]: struct.TrackException.html
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Severity {
+    /// The cause is known and expected, such as a track being unavailable in
+    /// the node's region.
+    #[serde(alias = "common")]
+    Common,
+    /// The cause is unknown, or is an issue with Lavalink itself.
+    #[serde(alias = "fault")]
+    Fault,
+    /// The cause might not be known, but is likely outside of Lavalink, such
+    /// as a source responding with an unexpected format.
+    #[serde(alias = "suspicious")]
+    Suspicious,
+    /// A severity that is not known to this library, such as one added by a
+    /// newer version of Lavalink.
+    #[serde(other)]
+    Unknown,
+}
+
+impl Severity {
+    /// Whether playing the track again may succeed.
+    ///
+    /// This is only the case for suspicious exceptions, which are likely
+    /// caused by a temporary issue with the source. Common exceptions will
+    /// occur again, and faults are issues with the node.
+    pub fn may_retry(&self) -> bool {
+        match *self {
+            Severity::Suspicious => true,
+            Severity::Common | Severity::Fault | Severity::Unknown => false,
+        }
+    }
+}
+
+/// A track ended on a guild's player.
+///
+/// The track of this and the other track events is the base64 encoded track.
+/// Lavalink v4 sends the track as an object instead, of which the encoded
+/// track is used.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackEndEvent {
+    /// The ID of the guild that the track ended for.
+    pub guild_id: GuildId,
+    /// The reason that the track ended.
+    pub reason: TrackEndReason,
+    /// The track that ended.
+    #[serde(deserialize_with = "deserialize_track")]
+    pub track: String,
+}
+
+/// The reason that a track ended on a guild's player.
+///
+/// Lavalink v4 sends the reason in camelCase, which is accepted as well.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TrackEndReason {
+    /// The player was cleaned up by the node, such as after being inactive.
+    #[serde(alias = "cleanup")]
+    Cleanup,
+    /// The track finished playing.
+    #[serde(alias = "finished")]
+    Finished,
+    /// The track failed to load or failed to start playing.
+    #[serde(alias = "loadFailed")]
+    LoadFailed,
+    /// The track was replaced by another track being played.
+    #[serde(alias = "replaced")]
+    Replaced,
+    /// The track was stopped.
+    #[serde(alias = "stopped")]
+    Stopped,
+    /// A reason that is not known to this library, such as one added by a
+    /// newer version of Lavalink.
+    #[serde(other)]
+    Unknown,
+}
+
+impl TrackEndReason {
+    /// Whether the next track in a queue may be started after a track ended
+    /// for this reason.
+    ///
+    /// This is only the case if the track finished or failed to load. Tracks
+    /// that were stopped or replaced ended because of a command, and tracks
+    /// that were cleaned up no longer have a player to start on. Unknown
+    /// reasons are assumed not to allow it.
+    pub fn may_start_next(&self) -> bool {
+        match *self {
+            TrackEndReason::Finished | TrackEndReason::LoadFailed => true,
+            TrackEndReason::Cleanup
+            | TrackEndReason::Replaced
+            | TrackEndReason::Stopped
+            | TrackEndReason::Unknown => false,
+        }
+    }
+}
+
+/// An exception that occurred while playing a track, within a
+/// [`TrackExceptionEvent`].
+///
+/// [`TrackExceptionEvent`]: struct.TrackExceptionEvent.html
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackException {
+    /// The message of the exception that caused this exception, if any.
+    #[serde(default)]
+    pub cause: Option<String>,
+    /// The message of the exception, if any.
+    #[serde(default)]
+    pub message: Option<String>,
+    /// The severity of the exception.
+    pub severity: Severity,
+}
+
+/// An exception occurred while playing a track on a guild's player.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackExceptionEvent {
+    /// The error message of the exception.
+    ///
+    /// This is empty for versions of Lavalink that only send the
+    /// [`exception`] object.
+    ///
+    /// [`exception`]: #structfield.exception
+    #[serde(default)]
+    pub error: String,
+    /// The structured exception, for versions of Lavalink that send it.
+    #[serde(default)]
+    pub exception: Option<TrackException>,
+    /// The ID of the guild that the exception occurred for.
+    pub guild_id: GuildId,
+    /// The track that the exception occurred for.
+    #[serde(deserialize_with = "deserialize_track")]
+    pub track: String,
+}
+
+impl TrackExceptionEvent {
+    /// Converts the event into its exception.
+    ///
+    /// For versions of Lavalink that only send an error message, the
+    /// exception has the message with a [`Severity::Fault`], as its cause is
+    /// unknown.
+    ///
+    /// [`Severity::Fault`]: enum.Severity.html#variant.Fault
+    pub fn into_exception(self) -> TrackException {
+        match self.exception {
+            Some(exception) => exception,
+            None => TrackException {
+                cause: None,
+                message: if self.error.is_empty() { None } else { Some(self.error) },
+                severity: Severity::Fault,
+            },
+        }
+    }
+}
+
+/// A track started playing on a guild's player.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackStartEvent {
+    /// The ID of the guild that the track started for.
+    pub guild_id: GuildId,
+    /// The track that started.
+    #[serde(deserialize_with = "deserialize_track")]
+    pub track: String,
+}
+
+/// A track is stuck playing on a guild's player.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackStuckEvent {
+    /// The ID of the guild that the track is stuck for.
+    pub guild_id: GuildId,
+    /// The threshold in milliseconds before the track was detected as being
+    /// stuck.
+    pub threshold_ms: i64,
+    /// The track that is stuck.
+    #[serde(deserialize_with = "deserialize_track")]
+    pub track: String,
+}
+
+/// Discord's voice WebSocket connection for a guild's player was closed.
+///
+/// Discord closes the connection with the code `4006` when the voice session
+/// is no longer valid and `4014` when the bot was disconnected from the
+/// channel, after which the channel may be rejoined.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketClosedEvent {
+    /// Whether the connection was closed by Discord.
+    pub by_remote: bool,
+    /// The close code sent by Discord.
+    pub code: i64,
+    /// The ID of the guild that the connection was closed for.
+    pub guild_id: GuildId,
+    /// The reason that the connection was closed.
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// The type of an event payload, used to determine which event model to
+/// deserialize the payload into.
+#[derive(Clone, Debug, Deserialize)]
+pub struct EventType {
+    /// The type of the event, such as `"TrackEndEvent"`.
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// Deserializes the track of an event, which is either the base64 encoded
+/// track or - for Lavalink v4 - an object containing it.
+fn deserialize_track<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Track {
+        Encoded(String),
+        Object {
+            encoded: String,
+        },
+    }
+
+    match Track::deserialize(deserializer)? {
+        Track::Encoded(track) | Track::Object { encoded: track } => Ok(track),
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+use ::Error;
+
+/// The scheme of a [`NodeAddress`].
+///
+/// [`NodeAddress`]: struct.NodeAddress.html
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Scheme {
+    /// HTTP without TLS.
+    Http,
+    /// HTTP over TLS.
+    Https,
+    /// WebSocket without TLS.
+    Ws,
+    /// WebSocket over TLS.
+    Wss,
+}
+
+impl Scheme {
+    /// The scheme as it appears in a URL, such as `"wss"`.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+            Scheme::Ws => "ws",
+            Scheme::Wss => "wss",
+        }
+    }
+
+    /// Whether the scheme is for HTTP rather than WebSocket.
+    pub fn is_http(&self) -> bool {
+        match *self {
+            Scheme::Http | Scheme::Https => true,
+            Scheme::Ws | Scheme::Wss => false,
+        }
+    }
+
+    /// Whether the scheme uses TLS.
+    pub fn is_secure(&self) -> bool {
+        match *self {
+            Scheme::Https | Scheme::Wss => true,
+            Scheme::Http | Scheme::Ws => false,
+        }
+    }
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = Error;
+
+    fn from_str(scheme: &str) -> Result<Self, Error> {
+        match &scheme.to_ascii_lowercase()[..] {
+            "http" => Ok(Scheme::Http),
+            "https" => Ok(Scheme::Https),
+            "ws" => Ok(Scheme::Ws),
+            "wss" => Ok(Scheme::Wss),
+            _ => Err(Error::InvalidConfig("The address has an unsupported scheme")),
+        }
+    }
+}
+
+/// The parsed address of a node, such as `lavalink.local`,
+/// `10.0.0.2:2333`, `[::1]:2333`, or `wss://lavalink.local/ws`.
+///
+/// The scheme, port, and path are all optional. IPv6 literals may be given
+/// in brackets, optionally followed by a port, or bare without a port.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate lavalink_futures;
+/// #
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// #
+/// use lavalink_futures::nodes::{NodeAddress, Scheme};
+///
+/// let address = NodeAddress::parse("[::1]:2333")?;
+///
+/// assert_eq!(address.host, "::1");
+/// assert_eq!(address.port, Some(2333));
+/// assert_eq!(address.url(Scheme::Ws, None), "ws://[::1]:2333");
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct NodeAddress {
+    /// The host, such as `lavalink.local` or `10.0.0.2`.
+    ///
+    /// IPv6 literals are stored without brackets, such as `::1`.
+    pub host: String,
+    /// The path, including any query, such as `/lavalink?region=eu`. This is
+    /// empty if the address has no path.
+    pub path: String,
+    /// The port, if one was given.
+    pub port: Option<u16>,
+    /// The scheme, if one was given.
+    pub scheme: Option<Scheme>,
+}
+
+impl NodeAddress {
+    /// Parses an address.
+    ///
+    /// Returns [`Error::InvalidConfig`] describing why the address is
+    /// malformed, such as if it has no host, an unsupported scheme, an
+    /// invalid port, or an invalid IPv6 literal.
+    ///
+    /// [`Error::InvalidConfig`]: ../enum.Error.html#variant.InvalidConfig
+    pub fn parse(address: &str) -> Result<Self, Error> {
+        let address = address.trim();
+
+        let (scheme, rest) = match address.find("://") {
+            Some(index) => (Some(address[..index].parse::<Scheme>()?), &address[index + 3..]),
+            None => (None, address),
+        };
+
+        let path_start = rest.find(&['/', '?'][..]).unwrap_or(rest.len());
+        let (authority, path) = rest.split_at(path_start);
+
+        let (host, port) = if authority.starts_with('[') {
+            let end = authority.find(']')
+                .ok_or(Error::InvalidConfig("The address has an unclosed IPv6 bracket"))?;
+
+            let mut after = authority[end + 1..].splitn(2, ':');
+
+            let port = match (after.next(), after.next()) {
+                (Some(""), None) => None,
+                (Some(""), Some(port)) => Some(parse_port(port)?),
+                _ => return Err(Error::InvalidConfig(
+                    "The address has characters after its IPv6 literal",
+                )),
+            };
+
+            (parse_ipv6(&authority[1..end])?, port)
+        } else if authority.matches(':').count() > 1 {
+            // A bare IPv6 literal can't be followed by a port, as the port
+            // would be ambiguous with the last segment.
+            (parse_ipv6(authority)?, None)
+        } else {
+            match authority.find(':') {
+                Some(index) => (&authority[..index], Some(parse_port(&authority[index + 1..])?)),
+                None => (authority, None),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(Error::InvalidConfig("The address has no host"));
+        }
+
+        Ok(Self {
+            host: host.to_owned(),
+            path: path.to_owned(),
+            port,
+            scheme,
+        })
+    }
+
+    /// The host and port of the address, with IPv6 literals in brackets,
+    /// such as `[::1]:2333`.
+    ///
+    /// The default port is used if the address has none. If neither is
+    /// present, then this is only the host.
+    pub fn authority(&self, default_port: Option<u16>) -> String {
+        let host = if self.is_ipv6() {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+
+        match self.port.or(default_port) {
+            Some(port) => format!("{}:{}", host, port),
+            None => host,
+        }
+    }
+
+    /// Whether the host is an IPv6 literal.
+    #[inline]
+    pub fn is_ipv6(&self) -> bool {
+        self.host.contains(':')
+    }
+
+    /// Formats the address as a URL with the scheme, ignoring the address's
+    /// own scheme.
+    ///
+    /// The default port is used if the address has none.
+    pub fn url(&self, scheme: Scheme, default_port: Option<u16>) -> String {
+        format!("{}://{}{}", scheme, self.authority(default_port), self.path)
+    }
+}
+
+impl Display for NodeAddress {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        if let Some(scheme) = self.scheme {
+            write!(f, "{}://", scheme)?;
+        }
+
+        write!(f, "{}{}", self.authority(None), self.path)
+    }
+}
+
+impl FromStr for NodeAddress {
+    type Err = Error;
+
+    fn from_str(address: &str) -> Result<Self, Error> {
+        Self::parse(address)
+    }
+}
+
+fn parse_ipv6(host: &str) -> Result<&str, Error> {
+    host.parse::<Ipv6Addr>()
+        .map(|_| host)
+        .map_err(|_| Error::InvalidConfig("The address has an invalid IPv6 literal"))
+}
+
+fn parse_port(port: &str) -> Result<u16, Error> {
+    port.parse()
+        .map_err(|_| Error::InvalidConfig("The address has an invalid port"))
+}
@@ -0,0 +1,843 @@
+use futures::{Future, Stream, future, stream};
+use percent_encoding::utf8_percent_encode;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use super::{
+    ChannelBuffer,
+    DEFAULT_POSITION_SYNC_THRESHOLD,
+    DEFAULT_STATS_HISTORY,
+    EventFilter,
+    HeartbeatConfig,
+    NodeAddress,
+    NodeConfig,
+    NodeManager,
+    PenaltyConfig,
+    ProtocolVersion,
+    ReplayConfig,
+    Scheme,
+    WebSocketHook,
+};
+use tokio_core::reactor::Handle;
+use ::audit::AuditLog;
+use ::executor::Executor;
+use ::metrics::{MetricsSink, NoopSink};
+use ::rest::{IDENTIFIER_ENCODE_SET, RestConfig};
+use ::{Error, EventHandler};
+
+/// A builder for creating a [`NodeConfig`] with sensible defaults.
+///
+/// By default, the node is expected to be at `127.0.0.1` on port `2333` for
+/// both HTTP and WebSocket connections without TLS, and the bot is expected
+/// to be using a single shard.
+///
+/// # Examples
+///
+/// Build the configuration for a node at `10.0.0.2` using TLS:
+///
+/// ```rust,no_run
+/// # extern crate lavalink_futures;
+/// #
+/// # use std::error::Error;
+/// #
+/// # fn try_main() -> Result<(), Box<Error>> {
+/// #
+/// use lavalink_futures::nodes::NodeConfigBuilder;
+///
+/// let config = NodeConfigBuilder::new()
+///     .address("10.0.0.2")
+///     .secure(true)
+///     .password("youshallnotpass")
+///     .user_id("1234567890")
+///     .num_shards(4)
+///     .build()?;
+///
+/// assert_eq!(config.http_host, "https://10.0.0.2:2333");
+/// assert_eq!(config.websocket_host, "wss://10.0.0.2:2333");
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {
+/// #     try_main().unwrap();
+/// # }
+/// ```
+///
+/// [`NodeConfig`]: struct.NodeConfig.html
+#[derive(Clone, Debug)]
+pub struct NodeConfigBuilder {
+    address: String,
+    audit: Option<AuditLog>,
+    channel_buffer: ChannelBuffer,
+    event_filter: EventFilter,
+    extra_headers: Vec<(String, String)>,
+    heartbeat: Option<HeartbeatConfig>,
+    http_host: Option<String>,
+    http_port: u16,
+    lenient_guild_ids: bool,
+    max_handler_futures: Option<usize>,
+    num_shards: u64,
+    password: Option<String>,
+    penalty: PenaltyConfig,
+    position_sync_threshold: Option<i64>,
+    protocol_version: ProtocolVersion,
+    query: Vec<(String, String)>,
+    ready_timeout: Option<Duration>,
+    replay: Option<ReplayConfig>,
+    resume_key: Option<String>,
+    rest: RestConfig,
+    resume_timeout: Option<u64>,
+    secure: bool,
+    stats_history: usize,
+    tags: HashMap<String, String>,
+    tls_root_certificates: Vec<Vec<u8>>,
+    user_id: Option<String>,
+    websocket_hook: Option<WebSocketHook>,
+    websocket_host: Option<String>,
+    websocket_port: u16,
+}
+
+impl NodeConfigBuilder {
+    /// Creates a new builder with the default settings.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the address of the node, such as `127.0.0.1`, `lavalink.local`,
+    /// or `[::1]`.
+    ///
+    /// This is used to derive the HTTP and WebSocket hosts if they are not
+    /// explicitly set. The address may include a port, which is used instead
+    /// of the [`http_port`] and [`websocket_port`], a path, which is appended
+    /// to both hosts, and a scheme, which overrides whether the node is
+    /// [`secure`]. Refer to [`NodeAddress`] for the accepted formats.
+    ///
+    /// [`NodeAddress`]: struct.NodeAddress.html
+    /// [`http_port`]: #method.http_port
+    /// [`secure`]: #method.secure
+    /// [`websocket_port`]: #method.websocket_port
+    pub fn address<S: Into<String>>(mut self, address: S) -> Self {
+        self.address = address.into();
+
+        self
+    }
+
+    /// Sets the log to write every payload sent to and received from the
+    /// node to.
+    ///
+    /// Refer to the [`audit`] module for more information.
+    ///
+    /// [`audit`]: ../audit/index.html
+    pub fn audit(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+
+        self
+    }
+
+    /// Sets the size of the buffers of the channels to and from the node.
+    ///
+    /// Defaults to [`ChannelBuffer::default`].
+    ///
+    /// [`ChannelBuffer::default`]: enum.ChannelBuffer.html#impl-Default
+    pub fn channel_buffer(mut self, channel_buffer: ChannelBuffer) -> Self {
+        self.channel_buffer = channel_buffer;
+
+        self
+    }
+
+    /// Sets the kinds of payloads from the node that are handled. Defaults to
+    /// [`EventFilter::all`].
+    ///
+    /// Refer to [`NodeConfig::event_filter`] for more information.
+    ///
+    /// [`EventFilter::all`]: struct.EventFilter.html#method.all
+    /// [`NodeConfig::event_filter`]: struct.NodeConfig.html#method.event_filter
+    pub fn event_filter(mut self, event_filter: EventFilter) -> Self {
+        self.event_filter = event_filter;
+
+        self
+    }
+
+    /// Adds a header to send when connecting to the node.
+    ///
+    /// Refer to [`NodeConfig::extra_headers`] for more information.
+    ///
+    /// [`NodeConfig::extra_headers`]: struct.NodeConfig.html#method.extra_headers
+    pub fn extra_header<K, V>(mut self, name: K, value: V) -> Self
+        where K: Into<String>, V: Into<String> {
+        self.extra_headers.push((name.into(), value.into()));
+
+        self
+    }
+
+    /// Sets the configuration of pings used to detect a stale connection, or
+    /// `None` to not send any.
+    ///
+    /// Defaults to [`HeartbeatConfig::default`].
+    ///
+    /// [`HeartbeatConfig::default`]: struct.HeartbeatConfig.html#impl-Default
+    pub fn heartbeat(mut self, heartbeat: Option<HeartbeatConfig>) -> Self {
+        self.heartbeat = heartbeat;
+
+        self
+    }
+
+    /// Sets the full HTTP host of the node, such as `http://127.0.0.1:2333`.
+    ///
+    /// This overrides the host derived from the [`address`] and
+    /// [`http_port`]. A host without a scheme, such as `127.0.0.1:2333`, uses
+    /// the `http` scheme.
+    ///
+    /// [`address`]: #method.address
+    /// [`http_port`]: #method.http_port
+    pub fn http_host<S: Into<String>>(mut self, http_host: S) -> Self {
+        self.http_host = Some(http_host.into());
+
+        self
+    }
+
+    /// Sets the port of the node's HTTP server. Defaults to `2333`.
+    pub fn http_port(mut self, http_port: u16) -> Self {
+        self.http_port = http_port;
+
+        self
+    }
+
+    /// Sets whether to accept the `guildId` of payloads from the node as a
+    /// JSON number as well as a string. Defaults to `true`.
+    ///
+    /// Refer to [`NodeConfig::lenient_guild_ids`] for more information.
+    ///
+    /// [`NodeConfig::lenient_guild_ids`]: struct.NodeConfig.html#method.lenient_guild_ids
+    pub fn lenient_guild_ids(mut self, lenient_guild_ids: bool) -> Self {
+        self.lenient_guild_ids = lenient_guild_ids;
+
+        self
+    }
+
+    /// Sets the maximum number of handler futures for the node's events that
+    /// may run at once, or `None` for no limit. Defaults to `None`.
+    ///
+    /// Refer to [`NodeConfig::max_handler_futures`] for more information.
+    ///
+    /// [`NodeConfig::max_handler_futures`]: struct.NodeConfig.html#method.max_handler_futures
+    pub fn max_handler_futures(mut self, max_handler_futures: Option<usize>) -> Self {
+        self.max_handler_futures = max_handler_futures;
+
+        self
+    }
+
+    /// Sets the number of shards that the bot is using. Defaults to `1`.
+    pub fn num_shards(mut self, num_shards: u64) -> Self {
+        self.num_shards = num_shards;
+
+        self
+    }
+
+    /// Sets the password of the node. This is required.
+    pub fn password<S: Into<String>>(mut self, password: S) -> Self {
+        self.password = Some(password.into());
+
+        self
+    }
+
+    /// Sets the weights used for calculating the penalty of the node.
+    ///
+    /// Defaults to [`PenaltyConfig::default`].
+    ///
+    /// [`PenaltyConfig::default`]: struct.PenaltyConfig.html#impl-Default
+    pub fn penalty(mut self, penalty: PenaltyConfig) -> Self {
+        self.penalty = penalty;
+
+        self
+    }
+
+    /// Sets how far in milliseconds a player's position may diverge after
+    /// the node is reconnected to before it is corrected, or `None` to never
+    /// correct it. Defaults to [`DEFAULT_POSITION_SYNC_THRESHOLD`].
+    ///
+    /// Refer to [`NodeConfig::position_sync_threshold`] for more information.
+    ///
+    /// [`DEFAULT_POSITION_SYNC_THRESHOLD`]: constant.DEFAULT_POSITION_SYNC_THRESHOLD.html
+    /// [`NodeConfig::position_sync_threshold`]: struct.NodeConfig.html#method.position_sync_threshold
+    pub fn position_sync_threshold(mut self, position_sync_threshold: Option<i64>) -> Self {
+        self.position_sync_threshold = position_sync_threshold;
+
+        self
+    }
+
+    /// Sets the version of Lavalink's protocol that the node speaks. Defaults
+    /// to [`ProtocolVersion::V3`].
+    ///
+    /// If the WebSocket host is derived from the [`address`], the
+    /// `/v4/websocket` path is appended to it for [`ProtocolVersion::V4`].
+    ///
+    /// Refer to [`NodeConfig::protocol_version`] for more information.
+    ///
+    /// [`NodeConfig::protocol_version`]: struct.NodeConfig.html#method.protocol_version
+    /// [`ProtocolVersion::V3`]: enum.ProtocolVersion.html#variant.V3
+    /// [`ProtocolVersion::V4`]: enum.ProtocolVersion.html#variant.V4
+    /// [`address`]: #method.address
+    pub fn protocol_version(mut self, protocol_version: ProtocolVersion) -> Self {
+        self.protocol_version = protocol_version;
+
+        self
+    }
+
+    /// Adds a query parameter to the WebSocket host, which is percent-encoded
+    /// when building.
+    ///
+    /// This applies to both the derived host and one set via
+    /// [`websocket_host`].
+    ///
+    /// [`websocket_host`]: #method.websocket_host
+    pub fn query_param<K, V>(mut self, key: K, value: V) -> Self
+        where K: Into<String>, V: Into<String> {
+        self.query.push((key.into(), value.into()));
+
+        self
+    }
+
+    /// Sets how long to wait for the node to send its first stats after
+    /// connecting, or `None` to not wait. Defaults to 10 seconds.
+    ///
+    /// Refer to [`NodeConfig::ready_timeout`] for more information.
+    ///
+    /// [`NodeConfig::ready_timeout`]: struct.NodeConfig.html#method.ready_timeout
+    pub fn ready_timeout(mut self, ready_timeout: Option<Duration>) -> Self {
+        self.ready_timeout = ready_timeout;
+
+        self
+    }
+
+    /// Sets the configuration of buffering messages sent while the node is
+    /// disconnected, or `None` to drop them.
+    ///
+    /// Defaults to [`ReplayConfig::default`].
+    ///
+    /// [`ReplayConfig::default`]: struct.ReplayConfig.html#impl-Default
+    pub fn replay(mut self, replay: Option<ReplayConfig>) -> Self {
+        self.replay = replay;
+
+        self
+    }
+
+    /// Sets the configuration of the pooled HTTP client used for REST
+    /// requests.
+    ///
+    /// Defaults to [`RestConfig::default`].
+    ///
+    /// [`RestConfig::default`]: ../rest/struct.RestConfig.html#impl-Default
+    pub fn rest(mut self, rest: RestConfig) -> Self {
+        self.rest = rest;
+
+        self
+    }
+
+    /// Sets the key to configure session resuming with.
+    ///
+    /// Refer to [`NodeConfig::resume_key`] for more information.
+    ///
+    /// [`NodeConfig::resume_key`]: struct.NodeConfig.html#method.resume_key
+    pub fn resume_key<S: Into<String>>(mut self, resume_key: S) -> Self {
+        self.resume_key = Some(resume_key.into());
+
+        self
+    }
+
+    /// Sets the number of seconds that the node should wait for a resume.
+    ///
+    /// Refer to [`NodeConfig::resume_timeout`] for more information.
+    ///
+    /// [`NodeConfig::resume_timeout`]: struct.NodeConfig.html#method.resume_timeout
+    pub fn resume_timeout(mut self, resume_timeout: u64) -> Self {
+        self.resume_timeout = Some(resume_timeout);
+
+        self
+    }
+
+    /// Sets whether to connect to the node over TLS when deriving the hosts
+    /// from the [`address`]. Defaults to `false`.
+    ///
+    /// [`address`]: #method.address
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+
+        self
+    }
+
+    /// Sets the number of stats samples to keep in the node's history.
+    /// Defaults to [`DEFAULT_STATS_HISTORY`].
+    ///
+    /// Refer to [`NodeConfig::stats_history`] for more information.
+    ///
+    /// [`DEFAULT_STATS_HISTORY`]: constant.DEFAULT_STATS_HISTORY.html
+    /// [`NodeConfig::stats_history`]: struct.NodeConfig.html#method.stats_history
+    pub fn stats_history(mut self, stats_history: usize) -> Self {
+        self.stats_history = stats_history;
+
+        self
+    }
+
+    /// Adds a tag describing the node, replacing any tag with the same key.
+    ///
+    /// Refer to [`NodeConfig::tags`] for more information.
+    ///
+    /// [`NodeConfig::tags`]: struct.NodeConfig.html#method.tags
+    pub fn tag<K, V>(mut self, key: K, value: V) -> Self
+        where K: Into<String>, V: Into<String> {
+        self.tags.insert(key.into(), value.into());
+
+        self
+    }
+
+    /// Adds a DER-encoded root certificate to trust when connecting over TLS.
+    ///
+    /// Refer to [`NodeConfig::tls_root_certificates`] for more information.
+    ///
+    /// [`NodeConfig::tls_root_certificates`]: struct.NodeConfig.html#method.tls_root_certificates
+    pub fn tls_root_certificate(mut self, der: Vec<u8>) -> Self {
+        self.tls_root_certificates.push(der);
+
+        self
+    }
+
+    /// Sets the ID of the bot user. This is required.
+    pub fn user_id<S: Into<String>>(mut self, user_id: S) -> Self {
+        self.user_id = Some(user_id.into());
+
+        self
+    }
+
+    /// Sets a hook for configuring the WebSocket client before connecting to
+    /// the node.
+    ///
+    /// Refer to [`WebSocketHook`] for more information.
+    ///
+    /// [`WebSocketHook`]: struct.WebSocketHook.html
+    pub fn websocket_hook(mut self, websocket_hook: WebSocketHook) -> Self {
+        self.websocket_hook = Some(websocket_hook);
+
+        self
+    }
+
+    /// Sets the full WebSocket host of the node, such as
+    /// `ws://127.0.0.1:2333`.
+    ///
+    /// This overrides the host derived from the [`address`] and
+    /// [`websocket_port`]. A host without a scheme, such as `127.0.0.1:2333`,
+    /// uses the `ws` scheme.
+    ///
+    /// [`address`]: #method.address
+    /// [`websocket_port`]: #method.websocket_port
+    pub fn websocket_host<S: Into<String>>(mut self, websocket_host: S) -> Self {
+        self.websocket_host = Some(websocket_host.into());
+
+        self
+    }
+
+    /// Sets the port of the node's WebSocket server. Defaults to `2333`.
+    pub fn websocket_port(mut self, websocket_port: u16) -> Self {
+        self.websocket_port = websocket_port;
+
+        self
+    }
+
+    /// Builds the configuration.
+    ///
+    /// Returns [`Error::InvalidConfig`] if the password or user ID were not
+    /// set, if the number of shards is 0, if the REST rate limit is invalid,
+    /// or if the address or a host is malformed or does not have a valid
+    /// scheme.
+    ///
+    /// [`Error::InvalidConfig`]: ../enum.Error.html#variant.InvalidConfig
+    pub fn build(self) -> Result<NodeConfig, Error> {
+        let password = self.password
+            .ok_or(Error::InvalidConfig("A password is required"))?;
+        let user_id = self.user_id
+            .ok_or(Error::InvalidConfig("A user ID is required"))?;
+
+        if self.num_shards == 0 {
+            return Err(Error::InvalidConfig("The number of shards must be at least 1"));
+        }
+
+        if let Some(ref rate_limit) = self.rest.rate_limit {
+            rate_limit.validate()?;
+        }
+
+        let address = NodeAddress::parse(&self.address)?;
+        let secure = address.scheme.map_or(self.secure, |scheme| scheme.is_secure());
+
+        let (http_scheme, websocket_scheme) = if secure {
+            (Scheme::Https, Scheme::Wss)
+        } else {
+            (Scheme::Http, Scheme::Ws)
+        };
+
+        let http_host = match self.http_host {
+            Some(host) => {
+                let host = NodeAddress::parse(&host)?;
+
+                match host.scheme {
+                    Some(scheme) if !scheme.is_http() => {
+                        return Err(Error::InvalidConfig(
+                            "The HTTP host must use the http or https scheme",
+                        ));
+                    },
+                    scheme => host.url(scheme.unwrap_or(Scheme::Http), None),
+                }
+            },
+            None => address.url(http_scheme, Some(self.http_port)),
+        };
+        let mut websocket_host = match self.websocket_host {
+            Some(host) => {
+                let host = NodeAddress::parse(&host)?;
+
+                match host.scheme {
+                    Some(scheme) if scheme.is_http() => {
+                        return Err(Error::InvalidConfig(
+                            "The WebSocket host must use the ws or wss scheme",
+                        ));
+                    },
+                    scheme => host.url(scheme.unwrap_or(Scheme::Ws), None),
+                }
+            },
+            None => {
+                let host = address.url(websocket_scheme, Some(self.websocket_port));
+
+                match self.protocol_version {
+                    ProtocolVersion::V3 => host,
+                    ProtocolVersion::V4 => {
+                        format!("{}/v4/websocket", host.trim_end_matches('/'))
+                    },
+                }
+            },
+        };
+
+        for (key, value) in self.query {
+            let separator = if websocket_host.contains('?') { '&' } else { '?' };
+
+            websocket_host.push(separator);
+            websocket_host.extend(utf8_percent_encode(&key, IDENTIFIER_ENCODE_SET));
+            websocket_host.push('=');
+            websocket_host.extend(utf8_percent_encode(&value, IDENTIFIER_ENCODE_SET));
+        }
+
+        Ok(NodeConfig {
+            audit: self.audit,
+            channel_buffer: self.channel_buffer,
+            event_filter: self.event_filter,
+            extra_headers: self.extra_headers,
+            heartbeat: self.heartbeat,
+            lenient_guild_ids: self.lenient_guild_ids,
+            max_handler_futures: self.max_handler_futures,
+            num_shards: self.num_shards,
+            penalty: self.penalty,
+            position_sync_threshold: self.position_sync_threshold,
+            protocol_version: self.protocol_version,
+            ready_timeout: self.ready_timeout,
+            replay: self.replay,
+            rest: self.rest,
+            resume_key: self.resume_key,
+            resume_timeout: self.resume_timeout,
+            stats_history: self.stats_history,
+            tags: self.tags,
+            tls_root_certificates: self.tls_root_certificates,
+            websocket_hook: self.websocket_hook,
+            http_host,
+            password,
+            user_id,
+            websocket_host,
+        })
+    }
+}
+
+impl Default for NodeConfigBuilder {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1".to_owned(),
+            audit: None,
+            channel_buffer: ChannelBuffer::default(),
+            event_filter: EventFilter::all(),
+            extra_headers: Vec::new(),
+            heartbeat: Some(HeartbeatConfig::default()),
+            http_host: None,
+            http_port: 2333,
+            lenient_guild_ids: true,
+            max_handler_futures: None,
+            num_shards: 1,
+            password: None,
+            penalty: PenaltyConfig::default(),
+            position_sync_threshold: Some(DEFAULT_POSITION_SYNC_THRESHOLD),
+            protocol_version: ProtocolVersion::V3,
+            query: Vec::new(),
+            ready_timeout: Some(Duration::from_secs(10)),
+            replay: Some(ReplayConfig::default()),
+            resume_key: None,
+            rest: RestConfig::default(),
+            resume_timeout: None,
+            secure: false,
+            stats_history: DEFAULT_STATS_HISTORY,
+            tags: HashMap::new(),
+            tls_root_certificates: Vec::new(),
+            user_id: None,
+            websocket_hook: None,
+            websocket_host: None,
+            websocket_port: 2333,
+        }
+    }
+}
+
+/// A builder for creating a [`NodeManager`] connected to a list of nodes
+/// sharing the same configuration.
+///
+/// Every node is configured from a [`NodeConfigBuilder`] holding the shared
+/// defaults, such as the password, user ID, and number of shards, the
+/// penalty weights used for balancing players across the nodes, and how
+/// connections are kept alive and resumed. Each node may then override the
+/// defaults.
+///
+/// # Examples
+///
+/// Connect to three nodes, one of which uses TLS:
+///
+/// ```rust,no_run
+/// # extern crate lavalink_futures;
+/// # extern crate tokio_core;
+/// #
+/// # use lavalink_futures::EventHandler;
+/// # use std::error::Error;
+/// # use std::sync::Mutex;
+/// # use tokio_core::reactor::Core;
+/// #
+/// # fn try_main(handler: Box<EventHandler>) -> Result<(), Box<Error>> {
+/// #
+/// use lavalink_futures::nodes::NodeManagerBuilder;
+///
+/// let mut core = Core::new()?;
+///
+/// let done = NodeManagerBuilder::new()
+///     .password("youshallnotpass")
+///     .user_id("1234567890")
+///     .num_shards(4)
+///     .nodes(vec!["10.0.0.2", "10.0.0.3"])
+///     .node_with(|node| node.address("lavalink.example.com").secure(true))
+///     .build(core.handle(), Mutex::new(handler));
+///
+/// let manager = core.run(done)?;
+///
+/// assert_eq!(manager.nodes.len(), 3);
+/// #     Ok(())
+/// # }
+/// #
+/// # fn main() {}
+/// ```
+///
+/// [`NodeConfigBuilder`]: struct.NodeConfigBuilder.html
+/// [`NodeManager`]: struct.NodeManager.html
+pub struct NodeManagerBuilder {
+    defaults: NodeConfigBuilder,
+    executor: Option<Box<Executor>>,
+    metrics: Option<Box<MetricsSink>>,
+    nodes: Vec<Box<Fn(NodeConfigBuilder) -> NodeConfigBuilder + Send>>,
+}
+
+impl NodeManagerBuilder {
+    /// Creates a new builder with the default settings and no nodes.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the configuration that every node is built from, replacing any
+    /// shared settings set so far.
+    ///
+    /// Defaults to [`NodeConfigBuilder::default`].
+    ///
+    /// [`NodeConfigBuilder::default`]: struct.NodeConfigBuilder.html#impl-Default
+    pub fn defaults(mut self, defaults: NodeConfigBuilder) -> Self {
+        self.defaults = defaults;
+
+        self
+    }
+
+    /// Sets the executor calling the handler for events from nodes.
+    ///
+    /// Refer to [`NodeManager::set_executor`] for more information.
+    ///
+    /// [`NodeManager::set_executor`]: struct.NodeManager.html#method.set_executor
+    pub fn executor(mut self, executor: Box<Executor>) -> Self {
+        self.executor = Some(executor);
+
+        self
+    }
+
+    /// Sets the configuration of pings used to detect a stale connection for
+    /// every node.
+    ///
+    /// Refer to [`NodeConfigBuilder::heartbeat`] for more information.
+    ///
+    /// [`NodeConfigBuilder::heartbeat`]: struct.NodeConfigBuilder.html#method.heartbeat
+    pub fn heartbeat(mut self, heartbeat: Option<HeartbeatConfig>) -> Self {
+        self.defaults = self.defaults.heartbeat(heartbeat);
+
+        self
+    }
+
+    /// Sets the sink that metrics about the nodes are emitted into.
+    ///
+    /// Refer to [`NodeManager::with_metrics`] for more information.
+    ///
+    /// [`NodeManager::with_metrics`]: struct.NodeManager.html#method.with_metrics
+    pub fn metrics(mut self, sink: Box<MetricsSink>) -> Self {
+        self.metrics = Some(sink);
+
+        self
+    }
+
+    /// Adds a node at the given address, configured with the shared
+    /// settings.
+    pub fn node<S: Into<String>>(self, address: S) -> Self {
+        let address = address.into();
+
+        self.node_with(move |node| node.address(address.clone()))
+    }
+
+    /// Adds a node configured by a function given a builder with the shared
+    /// settings, which may be overridden.
+    ///
+    /// The function is called when the manager is built, so the node
+    /// receives shared settings set after it was added.
+    pub fn node_with<F>(mut self, f: F) -> Self
+        where F: Fn(NodeConfigBuilder) -> NodeConfigBuilder + Send + 'static {
+        self.nodes.push(Box::new(f));
+
+        self
+    }
+
+    /// Adds a node at each of the given addresses.
+    ///
+    /// Refer to [`node`] for more information.
+    ///
+    /// [`node`]: #method.node
+    pub fn nodes<I, S>(mut self, addresses: I) -> Self
+        where I: IntoIterator<Item = S>, S: Into<String> {
+        for address in addresses {
+            self = self.node(address);
+        }
+
+        self
+    }
+
+    /// Sets the number of shards that the bot is using for every node.
+    /// Defaults to `1`.
+    pub fn num_shards(mut self, num_shards: u64) -> Self {
+        self.defaults = self.defaults.num_shards(num_shards);
+
+        self
+    }
+
+    /// Sets the password of every node.
+    pub fn password<S: Into<String>>(mut self, password: S) -> Self {
+        self.defaults = self.defaults.password(password);
+
+        self
+    }
+
+    /// Sets the weights used for calculating the penalty of every node when
+    /// balancing players across them.
+    ///
+    /// Refer to [`NodeConfigBuilder::penalty`] for more information.
+    ///
+    /// [`NodeConfigBuilder::penalty`]: struct.NodeConfigBuilder.html#method.penalty
+    pub fn penalty(mut self, penalty: PenaltyConfig) -> Self {
+        self.defaults = self.defaults.penalty(penalty);
+
+        self
+    }
+
+    /// Sets the configuration of buffering messages sent while a node is
+    /// disconnected for every node.
+    ///
+    /// Refer to [`NodeConfigBuilder::replay`] for more information.
+    ///
+    /// [`NodeConfigBuilder::replay`]: struct.NodeConfigBuilder.html#method.replay
+    pub fn replay(mut self, replay: Option<ReplayConfig>) -> Self {
+        self.defaults = self.defaults.replay(replay);
+
+        self
+    }
+
+    /// Sets the number of seconds that every node should wait for a resume.
+    ///
+    /// Refer to [`NodeConfig::resume_timeout`] for more information.
+    ///
+    /// [`NodeConfig::resume_timeout`]: struct.NodeConfig.html#method.resume_timeout
+    pub fn resume_timeout(mut self, resume_timeout: u64) -> Self {
+        self.defaults = self.defaults.resume_timeout(resume_timeout);
+
+        self
+    }
+
+    /// Sets the ID of the bot user for every node.
+    pub fn user_id<S: Into<String>>(mut self, user_id: S) -> Self {
+        self.defaults = self.defaults.user_id(user_id);
+
+        self
+    }
+
+    /// Builds the configuration of every node, in the order that they were
+    /// added.
+    ///
+    /// Returns [`Error::InvalidConfig`] if any configuration is invalid.
+    /// Refer to [`NodeConfigBuilder::build`] for more information.
+    ///
+    /// [`Error::InvalidConfig`]: ../enum.Error.html#variant.InvalidConfig
+    /// [`NodeConfigBuilder::build`]: struct.NodeConfigBuilder.html#method.build
+    pub fn build_configs(&self) -> Result<Vec<NodeConfig>, Error> {
+        self.nodes
+            .iter()
+            .map(|f| f(self.defaults.clone()).build())
+            .collect()
+    }
+
+    /// Builds the manager, resolving once every node has been connected to.
+    ///
+    /// Resolves to an error if any configuration is invalid, in which case no
+    /// node is connected to, or if there was a problem connecting to a node.
+    pub fn build(self, handle: Handle, handler: Mutex<Box<EventHandler>>)
+        -> Box<Future<Item = NodeManager, Error = Error> + Send> {
+        let configs = match self.build_configs() {
+            Ok(configs) => configs,
+            Err(why) => return Box::new(future::err(why)),
+        };
+
+        let sink = self.metrics.unwrap_or_else(|| Box::new(NoopSink));
+        let mut manager = NodeManager::with_metrics(handle, handler, sink);
+
+        if let Some(executor) = self.executor {
+            manager.set_executor(executor);
+        }
+
+        let done = stream::iter_ok(configs).fold(manager, |manager, config| {
+            manager.add_node(config).then(|res| match res {
+                Ok((manager, Ok(()))) => Ok(manager),
+                Ok((_, Err(why))) => Err(why),
+                Err(()) => unreachable!("adding a node doesn't fail"),
+            })
+        });
+
+        Box::new(done)
+    }
+}
+
+impl Default for NodeManagerBuilder {
+    fn default() -> Self {
+        Self {
+            defaults: NodeConfigBuilder::default(),
+            executor: None,
+            metrics: None,
+            nodes: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::ops::{BitAnd, BitOr, BitOrAssign, Not, Sub};
+
+/// A set of the kinds of payloads from a node that are handled, as
+/// configured via [`NodeConfig::event_filter`].
+///
+/// Payloads of kinds not in the filter are skipped before being deserialized
+/// into models, so they neither update the library's state - such as a
+/// player's position or a node's stats - nor are passed to the
+/// [`EventHandler`] or the node's event streams. This reduces the work done
+/// for bots with many players which only care about some events.
+///
+/// Filters are combined with `|`, and kinds are removed with `-`.
+///
+/// **Note**: Skipping [`TRACK_END`] keeps queued tracks from being played,
+/// and skipping [`STATS`] keeps the node's penalty from being known, so it
+/// is never the [best node].
+///
+/// # Examples
+///
+/// Only handle the events needed for queueing:
+///
+/// ```rust
+/// use lavalink_futures::nodes::EventFilter;
+///
+/// let filter = EventFilter::STATS | EventFilter::TRACK_END | EventFilter::TRACK_START;
+///
+/// assert!(filter.contains(EventFilter::TRACK_END));
+/// assert!(!filter.contains(EventFilter::PLAYER_UPDATE));
+/// ```
+///
+/// [`EventHandler`]: ../trait.EventHandler.html
+/// [`NodeConfig::event_filter`]: struct.NodeConfig.html#method.event_filter
+/// [`STATS`]: #associatedconstant.STATS
+/// [`TRACK_END`]: #associatedconstant.TRACK_END
+/// [best node]: struct.NodeManager.html#method.best_node
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct EventFilter(u32);
+
+impl EventFilter {
+    /// Events of a player reaching a line of its track's lyrics, from the
+    /// LavaLyrics plugin.
+    ///
+    /// These are only known with the `lyrics` feature, and are otherwise
+    /// [`UNKNOWN`].
+    ///
+    /// [`UNKNOWN`]: #associatedconstant.UNKNOWN
+    pub const LYRICS_LINE: EventFilter = EventFilter(1 << 8);
+    /// Player updates, with the position of a player.
+    pub const PLAYER_UPDATE: EventFilter = EventFilter(1);
+    /// Events of a segment of a player's track being skipped, from the
+    /// SponsorBlock plugin.
+    ///
+    /// These are only known with the `sponsorblock` feature, and are
+    /// otherwise [`UNKNOWN`].
+    ///
+    /// [`UNKNOWN`]: #associatedconstant.UNKNOWN
+    pub const SEGMENT_SKIPPED: EventFilter = EventFilter(1 << 9);
+    /// Stats about the node's load.
+    pub const STATS: EventFilter = EventFilter(1 << 1);
+    /// Events of a track ending.
+    pub const TRACK_END: EventFilter = EventFilter(1 << 2);
+    /// Events of an exception while playing a track.
+    pub const TRACK_EXCEPTION: EventFilter = EventFilter(1 << 3);
+    /// Events of a track starting.
+    pub const TRACK_START: EventFilter = EventFilter(1 << 4);
+    /// Events of a track being stuck.
+    pub const TRACK_STUCK: EventFilter = EventFilter(1 << 5);
+    /// Ops and events that the library doesn't handle, such as those of
+    /// plugins, passed to [`EventHandler::unknown_op`].
+    ///
+    /// [`EventHandler::unknown_op`]: ../trait.EventHandler.html#method.unknown_op
+    pub const UNKNOWN: EventFilter = EventFilter(1 << 6);
+    /// Events of Discord's voice WebSocket connection for a player closing.
+    pub const WEBSOCKET_CLOSED: EventFilter = EventFilter(1 << 7);
+
+    /// A filter of every kind of payload.
+    pub fn all() -> Self {
+        EventFilter((1 << 10) - 1)
+    }
+
+    /// A filter of no kinds of payloads.
+    pub fn empty() -> Self {
+        EventFilter(0)
+    }
+
+    /// The kind of an event payload with the given `type`, such as
+    /// [`TRACK_END`] for `"TrackEndEvent"`.
+    ///
+    /// Events of types that the library doesn't handle are [`UNKNOWN`].
+    ///
+    /// [`TRACK_END`]: #associatedconstant.TRACK_END
+    /// [`UNKNOWN`]: #associatedconstant.UNKNOWN
+    pub fn from_event_type(kind: &str) -> Self {
+        match kind {
+            #[cfg(feature = "lyrics")]
+            "LyricsLineEvent" => EventFilter::LYRICS_LINE,
+            #[cfg(feature = "sponsorblock")]
+            "SegmentSkipped" => EventFilter::SEGMENT_SKIPPED,
+            "TrackEndEvent" => EventFilter::TRACK_END,
+            "TrackExceptionEvent" => EventFilter::TRACK_EXCEPTION,
+            "TrackStartEvent" => EventFilter::TRACK_START,
+            "TrackStuckEvent" => EventFilter::TRACK_STUCK,
+            "WebSocketClosedEvent" => EventFilter::WEBSOCKET_CLOSED,
+            _ => EventFilter::UNKNOWN,
+        }
+    }
+
+    /// The raw bits of the filter.
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Whether the filter contains every kind of payload of another.
+    pub fn contains(&self, other: EventFilter) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Adds the kinds of payloads of another filter.
+    pub fn insert(&mut self, other: EventFilter) {
+        self.0 |= other.0;
+    }
+
+    /// Whether the filter contains no kinds of payloads.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Removes the kinds of payloads of another filter.
+    pub fn remove(&mut self, other: EventFilter) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitAnd for EventFilter {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        EventFilter(self.0 & other.0)
+    }
+}
+
+impl BitOr for EventFilter {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        EventFilter(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for EventFilter {
+    fn bitor_assign(&mut self, other: Self) {
+        self.insert(other);
+    }
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl Not for EventFilter {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        EventFilter(!self.0 & Self::all().0)
+    }
+}
+
+impl Sub for EventFilter {
+    type Output = Self;
+
+    fn sub(mut self, other: Self) -> Self {
+        self.remove(other);
+
+        self
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::vec_deque::Iter;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use ::model::NodeStats;
+
+/// The default number of stats samples kept for each node.
+///
+/// Lavalink sends stats every minute, so this is an hour of samples.
+pub const DEFAULT_STATS_HISTORY: usize = 60;
+
+/// Stats received from a node, as kept in its [`StatsHistory`].
+///
+/// [`StatsHistory`]: struct.StatsHistory.html
+#[derive(Clone, Debug)]
+pub struct StatsSample {
+    /// When the stats were received.
+    pub received_at: Instant,
+    /// The stats.
+    pub stats: NodeStats,
+}
+
+/// The minimum, maximum, and average of a statistic over the samples in a
+/// window of a [`StatsHistory`].
+///
+/// [`StatsHistory`]: struct.StatsHistory.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatsSummary {
+    /// The average of the statistic.
+    pub avg: f64,
+    /// The maximum of the statistic.
+    pub max: f64,
+    /// The minimum of the statistic.
+    pub min: f64,
+    /// The number of samples that the statistic was taken from.
+    pub samples: usize,
+}
+
+impl StatsSummary {
+    fn from_values<I: IntoIterator<Item = f64>>(values: I) -> Option<Self> {
+        let mut summary: Option<Self> = None;
+        let mut sum = 0.0;
+
+        for value in values {
+            sum += value;
+
+            summary = Some(match summary {
+                Some(summary) => Self {
+                    avg: 0.0,
+                    max: summary.max.max(value),
+                    min: summary.min.min(value),
+                    samples: summary.samples + 1,
+                },
+                None => Self {
+                    avg: 0.0,
+                    max: value,
+                    min: value,
+                    samples: 1,
+                },
+            });
+        }
+
+        summary.map(|summary| Self {
+            avg: sum / summary.samples as f64,
+            ..summary
+        })
+    }
+}
+
+/// A ring buffer of the last stats received from a node, stored in its
+/// [`State::history`].
+///
+/// Summaries of the samples over a window, such as the average CPU load in
+/// the last 10 minutes, can be used for balancing decisions and dashboards.
+/// Up to the [`NodeConfig::stats_history`] of samples are kept.
+///
+/// [`NodeConfig::stats_history`]: struct.NodeConfig.html#method.stats_history
+/// [`State::history`]: struct.State.html#structfield.history
+#[derive(Clone, Debug, Default)]
+pub struct StatsHistory {
+    capacity: usize,
+    samples: VecDeque<StatsSample>,
+}
+
+impl StatsHistory {
+    /// Creates a new, empty history keeping up to `capacity` samples.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// The maximum number of samples kept.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// A summary of the load of the Lavalink process, from `0.0` to `1.0`,
+    /// over the samples received within the window, or all samples if `None`.
+    ///
+    /// Returns `None` if there are no samples within the window.
+    pub fn cpu_lavalink_load(&self, window: Option<Duration>) -> Option<StatsSummary> {
+        StatsSummary::from_values(self.within(window).map(|stats| stats.cpu.lavalink_load))
+    }
+
+    /// A summary of the load of the node's system, from `0.0` to `1.0`, over
+    /// the samples received within the window, or all samples if `None`.
+    ///
+    /// Returns `None` if there are no samples within the window.
+    pub fn cpu_system_load(&self, window: Option<Duration>) -> Option<StatsSummary> {
+        StatsSummary::from_values(self.within(window).map(|stats| stats.cpu.system_load))
+    }
+
+    /// A summary of the number of frames not sent in time per player, over
+    /// the samples received within the window, or all samples if `None`.
+    ///
+    /// Samples without frame stats, sent when no players are playing, are
+    /// skipped. Returns `None` if there are no such samples within the
+    /// window.
+    pub fn frame_deficit(&self, window: Option<Duration>) -> Option<StatsSummary> {
+        StatsSummary::from_values(self.within(window).filter_map(|stats| {
+            stats.frame_stats.as_ref().map(|frames| frames.deficit as f64)
+        }))
+    }
+
+    /// Whether there are no samples.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// An iterator over the samples, from oldest to newest.
+    #[inline]
+    pub fn iter(&self) -> Iter<StatsSample> {
+        self.samples.iter()
+    }
+
+    /// The most recent sample, if any.
+    #[inline]
+    pub fn latest(&self) -> Option<&StatsSample> {
+        self.samples.back()
+    }
+
+    /// The number of samples.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Adds a sample of stats received now, evicting the oldest sample if the
+    /// capacity is reached.
+    pub fn push(&mut self, stats: NodeStats) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        while self.samples.len() >= self.capacity {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(StatsSample {
+            received_at: Instant::now(),
+            stats,
+        });
+    }
+
+    fn within<'a>(&'a self, window: Option<Duration>)
+        -> Box<Iterator<Item = &'a NodeStats> + 'a> {
+        let now = Instant::now();
+
+        Box::new(self.samples.iter().filter(move |sample| match window {
+            Some(window) => now.duration_since(sample.received_at) <= window,
+            None => true,
+        }).map(|sample| &sample.stats))
+    }
+}
@@ -1,26 +1,74 @@
 //! Structures for connecting to and interacting with Lavalink nodes.
 
+mod address;
+mod builder;
+mod filter;
+mod history;
 mod node;
 mod node_manager;
+mod replay;
+mod scheduler;
 
+pub use self::address::{NodeAddress, Scheme};
+pub use self::builder::{NodeConfigBuilder, NodeManagerBuilder};
+pub use self::filter::EventFilter;
+pub use self::history::{DEFAULT_STATS_HISTORY, StatsHistory, StatsSample, StatsSummary};
 pub use self::node::Node;
 pub use self::node_manager::NodeManager;
 
-use lavalink::stats::RemoteStats;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::usize;
+use websocket::{ClientBuilder, CloseData as WebSocketCloseData};
+use ::audit::AuditLog;
+use ::model::{
+    EventType,
+    NodeStats,
+    PlayerUpdate,
+    TrackEndEvent,
+    TrackExceptionEvent,
+    TrackStartEvent,
+    TrackStuckEvent,
+    WebSocketClosedEvent,
+};
+#[cfg(feature = "lyrics")]
+use ::plugins::lyrics::LyricsLineEvent;
+#[cfg(feature = "sponsorblock")]
+use ::plugins::sponsorblock::SegmentSkippedEvent;
+use ::rest::RestConfig;
+
+/// The default [`NodeConfig::position_sync_threshold`], in milliseconds.
+///
+/// [`NodeConfig::position_sync_threshold`]: struct.NodeConfig.html#method.position_sync_threshold
+pub const DEFAULT_POSITION_SYNC_THRESHOLD: i64 = 2000;
 
 /// Configuration identifying the bot user when connecting to a Lavalink node
 /// via [`Node::connect`].
 ///
+/// Configurations are created via a [`NodeConfigBuilder`], which has sensible
+/// defaults. Only the fields identifying the node and bot user are public,
+/// while other settings are read via the methods of the same names, so that
+/// settings can be added without breaking existing code.
+///
 /// [`Node::connect`]: struct.Node.html#method.connect
+/// [`NodeConfigBuilder`]: struct.NodeConfigBuilder.html
 #[derive(Clone, Debug)]
 pub struct NodeConfig {
     /// The HTTP server being connected to.
     ///
-    /// For example, this may be `http://127.0.0.1:14002`.
+    /// For example, this may be `http://127.0.0.1:14002`. Use the `https`
+    /// scheme to connect over TLS.
     pub http_host: String,
     /// The WebSocket host being connected to.
     ///
-    /// For example, this may be `ws://127.0.0.1:14001`.
+    /// For example, this may be `ws://127.0.0.1:14001`. Use the `wss` scheme
+    /// to connect over TLS. Query parameters, such as
+    /// `ws://127.0.0.1:14001/?token=abc`, are sent when connecting.
     pub websocket_host: String,
     /// The ID of the bot user.
     pub user_id: String,
@@ -29,11 +77,874 @@ pub struct NodeConfig {
     /// The number of shards that the Discord client user (bot) is currently
     /// using. This must be the same number of shards as the bot in total.
     pub num_shards: u64,
+    pub(crate) resume_key: Option<String>,
+    pub(crate) resume_timeout: Option<u64>,
+    pub(crate) tls_root_certificates: Vec<Vec<u8>>,
+    pub(crate) penalty: PenaltyConfig,
+    pub(crate) heartbeat: Option<HeartbeatConfig>,
+    pub(crate) rest: RestConfig,
+    pub(crate) channel_buffer: ChannelBuffer,
+    pub(crate) extra_headers: Vec<(String, String)>,
+    pub(crate) ready_timeout: Option<Duration>,
+    pub(crate) tags: HashMap<String, String>,
+    pub(crate) replay: Option<ReplayConfig>,
+    pub(crate) stats_history: usize,
+    pub(crate) websocket_hook: Option<WebSocketHook>,
+    pub(crate) max_handler_futures: Option<usize>,
+    pub(crate) position_sync_threshold: Option<i64>,
+    pub(crate) audit: Option<AuditLog>,
+    pub(crate) event_filter: EventFilter,
+    pub(crate) protocol_version: ProtocolVersion,
+    pub(crate) lenient_guild_ids: bool,
+}
+
+impl NodeConfig {
+    /// The log to write every payload sent to and received from the node to,
+    /// if any.
+    ///
+    /// Refer to the [`audit`] module for more information.
+    ///
+    /// [`audit`]: ../audit/index.html
+    #[inline]
+    pub fn audit(&self) -> Option<&AuditLog> {
+        self.audit.as_ref()
+    }
+
+    /// The size of the buffers of the channels to and from the node, such as
+    /// [`Node::user_to_node`].
+    ///
+    /// [`Node::user_to_node`]: struct.Node.html#structfield.user_to_node
+    #[inline]
+    pub fn channel_buffer(&self) -> ChannelBuffer {
+        self.channel_buffer
+    }
+
+    /// The kinds of payloads from the node that are handled.
+    ///
+    /// Payloads of other kinds are skipped, and neither update the library's
+    /// state nor are passed to the [`EventHandler`]. Refer to
+    /// [`EventFilter`] for more information.
+    ///
+    /// [`EventFilter`]: struct.EventFilter.html
+    /// [`EventHandler`]: ../trait.EventHandler.html
+    #[inline]
+    pub fn event_filter(&self) -> EventFilter {
+        self.event_filter
+    }
+
+    /// Additional headers to send when connecting to the node, as pairs of
+    /// names and values.
+    ///
+    /// This is useful for nodes behind authenticating proxies. These can't
+    /// replace the `Authorization`, `Num-Shards`, `User-Id`, or `Resume-Key`
+    /// headers sent by the library.
+    ///
+    /// Query parameters can be included in the [`websocket_host`] itself.
+    ///
+    /// [`websocket_host`]: #structfield.websocket_host
+    #[inline]
+    pub fn extra_headers(&self) -> &[(String, String)] {
+        &self.extra_headers
+    }
+
+    /// The configuration of pings used to detect when the connection to the
+    /// node has gone stale.
+    ///
+    /// No pings are sent if this is `None`.
+    #[inline]
+    pub fn heartbeat(&self) -> Option<&HeartbeatConfig> {
+        self.heartbeat.as_ref()
+    }
+
+    /// Whether to accept the `guildId` of payloads from the node as a JSON
+    /// number as well as a string, as some forks and plugins of Lavalink send
+    /// it as a number.
+    ///
+    /// Payloads with a numeric `guildId` are otherwise passed to
+    /// [`EventHandler::parse_error`].
+    ///
+    /// This only applies to payloads received from the node. Guild IDs are
+    /// always sent to the node as strings, which every version of Lavalink
+    /// accepts.
+    ///
+    /// [`EventHandler::parse_error`]: ../trait.EventHandler.html#method.parse_error
+    #[inline]
+    pub fn lenient_guild_ids(&self) -> bool {
+        self.lenient_guild_ids
+    }
+
+    /// Whether the node has every one of the given tags, as pairs of keys and
+    /// values.
+    pub fn matches_tags(&self, tags: &[(&str, &str)]) -> bool {
+        tags.iter().all(|&(key, value)| {
+            self.tags.get(key).map_or(false, |tag| tag == value)
+        })
+    }
+
+    /// The maximum number of futures returned by the [`EventHandler`] for
+    /// the node's events that may run at once, if any.
+    ///
+    /// Further calls of the handler are queued until one of the futures
+    /// completes, and are made in the order that the events were received.
+    /// This keeps a slow handler from accumulating thousands of in-flight
+    /// futures during a burst of events. There is no limit if this is
+    /// `None`.
+    ///
+    /// [`EventHandler`]: ../trait.EventHandler.html
+    #[inline]
+    pub fn max_handler_futures(&self) -> Option<usize> {
+        self.max_handler_futures
+    }
+
+    /// The weighting used to calculate the node's penalty when balancing
+    /// players across nodes.
+    #[inline]
+    pub fn penalty(&self) -> &PenaltyConfig {
+        &self.penalty
+    }
+
+    /// How far in milliseconds the position reported by a player's first
+    /// update after the node is reconnected to may be from the expected
+    /// position before the player is seeked to the expected position.
+    ///
+    /// Positions are never reconciled if this is `None`. Refer to
+    /// [`AudioPlayer::reconcile_position`] for more information.
+    ///
+    /// [`AudioPlayer::reconcile_position`]: ../player/struct.AudioPlayer.html#method.reconcile_position
+    #[inline]
+    pub fn position_sync_threshold(&self) -> Option<i64> {
+        self.position_sync_threshold
+    }
+
+    /// The version of Lavalink's protocol that the node speaks.
+    ///
+    /// Refer to [`ProtocolVersion`] for more information.
+    ///
+    /// [`ProtocolVersion`]: enum.ProtocolVersion.html
+    #[inline]
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
+    /// How long to wait after connecting for the node to send its first
+    /// stats, verifying that the connection was accepted.
+    ///
+    /// Connecting resolves to [`Error::AuthenticationFailed`] if the node
+    /// closes the connection due to a wrong password, or [`Error::Timeout`]
+    /// if no stats are received in time. Connecting resolves as soon as the
+    /// handshake finishes if this is `None`.
+    ///
+    /// [`Error::AuthenticationFailed`]: ../enum.Error.html#variant.AuthenticationFailed
+    /// [`Error::Timeout`]: ../enum.Error.html#variant.Timeout
+    #[inline]
+    pub fn ready_timeout(&self) -> Option<Duration> {
+        self.ready_timeout
+    }
+
+    /// The configuration of buffering messages sent to the node while it is
+    /// disconnected.
+    ///
+    /// Messages sent while disconnected are dropped if this is `None`.
+    #[inline]
+    pub fn replay(&self) -> Option<&ReplayConfig> {
+        self.replay.as_ref()
+    }
+
+    /// The configuration of the pooled HTTP client used for the node's REST
+    /// requests.
+    #[inline]
+    pub fn rest(&self) -> &RestConfig {
+        &self.rest
+    }
+
+    /// The key to configure session resuming with.
+    ///
+    /// When present, the key is sent to the node via a `configureResuming`
+    /// payload upon connecting and is sent as the `Resume-Key` header when
+    /// reconnecting, allowing the node to reattach the existing session so
+    /// that playing tracks aren't interrupted.
+    #[inline]
+    pub fn resume_key(&self) -> Option<&str> {
+        self.resume_key.as_ref().map(String::as_str)
+    }
+
+    /// The number of seconds that the node should keep a session alive for
+    /// after a disconnect, waiting for a resume.
+    ///
+    /// Only used if [`resume_key`] is present. Defaults to 60 seconds if not
+    /// provided.
+    ///
+    /// [`resume_key`]: #method.resume_key
+    #[inline]
+    pub fn resume_timeout(&self) -> Option<u64> {
+        self.resume_timeout
+    }
+
+    /// The number of stats samples to keep in the node's
+    /// [`State::history`].
+    ///
+    /// No samples are kept if this is 0.
+    ///
+    /// [`State::history`]: struct.State.html#structfield.history
+    #[inline]
+    pub fn stats_history(&self) -> usize {
+        self.stats_history
+    }
+
+    /// Tags describing the node, such as a `region` of `us-east` or a `tier`
+    /// of `premium`.
+    ///
+    /// Players can be placed on nodes with certain tags via
+    /// [`NodeManager::create_player_with_tags`].
+    ///
+    /// [`NodeManager::create_player_with_tags`]: struct.NodeManager.html#method.create_player_with_tags
+    #[inline]
+    pub fn tags(&self) -> &HashMap<String, String> {
+        &self.tags
+    }
+
+    /// Additional DER-encoded root certificates to trust when connecting to
+    /// the node over TLS.
+    ///
+    /// This is useful for nodes behind TLS terminators using self-signed
+    /// certificates. The system's root certificates are always trusted.
+    #[inline]
+    pub fn tls_root_certificates(&self) -> &[Vec<u8>] {
+        &self.tls_root_certificates
+    }
+
+    /// A hook for configuring the WebSocket client before connecting to the
+    /// node, if any.
+    ///
+    /// Refer to [`WebSocketHook`] for more information.
+    ///
+    /// [`WebSocketHook`]: struct.WebSocketHook.html
+    #[inline]
+    pub fn websocket_hook(&self) -> Option<&WebSocketHook> {
+        self.websocket_hook.as_ref()
+    }
+}
+
+/// The size of the buffer of a channel to or from a node.
+///
+/// A larger buffer allows more messages, such as player commands, to be sent
+/// in bursts before the channel applies backpressure.
+///
+/// Messages sent to a node are taken from the channel round-robin between
+/// guilds, so the commands of one guild can't starve those of others. Up to
+/// the buffer size of messages are held for scheduling at once.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChannelBuffer {
+    /// The channel buffers up to the given number of messages, in addition to
+    /// one message per sender.
+    Bounded(usize),
+    /// The channel never applies backpressure.
+    Unbounded,
+}
+
+impl ChannelBuffer {
+    /// The buffer size to create the channel with.
+    ///
+    /// The channels are always bounded, so an unbounded buffer is represented
+    /// by a size that won't be reached.
+    pub fn size(&self) -> usize {
+        match *self {
+            ChannelBuffer::Bounded(size) => size,
+            // The channel reserves the upper bits of its capacity.
+            ChannelBuffer::Unbounded => usize::MAX >> 3,
+        }
+    }
+}
+
+impl Default for ChannelBuffer {
+    fn default() -> Self {
+        ChannelBuffer::Bounded(16)
+    }
+}
+
+/// Configuration for periodically pinging a node to detect when its
+/// connection has gone stale.
+///
+/// A connection is stale if nothing, including pongs and stats, has been
+/// received from the node within the [`timeout`]. When this happens, the node
+/// is marked as [`stale`] and [`EventHandler::ws_stale`] is called.
+///
+/// [`EventHandler::ws_stale`]: ../trait.EventHandler.html#method.ws_stale
+/// [`stale`]: struct.State.html#structfield.stale
+/// [`timeout`]: #structfield.timeout
+#[derive(Clone, Debug)]
+pub struct HeartbeatConfig {
+    /// How often to ping the node. Defaults to 30 seconds.
+    pub interval: Duration,
+    /// Whether to reconnect to the node once the connection is stale.
+    /// Defaults to `false`.
+    ///
+    /// The connection is reconnected with the same configuration, so the
+    /// session is resumed if a [`resume_key`] is present. Failed reconnects
+    /// are retried with an exponential backoff, unless the error is [fatal].
+    ///
+    /// [`resume_key`]: struct.NodeConfig.html#method.resume_key
+    /// [fatal]: ../enum.Error.html#method.is_fatal
+    pub reconnect: bool,
+    /// How long to wait for anything to be received from the node before the
+    /// connection is considered stale. Defaults to 90 seconds.
+    pub timeout: Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            reconnect: false,
+            timeout: Duration::from_secs(90),
+        }
+    }
+}
+
+/// Weights used for calculating the penalty of a node, which is used to
+/// determine the best node to create a player on.
+///
+/// Each weight is multiplied with its respective part of the penalty, so a
+/// weight of `0.0` ignores that part entirely. The defaults match Lavalink's
+/// own load balancing.
+#[derive(Clone, Debug)]
+pub struct PenaltyConfig {
+    /// The weight of the system CPU load. Defaults to `1.0`.
+    pub cpu_weight: f64,
+    /// The weight of the frames that were sent late. Defaults to `1.0`.
+    pub deficit_frame_weight: f64,
+    /// The weight of the frames that were nulled. Defaults to `2.0`.
+    pub null_frame_weight: f64,
+    /// The weight of the number of playing players. Defaults to `1.0`.
+    pub playing_players_weight: f64,
+}
+
+impl PenaltyConfig {
+    /// Calculates the penalty of a node with the given stats.
+    pub fn calculate(&self, stats: &NodeStats) -> i32 {
+        let cpu = 1.05f64.powf(100f64 * stats.cpu.system_load) * 10f64 - 10f64;
+
+        let (deficit_frame, null_frame) = match stats.frame_stats.as_ref() {
+            Some(frame_stats) => {
+                (
+                    1.03f64.powf(500f64 * (frame_stats.deficit as f64 / 3000f64)) * 300f64 - 300f64,
+                    1.03f64.powf(500f64 * (frame_stats.nulled as f64 / 3000f64)) * 300f64 - 300f64,
+                )
+            },
+            None => (0f64, 0f64),
+        };
+
+        let players = stats.playing_players as f64 * self.playing_players_weight;
+
+        (players
+            + cpu * self.cpu_weight
+            + deficit_frame * self.deficit_frame_weight
+            + null_frame * self.null_frame_weight) as i32
+    }
+}
+
+impl Default for PenaltyConfig {
+    fn default() -> Self {
+        Self {
+            cpu_weight: 1.0,
+            deficit_frame_weight: 1.0,
+            null_frame_weight: 2.0,
+            playing_players_weight: 1.0,
+        }
+    }
+}
+
+/// What to do with a message sent to a disconnected node when its
+/// [`ReplayConfig::capacity`] is reached.
+///
+/// [`ReplayConfig::capacity`]: struct.ReplayConfig.html#structfield.capacity
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Drop the new message, keeping the messages already buffered.
+    DropNewest,
+    /// Drop the oldest buffered message to make room for the new message.
+    DropOldest,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        OverflowPolicy::DropOldest
+    }
+}
+
+/// Configuration for buffering the messages sent to a node while it is
+/// disconnected, such as player commands.
+///
+/// The buffered messages are sent once the node's session is resumed, so a
+/// short outage doesn't lose commands. If the node isn't configured with a
+/// [`resume_key`], the buffered messages are instead dropped, as the state of
+/// each player is replayed upon reconnecting.
+///
+/// Pings and closes are never buffered.
+///
+/// [`resume_key`]: struct.NodeConfig.html#method.resume_key
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReplayConfig {
+    /// The maximum number of messages to buffer. Defaults to 256.
+    pub capacity: usize,
+    /// What to do with a message sent once the capacity is reached. Defaults
+    /// to [`OverflowPolicy::DropOldest`].
+    ///
+    /// [`OverflowPolicy::DropOldest`]: enum.OverflowPolicy.html#variant.DropOldest
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// The version of Lavalink's protocol that a node speaks, which decides how
+/// the commands of its players are sent.
+///
+/// Lavalink v4 removed the WebSocket ops controlling players in favour of its
+/// REST API. The [`websocket_host`] of a v4 node must include the
+/// `/v4/websocket` path.
+///
+/// [`websocket_host`]: struct.NodeConfig.html#structfield.websocket_host
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ProtocolVersion {
+    /// Lavalink v3, with players controlled via WebSocket ops.
+    V3,
+    /// Lavalink v4, with players controlled via the
+    /// `/v4/sessions/{sessionId}/players/{guildId}` REST endpoint.
+    ///
+    /// Nodes configured as v3 are switched to this if they report a version
+    /// of Lavalink v4 when connected to. The configured host is connected to
+    /// without waiting on the report, so if its handshake is rejected - and
+    /// it has no path of its own - its `/v4/websocket` path is tried, and the
+    /// node is switched to this if that is accepted.
+    ///
+    /// Commands are sent once the node has sent its session ID in its `ready`
+    /// op, and are queued in order until then. Refer to [`RestCommand`] for
+    /// the commands that are translated. Other ops are still sent over the
+    /// WebSocket.
+    ///
+    /// [`RestCommand`]: ../player/enum.RestCommand.html
+    V4,
+}
+
+impl ProtocolVersion {
+    /// The version of the protocol spoken by a version of Lavalink, such as
+    /// `3.7.11` or `4.0.0`, as reported via its `/version` REST endpoint.
+    ///
+    /// Returns `None` if the version can not be parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use lavalink_futures::nodes::ProtocolVersion;
+    ///
+    /// assert_eq!(ProtocolVersion::from_server_version("4.0.0"), Some(ProtocolVersion::V4));
+    /// assert_eq!(ProtocolVersion::from_server_version("3.7.11"), Some(ProtocolVersion::V3));
+    /// assert_eq!(ProtocolVersion::from_server_version("Not Found"), None);
+    /// ```
+    pub fn from_server_version(version: &str) -> Option<Self> {
+        let major = version.trim().split('.').next()?.parse::<u64>().ok()?;
+
+        Some(if major >= 4 {
+            ProtocolVersion::V4
+        } else {
+            ProtocolVersion::V3
+        })
+    }
+}
+
+impl Default for ProtocolVersion {
+    fn default() -> Self {
+        ProtocolVersion::V3
+    }
+}
+
+/// A hook for configuring the WebSocket client used to connect to a node,
+/// such as to add protocols or extensions, without forking the crate.
+///
+/// The hook is given the client builder after the library has set its
+/// headers, and is called each time the node is connected to. Replacing the
+/// `Authorization`, `Num-Shards`, `User-Id`, or `Resume-Key` headers may
+/// prevent connecting.
+///
+/// # Examples
+///
+/// Request a subprotocol when connecting:
+///
+/// ```rust
+/// use lavalink_futures::nodes::WebSocketHook;
+///
+/// let hook = WebSocketHook::new(|builder| builder.add_protocol("lavalink"));
+/// ```
+#[derive(Clone)]
+pub struct WebSocketHook(Arc<Fn(ClientBuilder<'static>) -> ClientBuilder<'static> + Send + Sync>);
+
+impl WebSocketHook {
+    /// Creates a new hook from a function configuring the client builder.
+    pub fn new<F>(f: F) -> Self
+        where F: Fn(ClientBuilder<'static>) -> ClientBuilder<'static> + Send + Sync + 'static {
+        WebSocketHook(Arc::new(f))
+    }
+
+    /// Calls the hook with a client builder.
+    pub fn apply(&self, builder: ClientBuilder<'static>) -> ClientBuilder<'static> {
+        (self.0)(builder)
+    }
+}
+
+impl Debug for WebSocketHook {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("WebSocketHook").finish()
+    }
+}
+
+/// Statistics merged from the [`NodeStats`] of every node of a
+/// [`NodeManager`].
+///
+/// Refer to [`NodeManager::stats_aggregate`].
+///
+/// [`NodeManager`]: struct.NodeManager.html
+/// [`NodeManager::stats_aggregate`]: struct.NodeManager.html#method.stats_aggregate
+/// [`NodeStats`]: ../model/struct.NodeStats.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClusterStats {
+    /// The total number of CPU cores of the nodes.
+    pub cores: i64,
+    /// The total number of frames that were sent late in the last minute.
+    pub frames_deficit: i64,
+    /// The total number of frames that were nulled in the last minute.
+    pub frames_nulled: i64,
+    /// The total number of frames that were sent in the last minute.
+    pub frames_sent: i64,
+    /// The average Lavalink CPU load of the nodes, from `0.0` to `1.0`.
+    pub lavalink_load: f64,
+    /// The total memory allocated by the nodes, in bytes.
+    pub memory_allocated: i64,
+    /// The total free memory of the nodes, in bytes.
+    pub memory_free: i64,
+    /// The total reservable memory of the nodes, in bytes.
+    pub memory_reservable: i64,
+    /// The total memory used by the nodes, in bytes.
+    pub memory_used: i64,
+    /// The number of nodes that the stats were merged from.
+    pub nodes: usize,
+    /// The total number of players on the nodes.
+    pub players: i64,
+    /// The total number of players that are playing on the nodes.
+    pub playing_players: i64,
+    /// The average system CPU load of the nodes, from `0.0` to `1.0`.
+    pub system_load: f64,
+}
+
+impl ClusterStats {
+    /// Merges the stats of each node.
+    pub fn from_stats<'a, I>(stats: I) -> Self
+        where I: IntoIterator<Item = &'a NodeStats> {
+        let mut cluster = Self::default();
+
+        for stats in stats {
+            cluster.cores += stats.cpu.cores;
+            cluster.lavalink_load += stats.cpu.lavalink_load;
+            cluster.memory_allocated += stats.memory.allocated;
+            cluster.memory_free += stats.memory.free;
+            cluster.memory_reservable += stats.memory.reservable;
+            cluster.memory_used += stats.memory.used;
+            cluster.nodes += 1;
+            cluster.players += stats.players;
+            cluster.playing_players += stats.playing_players;
+            cluster.system_load += stats.cpu.system_load;
+
+            if let Some(ref frame_stats) = stats.frame_stats {
+                cluster.frames_deficit += frame_stats.deficit;
+                cluster.frames_nulled += frame_stats.nulled;
+                cluster.frames_sent += frame_stats.sent;
+            }
+        }
+
+        if cluster.nodes > 0 {
+            cluster.lavalink_load /= cluster.nodes as f64;
+            cluster.system_load /= cluster.nodes as f64;
+        }
+
+        cluster
+    }
+
+    /// The ratio of frames that were nulled or sent late, out of all of the
+    /// frames that should have been sent in the last minute.
+    ///
+    /// This is `0.0` if no frames should have been sent.
+    pub fn frame_loss(&self) -> f64 {
+        let lost = self.frames_deficit + self.frames_nulled;
+        let expected = self.frames_sent + lost;
+
+        if expected == 0 {
+            return 0.0;
+        }
+
+        lost as f64 / expected as f64
+    }
+}
+
+/// The status of the connection to a node.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum NodeStatus {
+    /// The connection was closed and will not be reconnected.
+    Closed,
+    /// The node is connected to.
+    Connected,
+    /// The node is being connected to for the first time.
+    Connecting,
+    /// The node is connected to, but nothing was received from it within its
+    /// [`HeartbeatConfig::timeout`].
+    ///
+    /// [`HeartbeatConfig::timeout`]: struct.HeartbeatConfig.html#structfield.timeout
+    Degraded,
+    /// The connection went stale and the node is being reconnected to.
+    Reconnecting,
+}
+
+impl NodeStatus {
+    /// Whether the node is connected to and not degraded, and so can be used
+    /// for new players.
+    pub fn is_healthy(&self) -> bool {
+        *self == NodeStatus::Connected
+    }
+}
+
+impl Default for NodeStatus {
+    fn default() -> Self {
+        NodeStatus::Connecting
+    }
+}
+
+/// Information about how the connection to a node was closed.
+///
+/// This is stored as the [`State::last_close`] and passed to
+/// [`EventHandler::ws_close`].
+///
+/// [`EventHandler::ws_close`]: ../trait.EventHandler.html#method.ws_close
+/// [`State::last_close`]: struct.State.html#structfield.last_close
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CloseData {
+    /// The status code of the close frame.
+    ///
+    /// This is `None` if the connection ended without a close frame, such as
+    /// when it was dropped or went stale, or if the frame had no code.
+    pub code: Option<u16>,
+    /// The reason of the close frame, which may be empty.
+    pub reason: String,
+}
+
+impl CloseData {
+    /// Creates close information for a connection that ended without a close
+    /// frame.
+    pub fn abnormal<S: Into<String>>(reason: S) -> Self {
+        Self {
+            code: None,
+            reason: reason.into(),
+        }
+    }
+
+    /// Whether the connection was closed normally, with a code of `1000`
+    /// (normal closure) or `1001` (going away).
+    pub fn is_normal(&self) -> bool {
+        match self.code {
+            Some(1000) | Some(1001) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<'a> From<Option<&'a WebSocketCloseData>> for CloseData {
+    fn from(data: Option<&'a WebSocketCloseData>) -> Self {
+        match data {
+            Some(data) => Self {
+                code: Some(data.status_code),
+                reason: data.reason.clone(),
+            },
+            None => Self {
+                code: None,
+                reason: String::new(),
+            },
+        }
+    }
 }
 
 /// State about a node.
 #[derive(Clone, Debug, Default)]
 pub struct State {
+    /// The address that the last connection to the node was made to, out of
+    /// those that its websocket host resolved to, if one has been made.
+    pub address: Option<SocketAddr>,
+    /// The last stats received from the node, up to the
+    /// [`NodeConfig::stats_history`] of samples.
+    ///
+    /// [`NodeConfig::stats_history`]: struct.NodeConfig.html#method.stats_history
+    pub history: StatsHistory,
+    /// How the last connection to the node was closed, if one has been.
+    pub last_close: Option<CloseData>,
+    /// When a message was last received from the node, if one has been.
+    pub last_received: Option<Instant>,
+    /// The penalty of the node, calculated when stats are received.
+    ///
+    /// Refer to [`PenaltyConfig`] for how this is calculated.
+    ///
+    /// [`PenaltyConfig`]: struct.PenaltyConfig.html
+    pub penalty: Option<i32>,
+    /// The version of Lavalink's protocol that the node speaks.
+    ///
+    /// This is the configured [`NodeConfig::protocol_version`], unless the
+    /// node reported a version of another protocol when connected to, or
+    /// only accepted a connection at its v4 path.
+    ///
+    /// [`NodeConfig::protocol_version`]: struct.NodeConfig.html#method.protocol_version
+    pub protocol_version: ProtocolVersion,
+    /// The version of Lavalink that the node reported via its `/version`
+    /// REST endpoint when last connected to, such as `3.7.11`.
+    ///
+    /// This is `None` if the node is older than Lavalink v3.3, as it has no
+    /// such endpoint.
+    pub server_version: Option<String>,
+    /// The ID of the node's session, as sent in its `ready` op once
+    /// connected to.
+    ///
+    /// This is only sent by Lavalink v3.7 and newer.
+    pub session_id: Option<String>,
+    /// Whether the connection to the node is stale, as nothing was received
+    /// from it within the [`HeartbeatConfig::timeout`].
+    ///
+    /// [`HeartbeatConfig::timeout`]: struct.HeartbeatConfig.html#structfield.timeout
+    pub stale: bool,
     /// Statistics about the node's load and players, if there is any.
-    pub stats: Option<RemoteStats>,
+    pub stats: Option<NodeStats>,
+    /// The status of the connection to the node.
+    pub status: NodeStatus,
+}
+
+/// An event about the nodes and players of a [`NodeManager`].
+///
+/// A stream of these can be retrieved via [`NodeManager::subscribe`]. Events
+/// about nodes contain the websocket host of the node.
+///
+/// [`NodeManager`]: struct.NodeManager.html
+/// [`NodeManager::subscribe`]: struct.NodeManager.html#method.subscribe
+#[derive(Clone, Debug)]
+pub enum ManagerEvent {
+    /// A node was added to the manager.
+    NodeAdded(String),
+    /// A node was connected to again after being disconnected, or after being
+    /// reconnected to via [`NodeManager::reconnect`].
+    ///
+    /// [`NodeManager::reconnect`]: struct.NodeManager.html#method.reconnect
+    NodeConnected(String),
+    /// The connection to a node was closed or went stale.
+    NodeDisconnected(String),
+    /// A node was removed from the manager.
+    NodeRemoved(String),
+    /// A player was created for a guild on a node.
+    PlayerCreated(u64, String),
+    /// The player of a guild was removed.
+    PlayerRemoved(u64),
+    /// A track ended on a guild's player on a node.
+    TrackEnd(String, TrackEndEvent),
+    /// An exception occurred while playing a track on a guild's player on a
+    /// node.
+    TrackException(String, TrackExceptionEvent),
+    /// A track started playing on a guild's player on a node.
+    TrackStart(String, TrackStartEvent),
+    /// A track is stuck playing on a guild's player on a node.
+    TrackStuck(String, TrackStuckEvent),
+    /// Discord's voice connection for a guild's player on a node was closed.
+    WebSocketClosed(String, WebSocketClosedEvent),
+}
+
+/// A typed event received from a node.
+///
+/// A stream of these can be retrieved via [`Node::events`].
+///
+/// [`Node::events`]: struct.Node.html#method.events
+#[derive(Clone, Debug)]
+pub enum NodeEvent {
+    /// A player reached a line of its track's lyrics, from the LavaLyrics
+    /// plugin.
+    #[cfg(feature = "lyrics")]
+    LyricsLine(LyricsLineEvent),
+    /// An update about the state of a guild's player.
+    PlayerUpdate(PlayerUpdate),
+    /// A segment of a player's track was skipped, from the SponsorBlock
+    /// plugin.
+    #[cfg(feature = "sponsorblock")]
+    SegmentSkipped(SegmentSkippedEvent),
+    /// Statistics about the node's load and players.
+    Stats(NodeStats),
+    /// The status of the connection to the node changed.
+    Status(NodeStatus),
+    /// A track ended on a guild's player.
+    TrackEnd(TrackEndEvent),
+    /// An exception occurred while playing a track on a guild's player.
+    TrackException(TrackExceptionEvent),
+    /// A track started playing on a guild's player.
+    TrackStart(TrackStartEvent),
+    /// A track is stuck playing on a guild's player.
+    TrackStuck(TrackStuckEvent),
+    /// A payload that is not known or could not be parsed into an event.
+    Unknown(Value),
+    /// Discord's voice connection for a guild's player was closed.
+    WebSocketClosed(WebSocketClosedEvent),
+}
+
+impl NodeEvent {
+    /// Parses an event from a payload, according to its `op` field.
+    ///
+    /// If the payload is not of a known type, or could not be parsed, then
+    /// [`NodeEvent::Unknown`] is returned containing the payload.
+    ///
+    /// [`NodeEvent::Unknown`]: #variant.Unknown
+    pub fn from_json(json: Value) -> Self {
+        Self::parse(&json).unwrap_or_else(|| NodeEvent::Unknown(json))
+    }
+
+    fn parse(json: &Value) -> Option<Self> {
+        match json.get("op")?.as_str()? {
+            "playerUpdate" => {
+                PlayerUpdate::deserialize(json).ok().map(NodeEvent::PlayerUpdate)
+            },
+            "stats" => {
+                NodeStats::deserialize(json).ok().map(NodeEvent::Stats)
+            },
+            "event" => match &EventType::deserialize(json).ok()?.kind[..] {
+                #[cfg(feature = "lyrics")]
+                "LyricsLineEvent" => {
+                    LyricsLineEvent::deserialize(json).ok().map(NodeEvent::LyricsLine)
+                },
+                #[cfg(feature = "sponsorblock")]
+                "SegmentSkipped" => {
+                    SegmentSkippedEvent::deserialize(json).ok().map(NodeEvent::SegmentSkipped)
+                },
+                "TrackEndEvent" => {
+                    TrackEndEvent::deserialize(json).ok().map(NodeEvent::TrackEnd)
+                },
+                "TrackExceptionEvent" => {
+                    TrackExceptionEvent::deserialize(json).ok().map(NodeEvent::TrackException)
+                },
+                "TrackStartEvent" => {
+                    TrackStartEvent::deserialize(json).ok().map(NodeEvent::TrackStart)
+                },
+                "TrackStuckEvent" => {
+                    TrackStuckEvent::deserialize(json).ok().map(NodeEvent::TrackStuck)
+                },
+                "WebSocketClosedEvent" => {
+                    WebSocketClosedEvent::deserialize(json).ok().map(NodeEvent::WebSocketClosed)
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
@@ -1,32 +1,79 @@
+use futures::future::{Either, Shared};
 use futures::prelude::*;
 use futures::sync::mpsc::{
     self,
     Receiver as SyncReceiver,
-    SendError as SyncSendError,
     Sender as SyncSender,
+    UnboundedSender,
 };
-use futures::{Future, StartSend, future};
-use lavalink::opcodes::Opcode;
-use serde::Deserialize;
-use serde_json::{self, Value};
+use futures::sync::oneshot;
+use futures::{Future, Poll, StartSend, future, stream};
+use hyper::Method;
+use serde::de::Error as DeError;
+use serde_json::{self, Error as JsonError, Value};
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
 use std::rc::Rc;
-use super::{NodeConfig, State};
-use websocket::async::Handle;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use super::replay::ReplayBuffer;
+use super::scheduler::{Ack, AckedMessage, FairScheduler, Outgoing};
+use super::{
+    CloseData,
+    EventFilter,
+    HeartbeatConfig,
+    NodeAddress,
+    NodeConfig,
+    NodeEvent,
+    NodeStatus,
+    ProtocolVersion,
+    State,
+    StatsHistory,
+};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::{Handle, Interval, Timeout};
+use tokio_tls::TlsConnectorExt;
+use websocket::client::async::Client as AsyncClient;
 use websocket::header::Headers;
-use websocket::{ClientBuilder, OwnedMessage, WebSocketError};
+use websocket::stream::async::Stream as AsyncStream;
+use websocket::{ClientBuilder, CloseData as WebSocketCloseData, OwnedMessage};
+use ::audit::Direction;
+use ::executor::{Executor, Task};
+use ::metrics::Metrics;
+use ::middleware::Middleware;
+use ::model::{
+    EventType,
+    NodeStats,
+    PlayerUpdate,
+    Ready,
+    TrackEndEvent,
+    TrackExceptionEvent,
+    TrackStartEvent,
+    TrackStuckEvent,
+    WebSocketClosedEvent,
+};
 use ::player::*;
-use ::{Error, EventHandler};
+#[cfg(feature = "lyrics")]
+use ::plugins::lyrics::{Lyrics, LyricsLineEvent};
+#[cfg(feature = "sponsorblock")]
+use ::plugins::sponsorblock::SegmentSkippedEvent;
+use ::rest::{self, LoadTracksResponse, LoadedTrack, NodeRest};
+use ::{Error, EventHandler, resolver, tls};
 
 /// The state of a connection to a Lavalink Node.
+///
+/// The tasks running the connection on the Core, such as reading from the
+/// node and sending heartbeats, end when the node is dropped, closing the
+/// socket without reconnecting.
 pub struct Node {
     /// The HTTP server URI of the connected node.
     pub http_host: String,
     /// The password of the node.
     pub password: String,
     /// The state of the node, containing statistics like load averages.
-    pub state: Rc<RefCell<State>>,
+    pub state: Arc<RwLock<State>>,
     /// A sender for sending messages over the WebSocket.
     ///
     /// It may be preferable to do this via an [`AudioPlayer`].
@@ -41,422 +88,2734 @@ pub struct Node {
     pub user_from_node: SyncReceiver<OwnedMessage>,
     /// The WS server URI of the connected node.
     pub websocket_host: String,
+    acked_to_node: UnboundedSender<AckedMessage>,
+    config: NodeConfig,
+    metrics: Metrics,
+    replay: Arc<Mutex<ReplayBuffer>>,
+    rest: NodeRest,
+    scheduler: Arc<Mutex<FairScheduler>>,
+    // Ends the tasks running the connection when the node is dropped.
+    shutdown: Option<oneshot::Sender<()>>,
+    subscribers: Arc<Mutex<Vec<UnboundedSender<NodeEvent>>>>,
 }
 
 impl Node {
     /// Connects to a Lavalink node.
     ///
     /// Requires a Handle to the tokio Core in use, configuration identifying
-    /// the node, and an Rc to the audio player manager and handler
-    /// implementation.
+    /// the node, an Arc to the audio player manager and handler
+    /// implementation, the [`Executor`] calling the handler, the [`Metrics`]
+    /// to emit into, and the [`Middleware`] to apply to the ops sent.
+    ///
+    /// The connection is made over TLS if the [`websocket_host`] uses the
+    /// `wss` scheme. If a [`heartbeat`] is configured, the connection is
+    /// pinged periodically to detect when it has gone stale.
+    ///
+    /// The host is resolved off of the Core, and if it resolves to multiple
+    /// addresses then each is tried in turn - alternating between IPv6 and
+    /// IPv4 - with a timeout of 5 seconds per attempt, until one connects.
+    /// The address that connected is available via [`address`].
+    ///
+    /// If a [`ready_timeout`] is configured, this resolves once the node sends
+    /// its first stats, and otherwise once the handshake finishes. Resolves to
+    /// [`Error::AuthenticationFailed`] if the node closes the connection due
+    /// to a wrong password, or [`Error::InvalidConfig`] if the REST
+    /// [rate limit] is invalid.
     ///
     /// It may be preferable to connect to a Node via [`NodeManager::add_node`].
     ///
+    /// [`Error::AuthenticationFailed`]: ../enum.Error.html#variant.AuthenticationFailed
+    /// [`Error::InvalidConfig`]: ../enum.Error.html#variant.InvalidConfig
+    /// [`Executor`]: ../executor/trait.Executor.html
+    /// [`Metrics`]: ../metrics/struct.Metrics.html
+    /// [`Middleware`]: ../middleware/struct.Middleware.html
     /// [`NodeManager::add_node`]: struct.NodeManager.html#method.add_node
+    /// [`address`]: #method.address
+    /// [`heartbeat`]: struct.NodeConfig.html#method.heartbeat
+    /// [`ready_timeout`]: struct.NodeConfig.html#method.ready_timeout
+    /// [`websocket_host`]: struct.NodeConfig.html#structfield.websocket_host
+    /// [rate limit]: ../rest/struct.RestConfig.html#structfield.rate_limit
     pub fn connect(
         handle: Handle,
         config: NodeConfig,
-        player_manager: Rc<RefCell<AudioPlayerManager>>,
-        handler: Rc<RefCell<Box<EventHandler>>>,
+        player_manager: Arc<Mutex<AudioPlayerManager>>,
+        handler: Arc<Mutex<Box<EventHandler>>>,
+        executor: Arc<Executor>,
+        metrics: Metrics,
+        middleware: Middleware,
     ) -> Box<Future<Item = Self, Error = Error>> {
-        let mut headers = Headers::new();
-        headers.set_raw("Authorization", vec![config.password.clone().into_bytes()]);
-        headers.set_raw("Num-Shards", vec![config.num_shards.to_string().into_bytes()]);
-        headers.set_raw("User-Id", vec![config.user_id.clone().into_bytes()]);
-
-        let handle2 = handle.clone();
-        let handle3 = handle.clone();
-        let websocket_host = config.websocket_host.clone();
-
-        let done = future::result(ClientBuilder::new(&websocket_host).map_err(From::from))
-            .and_then(move |builder| {
-                trace!(
-                    "Building node WS client & connecting: {}",
-                    websocket_host,
-                );
+        if let Some(ref rate_limit) = config.rest.rate_limit {
+            if let Err(why) = rate_limit.validate() {
+                return Box::new(future::err(why.with_node(config.websocket_host)));
+            }
+        }
 
-                builder.custom_headers(&headers)
-                    .async_connect_insecure(&handle2)
-            })
-            .map(move |(duplex, _)| {
-                trace!("Node WS client connected");
-
-                // user_to_node: user send to node (node handles)
-                // node_from_user: node receive from user (user handles)
-                // node_to_user: node send to user (node handles)
-                // user_from_node: to receive from node sending into user (user handles)
-                let (user_to_node, node_from_user) = mpsc::channel(0);
-                let (node_to_user, user_from_node) = mpsc::channel(0);
-
-                let (sink, stream) = duplex.split();
-                let state = Rc::new(RefCell::new(State::default()));
-                let ws_state = Rc::clone(&state);
-                let (sink_tx, sink_rx) = mpsc::unbounded();
-
-                let future = stream
-                    .map(move |msg| (msg, sink_tx.clone()))
-                    .filter_map(move |(msg, sink_tx)| {
-                        // todo: optimize
-                        match msg {
-                            OwnedMessage::Close(data) => {
-                                info!("Received a close: {:?}", data);
+        // user_to_node: user send to node (node handles)
+        // node_from_user: node receive from user (user handles)
+        // node_to_user: node send to user (node handles)
+        // user_from_node: to receive from node sending into user (user handles)
+        let buffer = config.channel_buffer.size();
+        let (user_to_node, node_from_user) = mpsc::channel(buffer);
+        let (node_to_user, user_from_node) = mpsc::channel(buffer);
+        let (acked_to_node, node_from_acked) = mpsc::unbounded();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
 
-                                Some(OwnedMessage::Close(None))
-                            },
-                            OwnedMessage::Ping(data) => {
-                                trace!("Received a ping: {:?}", data);
+        let replay = Arc::new(Mutex::new(ReplayBuffer::new(config.replay.clone())));
+        let scheduler = Arc::new(Mutex::new(
+            FairScheduler::new(node_from_user, node_from_acked, buffer),
+        ));
+        let state = Arc::new(RwLock::new(State {
+            history: StatsHistory::new(config.stats_history),
+            protocol_version: config.protocol_version,
+            ..State::default()
+        }));
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
 
-                                Some(OwnedMessage::Pong(data))
-                            },
-                            OwnedMessage::Text(data) => {
-                                trace!("Received text: {:?}", data);
-
-                                let done = handle_message(
-                                    &node_to_user,
-                                    data.as_bytes(),
-                                    Rc::clone(&handler),
-                                    &Rc::clone(&ws_state),
-                                    Rc::clone(&player_manager),
-                                ).map(move |msg_| {
-                                    if let Some(msg) = msg_ {
-                                        if let Err(why) = sink_tx.unbounded_send(msg) {
-                                            warn!(
-                                                "Err sending to sink: {:?}",
-                                                why,
-                                            );
-                                        }
-                                    }
-
-                                    ()
-                                }).map_err(|_| ());
-
-                                handle3.spawn(done);
-
-                                None
-                            },
-                            OwnedMessage::Binary(data) => {
-                                trace!("Received binary: {:?}", data);
-
-                                let done = handle_message(
-                                    &node_to_user,
-                                    &data,
-                                    Rc::clone(&handler),
-                                    &Rc::clone(&ws_state),
-                                    Rc::clone(&player_manager),
-                                ).map(move |msg| {
-                                    if let Some(msg) = msg {
-                                        if let Err(why) = sink_tx.unbounded_send(msg) {
-                                            warn!(
-                                                "Err sending to sink: {:?}",
-                                                why,
-                                            );
-                                        }
-                                    }
-
-                                    ()
-                                }).map_err(|_| ());
-
-                                handle3.spawn(done);
-
-                                None
-                            },
-                            OwnedMessage::Pong(data) => {
-                                warn!("Received a pong somehow? {:?}", data);
+        let rest = NodeRest::new(
+            handle.remote().clone(),
+            config.http_host.clone(),
+            config.password.clone(),
+            config.tls_root_certificates.clone(),
+            config.rest.clone(),
+        );
+        let rest_commands = spawn_rest_commands(
+            &handle,
+            rest.clone(),
+            Arc::clone(&state),
+            config.websocket_host.clone(),
+        );
 
-                                None
-                            },
-                        }
-                    })
-                    .select(node_from_user.map_err(|why| {
-                        warn!("Err selceting node_from_user: {:?}", why);
-
-                        WebSocketError::IoError(IoError::new(
-                            IoErrorKind::Other,
-                            "This should be unreachable",
-                        ))
+        let connection = Rc::new(Connection {
+            config: config.clone(),
+            handle: handle.clone(),
+            close: RefCell::new(None),
+            node_from_user: Arc::clone(&scheduler),
+            parked: RefCell::new(None),
+            ready: RefCell::new(None),
+            replay: Arc::clone(&replay),
+            rest: rest.clone(),
+            rest_commands,
+            shutdown: shutdown_rx.shared(),
+            state: Arc::clone(&state),
+            subscribers: Arc::clone(&subscribers),
+            metrics: metrics.clone(),
+            middleware,
+            _node_to_user: node_to_user,
+            handler: Dispatcher {
+                limit: config.max_handler_futures.map(|max| {
+                    Arc::new(Mutex::new(HandlerLimit {
+                        in_flight: 0,
+                        max: max.max(1),
+                        queued: VecDeque::new(),
                     }))
-                    .select(sink_rx.map_err(|why| {
-                        warn!("Err selecting sink_rx: {:?}", why);
+                }),
+                executor,
+                handler,
+            },
+            player_manager,
+        });
 
-                        WebSocketError::IoError(IoError::new(
-                            IoErrorKind::Other,
-                            "This should be unreachable",
-                        ))
-                    }))
-                    .map(|msg| {
-                        debug!("msg: {:?}", msg);
-
-                        msg
-                    })
-                    // .map(move |future| {
-                    //     (future, handle3.clone(), sink_tx.clone())
-                    // })
-                    // .for_each(|(future, handle, sink_tx)| {
-                    //     future.and_then(move |res| {
-                    //         res.and_then(|msg| {
-                    //             handle.spawn(sink_tx.send(msg).map(|_| ()).map_err(|_| ()));
-                    //             Some(())
-                    //         });
-                    //         Box::new(future::done(Ok(())))
-                    //     })
-                    //     .map_err(|_| {
-                    //         IoError::new(
-                    //             IoErrorKind::Other,
-                    //             "This should be unreachable",
-                    //         )
-                    //     })
-                    //     .from_err()
-                    // })
-                    .forward(sink)
-                    .map(|_| ())
-                    .map_err(|_| ());
-
-                handle.spawn(future);
-
-                Self {
-                    http_host: config.http_host,
-                    password: config.password,
-                    websocket_host: config.websocket_host,
-                    state,
-                    user_to_node,
-                    user_from_node,
-                }
-            })
-            .from_err();
+        let error_host = config.websocket_host.clone();
+
+        let done = start(connection).map_err(move |why| {
+            why.with_node(error_host)
+        }).map(move |_| Self {
+            http_host: config.http_host.clone(),
+            password: config.password.clone(),
+            websocket_host: config.websocket_host.clone(),
+            rest,
+            config,
+            metrics,
+            replay,
+            scheduler,
+            shutdown: Some(shutdown_tx),
+            state,
+            acked_to_node,
+            subscribers,
+            user_to_node,
+            user_from_node,
+        });
 
         Box::new(done)
     }
 
+    /// Sends a payload to the node, resolving once it has been written to
+    /// the WebSocket.
+    ///
+    /// Unlike sending over [`user_to_node`], this can be used to sequence
+    /// ops, such as sending a `voiceUpdate` before a `play`. Payloads sent
+    /// this way are queued ahead of messages from players that have not yet
+    /// been received by the node's connection, and after those that have, so
+    /// the order of ops for each guild is preserved.
+    ///
+    /// Resolves to [`Error::Canceled`] if the connection ended before the
+    /// payload was written, or [`Error::Closed`] if the node was dropped. For
+    /// nodes speaking [`ProtocolVersion::V4`], the ops of players resolve once
+    /// the node accepted them over REST, or to the error of the request.
+    ///
+    /// [`Error::Canceled`]: ../enum.Error.html#variant.Canceled
+    /// [`Error::Closed`]: ../enum.Error.html#variant.Closed
+    /// [`ProtocolVersion::V4`]: enum.ProtocolVersion.html#variant.V4
+    /// [`user_to_node`]: #structfield.user_to_node
+    pub fn send(&self, payload: &Value) -> Box<Future<Item = (), Error = Error> + Send> {
+        let (tx, rx) = oneshot::channel();
+        let message = OwnedMessage::Text(payload.to_string());
+
+        if self.acked_to_node.unbounded_send((message, tx)).is_err() {
+            return Box::new(future::err(Error::Closed));
+        }
+
+        Box::new(rx.from_err().and_then(|res| res))
+    }
+
+    /// Sends messages buffered by a previous connection to the node - such as
+    /// those taken via [`take_buffered`] - in order, in the same way as
+    /// [`send`].
+    ///
+    /// Unlike sending over [`user_to_node`], messages aren't dropped if the
+    /// sender is full. Returns the number of messages that were queued, which
+    /// is only less than given if the node was dropped.
+    ///
+    /// [`send`]: #method.send
+    /// [`take_buffered`]: #method.take_buffered
+    /// [`user_to_node`]: #structfield.user_to_node
+    pub fn send_buffered(&self, messages: Vec<OwnedMessage>) -> usize {
+        let mut count = 0;
+
+        for message in messages {
+            // Nothing awaits the delivery of a buffered message.
+            let (tx, _) = oneshot::channel();
+
+            if self.acked_to_node.unbounded_send((message, tx)).is_err() {
+                break;
+            }
+
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Sends a raw payload to the node, such as an op of a Lavalink plugin
+    /// that the library has no model for.
+    ///
+    /// This is sent in the same way as [`send`], so the order of ops for each
+    /// guild is preserved. Payloads that the library doesn't handle which are
+    /// received from the node are passed to [`EventHandler::unknown_op`].
+    ///
+    /// [`EventHandler::unknown_op`]: ../trait.EventHandler.html#method.unknown_op
+    /// [`send`]: #method.send
+    pub fn send_raw(&self, payload: Value) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.send(&payload)
+    }
+
+    /// The number of ops that were coalesced into an op of the same kind
+    /// queued before them for the same guild, such as consecutive volume
+    /// changes, rather than being sent.
+    pub fn coalesced_messages(&self) -> u64 {
+        self.scheduler.lock().map(|scheduler| scheduler.coalesced()).unwrap_or(0)
+    }
+
+    /// The number of messages received from players and via [`send`] that
+    /// are queued to be written to the node.
+    ///
+    /// [`send`]: #method.send
+    pub fn queued_messages(&self) -> usize {
+        self.scheduler.lock().map(|scheduler| scheduler.buffered()).unwrap_or(0)
+    }
+
+    /// The number of messages buffered while the node is disconnected, to be
+    /// sent once its session is resumed.
+    ///
+    /// Refer to [`ReplayConfig`] for more information.
+    ///
+    /// [`ReplayConfig`]: struct.ReplayConfig.html
+    pub fn buffered_messages(&self) -> usize {
+        self.replay.lock().map(|replay| replay.len()).unwrap_or(0)
+    }
+
+    /// Takes the messages buffered while the node is disconnected, from oldest
+    /// to newest.
+    ///
+    /// This is used to carry the messages over to a new connection, such as
+    /// via [`NodeManager::reconnect`].
+    ///
+    /// [`NodeManager::reconnect`]: struct.NodeManager.html#method.reconnect
+    pub fn take_buffered(&self) -> Vec<OwnedMessage> {
+        match self.replay.lock() {
+            Ok(mut replay) => replay.take().into_iter().collect(),
+            Err(why) => {
+                event!(warn, node = self.websocket_host; "Err locking replay buffer: {:?}", why);
+
+                Vec::new()
+            },
+        }
+    }
+
+    /// The configuration that the node was connected with.
+    ///
+    /// This is used to reconnect to the node, such as via
+    /// [`NodeManager::reconnect`].
+    ///
+    /// [`NodeManager::reconnect`]: struct.NodeManager.html#method.reconnect
+    #[inline]
+    pub fn config(&self) -> &NodeConfig {
+        &self.config
+    }
+
+    /// The handle to the node's REST API, which shares a pool of connections
+    /// between all of the node's requests.
+    #[inline]
+    pub fn rest(&self) -> &NodeRest {
+        &self.rest
+    }
+
+    /// Records the latency of a REST request under the given name once it
+    /// resolves.
+    fn timed<T: Send + 'static>(
+        &self,
+        name: &'static str,
+        future: Box<Future<Item = T, Error = Error> + Send>,
+    ) -> Box<Future<Item = T, Error = Error> + Send> {
+        let metrics = self.metrics.clone();
+        let started = Instant::now();
+        let ws_host = self.websocket_host.clone();
+
+        Box::new(future.then(move |res| {
+            metrics.rest_latency(&ws_host, name, started.elapsed());
+
+            res.map_err(|why| why.with_node(ws_host))
+        }))
+    }
+
+    /// Loads tracks from the node by identifier via its REST API.
+    ///
+    /// Refer to [`rest::load_tracks`] for more information.
+    ///
+    /// [`rest::load_tracks`]: ../rest/fn.load_tracks.html
+    pub fn load_tracks(&self, identifier: &str)
+        -> Box<Future<Item = LoadTracksResponse, Error = Error> + Send> {
+        self.timed("load_tracks", self.rest.load_tracks(identifier))
+    }
+
+    /// Searches YouTube for tracks matching the query via the node's REST API.
+    ///
+    /// Refer to [`rest::search_youtube`] for more information.
+    ///
+    /// [`rest::search_youtube`]: ../rest/fn.search_youtube.html
+    pub fn search_youtube(&self, query: &str)
+        -> Box<Future<Item = Vec<LoadedTrack>, Error = Error> + Send> {
+        self.timed("search_youtube", self.rest.search_youtube(query))
+    }
+
+    /// Searches SoundCloud for tracks matching the query via the node's REST
+    /// API.
+    ///
+    /// Refer to [`rest::search_soundcloud`] for more information.
+    ///
+    /// [`rest::search_soundcloud`]: ../rest/fn.search_soundcloud.html
+    pub fn search_soundcloud(&self, query: &str)
+        -> Box<Future<Item = Vec<LoadedTrack>, Error = Error> + Send> {
+        self.timed("search_soundcloud", self.rest.search_soundcloud(query))
+    }
+
+    /// Loads the lyrics of a base64 encoded track via the node's LavaLyrics
+    /// plugin.
+    ///
+    /// Refer to [`NodeRest::load_lyrics`] for more information.
+    ///
+    /// [`NodeRest::load_lyrics`]: ../rest/struct.NodeRest.html#method.load_lyrics
+    #[cfg(feature = "lyrics")]
+    pub fn load_lyrics(&self, track: &str, skip_track_source: bool)
+        -> Box<Future<Item = Option<Lyrics>, Error = Error> + Send> {
+        self.timed("load_lyrics", self.rest.load_lyrics(track, skip_track_source))
+    }
+
+    /// Subscribes a guild's player to the lyrics of its tracks via the node's
+    /// LavaLyrics plugin, so that [`EventHandler::lyrics_line`] is called as
+    /// the player reaches each line.
+    ///
+    /// Resolves to [`Error::None`] if the node has not yet sent its session
+    /// ID. Refer to [`NodeRest::subscribe_lyrics`] for more information.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    /// [`EventHandler::lyrics_line`]: ../trait.EventHandler.html#method.lyrics_line
+    /// [`NodeRest::subscribe_lyrics`]: ../rest/struct.NodeRest.html#method.subscribe_lyrics
+    #[cfg(feature = "lyrics")]
+    pub fn subscribe_lyrics(&self, guild_id: u64, skip_track_source: bool)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_session("subscribe_lyrics", guild_id, |rest, session_id| {
+            rest.subscribe_lyrics(session_id, guild_id, skip_track_source)
+        })
+    }
+
+    /// Unsubscribes a guild's player from the lyrics of its tracks.
+    ///
+    /// Resolves to [`Error::None`] if the node has not yet sent its session
+    /// ID.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    #[cfg(feature = "lyrics")]
+    pub fn unsubscribe_lyrics(&self, guild_id: u64)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_session("unsubscribe_lyrics", guild_id, |rest, session_id| {
+            rest.unsubscribe_lyrics(session_id, guild_id)
+        })
+    }
+
+    /// Retrieves the categories of segments that the node's SponsorBlock
+    /// plugin skips for a guild's player.
+    ///
+    /// Resolves to [`Error::None`] if the node has not yet sent its session
+    /// ID.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    #[cfg(feature = "sponsorblock")]
+    pub fn sponsorblock_categories(&self, guild_id: u64)
+        -> Box<Future<Item = Vec<String>, Error = Error> + Send> {
+        self.with_session("sponsorblock_categories", guild_id, |rest, session_id| {
+            rest.sponsorblock_categories(session_id, guild_id)
+        })
+    }
+
+    /// Sets the categories of segments that the node's SponsorBlock plugin
+    /// skips for a guild's player, such as `"sponsor"` and `"selfpromo"`.
+    ///
+    /// [`EventHandler::segment_skipped`] is called for each skipped segment.
+    /// Resolves to [`Error::None`] if the node has not yet sent its session
+    /// ID.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    /// [`EventHandler::segment_skipped`]: ../trait.EventHandler.html#method.segment_skipped
+    #[cfg(feature = "sponsorblock")]
+    pub fn set_sponsorblock_categories(&self, guild_id: u64, categories: &[&str])
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_session("set_sponsorblock_categories", guild_id, |rest, session_id| {
+            rest.set_sponsorblock_categories(session_id, guild_id, categories)
+        })
+    }
+
+    /// Stops the node's SponsorBlock plugin from skipping segments for a
+    /// guild's player.
+    ///
+    /// Resolves to [`Error::None`] if the node has not yet sent its session
+    /// ID.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    #[cfg(feature = "sponsorblock")]
+    pub fn clear_sponsorblock_categories(&self, guild_id: u64)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_session("clear_sponsorblock_categories", guild_id, |rest, session_id| {
+            rest.clear_sponsorblock_categories(session_id, guild_id)
+        })
+    }
+
+    /// Makes a REST request to a plugin's endpoint of a guild's player, which
+    /// requires the ID of the node's session.
+    #[cfg(any(feature = "lyrics", feature = "sponsorblock"))]
+    fn with_session<T, F>(&self, name: &'static str, guild_id: u64, f: F)
+        -> Box<Future<Item = T, Error = Error> + Send>
+        where F: FnOnce(&NodeRest, &str) -> Box<Future<Item = T, Error = Error> + Send>,
+              T: Send + 'static {
+        match self.session_id() {
+            Some(session_id) => self.timed(name, f(&self.rest, &session_id)),
+            None => {
+                let error = Error::None
+                    .with_guild(guild_id)
+                    .with_node(self.websocket_host.clone());
+
+                Box::new(future::err(error))
+            },
+        }
+    }
+
+    /// Creates a stream of typed events received from the node.
+    ///
+    /// This is an alternative to the [`EventHandler`] for consumers who prefer
+    /// stream-based processing. Events are dispatched to the handler
+    /// regardless of whether any streams exist. Each call creates a new stream
+    /// which receives every event from the time of its creation.
+    ///
+    /// [`EventHandler`]: ../trait.EventHandler.html
+    pub fn events(&self) -> Box<Stream<Item = NodeEvent, Error = Error> + Send> {
+        let (tx, rx) = mpsc::unbounded();
+
+        match self.subscribers.lock() {
+            Ok(mut subscribers) => subscribers.push(tx),
+            Err(why) => {
+                event!(
+                    warn,
+                    node = self.websocket_host;
+                    "Err locking event subscribers: {:?}",
+                    why
+                );
+            },
+        }
+
+        // Receiving from an unbounded receiver can't fail.
+        Box::new(rx.map_err(|_| Error::None))
+    }
+
     /// Sends a close code over the WebSocket, terminating the connection.
     ///
+    /// The tasks running the connection end once the node completes the
+    /// closing handshake, and the connection is not reconnected. Dropping the
+    /// node ends them immediately instead.
+    ///
     /// **Note**: This does _not_ remove it from the manager operating the node.
     /// Prefer to close nodes via the manager.
-    pub fn close(&mut self)
-        -> StartSend<OwnedMessage, SyncSendError<OwnedMessage>> {
-        self.user_to_node.start_send(OwnedMessage::Close(None))
+    pub fn close(&mut self) -> Result<(), Error> {
+        self.user_to_node
+            .start_send(OwnedMessage::Close(None))
+            .map(|_| ())
+            .map_err(From::from)
     }
 
-    /// Calculates the penalty of the node.
+    /// Retrieves the address that the last connection to the node was made
+    /// to, without blocking.
+    ///
+    /// The node's websocket host may resolve to multiple addresses, such as
+    /// behind round-robin DNS, which are tried in turn until one connects.
+    ///
+    /// Returns `None` if the node has not yet been connected to, or if the
+    /// internal [`state`] could not be accessed at the time.
+    ///
+    /// [`state`]: #structfield.state
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.state.try_read().ok()?.address
+    }
+
+    /// Retrieves the penalty of the node.
+    ///
+    /// The penalty is calculated when stats are received, using the node's
+    /// [`PenaltyConfig`].
     ///
     /// Returns `None` if the internal [`state`] could not be accessed at the
-    /// time or if there are not yet any stats. The state should never be
-    /// inaccessible by only the library's usage, so you should be cautious
-    /// about accessing it.
+    /// time or if there are not yet any stats. The state should only be
+    /// inaccessible if its lock was poisoned.
+    ///
+    /// [`PenaltyConfig`]: struct.PenaltyConfig.html
+    /// [`state`]: #structfield.state
     pub fn penalty(&self) -> Option<i32> {
-        let state = self.state.try_borrow().ok()?;
-        let stats = state.stats.as_ref()?;
+        self.state.read().ok()?.penalty
+    }
 
-        let cpu = 1.05f64.powf(100f64 * stats.cpu.system_load) * 10f64 - 10f64;
+    /// Retrieves the version of Lavalink that the node reported via its
+    /// `/version` REST endpoint when last connected to, without blocking.
+    ///
+    /// Returns `None` if the node is older than Lavalink v3.3 and so has no
+    /// such endpoint, if the endpoint could not be reached, or if the
+    /// internal [`state`] could not be accessed at the time.
+    ///
+    /// [`state`]: #structfield.state
+    pub fn server_version(&self) -> Option<String> {
+        self.state.try_read().ok()?.server_version.clone()
+    }
 
-        let (deficit_frame, null_frame) = match stats.frame_stats.as_ref() {
-            Some(frame_stats) => {
-                (
-                    1.03f64.powf(500f64 * (f64::from(frame_stats.deficit) / 3000f64)) * 300f64 - 300f64,
-                    (1.03f64.powf(500f64 * (f64::from(frame_stats.nulled) / 3000f64)) * 300f64 - 300f64) * 2f64,
-                )
-            },
-            None => (0f64, 0f64),
-        };
+    /// Retrieves the ID of the node's session, as sent in its `ready` op,
+    /// without blocking.
+    ///
+    /// Returns `None` if the node has not sent one - such as if it is older
+    /// than Lavalink v3.7 - or if the internal [`state`] could not be
+    /// accessed at the time.
+    ///
+    /// [`state`]: #structfield.state
+    pub fn session_id(&self) -> Option<String> {
+        self.state.try_read().ok()?.session_id.clone()
+    }
 
-        Some(stats.playing_players + cpu as i32 + deficit_frame as i32 + null_frame as i32)
+    /// Retrieves a copy of the last stats received from the node, without
+    /// blocking.
+    ///
+    /// Returns `None` if there are not yet any stats, or if the internal
+    /// [`state`] could not be accessed at the time, such as while it is
+    /// being updated or if its lock was poisoned.
+    ///
+    /// [`state`]: #structfield.state
+    pub fn stats(&self) -> Option<NodeStats> {
+        self.state.try_read().ok()?.stats.clone()
+    }
+
+    /// Retrieves the status of the connection to the node.
+    ///
+    /// Returns [`NodeStatus::Degraded`] if the internal [`state`] could not be
+    /// accessed, which should only be the case if its lock was poisoned.
+    ///
+    /// [`NodeStatus::Degraded`]: enum.NodeStatus.html#variant.Degraded
+    /// [`state`]: #structfield.state
+    pub fn status(&self) -> NodeStatus {
+        self.state.read().map(|state| state.status).unwrap_or(NodeStatus::Degraded)
     }
 }
 
-fn handle_message(
-    // todo: why is this not needed?
-    _: &SyncSender<OwnedMessage>,
-    bytes: &[u8],
-    handler: Rc<RefCell<Box<EventHandler>>>,
-    state: &Rc<RefCell<State>>,
-    mut player_manager: Rc<RefCell<AudioPlayerManager>>,
-) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
-    let json = match serde_json::from_slice::<Value>(bytes) {
-        Ok(json) => json,
-        Err(why) => {
-            warn!("Error parsing received JSON: {:?}", why);
+/// Everything needed to establish and run a WebSocket connection to a node.
+///
+/// This outlives any single connection, so that the node can be reconnected
+/// to without the players' sender to the node changing.
+struct Connection {
+    config: NodeConfig,
+    handle: Handle,
+    handler: Dispatcher,
+    // How the current connection was closed by the node, if it was.
+    close: RefCell<Option<CloseData>>,
+    metrics: Metrics,
+    middleware: Middleware,
+    node_from_user: Arc<Mutex<FairScheduler>>,
+    // Held so that the node's `user_from_node` receiver doesn't end.
+    _node_to_user: SyncSender<OwnedMessage>,
+    // Stops the task buffering messages while disconnected, if it's running.
+    parked: RefCell<Option<oneshot::Sender<()>>>,
+    player_manager: Arc<Mutex<AudioPlayerManager>>,
+    // Notified once the current connection is verified or rejected.
+    ready: RefCell<Option<oneshot::Sender<Result<(), Error>>>>,
+    replay: Arc<Mutex<ReplayBuffer>>,
+    rest: NodeRest,
+    // Sends the commands of players over REST, for when the node speaks v4.
+    rest_commands: UnboundedSender<RestMessage>,
+    // Resolves once the node is dropped.
+    shutdown: Shared<oneshot::Receiver<()>>,
+    state: Arc<RwLock<State>>,
+    subscribers: Arc<Mutex<Vec<UnboundedSender<NodeEvent>>>>,
+}
 
-            return Box::new(future::ok(None));
-        },
-    };
+/// The handler of a node's events, and the executor calling it.
+#[derive(Clone)]
+struct Dispatcher {
+    executor: Arc<Executor>,
+    handler: Arc<Mutex<Box<EventHandler>>>,
+    limit: Option<Arc<Mutex<HandlerLimit>>>,
+}
 
-    let op = match json.get("op").map(Opcode::deserialize) {
-        Some(Ok(op)) => op,
-        Some(Err(why)) => {
-            warn!("Failed to deserialize opcode: {:?}", why);
+/// The calls of a node's handler that are waiting for one of the in-flight
+/// handler futures to complete, as limited by
+/// [`NodeConfig::max_handler_futures`].
+///
+/// [`NodeConfig::max_handler_futures`]: struct.NodeConfig.html#method.max_handler_futures
+struct HandlerLimit {
+    in_flight: usize,
+    max: usize,
+    queued: VecDeque<Box<Task>>,
+}
 
-            return Box::new(future::ok(None));
-        },
-        None => {
-            warn!("No opcode present in payload: {:?}", json);
+/// How long connecting to one of the addresses of a node's host may take
+/// before the next address is tried.
+const CONNECT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(5);
 
-            return Box::new(future::ok(None));
-        },
-    };
+/// How long to wait before retrying the first failed reconnect to a stale
+/// node.
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
 
-    match op {
-        Opcode::PlayerUpdate => Box::new(handle_player_update(&json, &mut player_manager)),
-        Opcode::Stats => Box::new(handle_state(handler, json, state)),
-        Opcode::Event => Box::new(handle_event(handler, &json, &mut player_manager)),
-        _ => Box::new(future::ok(None)),
-    }
-}
+/// The longest to wait before retrying a failed reconnect to a stale node.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
 
-fn handle_event(handler: Rc<RefCell<Box<EventHandler>>>, json: &Value, player_manager: &mut Rc<RefCell<AudioPlayerManager>>)
-    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
-    let guild_id_str = json["guildId"]
-        .as_str()
-        .expect("invalid json guildId - should be str");
-    let guild_id = guild_id_str
-        .parse::<u64>()
-        .expect("could not parse json guild_id into u64");
-    let track = json["track"]
-        .as_str()
-        .expect("invalid json track - should be str");
-
-    let mut player_manager = match RefCell::try_borrow_mut(player_manager) {
-        Ok(player) => player,
-        Err(_) => {
-            warn!("Failed to get mutable reference to player manager");
+/// How far ahead of a player's position in milliseconds a marker may be for
+/// a timer to reach it, rather than the next player update. Lavalink sends
+/// player updates every 5 seconds by default.
+const MARKER_LOOKAHEAD: i64 = 5000;
 
-            return Box::new(future::ok(None));
-        },
-    };
+/// A stream to a node's websocket, before the handshake, and the address
+/// that it is connected to.
+type Connected = (Box<AsyncStream + Send>, SocketAddr);
 
-    let player = match player_manager.get_mut(&guild_id) {
-        Some(player) => player,
-        None => {
-            warn!(
-                "got invalid audio player update for guild {:?}",
-                guild_id,
-            );
+/// A client connected to a node's websocket, after the handshake.
+type Duplex = AsyncClient<Box<AsyncStream + Send>>;
 
-            return Box::new(future::ok(None));
-        },
-    };
+/// A message to the task sending the commands of players over REST.
+enum RestMessage {
+    /// A command of a player, with the senders to notify once it is sent.
+    Command(RestCommand, Vec<Ack>),
+    /// The node sent the ID of its session, so commands queued until then
+    /// can be sent.
+    Ready,
+}
 
-    match json["type"].as_str().expect("Err parsing type to str") {
-        "TrackEndEvent" => {
-            let reason = json["reason"]
-                .as_str()
-                .expect("invalid json reason - should be str");
-
-            // Set the player's track so nothing is playing, reset
-            // the time, and reset the position
-            player.track = None;
-            player.time = 0;
-            player.position = 0;
-
-            match handler.try_borrow_mut() {
-                Ok(mut handler) => {
-                    Box::new(handler.track_end(
-                        track.to_owned(),
-                        reason.to_owned(),
-                    ).map(|_| None))
-                },
-                Err(why) => {
-                    warn!("Err mutably borrowing handler: {:?}", why);
+/// A stream over a receiver shared between each connection to a node, so
+/// messages sent by the user are received by whichever connection is current.
+///
+/// Messages from players and acked messages are scheduled fairly between
+/// guilds, and messages buffered by the scheduler survive reconnects.
+struct SharedReceiver<S>(Arc<Mutex<S>>);
 
-                    Box::new(future::err(()))
-                },
-            }
-        },
-        "TrackExceptionEvent" => {
-            let error = json["error"]
-                .as_str()
-                .expect("invalid json error - should be str");
-
-            // TODO: determine if should keep playing
-
-            match handler.try_borrow_mut() {
-                Ok(mut handler) => {
-                    Box::new(handler.track_exception(
-                        track.to_owned(),
-                        error.to_owned(),
-                    ).map(|_| None))
-                },
-                Err(why) => {
-                    warn!("Err mutably borrowing handler: {:?}", why);
+impl<S: Stream<Error = ()>> Stream for SharedReceiver<S> {
+    type Item = S::Item;
+    type Error = ();
 
-                    Box::new(future::err(()))
-                },
-            }
-        },
-        "TrackStuckEvent" => {
-            let threshold_ms = json["thresholdMs"]
-                .as_i64()
-                .expect("invalid json thresholdMs - should be i64");
-
-            match handler.try_borrow_mut() {
-               Ok(mut handler) => {
-                    Box::new(handler.track_stuck(
-                        track.to_owned(),
-                        threshold_ms,
-                    ).map(|_| None))
-               },
-               Err(why) => {
-                   warn!("Err mutably borrowing handler: {:?}", why);
-
-                   Box::new(future::err(()))
-               },
-            }
-        },
-        other => {
-            warn!("Unexpected event type: {}", other);
+    fn poll(&mut self) -> Poll<Option<S::Item>, ()> {
+        match self.0.lock() {
+            Ok(mut receiver) => receiver.poll(),
+            Err(why) => {
+                event!(warn; "Err locking receiver from user: {:?}", why);
 
-            Box::new(future::ok(None))
-        },
+                Err(())
+            },
+        }
     }
 }
 
-fn handle_player_update(json: &Value, player_manager: &mut Rc<RefCell<AudioPlayerManager>>)
-    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
-    let guild_id_str = json["guildId"].as_str().unwrap();
-    let guild_id = guild_id_str.parse::<u64>().unwrap();
-    let state = json["state"].as_object().unwrap();
-    let time = state["time"].as_i64().unwrap();
-    let position = state["position"].as_i64().unwrap();
+/// A sink writing messages to the node, notifying the senders of acked
+/// messages once they are flushed.
+struct AckSink<S> {
+    inner: S,
+    pending: Vec<Ack>,
+}
 
-    let mut player_manager = match RefCell::try_borrow_mut(&player_manager) {
-        Ok(player) => player,
-        Err(_) => {
-            warn!("Failed to get mutable reference to player manager");
+impl<S: Sink<SinkItem = OwnedMessage>> Sink for AckSink<S> {
+    type SinkItem = Outgoing;
+    type SinkError = S::SinkError;
 
-            return Box::new(future::ok(None));
-        },
-    };
+    fn start_send(&mut self, (message, acks): Outgoing)
+        -> StartSend<Outgoing, S::SinkError> {
+        match self.inner.start_send(message)? {
+            AsyncSink::Ready => {
+                self.pending.extend(acks);
 
-    match player_manager.get_mut(&guild_id) {
-        Some(player) => {
-            player.time = time;
-            player.position = position;
-        },
-        None => {
-            warn!("Invalid player update received for guild {}", guild_id);
-        },
+                Ok(AsyncSink::Ready)
+            },
+            AsyncSink::NotReady(message) => Ok(AsyncSink::NotReady((message, acks))),
+        }
     }
 
-    Box::new(future::ok(None))
+    fn poll_complete(&mut self) -> Poll<(), S::SinkError> {
+        if let Async::NotReady = self.inner.poll_complete()? {
+            return Ok(Async::NotReady);
+        }
+
+        for ack in self.pending.drain(..) {
+            let _ = ack.send(Ok(()));
+        }
+
+        Ok(Async::Ready(()))
+    }
 }
 
-// todo: should this be needed?
-fn handle_state(_: Rc<RefCell<Box<EventHandler>>>, json: Value, state: &Rc<RefCell<State>>)
-    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+/// Connects to the node, spawning the tasks that run the connection onto the
+/// Core once it is established.
+///
+/// If the config has a [`ready_timeout`], this then waits for the node to
+/// verify the connection by sending its first stats.
+///
+/// [`ready_timeout`]: struct.NodeConfig.html#method.ready_timeout
+fn start(connection: Rc<Connection>) -> Box<Future<Item = (), Error = Error>> {
+    let mut headers = Headers::new();
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let ready_timeout = connection.config.ready_timeout;
 
-    match serde_json::from_value(json) {
-        Ok(parsed) => {
-            match state.try_borrow_mut() {
-                Ok(mut state) => {
-                    state.stats = Some(parsed);
-                },
-                Err(why) => {
-                    warn!("Err mutably borrowing state: {:?}", why);
+    *connection.ready.borrow_mut() = Some(ready_tx);
+
+    {
+        let config = &connection.config;
+
+        // Set the extra headers first, so that they can't replace the
+        // required headers.
+        for &(ref name, ref value) in &config.extra_headers {
+            headers.set_raw(name.clone(), vec![value.clone().into_bytes()]);
+        }
+
+        headers.set_raw("Authorization", vec![config.password.clone().into_bytes()]);
+        headers.set_raw("Num-Shards", vec![config.num_shards.to_string().into_bytes()]);
+        headers.set_raw("User-Id", vec![config.user_id.clone().into_bytes()]);
+
+        if let Some(ref key) = config.resume_key {
+            headers.set_raw("Resume-Key", vec![key.clone().into_bytes()]);
+        }
+
+        // Lavalink v4 requires clients to identify themselves, while older
+        // versions ignore it.
+        let name = concat!("lavalink-futures/", env!("CARGO_PKG_VERSION"));
+        headers.set_raw("Client-Name", vec![name.as_bytes().to_vec()]);
+    }
+
+    let ready_handle = connection.handle.clone();
+
+    // The version is recorded whenever the node responds, rather than holding
+    // up connecting.
+    connection.handle.spawn(probe_version(&connection));
+
+    let websocket_host = websocket_url(&connection);
+    let fallback = v4_fallback(&connection, &websocket_host);
+    let fallback_connection = Rc::clone(&connection);
+    let fallback_headers = headers.clone();
+
+    let done = handshake(&connection, websocket_host, &headers)
+        .or_else(move |why| -> Box<Future<Item = (Duplex, SocketAddr), Error = Error>> {
+            // Lavalink v4 rejects handshakes outside of its `/v4/websocket`
+            // path, so a node configured as v3 may have been upgraded.
+            let url = match fallback {
+                Some(url) => match *why.root() {
+                    Error::WebSocket(_) => url,
+                    _ => return Box::new(future::err(why)),
                 },
+                None => return Box::new(future::err(why)),
+            };
+
+            event!(
+                debug,
+                node = fallback_connection.config.websocket_host;
+                "Handshake rejected, retrying at {}: {:?}",
+                url,
+                why
+            );
+
+            let v4_connection = Rc::clone(&fallback_connection);
+            let done = handshake(&fallback_connection, url, &fallback_headers)
+                .map(move |connected| {
+                    match v4_connection.state.write() {
+                        Ok(mut state) => state.protocol_version = ProtocolVersion::V4,
+                        Err(why) => {
+                            let host = &v4_connection.config.websocket_host;
+
+                            event!(warn, node = host; "Err writing to state: {:?}", why);
+                        },
+                    }
+
+                    connected
+                })
+                // Report why the configured host failed, as the fallback is
+                // only a guess.
+                .map_err(move |_| why);
+
+            Box::new(done)
+        })
+        .map(move |(duplex, addr)| {
+            let host = &connection.config.websocket_host;
+
+            event!(debug, node = host, address = addr; "Node WS client connected");
+
+            match connection.state.write() {
+                Ok(mut state) => state.address = Some(addr),
+                Err(why) => event!(warn, node = host; "Err writing to state: {:?}", why),
             }
-        },
-        Err(why) => {
-            warn!("Failed to deserialize state payload: {:?}", why);
-        },
-    }
 
-    Box::new(future::ok(None))
+            let (sink, stream) = duplex.split();
+            let (sink_tx, sink_rx) = mpsc::unbounded();
+            let (abort_tx, abort_rx) = oneshot::channel();
+
+            mark_received(&connection);
+            set_status(&connection, NodeStatus::Connected);
+            reconnect_players(&connection);
+
+            if let Some(ref key) = connection.config.resume_key {
+                let op = OutboundOp::ConfigureResuming {
+                    key: key.clone(),
+                    timeout: connection.config.resume_timeout.unwrap_or(60),
+                };
+
+                event!(trace, node = connection.config.websocket_host; "Configuring resuming: {:?}", op);
+
+                match op.to_message() {
+                    Ok(msg) => if let Err(why) = sink_tx.unbounded_send(msg) {
+                        event!(
+                            warn,
+                            node = connection.config.websocket_host;
+                            "Err sending resume config to sink: {:?}",
+                            why
+                        );
+                    },
+                    Err(why) => {
+                        event!(
+                            warn,
+                            node = connection.config.websocket_host;
+                            "Err serializing resume config: {:?}",
+                            why
+                        );
+                    },
+                }
+            }
+
+            unpark(&connection);
+
+            let ws_connection = Rc::clone(&connection);
+            let reply_tx = sink_tx.clone();
+
+            let error_host = connection.config.websocket_host.clone();
+            let incoming = stream
+                .map_err(move |why| {
+                    event!(warn, node = error_host; "Err receiving from node: {:?}", why);
+                })
+                .filter_map(move |msg| {
+                    mark_received(&ws_connection);
+
+                    let host = &ws_connection.config.websocket_host;
+
+                    match msg {
+                        OwnedMessage::Close(data) => {
+                            event!(info, node = host; "Received a close: {:?}", data);
+
+                            mark_ready(&ws_connection, Err(close_error(data.as_ref())));
+                            *ws_connection.close.borrow_mut() = Some(CloseData::from(data.as_ref()));
+
+                            // Echo the code back, completing the closing
+                            // handshake.
+                            Some(OwnedMessage::Close(data))
+                        },
+                        OwnedMessage::Ping(data) => {
+                            event!(trace, node = host; "Received a ping: {:?}", data);
+
+                            Some(OwnedMessage::Pong(data))
+                        },
+                        OwnedMessage::Text(data) => {
+                            event!(trace, node = host; "Received text: {:?}", data);
+
+                            audit(&ws_connection, Direction::Inbound, data.as_bytes());
+
+                            dispatch(&ws_connection, data.as_bytes(), &reply_tx);
+
+                            None
+                        },
+                        OwnedMessage::Binary(data) => {
+                            event!(trace, node = host; "Received binary: {:?}", data);
+
+                            audit(&ws_connection, Direction::Inbound, &data);
+
+                            dispatch(&ws_connection, &data, &reply_tx);
+
+                            None
+                        },
+                        OwnedMessage::Pong(data) => {
+                            event!(trace, node = host; "Received a pong: {:?}", data);
+
+                            None
+                        },
+                    }
+                })
+                .map(|msg| Some((msg, Vec::new())))
+                // Mark the end of the node's stream, so that the connection
+                // ends rather than waiting on messages from the user.
+                .chain(stream::once(Ok(None)));
+
+            let outgoing = SharedReceiver(Arc::clone(&connection.node_from_user))
+                .select(sink_rx.map(|msg| (msg, Vec::new())))
+                .map(Some);
+
+            let middleware = connection.middleware.clone();
+            let middleware_host = connection.config.websocket_host.clone();
+            let rest_commands = connection.rest_commands.clone();
+            let rest_state = Arc::clone(&connection.state);
+            let send_audit = connection.config.audit.clone();
+            let send_host = connection.config.websocket_host.clone();
+            let sink_host = connection.config.websocket_host.clone();
+            let writer = incoming
+                .select(outgoing)
+                .take_while(|msg| Ok(msg.is_some()))
+                .filter_map(|msg| msg)
+                .filter_map(move |(msg, ack)| {
+                    match middleware.apply_outbound_message(msg) {
+                        Some(msg) => Some((msg, ack)),
+                        None => {
+                            event!(debug, node = middleware_host; "Middleware dropped a message");
+
+                            // Complete the deliveries of the dropped message,
+                            // as it has been handled.
+                            for tx in ack {
+                                let _ = tx.send(Ok(()));
+                            }
+
+                            None
+                        },
+                    }
+                })
+                .map(move |(msg, ack)| {
+                    event!(debug, node = send_host; "Sending message: {:?}", msg);
+
+                    if let Some(ref audit) = send_audit {
+                        match msg {
+                            OwnedMessage::Text(ref data) => {
+                                audit.record(&send_host, Direction::Outbound, data.as_bytes());
+                            },
+                            OwnedMessage::Binary(ref data) => {
+                                audit.record(&send_host, Direction::Outbound, data);
+                            },
+                            _ => {},
+                        }
+                    }
+
+                    (msg, ack)
+                })
+                .filter_map(move |(msg, ack)| {
+                    let (v4, filters) = rest_state
+                        .read()
+                        .map(|state| {
+                            let filters = state.server_version
+                                .as_ref()
+                                .map_or(true, |version| has_filters_op(version));
+
+                            (state.protocol_version == ProtocolVersion::V4, filters)
+                        })
+                        .unwrap_or((false, true));
+
+                    // Nodes older than v3.4 only have the equalizer.
+                    if !filters {
+                        if let Some(msg) = legacy_equalizer(&msg) {
+                            return Some((msg, ack));
+                        }
+                    }
+
+                    // The ops of players were removed in v4, so are sent over
+                    // REST instead.
+                    if v4 {
+                        if let Some(command) = RestCommand::from_message(&msg) {
+                            let message = RestMessage::Command(command, ack);
+
+                            if let Err(why) = rest_commands.unbounded_send(message) {
+                                event!(warn; "Err queueing player command: {:?}", why);
+                            }
+
+                            return None;
+                        }
+                    }
+
+                    Some((msg, ack))
+                })
+                .forward(AckSink {
+                    inner: sink.sink_map_err(move |why| {
+                        event!(warn, node = sink_host; "Err sending to node: {:?}", why);
+                    }),
+                    pending: Vec::new(),
+                })
+                .map(|_| ());
+
+            if let Some(heartbeat) = connection.config.heartbeat.clone() {
+                spawn_heartbeat(&connection, heartbeat, sink_tx, abort_tx);
+            }
+
+            let task_connection = Rc::clone(&connection);
+            let task = writer
+                .select2(abort_rx)
+                .then(|res| -> Box<Future<Item = bool, Error = ()>> {
+                    match res {
+                        // The heartbeat aborted the stale connection.
+                        Ok(Either::B(_)) => Box::new(future::ok(true)),
+                        // The heartbeat stopped without aborting, so keep the
+                        // connection running.
+                        Err(Either::B((_, writer))) => Box::new(writer.map(|_| false)),
+                        Ok(Either::A(_)) | Err(Either::A(_)) => Box::new(future::ok(false)),
+                    }
+                })
+                .map(move |reconnect| {
+                    let host = task_connection.config.websocket_host.clone();
+
+                    mark_ready(&task_connection, Err(Error::Closed));
+
+                    let close = task_connection.close.borrow_mut().take().unwrap_or_else(|| {
+                        if reconnect {
+                            CloseData::abnormal("The connection went stale")
+                        } else {
+                            CloseData::abnormal("The connection ended without a close frame")
+                        }
+                    });
+                    disconnect_players(&task_connection, &close);
+                    record_close(&task_connection, close);
+                    park(&task_connection);
+
+                    if !reconnect {
+                        event!(debug, node = host; "Connection to node ended");
+
+                        set_status(&task_connection, NodeStatus::Closed);
+
+                        return;
+                    }
+
+                    event!(info, node = host; "Reconnecting to stale node");
+
+                    reconnect_stale(task_connection, INITIAL_RECONNECT_DELAY);
+                });
+
+            spawn_until_shutdown(&connection, task);
+        })
+        .and_then(move |_| -> Box<Future<Item = (), Error = Error>> {
+            let timeout = match ready_timeout {
+                Some(timeout) => timeout,
+                None => return Box::new(future::ok(())),
+            };
+
+            let ready = ready_rx.from_err().and_then(|res| res);
+
+            rest::with_timeout(&ready_handle, Some(timeout), Box::new(ready))
+        });
+
+    Box::new(done)
+}
+
+/// Connects to a WebSocket host of the node and performs the handshake,
+/// resolving to the client and the address that it connected to.
+fn handshake(connection: &Connection, websocket_host: String, headers: &Headers)
+    -> Box<Future<Item = (Duplex, SocketAddr), Error = Error>> {
+    let builder = match ClientBuilder::new(&websocket_host) {
+        Ok(builder) => builder.custom_headers(headers),
+        Err(why) => return Box::new(future::err(From::from(why))),
+    };
+    let address = match NodeAddress::parse(&websocket_host) {
+        Ok(address) => address,
+        Err(why) => return Box::new(future::err(why)),
+    };
+
+    event!(trace, node = websocket_host; "Building node WS client & connecting");
+
+    let builder = match connection.config.websocket_hook {
+        Some(ref hook) => hook.apply(builder),
+        None => builder,
+    };
+
+    let handle = connection.handle.clone();
+    let root_certificates = connection.config.tls_root_certificates.clone();
+    let secure = address.scheme.map_or(false, |scheme| scheme.is_secure());
+    let port = address.port.unwrap_or(if secure { 443 } else { 80 });
+    let domain = address.host.clone();
+
+    let done = resolver::resolve(&address.host, port)
+        .and_then(move |addrs| connect_any(handle, interleave(addrs), None, websocket_host))
+        .and_then(move |(tcp, addr)| -> Box<Future<Item = Connected, Error = Error>> {
+            if !secure {
+                let stream: Box<AsyncStream + Send> = Box::new(tcp);
+
+                return Box::new(future::ok((stream, addr)));
+            }
+
+            let connector = match tls::connector(&root_certificates) {
+                Ok(connector) => connector,
+                Err(why) => return Box::new(future::err(why)),
+            };
+
+            let done = connector.connect_async(&domain, tcp).from_err().map(move |tls| {
+                let stream: Box<AsyncStream + Send> = Box::new(tls);
+
+                (stream, addr)
+            });
+
+            Box::new(done)
+        })
+        .and_then(move |(stream, addr)| {
+            builder
+                .async_connect_on(stream)
+                .map(move |(duplex, _)| (duplex, addr))
+                .from_err()
+        });
+
+    Box::new(done)
+}
+
+/// Writes a payload to the node's audit log, if it has one.
+fn audit(connection: &Connection, direction: Direction, payload: &[u8]) {
+    if let Some(ref audit) = connection.config.audit {
+        audit.record(&connection.config.websocket_host, direction, payload);
+    }
+}
+
+/// Stores how the connection was closed on the node's state and notifies the
+/// handler.
+fn record_close(connection: &Connection, close: CloseData) {
+    let host = &connection.config.websocket_host;
+
+    if close.is_normal() {
+        event!(debug, node = host; "Connection closed normally: {:?}", close);
+    } else {
+        event!(warn, node = host; "Connection closed abnormally: {:?}", close);
+    }
+
+    match connection.state.write() {
+        Ok(mut state) => state.last_close = Some(close.clone()),
+        Err(why) => event!(warn, node = host; "Err writing to state: {:?}", why),
+    }
+
+    let host = host.clone();
+
+    with_handler(&connection.handler, move |handler| handler.ws_close(&host, close));
+}
+
+/// Marks the players on the node as disconnected from it, notifying the
+/// handler of each player that was newly marked.
+fn disconnect_players(connection: &Connection, close: &CloseData) {
+    let host = &connection.config.websocket_host;
+
+    let players = lock_players(&connection.player_manager).mark_node_disconnected(host);
+
+    event!(debug, node = host, players = players.len(); "Marked players as disconnected");
+
+    for player in players {
+        let close = close.clone();
+
+        with_handler(&connection.handler, move |handler| {
+            handler.player_disconnected(player, close)
+        });
+    }
+}
+
+/// Marks the players on the node as reconnected to it, once a connection is
+/// established.
+fn reconnect_players(connection: &Connection) {
+    let host = &connection.config.websocket_host;
+
+    let count = lock_players(&connection.player_manager).mark_node_reconnected(host);
+
+    event!(debug, node = host, players = count; "Marked players as reconnected");
+}
+
+/// Notifies the pending [`start`] of whether the connection was verified, if
+/// it has not yet been notified.
+///
+/// [`start`]: fn.start.html
+fn mark_ready(connection: &Connection, result: Result<(), Error>) {
+    if let Some(tx) = connection.ready.borrow_mut().take() {
+        let _ = tx.send(result);
+    }
+}
+
+/// The error that a close frame received before the connection was verified
+/// represents.
+///
+/// Lavalink and the proxies in front of it close with a policy violation or an
+/// application-specific unauthorized code when the password is wrong.
+fn close_error(data: Option<&WebSocketCloseData>) -> Error {
+    match data.map(|data| data.status_code) {
+        Some(1008) | Some(4001) | Some(4003) | Some(4004) => Error::AuthenticationFailed,
+        _ => Error::Closed,
+    }
+}
+
+/// Queries the node's version via its `/version` REST endpoint, recording it
+/// and the version of the protocol that the node speaks in its state.
+///
+/// Nodes older than Lavalink v3.3 have no such endpoint, so failures are only
+/// logged, and the configured protocol version is kept.
+fn probe_version(connection: &Rc<Connection>) -> Box<Future<Item = (), Error = ()>> {
+    let connection = Rc::clone(connection);
+
+    // Don't hold up connecting to nodes whose REST API is unreachable.
+    let probe = rest::with_timeout(
+        &connection.handle,
+        Some(Duration::from_secs(5)),
+        connection.rest.send(Method::Get, "/version", None),
+    );
+
+    let done = probe.then(move |res| -> Result<(), ()> {
+        let node_host = &connection.config.websocket_host;
+
+        let version = match res {
+            Ok(body) => String::from_utf8_lossy(&body).trim().to_owned(),
+            Err(why) => {
+                event!(debug, node = node_host; "Err probing node version: {:?}", why);
+
+                return Ok(());
+            },
+        };
+
+        let protocol_version = match ProtocolVersion::from_server_version(&version) {
+            Some(protocol_version) => protocol_version,
+            None => {
+                event!(debug, node = node_host; "Node reported an unknown version: {}", version);
+
+                return Ok(());
+            },
+        };
+
+        event!(debug, node = node_host, version = version; "Probed node version");
+
+        match connection.state.write() {
+            Ok(mut state) => {
+                state.protocol_version = protocol_version;
+                state.server_version = Some(version);
+            },
+            Err(why) => {
+                event!(warn, node = node_host; "Err writing to state: {:?}", why);
+            },
+        }
+
+        Ok(())
+    });
+
+    Box::new(done)
+}
+
+/// The WebSocket host to connect to the node at.
+///
+/// This is the configured [`websocket_host`], with the `/v4/websocket` path
+/// if the node was found to speak v4 despite being configured for v3, and the
+/// host has no path of its own.
+///
+/// [`websocket_host`]: struct.NodeConfig.html#structfield.websocket_host
+fn websocket_url(connection: &Connection) -> String {
+    let host = &connection.config.websocket_host;
+    let v4 = connection.state
+        .read()
+        .map(|state| state.protocol_version == ProtocolVersion::V4)
+        .unwrap_or(false);
+
+    if !v4 || connection.config.protocol_version == ProtocolVersion::V4 || has_path(host) {
+        return host.clone();
+    }
+
+    v4_url(host)
+}
+
+/// The `/v4/websocket` path of a WebSocket host, keeping its query.
+fn v4_url(host: &str) -> String {
+    let (base, query) = match host.find('?') {
+        Some(idx) => host.split_at(idx),
+        None => (host, ""),
+    };
+
+    format!("{}/v4/websocket{}", base.trim_end_matches('/'), query)
+}
+
+/// The host to retry at if the handshake at the `websocket_url` is rejected.
+///
+/// This is only the case for a node configured as v3, whose host has no path
+/// of its own, and which isn't already being connected to at its v4 path.
+fn v4_fallback(connection: &Connection, websocket_url: &str) -> Option<String> {
+    let host = &connection.config.websocket_host;
+
+    if connection.config.protocol_version == ProtocolVersion::V4
+        || has_path(host)
+        || websocket_url != host {
+        return None;
+    }
+
+    Some(v4_url(host))
+}
+
+/// Whether a WebSocket host has a path, such as `/v4/websocket`, ignoring its
+/// query.
+fn has_path(host: &str) -> bool {
+    let base = host.split('?').next().unwrap_or(host);
+    let authority = base.find("://").map_or(0, |idx| idx + 3);
+
+    match base[authority..].find('/') {
+        Some(idx) => authority + idx + 1 < base.len(),
+        None => false,
+    }
+}
+
+/// Whether a version of Lavalink, as reported via its `/version` REST
+/// endpoint, has the `filters` op, which was added in v3.4.
+///
+/// Versions that can not be parsed are assumed to have it.
+fn has_filters_op(version: &str) -> bool {
+    let mut parts = version.trim().split('.').map(|part| part.parse::<u64>().ok());
+
+    match (parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor))) => (major, minor) >= (3, 4),
+        _ => true,
+    }
+}
+
+/// Rewrites a `filters` op as the `equalizer` op of Lavalink versions older
+/// than 3.4, which have no other filters.
+///
+/// As filters replace the previous filters, the bands that aren't set are
+/// reset. Returns `None` if the message isn't a `filters` op.
+fn legacy_equalizer(message: &OwnedMessage) -> Option<OwnedMessage> {
+    let payload = match *message {
+        OwnedMessage::Binary(ref data) => serde_json::from_slice::<Value>(data).ok()?,
+        OwnedMessage::Text(ref data) => serde_json::from_str::<Value>(data).ok()?,
+        _ => return None,
+    };
+
+    if payload.get("op")?.as_str()? != "filters" {
+        return None;
+    }
+
+    let set = payload
+        .get("equalizer")
+        .and_then(|bands| serde_json::from_value::<Vec<EqualizerBand>>(bands.clone()).ok())
+        .unwrap_or_default();
+    let bands = (0..15)
+        .map(|band| EqualizerBand {
+            band,
+            gain: set.iter().find(|set| set.band == band).map_or(0.0, |set| set.gain),
+        })
+        .collect::<Vec<_>>();
+
+    let payload = json!({
+        "op": "equalizer",
+        "guildId": payload.get("guildId")?,
+        "bands": bands,
+    });
+
+    Some(OwnedMessage::Text(payload.to_string()))
+}
+
+/// Orders addresses by alternating between address families, starting with
+/// the family of the first address, as in Happy Eyeballs.
+///
+/// This keeps an unreachable IPv6 network from delaying connecting via IPv4
+/// by the timeout of every IPv6 address.
+fn interleave(addrs: Vec<SocketAddr>) -> VecDeque<SocketAddr> {
+    let first_v6 = addrs.first().map_or(false, |addr| addr.is_ipv6());
+    let (mut first, mut second): (VecDeque<_>, VecDeque<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_v6);
+    let mut ordered = VecDeque::with_capacity(first.len() + second.len());
+
+    loop {
+        match (first.pop_front(), second.pop_front()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+/// Connects to each address in turn until one succeeds, giving each attempt
+/// up to [`CONNECT_ATTEMPT_TIMEOUT`].
+///
+/// Resolves to the error of the last attempt if none succeeded.
+fn connect_any(
+    handle: Handle,
+    mut addrs: VecDeque<SocketAddr>,
+    last_error: Option<Error>,
+    node_host: String,
+) -> Box<Future<Item = (TcpStream, SocketAddr), Error = Error>> {
+    let addr = match addrs.pop_front() {
+        Some(addr) => addr,
+        None => return Box::new(future::err(last_error.unwrap_or(Error::None))),
+    };
+
+    event!(trace, node = node_host, address = addr; "Connecting to address");
+
+    let attempt = TcpStream::connect(&addr, &handle).from_err();
+    let attempt = rest::with_timeout(&handle, Some(CONNECT_ATTEMPT_TIMEOUT), Box::new(attempt));
+
+    let done = attempt.then(move |res| {
+        let next: Box<Future<Item = (TcpStream, SocketAddr), Error = Error>> = match res {
+            Ok(tcp) => Box::new(future::ok((tcp, addr))),
+            Err(why) => {
+                event!(debug, node = node_host, address = addr; "Err connecting: {:?}", why);
+
+                connect_any(handle, addrs, Some(why), node_host)
+            },
+        };
+
+        next
+    });
+
+    Box::new(done)
+}
+
+/// Spawns a task sending the commands of players to a Lavalink v4 node via
+/// its REST API one at a time, so that they're made in the order that they
+/// were sent.
+///
+/// Commands are queued until the node has sent its session ID. The senders
+/// of a command are notified once it's accepted by the node, or of the error
+/// if it isn't.
+fn spawn_rest_commands(
+    handle: &Handle,
+    rest: NodeRest,
+    state: Arc<RwLock<State>>,
+    node_host: String,
+) -> UnboundedSender<RestMessage> {
+    let (tx, rx) = mpsc::unbounded::<RestMessage>();
+    // Commands issued before the node sent the ID of its session, which are
+    // sent in order once it has.
+    let mut waiting = VecDeque::new();
+
+    let done = rx.for_each(move |message| -> Box<Future<Item = (), Error = ()>> {
+        let session_id = state.read().ok().and_then(|state| state.session_id.clone());
+
+        if let RestMessage::Command(command, acks) = message {
+            if session_id.is_none() {
+                event!(
+                    debug,
+                    guild_id = command.guild_id(),
+                    node = node_host;
+                    "Queueing player command until the node is ready"
+                );
+            }
+
+            waiting.push_back((command, acks));
+        }
+
+        let session_id = match session_id {
+            Some(session_id) => session_id,
+            None => return Box::new(future::ok(())),
+        };
+
+        let node_host = node_host.clone();
+        let rest = rest.clone();
+        let commands = waiting.drain(..).collect::<Vec<_>>();
+
+        let done = stream::iter_ok(commands).for_each(move |(command, acks)| {
+            let node_host = node_host.clone();
+
+            rest.player_command(&session_id, &command).then(move |res| {
+                let guild_id = command.guild_id();
+
+                match res {
+                    Ok(()) => for ack in acks {
+                        let _ = ack.send(Ok(()));
+                    },
+                    Err(why) => {
+                        event!(
+                            warn,
+                            guild_id = guild_id,
+                            node = node_host;
+                            "Err sending player command: {:?}",
+                            why
+                        );
+
+                        // The error can only be sent to one of the commands
+                        // coalesced into this one, so the others are
+                        // canceled.
+                        if let Some(ack) = acks.into_iter().next() {
+                            let _ = ack.send(Err(why.with_guild(guild_id).with_node(node_host)));
+                        }
+                    },
+                }
+
+                Ok::<(), ()>(())
+            })
+        });
+
+        Box::new(done)
+    });
+
+    handle.spawn(done);
+
+    tx
+}
+
+/// Spawns a task which pings the node every heartbeat interval, checking
+/// whether anything has been received from the node within the timeout.
+///
+/// When the connection is first detected as stale, the handler is notified
+/// and, if configured, the connection is aborted via the `abort_tx` so that it
+/// is reconnected.
+///
+/// The task ends once pings can no longer be sent, as the connection ended.
+fn spawn_heartbeat(
+    connection: &Rc<Connection>,
+    heartbeat: HeartbeatConfig,
+    sink_tx: UnboundedSender<OwnedMessage>,
+    abort_tx: oneshot::Sender<()>,
+) {
+    let interval = match Interval::new(heartbeat.interval, &connection.handle) {
+        Ok(interval) => interval,
+        Err(why) => {
+            event!(
+                warn,
+                node = connection.config.websocket_host;
+                "Err creating heartbeat interval: {:?}",
+                why
+            );
+
+            return;
+        },
+    };
+
+    let task_connection = Rc::clone(connection);
+    let mut abort_tx = Some(abort_tx);
+
+    let task = interval
+        .map_err(|why| {
+            event!(warn; "Err ticking heartbeat: {:?}", why);
+        })
+        .for_each(move |_| {
+            let connection = &task_connection;
+
+            if mark_stale(connection, heartbeat.timeout) {
+                let host = &connection.config.websocket_host;
+
+                event!(warn, node = host; "Nothing received from node within the timeout");
+
+                let host = host.clone();
+
+                with_handler(&connection.handler, move |handler| handler.ws_stale(&host));
+
+                if heartbeat.reconnect {
+                    set_status(connection, NodeStatus::Reconnecting);
+
+                    if let Some(abort_tx) = abort_tx.take() {
+                        let _ = abort_tx.send(());
+                    }
+
+                    return Err(());
+                }
+            }
+
+            sink_tx.unbounded_send(OwnedMessage::Ping(Vec::new()))
+                .map_err(|_| {
+                    event!(trace; "Heartbeat stopped as the connection ended");
+                })
+        });
+
+    spawn_until_shutdown(connection, task);
+}
+
+/// Spawns a task running on the Core until it completes or the node is
+/// dropped, whichever is first.
+fn spawn_until_shutdown<F>(connection: &Connection, task: F)
+    where F: Future<Item = (), Error = ()> + 'static {
+    let host = connection.config.websocket_host.clone();
+
+    let task = task.select2(connection.shutdown.clone()).then(move |res| -> Result<(), ()> {
+        match res {
+            Ok(Either::B(_)) | Err(Either::B(_)) => {
+                event!(debug, node = host; "Node was dropped, ending its task");
+            },
+            Ok(Either::A(_)) | Err(Either::A(_)) => {},
+        }
+
+        Ok(())
+    });
+
+    connection.handle.spawn(task);
+}
+
+/// Reconnects to a node whose connection went stale.
+///
+/// If reconnecting fails with an error that isn't [fatal], it's retried after
+/// the delay, which doubles after each failure up to a minute.
+///
+/// [fatal]: ../enum.Error.html#method.is_fatal
+fn reconnect_stale(connection: Rc<Connection>, delay: Duration) {
+    let host = connection.config.websocket_host.clone();
+
+    connection.metrics.reconnect(&host);
+
+    let replay_connection = Rc::clone(&connection);
+    let failed_connection = Rc::clone(&connection);
+    let done = start(Rc::clone(&connection)).map(move |_| {
+        // Without resuming, the node has no knowledge of the players.
+        if replay_connection.config.resume_key.is_none() {
+            replay(&replay_connection);
+        }
+    }).or_else(move |why| -> Box<Future<Item = (), Error = ()>> {
+        if why.is_fatal() {
+            event!(warn, node = host; "Err reconnecting to node: {:?}", why);
+
+            set_status(&failed_connection, NodeStatus::Closed);
+
+            return Box::new(future::ok(()));
+        }
+
+        event!(
+            warn,
+            node = host;
+            "Err reconnecting to node, retrying in {:?}: {:?}",
+            delay,
+            why
+        );
+
+        let timer = match Timeout::new(delay, &failed_connection.handle) {
+            Ok(timer) => timer,
+            Err(why) => {
+                event!(warn, node = host; "Err creating reconnect timer: {:?}", why);
+
+                set_status(&failed_connection, NodeStatus::Closed);
+
+                return Box::new(future::ok(()));
+            },
+        };
+
+        let next = (delay * 2).min(MAX_RECONNECT_DELAY);
+        let done = timer.map(move |_| {
+            reconnect_stale(failed_connection, next);
+        }).map_err(|why| {
+            event!(warn; "Err waiting for reconnect timer: {:?}", why);
+        });
+
+        Box::new(done)
+    });
+
+    spawn_until_shutdown(&connection, done);
+}
+
+/// Replays the state of the players on the node over the current connection.
+fn replay(connection: &Connection) {
+    let host = &connection.config.websocket_host;
+
+    let count = lock_players(&connection.player_manager).replay_state(host);
+
+    event!(debug, node = host, players = count; "Replayed players");
+}
+
+/// Spawns a task buffering the messages sent by the user while the node is
+/// disconnected, until the next connection is established.
+///
+/// Nothing is spawned if the node isn't configured to buffer messages.
+fn park(connection: &Rc<Connection>) {
+    match connection.replay.lock() {
+        Ok(ref replay) if replay.is_enabled() => {},
+        Ok(_) => return,
+        Err(why) => {
+            event!(
+                warn,
+                node = connection.config.websocket_host;
+                "Err locking replay buffer: {:?}",
+                why
+            );
+
+            return;
+        },
+    }
+
+    let (stop_tx, stop_rx) = oneshot::channel();
+    *connection.parked.borrow_mut() = Some(stop_tx);
+
+    let host = connection.config.websocket_host.clone();
+    let replay = Arc::clone(&connection.replay);
+    let buffer = SharedReceiver(Arc::clone(&connection.node_from_user))
+        // Acked messages are dropped rather than buffered, canceling their
+        // sends.
+        .for_each(move |(msg, _)| {
+            match replay.lock() {
+                Ok(mut replay) => {
+                    if replay.push(msg) {
+                        event!(warn, node = host; "Replay buffer is full, dropped a message");
+                    }
+                },
+                Err(why) => event!(warn, node = host; "Err locking replay buffer: {:?}", why),
+            }
+
+            Ok(())
+        });
+
+    // The stop is polled first, so that no messages are buffered once the
+    // node is connected.
+    let done = stop_rx.select2(buffer).then(|_| -> Result<(), ()> { Ok(()) });
+
+    spawn_until_shutdown(connection, done);
+}
+
+/// Stops buffering messages sent by the user now that the node is connected.
+///
+/// If the session is being resumed, the buffered messages are put back at the
+/// front of the scheduler's queues, to be sent ahead of messages sent since.
+/// Otherwise they are dropped, as the state of each player is replayed
+/// instead.
+fn unpark(connection: &Connection) {
+    if let Some(stop_tx) = connection.parked.borrow_mut().take() {
+        let _ = stop_tx.send(());
+    }
+
+    let buffered = match connection.replay.lock() {
+        Ok(mut replay) => replay.take(),
+        Err(why) => {
+            event!(
+                warn,
+                node = connection.config.websocket_host;
+                "Err locking replay buffer: {:?}",
+                why
+            );
+
+            return;
+        },
+    };
+
+    if buffered.is_empty() {
+        return;
+    }
+
+    let host = &connection.config.websocket_host;
+
+    if connection.config.resume_key.is_none() {
+        event!(debug, node = host, messages = buffered.len(); "Dropping buffered messages");
+
+        return;
+    }
+
+    event!(debug, node = host, messages = buffered.len(); "Sending buffered messages");
+
+    match connection.node_from_user.lock() {
+        Ok(mut scheduler) => scheduler.requeue(buffered),
+        Err(why) => event!(warn, node = host; "Err locking receiver from user: {:?}", why),
+    }
+}
+
+/// Sets the status of the node, sending it to the node's event streams if it
+/// changed.
+fn set_status(connection: &Connection, status: NodeStatus) {
+    let changed = match connection.state.write() {
+        Ok(mut state) => {
+            let changed = state.status != status;
+            state.status = status;
+
+            changed
+        },
+        Err(why) => {
+            event!(
+                warn,
+                node = connection.config.websocket_host;
+                "Err writing to state: {:?}",
+                why
+            );
+
+            return;
+        },
+    };
+
+    if changed {
+        send_event(&connection.subscribers, NodeEvent::Status(status));
+    }
+}
+
+/// Records that a message was received from the node, marking it as no longer
+/// stale.
+fn mark_received(connection: &Connection) {
+    let recovered = match connection.state.write() {
+        Ok(mut state) => {
+            state.last_received = Some(Instant::now());
+            state.stale = false;
+
+            state.status == NodeStatus::Degraded
+        },
+        Err(why) => {
+            event!(
+                warn,
+                node = connection.config.websocket_host;
+                "Err writing to state: {:?}",
+                why
+            );
+
+            return;
+        },
+    };
+
+    if recovered {
+        set_status(connection, NodeStatus::Connected);
+    }
+}
+
+/// Marks the node as stale if nothing was received from it within the timeout.
+///
+/// Returns whether the node was not already marked as stale.
+fn mark_stale(connection: &Connection, timeout: Duration) -> bool {
+    {
+        let mut state = match connection.state.write() {
+            Ok(state) => state,
+            Err(why) => {
+                event!(
+                    warn,
+                    node = connection.config.websocket_host;
+                    "Err writing to state: {:?}",
+                    why
+                );
+
+                return false;
+            },
+        };
+
+        let elapsed = match state.last_received {
+            Some(last_received) => last_received.elapsed(),
+            None => return false,
+        };
+
+        if state.stale || elapsed < timeout {
+            return false;
+        }
+
+        state.stale = true;
+    }
+
+    set_status(connection, NodeStatus::Degraded);
+
+    true
+}
+
+/// Handles a message from the node on the Core, sending any reply over the
+/// connection.
+fn dispatch(
+    connection: &Rc<Connection>,
+    bytes: &[u8],
+    reply_tx: &UnboundedSender<OwnedMessage>,
+) {
+    let bytes = match connection.middleware.apply_inbound_bytes(bytes) {
+        Some(bytes) => bytes,
+        None => {
+            event!(trace, node = connection.config.websocket_host; "Middleware consumed a payload");
+
+            return;
+        },
+    };
+    let reply_tx = reply_tx.clone();
+
+    let done = handle_message(connection, &bytes).map(move |msg| {
+        if let Some(msg) = msg {
+            if let Err(why) = reply_tx.unbounded_send(msg) {
+                event!(warn; "Err sending to sink: {:?}", why);
+            }
+        }
+    });
+
+    connection.handle.spawn(done);
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.rest.close();
+
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+fn handle_message(connection: &Connection, bytes: &[u8])
+    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let handler = &connection.handler;
+    let node_host = &connection.config.websocket_host;
+
+    let _span = span!("message", node = node_host);
+
+    // Only the op is read up front, so that payloads are deserialized once,
+    // directly into their model, and filtered payloads aren't at all.
+    let peek = match serde_json::from_slice::<OpPeek>(bytes) {
+        Ok(peek) => peek,
+        Err(why) => {
+            event!(warn, node = node_host; "Error parsing received JSON: {:?}", why);
+
+            return dispatch_parse_error(handler, node_host, lossy(bytes), why);
+        },
+    };
+
+    if peek.op.is_empty() {
+        event!(warn, node = node_host; "No opcode present in payload: {}", lossy(bytes));
+
+        return Box::new(future::ok(None));
+    }
+
+    // The models accept a numeric guild ID as sent by some forks and plugins
+    // of Lavalink, so it is only rejected here if leniency is disabled.
+    if peek.guild_id.as_ref().map_or(false, Value::is_number)
+        && !connection.config.lenient_guild_ids {
+        let why = JsonError::custom("guildId is a number");
+
+        return dispatch_parse_error(handler, node_host, lossy(bytes), why);
+    }
+
+    let op_name = op_label(&peek.op);
+    let _span = span!("dispatch", node = node_host, op = op_name);
+
+    event!(trace, node = node_host, op = op_name; "Dispatching op");
+
+    connection.metrics.message_received(node_host, op_name);
+
+    let kind = match &peek.op[..] {
+        "event" => EventFilter::from_event_type(&peek.kind),
+        "playerUpdate" => EventFilter::PLAYER_UPDATE,
+        // The session is needed to control players, so is never filtered.
+        "ready" => EventFilter::empty(),
+        "stats" => EventFilter::STATS,
+        // Ops of plugins aren't known to the library.
+        _ => EventFilter::UNKNOWN,
+    };
+
+    if !connection.config.event_filter.contains(kind) {
+        event!(trace, node = node_host, op = op_name; "Skipping filtered op");
+
+        // The first stats still verify the connection.
+        if kind == EventFilter::STATS {
+            mark_ready(connection, Ok(()));
+        }
+
+        return Box::new(future::ok(None));
+    }
+
+    broadcast(&connection.subscribers, bytes);
+
+    match &peek.op[..] {
+        "event" => handle_event(connection, &peek.kind, bytes),
+        "playerUpdate" => handle_player_update(connection, bytes),
+        "ready" => handle_ready(connection, bytes),
+        "stats" => {
+            mark_ready(connection, Ok(()));
+
+            handle_state(connection, bytes)
+        },
+        op => dispatch_unknown_op(connection, op, bytes),
+    }
+}
+
+/// The op of a payload, the type if it is an event, and the guild ID if it has
+/// one, read without deserializing the rest of it.
+#[derive(Deserialize)]
+struct OpPeek<'a> {
+    #[serde(default, rename = "guildId")]
+    guild_id: Option<Value>,
+    #[serde(borrow, default, rename = "type")]
+    kind: Cow<'a, str>,
+    #[serde(borrow, default)]
+    op: Cow<'a, str>,
+}
+
+/// The name of an op in metrics and diagnostics.
+fn op_label(op: &str) -> &str {
+    match op {
+        "event" => "Event",
+        "playerUpdate" => "PlayerUpdate",
+        "ready" => "Ready",
+        "stats" => "Stats",
+        other => other,
+    }
+}
+
+/// A payload as a string, for diagnostics and parse errors.
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Passes a payload that the library doesn't handle to the handler.
+fn dispatch_unknown_op(connection: &Connection, op: &str, bytes: &[u8])
+    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let handler = &connection.handler;
+    let node_host = &connection.config.websocket_host;
+
+    event!(debug, node = node_host, op = op; "Dispatching unknown op");
+
+    let json = match serde_json::from_slice::<Value>(bytes) {
+        Ok(json) => json,
+        Err(why) => {
+            event!(warn, node = node_host; "Error parsing received JSON: {:?}", why);
+
+            return dispatch_parse_error(handler, node_host, lossy(bytes), why);
+        },
+    };
+
+    let node_host = node_host.clone();
+    let op = op.to_owned();
+
+    with_handler(handler, move |handler| {
+        handler.unknown_op(&node_host, &op, json)
+    })
+}
+
+/// Sends an event parsed from the payload to all of the streams created via
+/// [`Node::events`], removing streams that have been dropped.
+///
+/// [`Node::events`]: struct.Node.html#method.events
+fn broadcast(subscribers: &Arc<Mutex<Vec<UnboundedSender<NodeEvent>>>>, bytes: &[u8]) {
+    let mut subscribers = match subscribers.lock() {
+        Ok(subscribers) => subscribers,
+        Err(why) => {
+            event!(warn; "Err locking event subscribers: {:?}", why);
+
+            return;
+        },
+    };
+
+    // Don't bother parsing the event if nothing would receive it.
+    if subscribers.is_empty() {
+        return;
+    }
+
+    let event = match serde_json::from_slice::<Value>(bytes) {
+        Ok(json) => NodeEvent::from_json(json),
+        Err(why) => {
+            event!(warn; "Err parsing event for subscribers: {:?}", why);
+
+            return;
+        },
+    };
+
+    subscribers.retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+}
+
+/// Sends an event to all of the streams created via [`Node::events`],
+/// removing streams that have been dropped.
+///
+/// [`Node::events`]: struct.Node.html#method.events
+fn send_event(subscribers: &Arc<Mutex<Vec<UnboundedSender<NodeEvent>>>>, event: NodeEvent) {
+    match subscribers.lock() {
+        Ok(mut subscribers) => {
+            subscribers.retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+        },
+        Err(why) => {
+            event!(warn; "Err locking event subscribers: {:?}", why);
+        },
+    }
+}
+
+fn handle_event(connection: &Connection, kind: &str, bytes: &[u8])
+    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let handler = &connection.handler;
+    let node_host = &connection.config.websocket_host;
+    let player_manager = &connection.player_manager;
+
+    // Deserialize the type again to report why it is missing.
+    if kind.is_empty() {
+        if let Err(why) = serde_json::from_slice::<EventType>(bytes) {
+            event!(warn, node = node_host; "Failed to deserialize event type: {:?}", why);
+
+            return dispatch_parse_error(handler, node_host, lossy(bytes), why);
+        }
+    }
+
+    let result = match kind {
+        #[cfg(feature = "lyrics")]
+        "LyricsLineEvent" => serde_json::from_slice::<LyricsLineEvent>(bytes).map(|event| {
+            handle_lyrics_line(handler, event, player_manager)
+        }),
+        #[cfg(feature = "sponsorblock")]
+        "SegmentSkipped" => serde_json::from_slice::<SegmentSkippedEvent>(bytes).map(|event| {
+            handle_segment_skipped(handler, event, player_manager)
+        }),
+        "TrackEndEvent" => serde_json::from_slice::<TrackEndEvent>(bytes).map(|event| {
+            handle_track_end(handler, event, player_manager)
+        }),
+        "TrackExceptionEvent" => serde_json::from_slice::<TrackExceptionEvent>(bytes).map(|event| {
+            handle_track_exception(handler, event, player_manager)
+        }),
+        "TrackStartEvent" => serde_json::from_slice::<TrackStartEvent>(bytes).map(|event| {
+            handle_track_start(handler, event, player_manager)
+        }),
+        "TrackStuckEvent" => serde_json::from_slice::<TrackStuckEvent>(bytes).map(|event| {
+            handle_track_stuck(handler, event, player_manager)
+        }),
+        "WebSocketClosedEvent" => serde_json::from_slice::<WebSocketClosedEvent>(bytes).map(|event| {
+            handle_websocket_closed(handler, event, player_manager)
+        }),
+        other => {
+            event!(debug, node = node_host, event = other; "Unknown event type");
+
+            return dispatch_unknown_op(connection, "event", bytes);
+        },
+    };
+
+    match result {
+        Ok(future) => {
+            connection.metrics.event_dispatched(node_host, kind);
+
+            future
+        },
+        Err(why) => {
+            event!(warn, node = node_host, event = kind; "Failed to deserialize event: {:?}", why);
+
+            dispatch_parse_error(handler, node_host, lossy(bytes), why)
+        },
+    }
+}
+
+#[cfg(feature = "lyrics")]
+fn handle_lyrics_line(
+    handler: &Dispatcher,
+    event: LyricsLineEvent,
+    player_manager: &Arc<Mutex<AudioPlayerManager>>,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let player = match lock_players(player_manager).get(&event.guild_id) {
+        Some(player) => player.clone(),
+        None => {
+            event!(warn, guild_id = event.guild_id; "Received lyrics line for unknown guild");
+
+            return Box::new(future::ok(None));
+        },
+    };
+
+    with_handler(handler, move |handler| {
+        handler.lyrics_line(player, event.line, event.skipped)
+    })
+}
+
+#[cfg(feature = "sponsorblock")]
+fn handle_segment_skipped(
+    handler: &Dispatcher,
+    event: SegmentSkippedEvent,
+    player_manager: &Arc<Mutex<AudioPlayerManager>>,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let player = match lock_players(player_manager).get(&event.guild_id) {
+        Some(player) => player.clone(),
+        None => {
+            event!(warn, guild_id = event.guild_id; "Received skipped segment for unknown guild");
+
+            return Box::new(future::ok(None));
+        },
+    };
+
+    with_handler(handler, move |handler| handler.segment_skipped(player, event.segment))
+}
+
+fn handle_track_end(
+    handler: &Dispatcher,
+    event: TrackEndEvent,
+    player_manager: &Arc<Mutex<AudioPlayerManager>>,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    // The player manager's lock must be released before calling the handler
+    // and listeners, as they may lock it themselves.
+    let (data, ended) = {
+        let mut player_manager = lock_players(player_manager);
+
+        let player = match player_manager.get_mut(&event.guild_id) {
+            Some(player) => player,
+            None => {
+                event!(warn, guild_id = event.guild_id; "Received track end for unknown guild");
+
+                return Box::new(future::ok(None));
+            },
+        };
+
+        // A track played without replacing the one that ended was ignored.
+        if player.pending_track.take().is_some() {
+            player.play_status = PlayStatus::Ignored;
+        }
+
+        player.record_track_end(event.track.clone(), event.reason);
+
+        // Set the player's track so nothing is playing, reset
+        // the time, and reset the position
+        player.track = None;
+        player.track_started = false;
+        player.time = 0;
+        player.position = 0;
+
+        // The listeners are called with the player as it was when the track
+        // ended, before the next track is played.
+        let ended = if player.listeners().is_empty() {
+            None
+        } else {
+            Some(player.clone())
+        };
+
+        let data = player.track_data.take();
+
+        if event.reason.may_start_next() {
+            if let Err(why) = player.play_queued() {
+                event!(warn, guild_id = event.guild_id; "Err playing queued track: {:?}", why);
+            }
+        }
+
+        (data, ended)
+    };
+
+    if let Some(player) = ended {
+        for listener in player.listeners() {
+            listener.track_end(&player, &event.track, event.reason);
+        }
+    }
+
+    with_handler(handler, move |handler| {
+        handler.track_end_with_data(event.track, event.reason, data)
+    })
+}
+
+fn handle_track_exception(
+    handler: &Dispatcher,
+    event: TrackExceptionEvent,
+    player_manager: &Arc<Mutex<AudioPlayerManager>>,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let data = match lock_players(player_manager).get(&event.guild_id) {
+        Some(player) => player.track_data.clone(),
+        None => {
+            event!(warn, guild_id = event.guild_id; "Received track exception for unknown guild");
+
+            return Box::new(future::ok(None));
+        },
+    };
+
+    let track = event.track.clone();
+    let exception = event.into_exception();
+
+    with_handler(handler, move |handler| {
+        handler.track_exception_with_data(track, exception, data)
+    })
+}
+
+fn handle_track_start(
+    handler: &Dispatcher,
+    event: TrackStartEvent,
+    player_manager: &Arc<Mutex<AudioPlayerManager>>,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let started = {
+        let mut player_manager = lock_players(player_manager);
+
+        let player = match player_manager.get_mut(&event.guild_id) {
+            Some(player) => player,
+            None => {
+                event!(warn, guild_id = event.guild_id; "Received track start for unknown guild");
+
+                return Box::new(future::ok(None));
+            },
+        };
+
+        if let Some(pending) = player.pending_track.take() {
+            if pending == event.track {
+                player.play_status = PlayStatus::Accepted;
+                player.track_started = false;
+                player.rearm_markers(0);
+            } else {
+                player.play_status = PlayStatus::Ignored;
+            }
+        }
+
+        player.track = Some(event.track.clone());
+
+        if start_track(player) {
+            Some(player.clone())
+        } else {
+            None
+        }
+    };
+
+    match started {
+        Some(player) => dispatch_track_start(handler, player, event.track),
+        // The start was already synthesized from a player update.
+        None => Box::new(future::ok(None)),
+    }
+}
+
+fn handle_track_stuck(
+    handler: &Dispatcher,
+    event: TrackStuckEvent,
+    player_manager: &Arc<Mutex<AudioPlayerManager>>,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    // The player manager's lock must be released before calling the handler,
+    // as the handler may lock it itself.
+    {
+        let mut player_manager = lock_players(player_manager);
+
+        let player = match player_manager.get_mut(&event.guild_id) {
+            Some(player) => player,
+            None => {
+                event!(warn, guild_id = event.guild_id; "Received track stuck for unknown guild");
+
+                return Box::new(future::ok(None));
+            },
+        };
+
+        match player.recover_stuck(&event.track) {
+            Ok(Some(action)) => {
+                event!(debug, guild_id = event.guild_id; "Recovered stuck track: {:?}", action);
+            },
+            Ok(None) => {},
+            Err(why) => {
+                event!(warn, guild_id = event.guild_id; "Err recovering stuck track: {:?}", why);
+            },
+        }
+    }
+
+    with_handler(handler, move |handler| {
+        handler.track_stuck(event.track, event.threshold_ms)
+    })
+}
+
+fn handle_websocket_closed(
+    handler: &Dispatcher,
+    event: WebSocketClosedEvent,
+    player_manager: &Arc<Mutex<AudioPlayerManager>>,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let player = match lock_players(player_manager).get(&event.guild_id) {
+        Some(player) => player.clone(),
+        None => {
+            event!(warn, guild_id = event.guild_id; "Received websocket close for unknown guild");
+
+            return Box::new(future::ok(None));
+        },
+    };
+
+    with_handler(handler, move |handler| {
+        handler.websocket_closed(player, event.code, event.reason, event.by_remote)
+    })
+}
+
+fn handle_player_update(connection: &Connection, bytes: &[u8])
+    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let handler = &connection.handler;
+    let node_host = &connection.config.websocket_host;
+    let player_manager = &connection.player_manager;
+
+    let update = match serde_json::from_slice::<PlayerUpdate>(bytes) {
+        Ok(update) => update,
+        Err(why) => {
+            event!(warn, node = node_host; "Failed to deserialize player update: {:?}", why);
+
+            return dispatch_parse_error(handler, node_host, lossy(bytes), why);
+        },
+    };
+
+    let mut started = None;
+    let mut reached = None;
+    let mut known = false;
+
+    {
+        let mut player_manager = lock_players(player_manager);
+
+        match player_manager.get_mut(&update.guild_id) {
+            Some(player) => {
+                player.time = update.state.time;
+                player.position = update.state.position;
+                known = true;
+
+                if let Some(threshold) = connection.config.position_sync_threshold {
+                    match player.reconcile_position(update.state.position, threshold) {
+                        Ok(Some(position)) => event!(
+                            debug,
+                            node = node_host,
+                            guild_id = update.guild_id;
+                            "Seeked player from {} to {} after replay",
+                            update.state.position,
+                            position
+                        ),
+                        Ok(None) => {},
+                        Err(why) => event!(
+                            warn,
+                            node = node_host,
+                            guild_id = update.guild_id;
+                            "Err reconciling player position: {:?}",
+                            why
+                        ),
+                    }
+                }
+
+                // Older versions of Lavalink don't send a TrackStartEvent, so
+                // synthesize one from the first update after a play.
+                if let Some(track) = player.track.clone() {
+                    if start_track(player) {
+                        started = Some((player.clone(), track));
+                    }
+                }
+
+                let position = player.position;
+                let names = player.reach_markers(position);
+
+                if !names.is_empty() {
+                    reached = Some((player.clone(), names));
+                }
+
+                schedule_markers(connection, player);
+            },
+            None => {
+                event!(
+                    warn,
+                    node = node_host,
+                    guild_id = update.guild_id;
+                    "Invalid player update received for unknown guild"
+                );
+            },
+        }
+    }
+
+    let start = match started {
+        Some((player, track)) => dispatch_track_start(handler, player, track),
+        None => Box::new(future::ok(None)),
+    };
+
+    if !known {
+        return start;
+    }
+
+    let markers = match reached {
+        Some((player, names)) => dispatch_markers(handler, player, names),
+        None => Box::new(future::ok(None)),
+    };
+    let (guild_id, time, position) = (update.guild_id, update.state.time, update.state.position);
+    let progress = with_handler(handler, move |handler| {
+        handler.player_update(guild_id, time, position)
+    });
+
+    Box::new(start.join3(markers, progress).map(|_| None))
+}
+
+fn handle_ready(connection: &Connection, bytes: &[u8])
+    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let handler = &connection.handler;
+    let node_host = &connection.config.websocket_host;
+
+    let ready = match serde_json::from_slice::<Ready>(bytes) {
+        Ok(ready) => ready,
+        Err(why) => {
+            event!(warn, node = node_host; "Failed to deserialize ready payload: {:?}", why);
+
+            return dispatch_parse_error(handler, node_host, lossy(bytes), why);
+        },
+    };
+
+    event!(
+        debug,
+        node = node_host,
+        resumed = ready.resumed;
+        "Received session ID"
+    );
+
+    match connection.state.write() {
+        Ok(mut state) => {
+            state.session_id = Some(ready.session_id.clone());
+        },
+        Err(why) => {
+            event!(warn, node = node_host; "Err writing to state: {:?}", why);
+        },
+    }
+
+    // Send the commands of players that were issued before the session ID
+    // was known.
+    if let Err(why) = connection.rest_commands.unbounded_send(RestMessage::Ready) {
+        event!(warn, node = node_host; "Err notifying player commands of the session: {:?}", why);
+    }
+
+    let node_host = node_host.clone();
+
+    with_handler(handler, move |handler| {
+        handler.ready(&node_host, ready.resumed, &ready.session_id)
+    })
+}
+
+fn handle_state(connection: &Connection, bytes: &[u8])
+    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let handler = &connection.handler;
+    let node_host = &connection.config.websocket_host;
+
+    let parsed = match serde_json::from_slice::<NodeStats>(bytes) {
+        Ok(parsed) => parsed,
+        Err(why) => {
+            event!(warn, node = node_host; "Failed to deserialize state payload: {:?}", why);
+
+            return dispatch_parse_error(handler, node_host, lossy(bytes), why);
+        },
+    };
+
+    let penalty = connection.config.penalty.calculate(&parsed);
+
+    connection.metrics.penalty(node_host, penalty);
+
+    match connection.state.write() {
+        Ok(mut state) => {
+            state.history.push(parsed.clone());
+            state.penalty = Some(penalty);
+            state.stats = Some(parsed.clone());
+        },
+        Err(why) => {
+            event!(warn, node = node_host; "Err writing to state: {:?}", why);
+        },
+    }
+
+    let node_host = node_host.clone();
+
+    with_handler(handler, move |handler| {
+        handler.stats_update(&node_host, parsed)
+    })
+}
+
+/// Marks the player's current track as started.
+///
+/// Returns whether the track was not already marked as started, in which case
+/// the start should be dispatched via [`dispatch_track_start`].
+///
+/// [`dispatch_track_start`]: fn.dispatch_track_start.html
+fn start_track(player: &mut AudioPlayer) -> bool {
+    if player.track_started {
+        return false;
+    }
+
+    player.track_started = true;
+
+    if let Some(ref track) = player.track {
+        player.acknowledge_start(track);
+    }
+
+    true
+}
+
+/// Spawns a timer for each of a player's markers that its estimated position
+/// is expected to reach before its next player update, reaching them once
+/// due.
+///
+/// The markers aren't reached by a timer if the player received another
+/// update, seeked, was paused, or started another track meanwhile, as that
+/// update or change checks the markers instead.
+fn schedule_markers(connection: &Connection, player: &AudioPlayer) {
+    if player.paused || player.node_disconnected || player.track.is_none() {
+        return;
+    }
+
+    let delays = player.markers
+        .iter()
+        .filter(|marker| !marker.reached)
+        .map(|marker| marker.position - player.position)
+        .filter(|&delay| delay > 0 && delay <= MARKER_LOOKAHEAD);
+
+    for delay in delays {
+        let timeout = match Timeout::new(Duration::from_millis(delay as u64), &connection.handle) {
+            Ok(timeout) => timeout,
+            Err(why) => {
+                event!(
+                    warn,
+                    node = connection.config.websocket_host,
+                    guild_id = player.guild_id;
+                    "Err creating marker timer: {:?}",
+                    why
+                );
+
+                continue;
+            },
+        };
+
+        let guild_id = player.guild_id;
+        let handler = connection.handler.clone();
+        let player_manager = Arc::clone(&connection.player_manager);
+        let (position, time, track) = (player.position, player.time, player.track.clone());
+
+        let done = timeout.then(move |_| {
+            let reached = lock_players(&player_manager).get_mut(&guild_id).and_then(|player| {
+                // A seek moves the position without necessarily moving the
+                // time of the last update, so both are compared.
+                let unchanged = player.time == time
+                    && player.position == position
+                    && player.track == track
+                    && !player.paused
+                    && !player.node_disconnected;
+
+                if !unchanged {
+                    return None;
+                }
+
+                let names = player.reach_markers(position + delay);
+
+                if names.is_empty() {
+                    None
+                } else {
+                    Some((player.clone(), names))
+                }
+            });
+
+            match reached {
+                Some((player, names)) => dispatch_markers(&handler, player, names),
+                None => Box::new(future::ok(None)),
+            }
+        }).map(|_| ());
+
+        connection.handle.spawn(done);
+    }
+}
+
+fn dispatch_markers(
+    handler: &Dispatcher,
+    player: AudioPlayer,
+    names: Vec<String>,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let called = names
+        .into_iter()
+        .map(|name| {
+            let player = player.clone();
+
+            with_handler(handler, move |handler| handler.marker_reached(player, name))
+        })
+        .collect::<Vec<_>>();
+
+    Box::new(future::join_all(called).map(|_| None))
+}
+
+/// Calls the listeners of the player and the handler for the start of a
+/// track, which must be done without the player manager's lock held.
+fn dispatch_track_start(
+    handler: &Dispatcher,
+    player: AudioPlayer,
+    track: String,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    for listener in player.listeners() {
+        listener.track_start(&player, &track);
+    }
+
+    with_handler(handler, move |handler| handler.track_start(player, track))
+}
+
+fn dispatch_parse_error(
+    handler: &Dispatcher,
+    node_host: &str,
+    payload: String,
+    error: JsonError,
+) -> Box<Future<Item = Option<OwnedMessage>, Error = ()>> {
+    let node_host = node_host.to_owned();
+
+    with_handler(handler, move |handler| {
+        handler.parse_error(&node_host, payload, Error::Json(error))
+    })
+}
+
+/// Calls a tymethod on the handler via its executor, resolving immediately
+/// to not reply with a message.
+fn with_handler<F>(handler: &Dispatcher, f: F)
+    -> Box<Future<Item = Option<OwnedMessage>, Error = ()>>
+    where F: FnOnce(&mut Box<EventHandler>) -> Box<Future<Item = (), Error = Error>>
+        + Send
+        + 'static {
+    let called = Arc::clone(&handler.handler);
+    let task: Box<Task> = Box::new(move || call_handler(&called, f));
+
+    match handler.limit {
+        Some(ref limit) => execute_limited(&handler.executor, limit, task),
+        None => handler.executor.execute(task),
+    }
+
+    Box::new(future::ok(None))
+}
+
+/// Executes a task if fewer than the maximum number of handler futures are
+/// in flight, and otherwise queues it until one completes.
+fn execute_limited(executor: &Arc<Executor>, limit: &Arc<Mutex<HandlerLimit>>, task: Box<Task>) {
+    match limit.lock() {
+        Ok(mut limit) => {
+            if limit.in_flight >= limit.max {
+                limit.queued.push_back(task);
+
+                return;
+            }
+
+            limit.in_flight += 1;
+        },
+        Err(why) => event!(warn; "Err locking handler limit: {:?}", why),
+    }
+
+    execute_counted(executor, limit, task);
+}
+
+/// Executes a task counted as in flight, executing the next queued task in
+/// its place once its future completes.
+fn execute_counted(executor: &Arc<Executor>, limit: &Arc<Mutex<HandlerLimit>>, task: Box<Task>) {
+    let next_executor = Arc::clone(executor);
+    let limit = Arc::clone(limit);
+
+    executor.execute(Box::new(move || -> Box<Future<Item = (), Error = ()>> {
+        Box::new(task.run().then(move |_| {
+            let next = match limit.lock() {
+                Ok(mut limit) => {
+                    let next = limit.queued.pop_front();
+
+                    if next.is_none() {
+                        limit.in_flight -= 1;
+                    }
+
+                    next
+                },
+                Err(why) => {
+                    event!(warn; "Err locking handler limit: {:?}", why);
+
+                    None
+                },
+            };
+
+            if let Some(next) = next {
+                execute_counted(&next_executor, &limit, next);
+            }
+
+            Ok::<(), ()>(())
+        }))
+    }));
+}
+
+/// Locks the handler and calls a tymethod on it.
+///
+/// If the resultant future resolves an error, it is passed to the handler's
+/// [`handler_error`].
+///
+/// [`handler_error`]: ../trait.EventHandler.html#method.handler_error
+fn call_handler<F>(handler: &Arc<Mutex<Box<EventHandler>>>, f: F)
+    -> Box<Future<Item = (), Error = ()>>
+    where F: FnOnce(&mut Box<EventHandler>) -> Box<Future<Item = (), Error = Error>> {
+    let done = match handler.lock() {
+        Ok(mut handler) => f(&mut handler),
+        Err(why) => {
+            event!(warn; "Err locking handler: {:?}", why);
+
+            return Box::new(future::err(()));
+        },
+    };
+
+    let handler = Arc::clone(handler);
+
+    Box::new(done.or_else(move |why| -> Result<(), ()> {
+        match handler.lock() {
+            Ok(mut handler) => handler.handler_error(why),
+            Err(lock_why) => {
+                event!(warn; "Err locking handler for error {:?}: {:?}", why, lock_why);
+            },
+        }
+
+        Ok(())
+    }))
 }
@@ -1,23 +1,56 @@
-use futures::Future;
-use std::collections::HashMap;
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::i32;
-use super::{Node, NodeConfig};
-use websocket::async::Handle;
-use ::player::AudioPlayerManager;
-use ::{Error, EventHandler};
+use futures::sync::mpsc::{self, Sender as MpscSender, UnboundedSender};
+use futures::{Future, Stream, future, stream};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use super::{ClusterStats, ManagerEvent, Node, NodeConfig, NodeEvent, NodeStatus};
+use tokio_core::reactor::{Handle, Remote};
+use websocket::OwnedMessage;
+use ::executor::{Executor, RemoteExecutor};
+use ::metrics::{Exposition, Metrics, MetricsSink, NoopSink};
+use ::middleware::{InboundMiddleware, Middleware, OutboundMiddleware};
+use ::model::{GuildId, NodeStats};
+use ::player::{
+    AudioPlayer,
+    AudioPlayerHandle,
+    AudioPlayerManager,
+    PlayOptions,
+    PlayerSnapshot,
+    VoiceUpdate,
+    lock_players,
+};
+use ::{Error, EventHandler, HandlerChain, remote};
 
 /// A struct responsible for connecting to Lavalink nodes and providing
 /// shortcuts for audio player usage.
+///
+/// The manager is `Send` and `Sync`, and so can be shared across threads. Nodes
+/// are always connected on the tokio Core that the manager was created with.
+///
+/// Deployments running multiple bot users against the same nodes can keep the
+/// nodes and players of each bot user apart as [tenants] of one manager.
+///
+/// [tenants]: #method.add_tenant
 pub struct NodeManager {
-    handle: Handle,
-    handler: Rc<RefCell<Box<EventHandler>>>,
+    remote: Remote,
+    draining: Mutex<HashSet<String>>,
+    events: Arc<Mutex<Vec<UnboundedSender<ManagerEvent>>>>,
+    executor: Arc<Executor>,
+    handler: Arc<Mutex<Box<EventHandler>>>,
+    metrics: Metrics,
+    middleware: Middleware,
+    routes: Mutex<HashMap<u64, String>>,
+    stats: Arc<Mutex<StatsAggregate>>,
     /// HashMap of nodes, keyed by the websocket host.
     pub nodes: HashMap<String, Node>,
     /// The player manager holding all of the audio players for nodes managed
     /// under the instance of a `NodeManager`.
-    pub player_manager: Rc<RefCell<AudioPlayerManager>>,
+    pub player_manager: Arc<Mutex<AudioPlayerManager>>,
+    /// HashMap of the managers of [tenants], keyed by the ID of their bot
+    /// user.
+    ///
+    /// [tenants]: #method.add_tenant
+    pub tenants: HashMap<String, NodeManager>,
 }
 
 impl NodeManager {
@@ -27,72 +60,354 @@ impl NodeManager {
     /// implementing the [`EventHandler`] trait.
     ///
     /// [`EventHandler`]: ../trait.EventHandler.html
-    pub fn new(handle: Handle, handler: RefCell<Box<EventHandler>>) -> Self {
+    pub fn new(handle: Handle, handler: Mutex<Box<EventHandler>>) -> Self {
+        Self::with_metrics(handle, handler, Box::new(NoopSink))
+    }
+
+    /// Creates a new NodeManager which emits metrics about its nodes into the
+    /// given sink.
+    ///
+    /// Refer to the [`metrics`] module for the metrics that are emitted.
+    ///
+    /// [`metrics`]: ../metrics/index.html
+    pub fn with_metrics(
+        handle: Handle,
+        handler: Mutex<Box<EventHandler>>,
+        sink: Box<MetricsSink>,
+    ) -> Self {
         Self {
             nodes: HashMap::new(),
-            player_manager: Rc::new(RefCell::new(AudioPlayerManager::default())),
-            handle,
-            handler: Rc::new(handler),
+            player_manager: Arc::new(Mutex::new(AudioPlayerManager::default())),
+            remote: handle.remote().clone(),
+            draining: Mutex::new(HashSet::new()),
+            events: Arc::new(Mutex::new(Vec::new())),
+            executor: Arc::new(RemoteExecutor::new(handle.remote().clone())),
+            handler: Arc::new(handler),
+            metrics: Metrics::new(sink),
+            middleware: Middleware::new(),
+            routes: Mutex::new(HashMap::new()),
+            stats: Arc::new(Mutex::new(StatsAggregate::default())),
+            tenants: HashMap::new(),
         }
     }
 
+    /// Adds a handler to be called for events after the existing handlers,
+    /// such as to install an application's handler alongside one installed
+    /// by a library.
+    ///
+    /// The handler is added to the end of the existing [`HandlerChain`] if
+    /// it short-circuits as given. Otherwise the existing handler and the new
+    /// one are combined into a new chain, which short-circuits if
+    /// `short_circuit` is set. Refer to it for how the results of the
+    /// handlers are combined.
+    ///
+    /// [`HandlerChain`]: ../struct.HandlerChain.html
+    pub fn add_handler(&self, handler: Box<EventHandler>, short_circuit: bool)
+        -> Result<(), Error> {
+        let mut current = self.handler.lock()?;
+
+        if let Some(chain) = current.as_handler_chain() {
+            if chain.short_circuit() == short_circuit {
+                chain.push(handler);
+
+                return Ok(());
+            }
+        }
+
+        // The new chain takes the place of the existing handler, which is
+        // then moved into it.
+        let existing = mem::replace(&mut *current, Box::new(HandlerChain::new(short_circuit)));
+
+        if let Some(chain) = current.as_handler_chain() {
+            chain.push(existing);
+            chain.push(handler);
+        }
+
+        Ok(())
+    }
+
+    /// Adds a middleware to be called with every payload received from the
+    /// managed nodes before it is dispatched, after the existing middleware.
+    ///
+    /// The middleware can change the payload, or consume it so that it isn't
+    /// dispatched, such as to handle the ops of experimental server features.
+    /// It applies to the nodes that are already connected as well as those
+    /// added later. Refer to the [`middleware`] module for more information.
+    ///
+    /// [`middleware`]: ../middleware/index.html
+    pub fn add_inbound_middleware(&self, middleware: InboundMiddleware)
+        -> Result<(), Error> {
+        self.middleware.add_inbound(middleware)
+    }
+
+    /// Adds a middleware to be called with every op sent to the managed nodes,
+    /// after the existing middleware, such as to cap the volume of players.
+    ///
+    /// The middleware can change the op, or drop it so that it isn't sent.
+    /// It applies to the nodes that are already connected as well as those
+    /// added later. Refer to the [`middleware`] module for more information.
+    ///
+    /// [`middleware`]: ../middleware/index.html
+    pub fn add_outbound_middleware(&self, middleware: OutboundMiddleware)
+        -> Result<(), Error> {
+        self.middleware.add_outbound(middleware)
+    }
+
     /// Adds a new node to be managed.
     ///
     /// This will add the node to [`nodes`] once the connection successfully
     /// resolves.
     ///
-    /// Resolves to an error if there was a problem connecting to the node.
+    /// Resolves to the manager along with the result of connecting to the
+    /// node, so that the manager and its other nodes are kept if there was a
+    /// problem connecting; the future itself doesn't fail.
     ///
     /// [`nodes`]: #structfield.nodes
     pub fn add_node(mut self, config: NodeConfig)
-        -> Box<Future<Item = Self, Error = Error>> {
+        -> Box<Future<Item = (Self, Result<(), Error>), Error = ()> + Send> {
         let ws_host = config.websocket_host.clone();
+        let shared = self.shared();
 
-        let done = Node::connect(
-            self.handle.clone(),
-            config,
-            Rc::clone(&self.player_manager),
-            Rc::clone(&self.handler),
-        ).map(move |node| {
-            self.nodes.insert(ws_host, node);
+        let done = remote::run(&self.remote, move |handle| {
+            connect(handle, config, shared)
+        }).then(move |res| -> Result<_, ()> {
+            let result = res.map(|node| {
+                emit(&self.events, ManagerEvent::NodeAdded(ws_host.clone()));
+                emit(&self.events, ManagerEvent::NodeConnected(ws_host.clone()));
 
-            self
-        }).map_err(|why| {
-            trace!("Err adding node: {:?}", why);
+                self.nodes.insert(ws_host, node);
+            }).map_err(|why| {
+                event!(trace; "Err adding node: {:?}", why);
 
-            why
+                why
+            });
+
+            Ok((self, result))
         });
 
         Box::new(done)
     }
 
+    /// Adds a tenant: a bot user whose nodes and players are kept apart from
+    /// those of the manager and of other tenants, so that one manager can
+    /// serve several bots connected to the same nodes.
+    ///
+    /// The tenant is itself a manager, with its own nodes, players, event
+    /// subscribers, and handler called for the events of its players. It
+    /// shares the manager's Core, [executor], metrics, and middleware. Add
+    /// nodes to it via [`add_tenant_node`], and create players via
+    /// [`create_tenant_player`] or the tenant's own methods.
+    ///
+    /// Returns the tenant's manager. If the tenant already exists then it is
+    /// returned as-is, and the given handler is dropped.
+    ///
+    /// [`add_tenant_node`]: #method.add_tenant_node
+    /// [`create_tenant_player`]: #method.create_tenant_player
+    /// [executor]: #method.set_executor
+    pub fn add_tenant(&mut self, user_id: &str, handler: Mutex<Box<EventHandler>>)
+        -> &mut NodeManager {
+        if !self.tenants.contains_key(user_id) {
+            let tenant = Self {
+                nodes: HashMap::new(),
+                player_manager: Arc::new(Mutex::new(AudioPlayerManager::default())),
+                remote: self.remote.clone(),
+                draining: Mutex::new(HashSet::new()),
+                events: Arc::new(Mutex::new(Vec::new())),
+                executor: Arc::clone(&self.executor),
+                handler: Arc::new(handler),
+                metrics: self.metrics.clone(),
+                middleware: self.middleware.clone(),
+                routes: Mutex::new(HashMap::new()),
+                stats: Arc::new(Mutex::new(StatsAggregate::default())),
+                tenants: HashMap::new(),
+            };
+
+            self.tenants.insert(user_id.to_owned(), tenant);
+        }
+
+        self.tenants.get_mut(user_id).expect("tenant was just inserted")
+    }
+
+    /// Adds a new node to be managed by the [tenant] of the config's
+    /// [`user_id`].
+    ///
+    /// Resolves to the manager along with the result of adding the node, as
+    /// with [`add_node`], so that the manager and its other tenants are kept
+    /// if the node can't be connected to. The result is [`Error::None`] if
+    /// there is no such tenant.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    /// [`add_node`]: #method.add_node
+    /// [`user_id`]: struct.NodeConfig.html#structfield.user_id
+    /// [tenant]: #method.add_tenant
+    pub fn add_tenant_node(mut self, config: NodeConfig)
+        -> Box<Future<Item = (Self, Result<(), Error>), Error = ()> + Send> {
+        let shared = match self.tenants.get(&config.user_id) {
+            Some(tenant) => tenant.shared(),
+            None => {
+                let why = Error::None.with_node(config.websocket_host);
+
+                return Box::new(future::ok((self, Err(why))));
+            },
+        };
+        let user_id = config.user_id.clone();
+        let ws_host = config.websocket_host.clone();
+
+        // The tenant is left in place while connecting, so that nothing is
+        // lost if connecting fails.
+        let done = remote::run(&self.remote, move |handle| {
+            connect(handle, config, shared)
+        }).then(move |res| -> Result<_, ()> {
+            let result = res.map(|node| {
+                if let Some(tenant) = self.tenants.get_mut(&user_id) {
+                    emit(&tenant.events, ManagerEvent::NodeAdded(ws_host.clone()));
+                    emit(&tenant.events, ManagerEvent::NodeConnected(ws_host.clone()));
+
+                    tenant.nodes.insert(ws_host, node);
+                }
+            });
+
+            Ok((self, result))
+        });
+
+        Box::new(done)
+    }
+
+    /// Retrieves a copy of the last stats received from each node, keyed by
+    /// the websocket host, without blocking.
+    ///
+    /// Nodes without stats, or whose state could not be accessed at the time,
+    /// are omitted. Refer to [`Node::stats`] for more information.
+    ///
+    /// [`Node::stats`]: struct.Node.html#method.stats
+    pub fn all_stats(&self) -> HashMap<String, NodeStats> {
+        self.nodes
+            .iter()
+            .filter_map(|(host, node)| node.stats().map(|stats| (host.clone(), stats)))
+            .collect()
+    }
+
     /// Determines the best node, if any.
     ///
+    /// Only [healthy] nodes that are not [draining] are considered. The node
+    /// with the lowest penalty is chosen, and ties are broken by the node with
+    /// the fewest players and then by the lowest websocket host, so the same
+    /// node is chosen regardless of the order of [`nodes`]. Nodes without
+    /// stats have no known load, so are only chosen if no other node is
+    /// available.
+    ///
     /// This does not return the node, but does return the websocket host (keyed
     /// in [`nodes`]).
     ///
     /// [`nodes`]: #structfield.nodes
+    /// [draining]: #method.set_draining
+    /// [healthy]: enum.NodeStatus.html#method.is_healthy
     pub fn best_node(&self) -> Option<&str> {
-        let mut record = i32::MAX;
-        let mut best = None;
+        self.best_node_with_tags(&[])
+    }
 
-        for (name, node) in &self.nodes {
-            let total = node.penalty().unwrap_or(0);
+    /// Determines the best node with every one of the given tags, if any.
+    ///
+    /// Refer to [`best_node`] and [`NodeConfig::tags`] for more information.
+    ///
+    /// [`NodeConfig::tags`]: struct.NodeConfig.html#method.tags
+    /// [`best_node`]: #method.best_node
+    pub fn best_node_with_tags(&self, tags: &[(&str, &str)]) -> Option<&str> {
+        let draining = self.draining.lock().unwrap_or_else(|poisoned| {
+            event!(warn; "Draining nodes were poisoned, recovering them");
 
-            if total < record {
-                best = Some(name.as_ref());
-                record = total;
-            }
-        }
+            poisoned.into_inner()
+        });
+
+        let best = self.nodes
+            .iter()
+            .filter(|&(name, node)| {
+                node.status().is_healthy()
+                    && node.config().matches_tags(tags)
+                    && !draining.contains(name)
+            })
+            .min_by_key(|&(name, node)| {
+                let penalty = node.penalty();
+                let players = node.stats().map_or(0, |stats| stats.players);
+
+                (penalty.is_none(), penalty.unwrap_or(0), players, name)
+            })
+            .map(|(name, _)| name.as_ref());
 
         best
     }
 
+    /// Sends voice updates for many guilds at once, such as when reconnecting
+    /// every guild after a gateway outage.
+    ///
+    /// Each update is routed to the node that its guild's player is on, and
+    /// the updates are sent one at a time: the next update is only sent once
+    /// the previous one was flushed to its node, to avoid flooding the nodes'
+    /// websocket sinks.
+    ///
+    /// Resolves to the result of each guild's update, keyed by guild ID.
+    /// Guilds without a player or a node result in [`Error::None`]; the
+    /// future itself doesn't fail.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    pub fn bulk_voice_update(&self, updates: Vec<(u64, VoiceUpdate)>)
+        -> Box<Future<Item = HashMap<u64, Result<(), Error>>, Error = Error> + Send> {
+        let routed = updates
+            .into_iter()
+            .map(|(guild_id, update)| (guild_id, update, self.route(guild_id)))
+            .collect::<Vec<_>>();
+        let player_manager = Arc::clone(&self.player_manager);
+
+        let done = stream::iter_ok(routed).and_then(move |(guild_id, update, route)| {
+            let flushed = route.and_then(|(host, sender)| {
+                let mut manager = lock_players(&player_manager);
+                let player = manager
+                    .get_mut(&guild_id)
+                    .ok_or_else(|| Error::None.with_guild(guild_id))?;
+
+                if player.node_websocket_host != host {
+                    player.node_websocket_host = host;
+                    player.set_sender(sender);
+                }
+
+                player.voice_update(&update.session_id, &update.token, &update.endpoint)?;
+
+                Ok(player.flush())
+            });
+
+            match flushed {
+                Ok(flush) => {
+                    future::Either::A(flush.then(move |res| Ok::<_, Error>((guild_id, res))))
+                },
+                Err(why) => future::Either::B(future::ok((guild_id, Err(why)))),
+            }
+        });
+
+        Box::new(done.collect().map(|results| results.into_iter().collect()))
+    }
+
+    /// Retrieves the websocket hosts of the nodes that are [healthy].
+    ///
+    /// [healthy]: enum.NodeStatus.html#method.is_healthy
+    pub fn healthy_nodes(&self) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|&(_, node)| node.status().is_healthy())
+            .map(|(name, _)| name.as_ref())
+            .collect()
+    }
+
     /// Closes a node by websocket host.
     ///
     /// Returns whether closing the node was successful. This can fail if the
     /// node is not recognized by host.
     ///
+    /// **Note**: This does _not_ remove the node from [`nodes`], and players on
+    /// the node are left as-is. Prefer [`remove_node`] to fully remove a node.
+    ///
+    /// [`nodes`]: #structfield.nodes
+    /// [`remove_node`]: #method.remove_node
+    ///
     /// Returns `Ok(true)` if the closing was successful. Returns `Ok(false)` if
     /// it wasn't.
     pub fn close(&mut self, websocket_host: &str) -> Result<bool, Error> {
@@ -114,31 +429,97 @@ impl NodeManager {
     pub fn close_all(&mut self) {
         self.nodes.values_mut().for_each(|node| {
             if let Err(why) = node.close() {
-                error!("Failed to close node: {:?}", why);
+                event!(error, node = node.websocket_host; "Failed to close node: {:?}", why);
             }
         });
     }
 
     /// Creates a new player using a [`Node`].
     ///
+    /// Returns a handle to the player, which can be used to send commands to
+    /// it.
+    ///
     /// [`Node`]: struct.Node.html
-    pub fn create_player<'a>(
+    pub fn create_player<'a, G: Into<GuildId>>(
         &'a mut self,
-        guild_id: u64,
+        guild_id: G,
         node_websocket_host: Option<&str>,
-    ) -> Result<(), Error> {
+    ) -> Result<AudioPlayerHandle, Error> {
+        let guild_id = guild_id.into().0;
         let node = match node_websocket_host {
-            Some(host) => self.nodes.get(host).ok_or(Error::None)?,
+            Some(host) => {
+                self.nodes
+                    .get(host)
+                    .ok_or_else(|| Error::None.with_guild(guild_id).with_node(host))?
+            },
             None => {
                 self.best_node()
                     .and_then(|name| self.nodes.get(name))
-                    .ok_or(Error::None)?
+                    .ok_or_else(|| Error::None.with_guild(guild_id))?
             },
         };
 
-        let mut manager = self.player_manager.try_borrow_mut()?;
+        let mut manager = lock_players(&self.player_manager);
+        let player = manager.create(
+            guild_id,
+            node.websocket_host.clone(),
+            node.user_to_node.clone(),
+        ).map_err(|why| {
+            why.with_guild(guild_id).with_node(node.websocket_host.clone())
+        })?;
 
-        manager.create(guild_id, node.user_to_node.clone()).map(|_| ())
+        player.set_handler(Arc::clone(&self.handler), node.config().num_shards);
+        player.set_remote(self.remote.clone());
+
+        self.routes.lock()?.insert(guild_id, node.websocket_host.clone());
+
+        emit(&self.events, ManagerEvent::PlayerCreated(guild_id, node.websocket_host.clone()));
+
+        Ok(AudioPlayerHandle::new(guild_id, Arc::clone(&self.player_manager)))
+    }
+
+    /// Creates a new player for a [tenant], using one of its nodes.
+    ///
+    /// Returns [`Error::None`] if there is no such tenant. Refer to
+    /// [`create_player`] for more information.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    /// [`create_player`]: #method.create_player
+    /// [tenant]: #method.add_tenant
+    pub fn create_tenant_player<G: Into<GuildId>>(
+        &mut self,
+        user_id: &str,
+        guild_id: G,
+        node_websocket_host: Option<&str>,
+    ) -> Result<AudioPlayerHandle, Error> {
+        let guild_id = guild_id.into().0;
+
+        self.tenants
+            .get_mut(user_id)
+            .ok_or_else(|| Error::None.with_guild(guild_id))?
+            .create_player(guild_id, node_websocket_host)
+    }
+
+    /// Creates a new player on the best node with every one of the given
+    /// tags, such as `&[("region", "us-east")]`.
+    ///
+    /// Returns [`Error::None`] if no [healthy] node has the tags. Refer to
+    /// [`create_player`] for more information.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    /// [`create_player`]: #method.create_player
+    /// [healthy]: enum.NodeStatus.html#method.is_healthy
+    pub fn create_player_with_tags<G: Into<GuildId>>(
+        &mut self,
+        guild_id: G,
+        tags: &[(&str, &str)],
+    ) -> Result<AudioPlayerHandle, Error> {
+        let guild_id = guild_id.into().0;
+        let host = self.best_node_with_tags(tags)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| Error::None.with_guild(guild_id))?;
+
+        self.create_player(guild_id, Some(&host))
     }
 
     /// Retrieves a node by websocket host.
@@ -146,12 +527,863 @@ impl NodeManager {
         self.nodes.get(node_websocket_host)
     }
 
+    /// Retrieves a handle to a guild's player, if it has one.
+    ///
+    /// Use [`with_player`] to access the player's state directly.
+    ///
+    /// [`with_player`]: #method.with_player
+    pub fn get_player<G: Into<GuildId>>(&self, guild_id: G) -> Option<AudioPlayerHandle> {
+        let guild_id = guild_id.into().0;
+
+        if lock_players(&self.player_manager).has(guild_id) {
+            Some(AudioPlayerHandle::new(guild_id, Arc::clone(&self.player_manager)))
+        } else {
+            None
+        }
+    }
+
+    /// Whether a node is [draining].
+    ///
+    /// [draining]: #method.set_draining
+    pub fn is_draining(&self, websocket_host: &str) -> bool {
+        match self.draining.lock() {
+            Ok(draining) => draining.contains(websocket_host),
+            Err(why) => {
+                event!(warn, node = websocket_host; "Err locking draining nodes: {:?}", why);
+
+                false
+            },
+        }
+    }
+
+    /// Retrieves the websocket host of the node that a guild's player is on.
+    ///
+    /// This is tracked as players are created, removed, restored, and
+    /// migrated via the manager. Returns `None` if the guild has no player
+    /// created via the manager.
+    pub fn node_for_guild<G: Into<GuildId>>(&self, guild_id: G) -> Option<String> {
+        match self.routes.lock() {
+            Ok(routes) => routes.get(&guild_id.into().0).cloned(),
+            Err(why) => {
+                event!(warn; "Err locking guild routes: {:?}", why);
+
+                None
+            },
+        }
+    }
+
+    /// Plays a track for a guild, creating the guild's player if it does not
+    /// exist.
+    ///
+    /// The player is created on the node in the [`PlayOptions`] if given,
+    /// otherwise the [best node].
+    ///
+    /// Resolves to a handle to the player, which can be used to send further
+    /// commands for the guild.
+    ///
+    /// [`PlayOptions`]: ../player/struct.PlayOptions.html
+    /// [best node]: #method.best_node
+    pub fn play<G: Into<GuildId>>(&mut self, guild_id: G, track: &str, options: PlayOptions)
+        -> Box<Future<Item = AudioPlayerHandle, Error = Error> + Send> {
+        Box::new(future::result(self.play_track(guild_id.into().0, track, options)))
+    }
+
+    fn play_track(&mut self, guild_id: u64, track: &str, options: PlayOptions)
+        -> Result<AudioPlayerHandle, Error> {
+        let exists = lock_players(&self.player_manager).has(&guild_id);
+
+        let player = if exists {
+            AudioPlayerHandle::new(guild_id, Arc::clone(&self.player_manager))
+        } else {
+            let host = options.node_websocket_host.as_ref().map(|host| &host[..]);
+
+            self.create_player(guild_id, host)?
+        };
+
+        player.play_with_options(track, &options)?;
+
+        Ok(player)
+    }
+
+    /// Reconnects to a node by websocket host, using the configuration it was
+    /// originally connected with.
+    ///
+    /// If the node was configured with a [`resume_key`], then the node will
+    /// reattach the existing session, meaning that tracks that were playing
+    /// will not be interrupted.
+    ///
+    /// All of the audio players on the node will be updated to send over the
+    /// new connection. If the node was not configured with a resume key, the
+    /// state of each player is replayed via [`AudioPlayer::replay_state`].
+    /// Otherwise, the messages buffered while the node was disconnected are
+    /// sent over the new connection, as configured by its [`replay`].
+    ///
+    /// The node is only replaced in [`nodes`] once the new connection
+    /// succeeds. If it fails, the closed node is kept - buffering the
+    /// messages of its players - so that it can be reconnected to again.
+    ///
+    /// Resolves to the manager along with the result of reconnecting, so that
+    /// the manager and its other nodes are kept if the node can't be
+    /// connected to; the future itself doesn't fail. The result is
+    /// [`Error::None`] if there is no node with the given host.
+    ///
+    /// [`AudioPlayer::replay_state`]: ../player/struct.AudioPlayer.html#method.replay_state
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    /// [`nodes`]: #structfield.nodes
+    /// [`replay`]: struct.NodeConfig.html#method.replay
+    /// [`resume_key`]: struct.NodeConfig.html#method.resume_key
+    pub fn reconnect(mut self, websocket_host: &str)
+        -> Box<Future<Item = (Self, Result<(), Error>), Error = ()> + Send> {
+        match self.close_for_reconnect(websocket_host) {
+            Some(config) => self.reattach(config),
+            None => {
+                let why = Error::None.with_node(websocket_host);
+
+                Box::new(future::ok((self, Err(why))))
+            },
+        }
+    }
+
+    /// Connects to a node that was closed via [`close_for_reconnect`],
+    /// swapping it for the new connection in [`nodes`] and pointing its
+    /// players at the new connection once connected.
+    ///
+    /// If the session is resumed, the messages that the old node buffered
+    /// while disconnected are sent over the new connection. If connecting
+    /// fails, the old node is left in place.
+    ///
+    /// [`close_for_reconnect`]: #method.close_for_reconnect
+    /// [`nodes`]: #structfield.nodes
+    fn reattach(mut self, config: NodeConfig)
+        -> Box<Future<Item = (Self, Result<(), Error>), Error = ()> + Send> {
+        let ws_host = config.websocket_host.clone();
+        let resumed = config.resume_key.is_some();
+
+        self.metrics.reconnect(&ws_host);
+
+        let shared = self.shared();
+
+        let done = remote::run(&self.remote, move |handle| {
+            connect(handle, config, shared)
+        }).then(move |res| -> Result<_, ()> {
+            let node = match res {
+                Ok(node) => node,
+                Err(why) => {
+                    event!(trace, node = ws_host; "Err reconnecting node: {:?}", why);
+
+                    return Ok((self, Err(why)));
+                },
+            };
+
+            {
+                let mut manager = lock_players(&self.player_manager);
+                let count = manager.replace_sender(&ws_host, &node.user_to_node);
+
+                event!(debug, node = ws_host, players = count; "Reattached players");
+
+                // Without resuming, the node has no knowledge of the players.
+                if !resumed {
+                    let count = manager.replay_state(&ws_host);
+
+                    event!(debug, node = ws_host, players = count; "Replayed players");
+                }
+            }
+
+            // Taken once the players send over the new connection, so that
+            // nothing more is buffered by the old node.
+            let buffered = self.nodes
+                .remove(&ws_host)
+                .map(|old| old.take_buffered())
+                .unwrap_or_default();
+
+            if resumed && !buffered.is_empty() {
+                let total = buffered.len();
+                let count = node.send_buffered(buffered);
+
+                event!(
+                    debug,
+                    node = ws_host,
+                    messages = count,
+                    buffered = total;
+                    "Sent buffered messages"
+                );
+            }
+
+            emit(&self.events, ManagerEvent::NodeConnected(ws_host.clone()));
+
+            self.nodes.insert(ws_host, node);
+
+            Ok((self, Ok(())))
+        });
+
+        Box::new(done)
+    }
+
+    /// Removes a node by websocket host, closing its connection.
+    ///
+    /// The audio players that were on the node are migrated to the best
+    /// remaining node, if there is one. Refer to
+    /// [`AudioPlayerManager::migrate`] for more information. If there are no
+    /// remaining nodes, then the players are removed.
+    ///
+    /// Returns the number of players that were migrated or removed. Returns
+    /// `Ok(0)` if the node did not exist.
+    ///
+    /// [`AudioPlayerManager::migrate`]: ../player/struct.AudioPlayerManager.html#method.migrate
+    pub fn remove_node(&mut self, websocket_host: &str) -> Result<usize, Error> {
+        // Take every lock first, so that the node is left as-is if any of
+        // them can't be taken.
+        let mut manager = lock_players(&self.player_manager);
+        let mut routes = self.routes.lock()?;
+        let mut draining = self.draining.lock()?;
+        let mut stats = self.stats.lock()?;
+
+        let mut node = match self.nodes.remove(websocket_host) {
+            Some(node) => node,
+            None => return Ok(0),
+        };
+
+        if let Err(why) = node.close() {
+            event!(debug, node = websocket_host; "Err closing removed node: {:?}", why);
+        }
+
+        emit(&self.events, ManagerEvent::NodeRemoved(websocket_host.to_owned()));
+
+        draining.remove(websocket_host);
+        stats.remove(websocket_host);
+
+        // Choosing the best node locks the draining nodes itself.
+        drop(draining);
+
+        let best = self.best_node().and_then(|name| self.nodes.get(name));
+        let guild_ids = manager.guild_ids_on_node(websocket_host);
+
+        let count = match best {
+            Some(best) => {
+                for guild_id in guild_ids {
+                    routes.insert(guild_id, best.websocket_host.clone());
+                }
+
+                manager.migrate(
+                    websocket_host,
+                    &best.websocket_host,
+                    &best.user_to_node,
+                )
+            },
+            None => {
+                for guild_id in guild_ids {
+                    routes.remove(&guild_id);
+                    emit(&self.events, ManagerEvent::PlayerRemoved(guild_id));
+                }
+
+                manager.remove_on_node(websocket_host)
+            },
+        };
+
+        Ok(count)
+    }
+
+    /// Removes a [tenant], returning its manager if it existed.
+    ///
+    /// The tenant's nodes are closed once its manager is dropped.
+    ///
+    /// [tenant]: #method.add_tenant
+    pub fn remove_tenant(&mut self, user_id: &str) -> Option<NodeManager> {
+        self.tenants.remove(user_id)
+    }
+
     /// Removes a player by guild ID.
     ///
     /// Returns `Ok(true)` if the player existed and was removed. Returns
     /// `Ok(false)` if the player did not exist.
-    pub fn remove_player(&mut self, guild_id: &u64) -> Result<bool, Error> {
-        Ok(self.player_manager.try_borrow_mut()?.remove(guild_id))
+    pub fn remove_player<G: Into<GuildId>>(&mut self, guild_id: G) -> Result<bool, Error> {
+        let guild_id = guild_id.into().0;
+        let removed = lock_players(&self.player_manager).remove(guild_id);
+
+        self.routes.lock()?.remove(&guild_id);
+
+        if removed {
+            emit(&self.events, ManagerEvent::PlayerRemoved(guild_id));
+        }
+
+        Ok(removed)
+    }
+
+    /// Renders the library's [metrics], the latest stats of each node, and the
+    /// number of audio players on each node in the Prometheus text exposition
+    /// format.
+    ///
+    /// This can be served from an HTTP server for Prometheus to scrape. Nodes
+    /// that have not yet sent stats only have their `lavalink_node_up` metric
+    /// rendered.
+    ///
+    /// [metrics]: ../metrics/index.html
+    pub fn render_metrics(&self) -> String {
+        let mut exposition = Exposition::new();
+        self.metrics.render(&mut exposition);
+
+        let mut nodes = self.nodes.iter().filter_map(|(host, node)| {
+            match node.state.read() {
+                Ok(state) => Some((host.as_str(), state.status, state.stats.clone())),
+                Err(why) => {
+                    event!(warn, node = host; "Err reading state: {:?}", why);
+
+                    None
+                },
+            }
+        }).collect::<Vec<_>>();
+        nodes.sort_by(|a, b| a.0.cmp(b.0));
+
+        exposition.header("lavalink_node_up", "gauge", "Whether the node is connected.");
+
+        for &(host, status, _) in &nodes {
+            let up = if status == NodeStatus::Connected { 1.0 } else { 0.0 };
+
+            exposition.sample("lavalink_node_up", &[("node", host)], up);
+        }
+
+        let stats = nodes
+            .iter()
+            .filter_map(|&(host, _, ref stats)| stats.as_ref().map(|stats| (host, stats)))
+            .collect::<Vec<_>>();
+
+        render_node_gauge(
+            &mut exposition,
+            "lavalink_node_players",
+            "Players on the node, as reported by the node.",
+            &stats,
+            |stats| Some(stats.players as f64),
+        );
+        render_node_gauge(
+            &mut exposition,
+            "lavalink_node_playing_players",
+            "Players playing a track on the node, as reported by the node.",
+            &stats,
+            |stats| Some(stats.playing_players as f64),
+        );
+        render_node_gauge(
+            &mut exposition,
+            "lavalink_node_uptime_seconds",
+            "The uptime of the node.",
+            &stats,
+            |stats| stats.uptime.map(|uptime| uptime as f64 / 1000.0),
+        );
+        render_node_gauge(
+            &mut exposition,
+            "lavalink_node_cpu_cores",
+            "The number of CPU cores of the node.",
+            &stats,
+            |stats| Some(stats.cpu.cores as f64),
+        );
+        render_node_gauge(
+            &mut exposition,
+            "lavalink_node_cpu_lavalink_load",
+            "The CPU load of the Lavalink process.",
+            &stats,
+            |stats| Some(stats.cpu.lavalink_load),
+        );
+        render_node_gauge(
+            &mut exposition,
+            "lavalink_node_cpu_system_load",
+            "The CPU load of the node's system.",
+            &stats,
+            |stats| Some(stats.cpu.system_load),
+        );
+
+        exposition.header("lavalink_node_memory_bytes", "gauge", "The memory of the node.");
+
+        for &(host, stats) in &stats {
+            let memory = &stats.memory;
+
+            for &(kind, value) in &[
+                ("allocated", memory.allocated),
+                ("free", memory.free),
+                ("reservable", memory.reservable),
+                ("used", memory.used),
+            ] {
+                let labels = [("node", host), ("kind", kind)];
+
+                exposition.sample("lavalink_node_memory_bytes", &labels, value as f64);
+            }
+        }
+
+        exposition.header(
+            "lavalink_node_frames",
+            "gauge",
+            "The average frames per minute sent, nulled, and in deficit on the node.",
+        );
+
+        for &(host, stats) in &stats {
+            let frames = match stats.frame_stats {
+                Some(ref frames) => frames,
+                None => continue,
+            };
+
+            for &(kind, value) in &[
+                ("deficit", frames.deficit),
+                ("nulled", frames.nulled),
+                ("sent", frames.sent),
+            ] {
+                let labels = [("node", host), ("kind", kind)];
+
+                exposition.sample("lavalink_node_frames", &labels, value as f64);
+            }
+        }
+
+        let mut players = BTreeMap::new();
+
+        for player in lock_players(&self.player_manager).iter() {
+            *players.entry(player.node_websocket_host.clone()).or_insert(0u32) += 1;
+        }
+
+        exposition.header("lavalink_players", "gauge", "Audio players managed by the library.");
+
+        for (host, count) in players {
+            exposition.sample("lavalink_players", &[("node", host.as_str())], f64::from(count));
+        }
+
+        exposition.into_string()
+    }
+
+    /// Restores audio players from snapshots created via
+    /// [`AudioPlayerManager::snapshot`], such as after restarting.
+    ///
+    /// Each player is restored onto the node that it was on if that node is
+    /// [healthy], and otherwise onto the [best node]. Snapshots of guilds that
+    /// already have a player are skipped.
+    ///
+    /// Returns the number of players that were restored, or [`Error::None`]
+    /// if there is no node to restore a player onto, in which case none of
+    /// the players are restored.
+    ///
+    /// [`AudioPlayerManager::snapshot`]: ../player/struct.AudioPlayerManager.html#method.snapshot
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    /// [best node]: #method.best_node
+    /// [healthy]: enum.NodeStatus.html#method.is_healthy
+    pub fn restore(&self, snapshots: Vec<PlayerSnapshot>) -> Result<usize, Error> {
+        // Find the node of every snapshot before restoring any, so that either
+        // all of the players are restored or none are.
+        let mut placed = Vec::with_capacity(snapshots.len());
+
+        for snapshot in snapshots {
+            let node = match self.nodes.get(&snapshot.node_websocket_host) {
+                Some(node) if node.status().is_healthy() => node,
+                _ => {
+                    self.best_node()
+                        .and_then(|name| self.nodes.get(name))
+                        .ok_or_else(|| Error::None.with_guild(snapshot.guild_id))?
+                },
+            };
+
+            placed.push((snapshot, node));
+        }
+
+        let mut manager = lock_players(&self.player_manager);
+        let mut routes = self.routes.lock()?;
+        let mut count = 0;
+
+        for (snapshot, node) in placed {
+            let guild_id = snapshot.guild_id;
+
+            if manager.restore(vec![snapshot], &node.websocket_host, &node.user_to_node) == 0 {
+                continue;
+            }
+
+            if let Some(player) = manager.get_mut(&guild_id) {
+                player.set_handler(Arc::clone(&self.handler), node.config().num_shards);
+                player.set_remote(self.remote.clone());
+            }
+
+            routes.insert(guild_id, node.websocket_host.clone());
+
+            emit(&self.events, ManagerEvent::PlayerCreated(guild_id, node.websocket_host.clone()));
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// The websocket host of the node a guild is routed to, along with the
+    /// sender to the node.
+    fn route(&self, guild_id: u64) -> Result<(String, MpscSender<OwnedMessage>), Error> {
+        let host = self.node_for_guild(guild_id)
+            .ok_or_else(|| Error::None.with_guild(guild_id))?;
+        let node = self.nodes
+            .get(&host)
+            .ok_or_else(|| Error::None.with_guild(guild_id).with_node(host.clone()))?;
+
+        Ok((host, node.user_to_node.clone()))
+    }
+
+    /// Sets whether a node is draining, such as before it is taken down for
+    /// maintenance.
+    ///
+    /// Draining nodes stay connected and keep their existing players, but are
+    /// never the [best node], so new players are created on other nodes.
+    ///
+    /// [best node]: #method.best_node
+    pub fn set_draining(&self, websocket_host: &str, draining: bool) -> Result<(), Error> {
+        let mut nodes = self.draining.lock()?;
+
+        if draining {
+            nodes.insert(websocket_host.to_owned());
+        } else {
+            nodes.remove(websocket_host);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the executor calling the handler for events from nodes, such as a
+    /// [`RemoteExecutor`] on a dedicated thread.
+    ///
+    /// Only nodes added or reconnected afterwards use the executor. Refer to
+    /// the [`executor`] module for more information.
+    ///
+    /// [`RemoteExecutor`]: ../executor/struct.RemoteExecutor.html
+    /// [`executor`]: ../executor/index.html
+    pub fn set_executor(&mut self, executor: Box<Executor>) {
+        self.executor = Arc::from(executor);
+    }
+
+    /// The stats of every node merged into stats of the whole cluster.
+    ///
+    /// This is recomputed whenever a node sends its stats, so calling this is
+    /// cheap. Nodes that have not yet sent stats are not included.
+    pub fn stats_aggregate(&self) -> Result<ClusterStats, Error> {
+        Ok(self.stats.lock()?.cluster.clone())
+    }
+
+    /// Creates a stream of events about the manager's nodes and players, as
+    /// well as the track events of every node.
+    ///
+    /// This is an alternative to the [`EventHandler`] for consumers who prefer
+    /// stream-based processing. Each call creates a new stream which receives
+    /// every event from the time of its creation.
+    ///
+    /// [`EventHandler`]: ../trait.EventHandler.html
+    pub fn subscribe(&self) -> Box<Stream<Item = ManagerEvent, Error = Error> + Send> {
+        let (tx, rx) = mpsc::unbounded();
+
+        match self.events.lock() {
+            Ok(mut events) => events.push(tx),
+            Err(why) => event!(warn; "Err locking manager subscribers: {:?}", why),
+        }
+
+        // Receiving from an unbounded receiver can't fail.
+        Box::new(rx.map_err(|_| Error::None))
+    }
+
+    /// The state shared with the nodes that the manager connects to.
+    fn shared(&self) -> Shared {
+        Shared {
+            events: Arc::clone(&self.events),
+            executor: Arc::clone(&self.executor),
+            handler: Arc::clone(&self.handler),
+            metrics: self.metrics.clone(),
+            middleware: self.middleware.clone(),
+            player_manager: Arc::clone(&self.player_manager),
+            stats: Arc::clone(&self.stats),
+        }
+    }
+
+    /// Closes the connection of a node ahead of reconnecting to it, returning
+    /// the configuration that it was connected with.
+    ///
+    /// The node is left in [`nodes`] - buffering the messages of its players
+    /// - until the new connection succeeds.
+    ///
+    /// [`nodes`]: #structfield.nodes
+    fn close_for_reconnect(&mut self, websocket_host: &str) -> Option<NodeConfig> {
+        self.nodes.get_mut(websocket_host).map(|node| {
+            if let Err(why) = node.close() {
+                event!(
+                    debug,
+                    node = websocket_host;
+                    "Err closing node before reconnect: {:?}",
+                    why
+                );
+            }
+
+            node.config().clone()
+        })
+    }
+
+    /// Retrieves the manager of a [tenant] by the ID of its bot user.
+    ///
+    /// [tenant]: #method.add_tenant
+    pub fn tenant(&self, user_id: &str) -> Option<&NodeManager> {
+        self.tenants.get(user_id)
+    }
+
+    /// Retrieves a mutable reference to the manager of a [tenant] by the ID
+    /// of its bot user.
+    ///
+    /// [tenant]: #method.add_tenant
+    pub fn tenant_mut(&mut self, user_id: &str) -> Option<&mut NodeManager> {
+        self.tenants.get_mut(user_id)
+    }
+
+    /// Updates the number of shards that the bot is using, such as after
+    /// resharding.
+    ///
+    /// Nodes are told the number of shards when connecting, so each node is
+    /// reconnected one at a time with the new number, as with [`reconnect`].
+    /// Nodes configured with a [`resume_key`] resume their sessions. Every
+    /// audio player calculates the shard to forward payloads to using the new
+    /// number.
+    ///
+    /// **Note**: A [`VoiceConnectionManager`] must be updated separately via
+    /// [`VoiceConnectionManager::set_num_shards`].
+    ///
+    /// Resolves to the manager along with the first error reconnecting to a
+    /// node, as with [`reconnect`]; the future itself doesn't fail. The
+    /// result is [`Error::InvalidConfig`] if the number of shards is 0.
+    ///
+    /// [`Error::InvalidConfig`]: ../enum.Error.html#variant.InvalidConfig
+    /// [`VoiceConnectionManager`]: ../voice/struct.VoiceConnectionManager.html
+    /// [`VoiceConnectionManager::set_num_shards`]: ../voice/struct.VoiceConnectionManager.html#method.set_num_shards
+    /// [`reconnect`]: #method.reconnect
+    /// [`resume_key`]: struct.NodeConfig.html#method.resume_key
+    pub fn update_shard_count(mut self, num_shards: u64)
+        -> Box<Future<Item = (Self, Result<(), Error>), Error = ()> + Send> {
+        if num_shards == 0 {
+            let error = Error::InvalidConfig("The number of shards must be at least 1");
+
+            return Box::new(future::ok((self, Err(error))));
+        }
+
+        lock_players(&self.player_manager).set_num_shards(num_shards);
+
+        let hosts = self.nodes.keys().cloned().collect::<Vec<_>>();
+
+        // Each node is only closed once the previous one was reconnected to,
+        // so that the other nodes stay up in the meantime. A node failing to
+        // reconnect doesn't stop the rest from being reconnected to.
+        let step = move |(mut manager, result): (Self, Result<(), Error>), host: String|
+            -> Box<Future<Item = (Self, Result<(), Error>), Error = ()> + Send> {
+            let config = match manager.close_for_reconnect(&host) {
+                Some(mut config) => {
+                    config.num_shards = num_shards;
+
+                    config
+                },
+                None => return Box::new(future::ok((manager, result))),
+            };
+
+            Box::new(manager.reattach(config).map(move |(manager, res)| {
+                (manager, result.and(res))
+            }))
+        };
+        let done = stream::iter_ok(hosts).fold((self, Ok(())), step);
+
+        Box::new(done)
+    }
+
+    /// Sends a voice update to the node that a guild's player is on, as
+    /// found via [`node_for_guild`].
+    ///
+    /// If the player is not sending over the routed node - such as after
+    /// being moved between nodes outside of the manager - then the player is
+    /// first pointed at the routed node.
+    ///
+    /// Returns [`Error::None`] if the guild has no player or its node no
+    /// longer exists.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    /// [`node_for_guild`]: #method.node_for_guild
+    pub fn voice_update<G: Into<GuildId>>(
+        &self,
+        guild_id: G,
+        session_id: &str,
+        token: &str,
+        endpoint: &str,
+    ) -> Result<(), Error> {
+        let guild_id = guild_id.into().0;
+        let (host, sender) = self.route(guild_id)?;
+
+        let mut manager = lock_players(&self.player_manager);
+        let player = manager
+            .get_mut(&guild_id)
+            .ok_or_else(|| Error::None.with_guild(guild_id))?;
+
+        if player.node_websocket_host != host {
+            player.node_websocket_host = host;
+            player.set_sender(sender);
+        }
+
+        player.voice_update(session_id, token, endpoint)
+    }
+
+    /// Calls a function with a mutable reference to a guild's player, while
+    /// holding the player manager's lock.
+    ///
+    /// Returns [`Error::None`] if the guild has no player. The lock must not
+    /// be taken again within the function.
+    ///
+    /// # Examples
+    ///
+    /// Queue a track for a guild:
+    ///
+    /// ```rust,ignore
+    /// let queued = manager.with_player(guild_id, |player| player.enqueue(&track))?;
+    /// ```
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    pub fn with_player<G, F, T>(&self, guild_id: G, f: F) -> Result<T, Error>
+        where F: FnOnce(&mut AudioPlayer) -> Result<T, Error>,
+              G: Into<GuildId> {
+        let guild_id = guild_id.into().0;
+        let mut manager = lock_players(&self.player_manager);
+        let player = manager
+            .get_mut(guild_id)
+            .ok_or_else(|| Error::None.with_guild(guild_id))?;
+
+        f(player)
+    }
+}
+
+/// Renders a gauge of each node's stats, skipping nodes without a value.
+fn render_node_gauge<F>(
+    exposition: &mut Exposition,
+    name: &str,
+    help: &str,
+    stats: &[(&str, &NodeStats)],
+    f: F,
+) where F: Fn(&NodeStats) -> Option<f64> {
+    exposition.header(name, "gauge", help);
+
+    for &(host, stats) in stats {
+        if let Some(value) = f(stats) {
+            exposition.sample(name, &[("node", host)], value);
+        }
+    }
+}
+
+/// The state of a manager shared with the nodes that it connects to.
+#[derive(Clone)]
+struct Shared {
+    events: Arc<Mutex<Vec<UnboundedSender<ManagerEvent>>>>,
+    executor: Arc<Executor>,
+    handler: Arc<Mutex<Box<EventHandler>>>,
+    metrics: Metrics,
+    middleware: Middleware,
+    player_manager: Arc<Mutex<AudioPlayerManager>>,
+    stats: Arc<Mutex<StatsAggregate>>,
+}
+
+/// Connects to a node, forwarding its events to the manager's subscribers.
+fn connect(handle: &Handle, config: NodeConfig, shared: Shared)
+    -> Box<Future<Item = Node, Error = Error>> {
+    let handle2 = handle.clone();
+    let Shared { events, executor, handler, metrics, middleware, player_manager, stats } = shared;
+
+    let done = Node::connect(
+        handle.clone(),
+        config,
+        player_manager,
+        handler,
+        executor,
+        metrics,
+        middleware,
+    ).map(move |node| {
+        forward_events(&handle2, &node, events, stats);
+
+        node
+    });
+
+    Box::new(done)
+}
+
+/// Spawns a task forwarding the events of a node to the manager's
+/// subscribers as [`ManagerEvent`]s, and merging the node's stats into the
+/// cluster's stats.
+///
+/// [`ManagerEvent`]: enum.ManagerEvent.html
+fn forward_events(
+    handle: &Handle,
+    node: &Node,
+    events: Arc<Mutex<Vec<UnboundedSender<ManagerEvent>>>>,
+    stats: Arc<Mutex<StatsAggregate>>,
+) {
+    let host = node.websocket_host.clone();
+    let mut disconnected = false;
+
+    let done = node.events().for_each(move |event| {
+        let event = match event {
+            NodeEvent::Status(NodeStatus::Connected) if disconnected => {
+                disconnected = false;
+
+                ManagerEvent::NodeConnected(host.clone())
+            },
+            NodeEvent::Status(NodeStatus::Closed)
+            | NodeEvent::Status(NodeStatus::Reconnecting) if !disconnected => {
+                disconnected = true;
+
+                ManagerEvent::NodeDisconnected(host.clone())
+            },
+            NodeEvent::Stats(node_stats) => {
+                match stats.lock() {
+                    Ok(mut stats) => stats.update(&host, node_stats),
+                    Err(why) => event!(warn, node = host; "Err locking cluster stats: {:?}", why),
+                }
+
+                return Ok(());
+            },
+            NodeEvent::TrackEnd(event) => ManagerEvent::TrackEnd(host.clone(), event),
+            NodeEvent::TrackException(event) => {
+                ManagerEvent::TrackException(host.clone(), event)
+            },
+            NodeEvent::TrackStart(event) => ManagerEvent::TrackStart(host.clone(), event),
+            NodeEvent::TrackStuck(event) => ManagerEvent::TrackStuck(host.clone(), event),
+            NodeEvent::WebSocketClosed(event) => {
+                ManagerEvent::WebSocketClosed(host.clone(), event)
+            },
+            _ => return Ok(()),
+        };
+
+        emit(&events, event);
+
+        Ok(())
+    }).map_err(|why| {
+        event!(warn; "Err forwarding node events: {:?}", why);
+    });
+
+    handle.spawn(done);
+}
+
+/// The latest stats of each node, and the cluster's stats merged from them.
+#[derive(Debug, Default)]
+struct StatsAggregate {
+    cluster: ClusterStats,
+    nodes: HashMap<String, NodeStats>,
+}
+
+impl StatsAggregate {
+    fn remove(&mut self, host: &str) {
+        if self.nodes.remove(host).is_some() {
+            self.cluster = ClusterStats::from_stats(self.nodes.values());
+        }
+    }
+
+    fn update(&mut self, host: &str, stats: NodeStats) {
+        self.nodes.insert(host.to_owned(), stats);
+        self.cluster = ClusterStats::from_stats(self.nodes.values());
+    }
+}
+
+/// Sends an event to all of the streams created via
+/// [`NodeManager::subscribe`], removing streams that have been dropped.
+///
+/// [`NodeManager::subscribe`]: struct.NodeManager.html#method.subscribe
+fn emit(events: &Arc<Mutex<Vec<UnboundedSender<ManagerEvent>>>>, event: ManagerEvent) {
+    match events.lock() {
+        Ok(mut events) => {
+            events.retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+        },
+        Err(why) => {
+            event!(warn; "Err locking manager subscribers: {:?}", why);
+        },
     }
 }
 
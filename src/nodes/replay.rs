@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+use std::mem;
+use super::{OverflowPolicy, ReplayConfig};
+use websocket::OwnedMessage;
+
+/// A bounded buffer of the messages sent to a node while it is disconnected,
+/// to be sent once its session is resumed.
+///
+/// Messages are dropped if there is no configuration.
+pub struct ReplayBuffer {
+    config: Option<ReplayConfig>,
+    messages: VecDeque<OwnedMessage>,
+}
+
+impl ReplayBuffer {
+    /// Creates a new, empty buffer.
+    pub fn new(config: Option<ReplayConfig>) -> Self {
+        Self {
+            config,
+            messages: VecDeque::new(),
+        }
+    }
+
+    /// Whether messages are buffered rather than dropped.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    /// The number of buffered messages.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Buffers a message, applying the overflow policy if the buffer is full.
+    ///
+    /// Returns whether a message was dropped. Messages other than text and
+    /// binary payloads, such as closes, are ignored.
+    pub fn push(&mut self, message: OwnedMessage) -> bool {
+        match message {
+            OwnedMessage::Binary(_) | OwnedMessage::Text(_) => {},
+            _ => return false,
+        }
+
+        let config = match self.config {
+            Some(ref config) => config,
+            None => return true,
+        };
+
+        if self.messages.len() < config.capacity {
+            self.messages.push_back(message);
+
+            return false;
+        }
+
+        if config.overflow == OverflowPolicy::DropOldest && config.capacity > 0 {
+            self.messages.pop_front();
+            self.messages.push_back(message);
+        }
+
+        true
+    }
+
+    /// Takes every buffered message, from oldest to newest.
+    pub fn take(&mut self) -> VecDeque<OwnedMessage> {
+        mem::replace(&mut self.messages, VecDeque::new())
+    }
+}
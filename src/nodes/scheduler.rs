@@ -0,0 +1,420 @@
+use futures::sync::mpsc::{Receiver as SyncReceiver, UnboundedReceiver};
+use futures::sync::oneshot;
+use futures::{Async, Poll, Stream};
+use serde_json::{self, Value};
+use std::collections::{HashMap, VecDeque};
+use websocket::OwnedMessage;
+use ::model::GuildId;
+use ::Error;
+
+/// The ops of which only the latest queued for a guild needs to be sent, as
+/// each replaces the state set by the previous.
+const COALESCED_OPS: &[&str] = &["filters", "pause", "seek", "volume"];
+
+/// The sender notified once a message is written, or of why it could not be
+/// sent.
+pub type Ack = oneshot::Sender<Result<(), Error>>;
+
+/// A message sent via [`Node::send`], with the sender notified once it is
+/// written.
+///
+/// [`Node::send`]: struct.Node.html#method.send
+pub type AckedMessage = (OwnedMessage, Ack);
+
+/// A message to send to a node, with the senders to notify once it is
+/// written.
+pub type Outgoing = (OwnedMessage, Vec<Ack>);
+
+/// A stream of the messages sent by the user to a node, yielding the pending
+/// messages of each guild in turn.
+///
+/// Messages are drained from the receivers into a queue per guild - up to the
+/// capacity - and are yielded by start-time fair queueing, so a guild sending
+/// many commands while the node's sink is saturated can't starve the commands
+/// of other guilds. Each message is tagged when drained with the virtual time
+/// at which its guild's previous message is done, which carries across
+/// drains: a guild whose messages are drained later isn't queued behind the
+/// whole backlog of a guild drained before it.
+///
+/// Every message for a guild passes through its queue, whether it was sent
+/// by a player or via [`Node::send`], so the order of messages for a single
+/// guild is preserved. A message replacing the state set by the message
+/// queued before it, such as consecutive volume changes, is coalesced into
+/// it.
+///
+/// Messages without a guild, such as a close, are pinned in place: they are
+/// yielded after every message drained before them, and before every message
+/// drained after them.
+///
+/// [`Node::send`]: struct.Node.html#method.send
+pub struct FairScheduler {
+    acked: UnboundedReceiver<AckedMessage>,
+    acked_ended: bool,
+    buffered: usize,
+    capacity: usize,
+    coalesced: u64,
+    ended: bool,
+    front_seq: i64,
+    next_seq: i64,
+    queues: HashMap<Option<u64>, VecDeque<Queued>>,
+    receiver: SyncReceiver<OwnedMessage>,
+    virtual_time: u64,
+}
+
+impl FairScheduler {
+    /// Creates a new scheduler over the receivers of messages from players
+    /// and of acked messages, buffering up to `capacity` messages at once.
+    pub fn new(
+        receiver: SyncReceiver<OwnedMessage>,
+        acked: UnboundedReceiver<AckedMessage>,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            acked,
+            acked_ended: false,
+            buffered: 0,
+            capacity: capacity.max(1),
+            coalesced: 0,
+            ended: false,
+            front_seq: 0,
+            next_seq: 0,
+            queues: HashMap::new(),
+            receiver,
+            virtual_time: 0,
+        }
+    }
+
+    /// The number of messages drained from the receivers but not yet yielded.
+    #[inline]
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    /// The number of messages that were coalesced into a message queued
+    /// before them.
+    #[inline]
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+
+    /// Puts messages back at the front of their guilds' queues, such as
+    /// messages buffered while the node was disconnected, so that they are
+    /// sent before messages queued since.
+    pub fn requeue(&mut self, messages: VecDeque<OwnedMessage>) {
+        for message in messages.into_iter().rev() {
+            self.front_seq -= 1;
+
+            let mut queued = Queued::new(message, Vec::new(), self.front_seq);
+            queued.tag = self.virtual_time;
+
+            self.queues
+                .entry(queued.guild_id)
+                .or_insert_with(VecDeque::new)
+                .push_front(queued);
+            self.buffered += 1;
+        }
+    }
+
+    fn drain(&mut self) -> Result<(), ()> {
+        // Acked messages are drained first, as they are sent to sequence ops
+        // ahead of the commands of players.
+        while !self.acked_ended {
+            match self.acked.poll()? {
+                Async::Ready(Some((message, ack))) => self.push(message, vec![ack]),
+                Async::Ready(None) => self.acked_ended = true,
+                Async::NotReady => break,
+            }
+        }
+
+        while !self.ended && self.buffered < self.capacity {
+            match self.receiver.poll()? {
+                Async::Ready(Some(message)) => self.push(message, Vec::new()),
+                Async::Ready(None) => self.ended = true,
+                Async::NotReady => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, message: OwnedMessage, acks: Vec<Ack>) {
+        let mut queued = Queued::new(message, acks, self.next_seq);
+        self.next_seq += 1;
+
+        // The last message without a guild, which messages queued before it
+        // can't be coalesced across.
+        let pinned = self.queues.get(&None).and_then(VecDeque::back).map(|last| last.seq);
+        let virtual_time = self.virtual_time;
+        let queue = self.queues.entry(queued.guild_id).or_insert_with(VecDeque::new);
+
+        if let Some(last) = queue.back_mut() {
+            if last.is_replaced_by(&queued) && pinned.map_or(true, |seq| seq < last.seq) {
+                last.acks.extend(queued.acks);
+                last.message = queued.message;
+                self.coalesced += 1;
+
+                return;
+            }
+        }
+
+        queued.tag = match queue.back() {
+            Some(last) => virtual_time.max(last.tag + 1),
+            None => virtual_time,
+        };
+
+        queue.push_back(queued);
+        self.buffered += 1;
+    }
+
+    fn pop(&mut self) -> Option<Outgoing> {
+        let pinned = self.queues.get(&None).and_then(VecDeque::front).map(|first| first.seq);
+
+        // The guild with the lowest tag among the messages queued before the
+        // first message without a guild, which is otherwise next.
+        let next = self.queues
+            .iter()
+            .filter_map(|(guild_id, queue)| {
+                let first = queue.front()?;
+
+                if guild_id.is_none() || pinned.map_or(false, |seq| first.seq > seq) {
+                    return None;
+                }
+
+                Some(((first.tag, first.seq), *guild_id))
+            })
+            .min()
+            .map(|(_, guild_id)| guild_id);
+
+        let guild_id = match next {
+            Some(guild_id) => guild_id,
+            None if pinned.is_some() => None,
+            None => return None,
+        };
+
+        let (queued, remaining) = {
+            let queue = self.queues.get_mut(&guild_id)?;
+
+            (queue.pop_front()?, queue.len())
+        };
+
+        if remaining == 0 {
+            self.queues.remove(&guild_id);
+        }
+
+        if guild_id.is_some() {
+            self.virtual_time = self.virtual_time.max(queued.tag);
+        }
+
+        self.buffered -= 1;
+
+        Some((queued.message, queued.acks))
+    }
+}
+
+impl Stream for FairScheduler {
+    type Item = Outgoing;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Outgoing>, ()> {
+        self.drain()?;
+
+        match self.pop() {
+            Some(outgoing) => Ok(Async::Ready(Some(outgoing))),
+            None if self.ended => Ok(Async::Ready(None)),
+            None => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// A message in a guild's queue.
+struct Queued {
+    acks: Vec<Ack>,
+    guild_id: Option<u64>,
+    message: OwnedMessage,
+    op: Option<String>,
+    /// The order in which the message was drained, which is negative for
+    /// requeued messages.
+    seq: i64,
+    /// The virtual time at which the message may start being sent.
+    tag: u64,
+}
+
+impl Queued {
+    fn new(message: OwnedMessage, acks: Vec<Ack>, seq: i64) -> Self {
+        let target = match message {
+            OwnedMessage::Binary(ref bytes) => serde_json::from_slice::<Target>(bytes).ok(),
+            OwnedMessage::Text(ref text) => serde_json::from_str::<Target>(text).ok(),
+            _ => None,
+        }.unwrap_or_default();
+
+        Self {
+            acks,
+            guild_id: target.guild_id.as_ref().and_then(GuildId::from_value).map(|id| id.0),
+            message,
+            op: target.op,
+            seq,
+            tag: 0,
+        }
+    }
+
+    /// Whether the message may be replaced by the next message queued for
+    /// the same guild.
+    fn is_replaced_by(&self, next: &Queued) -> bool {
+        let op = match self.op {
+            Some(ref op) => op,
+            None => return false,
+        };
+
+        self.guild_id.is_some()
+            && next.op.as_ref() == Some(op)
+            && COALESCED_OPS.contains(&&op[..])
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct Target {
+    #[serde(default, rename = "guildId")]
+    guild_id: Option<Value>,
+    #[serde(default)]
+    op: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::mpsc;
+    use std::collections::VecDeque;
+    use websocket::OwnedMessage;
+    use super::FairScheduler;
+
+    fn scheduler() -> FairScheduler {
+        let (_, receiver) = mpsc::channel(1);
+        let (_, acked) = mpsc::unbounded();
+
+        FairScheduler::new(receiver, acked, 16)
+    }
+
+    fn op(guild_id: u64, op: &str, value: i64) -> OwnedMessage {
+        OwnedMessage::Text(json!({
+            "op": op,
+            "guildId": guild_id.to_string(),
+            "value": value,
+        }).to_string())
+    }
+
+    fn close() -> OwnedMessage {
+        OwnedMessage::Close(None)
+    }
+
+    fn push(scheduler: &mut FairScheduler, message: OwnedMessage) {
+        scheduler.push(message, Vec::new());
+    }
+
+    fn pop_all(scheduler: &mut FairScheduler) -> Vec<OwnedMessage> {
+        let mut messages = Vec::new();
+
+        while let Some((message, _)) = scheduler.pop() {
+            messages.push(message);
+        }
+
+        messages
+    }
+
+    #[test]
+    fn test_interleaves_guilds() {
+        let mut scheduler = scheduler();
+        push(&mut scheduler, op(1, "play", 1));
+        push(&mut scheduler, op(1, "play", 2));
+        push(&mut scheduler, op(1, "play", 3));
+        push(&mut scheduler, op(2, "play", 1));
+        push(&mut scheduler, op(2, "play", 2));
+
+        assert_eq!(pop_all(&mut scheduler), vec![
+            op(1, "play", 1),
+            op(2, "play", 1),
+            op(1, "play", 2),
+            op(2, "play", 2),
+            op(1, "play", 3),
+        ]);
+        assert_eq!(scheduler.buffered(), 0);
+    }
+
+    #[test]
+    fn test_fair_across_drains() {
+        let mut scheduler = scheduler();
+        push(&mut scheduler, op(1, "play", 1));
+        push(&mut scheduler, op(1, "play", 2));
+        push(&mut scheduler, op(1, "play", 3));
+
+        assert_eq!(scheduler.pop().map(|(message, _)| message), Some(op(1, "play", 1)));
+
+        // Drained after the first guild's backlog, but not queued behind it.
+        push(&mut scheduler, op(2, "play", 1));
+        push(&mut scheduler, op(2, "play", 2));
+
+        assert_eq!(pop_all(&mut scheduler), vec![
+            op(2, "play", 1),
+            op(1, "play", 2),
+            op(2, "play", 2),
+            op(1, "play", 3),
+        ]);
+    }
+
+    #[test]
+    fn test_pins_messages_without_guild() {
+        let mut scheduler = scheduler();
+        push(&mut scheduler, op(1, "play", 1));
+        push(&mut scheduler, op(1, "play", 2));
+        push(&mut scheduler, close());
+        push(&mut scheduler, op(2, "play", 1));
+
+        assert_eq!(pop_all(&mut scheduler), vec![
+            op(1, "play", 1),
+            op(1, "play", 2),
+            close(),
+            op(2, "play", 1),
+        ]);
+    }
+
+    #[test]
+    fn test_coalesces_replaced_state() {
+        let mut scheduler = scheduler();
+        push(&mut scheduler, op(1, "volume", 10));
+        push(&mut scheduler, op(1, "volume", 20));
+
+        assert_eq!(scheduler.coalesced(), 1);
+        assert_eq!(pop_all(&mut scheduler), vec![op(1, "volume", 20)]);
+    }
+
+    #[test]
+    fn test_does_not_coalesce_across_pinned() {
+        let mut scheduler = scheduler();
+        push(&mut scheduler, op(1, "volume", 10));
+        push(&mut scheduler, close());
+        push(&mut scheduler, op(1, "volume", 20));
+
+        assert_eq!(scheduler.coalesced(), 0);
+        assert_eq!(pop_all(&mut scheduler), vec![
+            op(1, "volume", 10),
+            close(),
+            op(1, "volume", 20),
+        ]);
+    }
+
+    #[test]
+    fn test_requeue_sends_first() {
+        let mut scheduler = scheduler();
+        push(&mut scheduler, op(1, "play", 3));
+        push(&mut scheduler, op(2, "play", 1));
+
+        let requeued = vec![op(1, "play", 1), close(), op(1, "play", 2)];
+        scheduler.requeue(requeued.into_iter().collect::<VecDeque<_>>());
+
+        assert_eq!(pop_all(&mut scheduler), vec![
+            op(1, "play", 1),
+            close(),
+            op(1, "play", 2),
+            op(1, "play", 3),
+            op(2, "play", 1),
+        ]);
+    }
+}
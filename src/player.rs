@@ -2,23 +2,101 @@
 //! audio for guilds.
 
 use futures::sync::mpsc::Sender as MpscSender;
-use futures::Sink;
-use lavalink::model::{
-    Pause,
-    Play,
-    Seek,
-    Stop,
-    Volume,
-};
-use serde_json;
-use std::collections::HashMap;
+use futures::sync::oneshot;
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream, future};
+use serde_json::{self, Value};
+use std::any::Any;
+use std::collections::hash_map::{Values, ValuesMut};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_core::reactor::{Handle, Interval, Remote, Timeout};
 use websocket::OwnedMessage;
-use ::Error;
+use ::model::{GuildId, TrackEndReason};
+use ::rest::{self, LoadedTrackInfo};
+use ::validation::{self, PlayerContext};
+use ::voice::VoiceConnectionManager;
+use ::{Error, EventHandler};
+
+/// Trait for listening to events of a single [`AudioPlayer`].
+///
+/// This is useful for per-guild bookkeeping, as opposed to the global
+/// [`EventHandler`]. Listeners are added via [`AudioPlayer::add_listener`].
+///
+/// The track start and end methods are called once the node sends the event,
+/// without the lock of the [`AudioPlayerManager`] held. The pause, volume,
+/// and seek methods are called when the player sends the command - not once
+/// the node applies it - while the player is borrowed, so they must not lock
+/// the player manager, such as via an [`AudioPlayerHandle`].
+///
+/// All of the methods do nothing by default.
+///
+/// [`AudioPlayer`]: struct.AudioPlayer.html
+/// [`AudioPlayer::add_listener`]: struct.AudioPlayer.html#method.add_listener
+/// [`AudioPlayerHandle`]: struct.AudioPlayerHandle.html
+/// [`AudioPlayerManager`]: struct.AudioPlayerManager.html
+/// [`EventHandler`]: ../trait.EventHandler.html
+pub trait AudioPlayerListener: Send + Sync {
+    /// Called when the node confirms that a track started playing on the
+    /// player.
+    fn track_start(&self, player: &AudioPlayer, track: &str) {
+        let _ = (player, track);
+    }
+
+    /// Called when a track ends on the player.
+    fn track_end(&self, player: &AudioPlayer, track: &str, reason: TrackEndReason) {
+        let _ = (player, track, reason);
+    }
+
+    /// Called when the player sends a command to pause or resume.
+    fn pause_toggle(&self, player: &AudioPlayer, paused: bool) {
+        let _ = (player, paused);
+    }
+
+    /// Called when the player sends a command to change its volume.
+    fn volume_change(&self, player: &AudioPlayer, volume: i32) {
+        let _ = (player, volume);
+    }
+
+    /// Called when the player sends a command to seek to a position.
+    fn seek(&self, player: &AudioPlayer, position: i64) {
+        let _ = (player, position);
+    }
+}
+
+/// Opaque data attached to the track of a player via
+/// [`AudioPlayer::play_with_data`], such as the ID of the user who requested
+/// it.
+///
+/// [`AudioPlayer::play_with_data`]: struct.AudioPlayer.html#method.play_with_data
+pub type TrackData = Arc<Any + Send + Sync>;
+
+/// The volume of a player when none has been set, as used by Lavalink.
+pub const DEFAULT_VOLUME: i32 = 100;
+/// How often the volume of a player is lowered while fading out via
+/// [`AudioPlayerHandle::stop_with_fade`].
+///
+/// [`AudioPlayerHandle::stop_with_fade`]: struct.AudioPlayerHandle.html#method.stop_with_fade
+pub const FADE_STEP: Duration = Duration::from_millis(100);
+/// The maximum volume of a player.
+pub const MAX_VOLUME: i32 = 1000;
+/// The minimum volume of a player.
+pub const MIN_VOLUME: i32 = 0;
 
 /// A light wrapper around a hashmap keyed by guild IDs with audio players.
-#[derive(Clone, Debug, Default)]
+///
+/// Functions taking a guild ID accept a [`GuildId`] or, while migrating, a
+/// raw `u64`.
+///
+/// [`GuildId`]: ../model/struct.GuildId.html
+#[derive(Clone, Debug)]
 pub struct AudioPlayerManager {
+    default_volume: i32,
     players: HashMap<u64, AudioPlayer>,
+    stuck_policy: Option<StuckPolicy>,
+    validation: bool,
 }
 
 impl AudioPlayerManager {
@@ -28,40 +106,437 @@ impl AudioPlayerManager {
         Self::default()
     }
 
+    /// The volume applied to newly created players.
+    ///
+    /// This is [`DEFAULT_VOLUME`] unless [set].
+    ///
+    /// [`DEFAULT_VOLUME`]: constant.DEFAULT_VOLUME.html
+    /// [set]: #method.set_default_volume
+    #[inline]
+    pub fn default_volume(&self) -> i32 {
+        self.default_volume
+    }
+
+    /// Sets the volume applied to newly created players.
+    ///
+    /// Returns [`Error::InvalidVolume`] if the volume is not within the range
+    /// of [`MIN_VOLUME`] to [`MAX_VOLUME`].
+    ///
+    /// [`Error::InvalidVolume`]: ../enum.Error.html#variant.InvalidVolume
+    /// [`MAX_VOLUME`]: constant.MAX_VOLUME.html
+    /// [`MIN_VOLUME`]: constant.MIN_VOLUME.html
+    pub fn set_default_volume(&mut self, volume: i32) -> Result<(), Error> {
+        validate_volume(volume)?;
+        self.default_volume = volume;
+
+        Ok(())
+    }
+
+    /// Sets the number of shards that every audio player uses to calculate
+    /// the shard to forward payloads to, such as after resharding.
+    pub fn set_num_shards(&mut self, num_shards: u64) {
+        for player in self.players.values_mut() {
+            player.voice_connections.set_num_shards(num_shards);
+        }
+    }
+
+    /// Sets how every audio player - existing and newly created - recovers
+    /// from its track being stuck.
+    ///
+    /// Refer to [`AudioPlayer::stuck_policy`] for more information.
+    ///
+    /// [`AudioPlayer::stuck_policy`]: struct.AudioPlayer.html#structfield.stuck_policy
+    pub fn set_stuck_policy(&mut self, stuck_policy: Option<StuckPolicy>) {
+        self.stuck_policy = stuck_policy;
+
+        for player in self.players.values_mut() {
+            player.stuck_policy = stuck_policy;
+        }
+    }
+
+    /// Sets whether every audio player - existing and newly created - checks
+    /// its outgoing payloads before they are sent.
+    ///
+    /// Refer to [`AudioPlayer::validation`] for more information.
+    ///
+    /// [`AudioPlayer::validation`]: struct.AudioPlayer.html#structfield.validation
+    pub fn set_validation(&mut self, validation: bool) {
+        self.validation = validation;
+
+        for player in self.players.values_mut() {
+            player.validation = validation;
+        }
+    }
+
+    /// The last applied volume of the guild's player, if it has one.
+    pub fn last_volume<G: Into<GuildId>>(&self, guild_id: G) -> Option<i32> {
+        self.players.get(&guild_id.into().0).map(|player| player.volume)
+    }
+
     /// Creates an audio player for the guild of the given ID.
     ///
     /// The `sender` must be a clone of [`Node::user_to_node`].
     ///
+    /// The `node_websocket_host` is the websocket host of the node that the
+    /// sender belongs to.
+    ///
     /// It may be preferable to use [`NodeManager::create_player`].
     ///
     /// [`Node::user_to_node`]: ../nodes/struct.Node.html#structfield.user_to_node
     /// [`NodeManager::create_player`]: ../nodes/struct.NodeManager.html#method.create_player
-    pub fn create(&mut self, guild_id: u64, sender: MpscSender<OwnedMessage>)
-        -> Result<&mut AudioPlayer, Error> {
+    pub fn create<G: Into<GuildId>>(
+        &mut self,
+        guild_id: G,
+        node_websocket_host: String,
+        sender: MpscSender<OwnedMessage>,
+    ) -> Result<&mut AudioPlayer, Error> {
+        let guild_id = guild_id.into().0;
+
         if self.players.contains_key(&guild_id) {
             return Err(Error::PlayerAlreadyExists);
         }
 
-        self.players.insert(guild_id, AudioPlayer::new(guild_id, sender));
+        let mut player = AudioPlayer::new(guild_id, node_websocket_host, sender);
+
+        if self.default_volume != DEFAULT_VOLUME {
+            if let Err(why) = player.volume(self.default_volume) {
+                event!(warn, guild_id = guild_id; "Err applying volume to player: {:?}", why);
+            }
+        }
+
+        player.stuck_policy = self.stuck_policy;
+        player.validation = self.validation;
+        self.players.insert(guild_id, player);
 
         Ok(self.players.get_mut(&guild_id).unwrap())
     }
 
     /// Retrieves an immutable reference to the audio player for the guild, if
     /// it exists.
-    pub fn get(&self, guild_id: &u64) -> Option<&AudioPlayer> {
-        self.players.get(guild_id)
+    pub fn get<G: Into<GuildId>>(&self, guild_id: G) -> Option<&AudioPlayer> {
+        self.players.get(&guild_id.into().0)
     }
 
     /// Retrieves a mutable reference to the audio player for the guild, if it
     /// exists.
-    pub fn get_mut(&mut self, guild_id: &u64) -> Option<&mut AudioPlayer> {
-        self.players.get_mut(guild_id)
+    pub fn get_mut<G: Into<GuildId>>(&mut self, guild_id: G) -> Option<&mut AudioPlayer> {
+        self.players.get_mut(&guild_id.into().0)
     }
 
     /// Whether the manager contains a player for the given guild.
-    pub fn has(&self, guild_id: &u64) -> bool {
-        self.players.contains_key(guild_id)
+    pub fn has<G: Into<GuildId>>(&self, guild_id: G) -> bool {
+        self.players.contains_key(&guild_id.into().0)
+    }
+
+    /// Whether the manager contains no audio players.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    /// An iterator over every audio player, in arbitrary order.
+    #[inline]
+    pub fn iter(&self) -> Values<u64, AudioPlayer> {
+        self.players.values()
+    }
+
+    /// An iterator over mutable references to every audio player, in
+    /// arbitrary order.
+    #[inline]
+    pub fn iter_mut(&mut self) -> ValuesMut<u64, AudioPlayer> {
+        self.players.values_mut()
+    }
+
+    /// The number of audio players in the manager.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    /// The IDs of the guilds with audio players, in arbitrary order.
+    pub fn guild_ids(&self) -> Vec<u64> {
+        self.players.keys().cloned().collect()
+    }
+
+    /// The audio players on the node with the given websocket host, in
+    /// arbitrary order.
+    pub fn players_on_node(&self, node_websocket_host: &str) -> Vec<&AudioPlayer> {
+        self.players
+            .values()
+            .filter(|player| player.node_websocket_host == node_websocket_host)
+            .collect()
+    }
+
+    /// Marks every audio player on the node with the given websocket host as
+    /// having lost the connection to it. Refer to
+    /// [`AudioPlayer::mark_node_disconnected`] for more information.
+    ///
+    /// Returns clones of the players that were newly marked.
+    ///
+    /// [`AudioPlayer::mark_node_disconnected`]: struct.AudioPlayer.html#method.mark_node_disconnected
+    pub fn mark_node_disconnected(&mut self, node_websocket_host: &str) -> Vec<AudioPlayer> {
+        self.players
+            .values_mut()
+            .filter(|player| {
+                player.node_websocket_host == node_websocket_host && !player.node_disconnected
+            })
+            .map(|player| {
+                player.mark_node_disconnected();
+
+                player.clone()
+            })
+            .collect()
+    }
+
+    /// Marks every audio player on the node with the given websocket host as
+    /// being reconnected to it. Refer to
+    /// [`AudioPlayer::mark_node_reconnected`] for more information.
+    ///
+    /// Returns the number of players that were marked.
+    ///
+    /// [`AudioPlayer::mark_node_reconnected`]: struct.AudioPlayer.html#method.mark_node_reconnected
+    pub fn mark_node_reconnected(&mut self, node_websocket_host: &str) -> usize {
+        let mut count = 0;
+
+        for player in self.players.values_mut() {
+            if player.node_websocket_host == node_websocket_host && player.node_disconnected {
+                player.mark_node_reconnected();
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Retains only the audio players for which the predicate returns `true`,
+    /// removing the others.
+    ///
+    /// This is useful for pruning players, such as those of idle guilds.
+    ///
+    /// Returns the number of players that were removed.
+    pub fn retain<F>(&mut self, mut f: F) -> usize
+        where F: FnMut(&mut AudioPlayer) -> bool {
+        let before = self.players.len();
+
+        self.players.retain(|_, player| f(player));
+
+        before - self.players.len()
+    }
+
+    /// Pauses or unpauses every audio player, such as when entering a
+    /// maintenance mode.
+    ///
+    /// The players are iterated grouped by node. Returns the result of each
+    /// player, keyed by guild ID.
+    pub fn pause_all(&mut self, pause: bool) -> HashMap<u64, Result<(), Error>> {
+        self.for_each_by_node(|player| player.pause(pause))
+    }
+
+    /// Sets the volume of every audio player.
+    ///
+    /// The players are iterated grouped by node. Returns the result of each
+    /// player, keyed by guild ID. Refer to [`AudioPlayer::volume`] for more
+    /// information.
+    ///
+    /// [`AudioPlayer::volume`]: struct.AudioPlayer.html#method.volume
+    pub fn set_volume_all(&mut self, volume: i32) -> HashMap<u64, Result<(), Error>> {
+        self.for_each_by_node(|player| player.volume(volume))
+    }
+
+    /// Stops every audio player, such as before gracefully shutting down a
+    /// shard.
+    ///
+    /// The players are iterated grouped by node. Returns the result of each
+    /// player, keyed by guild ID.
+    pub fn stop_all(&mut self) -> HashMap<u64, Result<(), Error>> {
+        self.for_each_by_node(|player| player.stop())
+    }
+
+    /// Calls a function with every player, one node at a time, so that the
+    /// messages to each node are sent together.
+    fn for_each_by_node<F>(&mut self, mut f: F) -> HashMap<u64, Result<(), Error>>
+        where F: FnMut(&mut AudioPlayer) -> Result<(), Error> {
+        let mut guild_ids = self.players
+            .values()
+            .map(|player| (player.node_websocket_host.clone(), player.guild_id.0))
+            .collect::<Vec<_>>();
+        guild_ids.sort();
+
+        let mut results = HashMap::with_capacity(guild_ids.len());
+
+        for (_, guild_id) in guild_ids {
+            if let Some(player) = self.players.get_mut(&guild_id) {
+                results.insert(guild_id, f(player));
+            }
+        }
+
+        results
+    }
+
+    /// Replaces the sender of every audio player on the node with the given
+    /// websocket host.
+    ///
+    /// This is used when a node is reconnected to, so that players send over
+    /// the new connection.
+    ///
+    /// Returns the number of players that were updated.
+    pub fn replace_sender(
+        &mut self,
+        node_websocket_host: &str,
+        sender: &MpscSender<OwnedMessage>,
+    ) -> usize {
+        let mut count = 0;
+
+        for player in self.players.values_mut() {
+            if player.node_websocket_host == node_websocket_host {
+                player.set_sender(sender.clone());
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Moves every audio player on the node with the websocket host `from` to
+    /// the node with the websocket host `to`, using the given sender of the
+    /// new node.
+    ///
+    /// **Note**: The new node does not know about the players. A voice update
+    /// must be sent for each player before they can be used again.
+    ///
+    /// Returns the number of players that were moved.
+    pub fn migrate(
+        &mut self,
+        from: &str,
+        to: &str,
+        sender: &MpscSender<OwnedMessage>,
+    ) -> usize {
+        let mut count = 0;
+
+        for player in self.players.values_mut() {
+            if player.node_websocket_host == from {
+                player.node_websocket_host = to.to_owned();
+                player.set_sender(sender.clone());
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Replays the state of every audio player on the node with the given
+    /// websocket host.
+    ///
+    /// This is used when a node is reconnected to without resuming its
+    /// session, as the node no longer knows about the players. Refer to
+    /// [`AudioPlayer::replay_state`] for more information.
+    ///
+    /// Returns the number of players that were replayed.
+    ///
+    /// [`AudioPlayer::replay_state`]: struct.AudioPlayer.html#method.replay_state
+    pub fn replay_state(&mut self, node_websocket_host: &str) -> usize {
+        let mut count = 0;
+
+        for player in self.players.values_mut() {
+            if player.node_websocket_host != node_websocket_host {
+                continue;
+            }
+
+            match player.replay_state() {
+                Ok(()) => count += 1,
+                Err(why) => {
+                    event!(warn, guild_id = player.guild_id; "Err replaying player: {:?}", why);
+                },
+            }
+        }
+
+        count
+    }
+
+    /// The IDs of the guilds with audio players on the node with the given
+    /// websocket host.
+    pub fn guild_ids_on_node(&self, node_websocket_host: &str) -> Vec<u64> {
+        self.players
+            .values()
+            .filter(|player| player.node_websocket_host == node_websocket_host)
+            .map(|player| player.guild_id.0)
+            .collect()
+    }
+
+    /// Creates a snapshot of the state of every audio player.
+    ///
+    /// The snapshots can be persisted before restarting and passed to
+    /// [`restore`] afterwards to resume playback. The position of each
+    /// snapshot is the player's [estimated position].
+    ///
+    /// [`restore`]: #method.restore
+    /// [estimated position]: struct.AudioPlayer.html#method.estimated_position
+    pub fn snapshot(&self) -> Vec<PlayerSnapshot> {
+        self.players.values().map(PlayerSnapshot::from).collect()
+    }
+
+    /// Creates audio players from snapshots on the node with the given
+    /// websocket host, and replays their state to the node via
+    /// [`AudioPlayer::replay_state`].
+    ///
+    /// The `sender` must be a clone of the node's [`Node::user_to_node`].
+    /// Snapshots of guilds that already have a player are skipped.
+    ///
+    /// It may be preferable to use [`NodeManager::restore`], which restores
+    /// players onto the node they were on if it is available.
+    ///
+    /// Returns the number of players that were restored.
+    ///
+    /// [`AudioPlayer::replay_state`]: struct.AudioPlayer.html#method.replay_state
+    /// [`Node::user_to_node`]: ../nodes/struct.Node.html#structfield.user_to_node
+    /// [`NodeManager::restore`]: ../nodes/struct.NodeManager.html#method.restore
+    pub fn restore(
+        &mut self,
+        snapshots: Vec<PlayerSnapshot>,
+        node_websocket_host: &str,
+        sender: &MpscSender<OwnedMessage>,
+    ) -> usize {
+        let mut count = 0;
+
+        for snapshot in snapshots {
+            if self.players.contains_key(&snapshot.guild_id) {
+                continue;
+            }
+
+            let guild_id = snapshot.guild_id;
+            let mut player = AudioPlayer::new(
+                guild_id,
+                node_websocket_host.to_owned(),
+                sender.clone(),
+            );
+            player.channel_id = snapshot.channel_id;
+            player.connected = snapshot.voice.is_some();
+            player.filters = snapshot.filters;
+            player.paused = snapshot.paused;
+            player.position = snapshot.position;
+            player.queue = snapshot.queue.into_iter().collect();
+            player.track = snapshot.track;
+            player.voice = snapshot.voice;
+            player.volume = snapshot.volume.max(MIN_VOLUME).min(MAX_VOLUME);
+
+            if let Err(why) = player.replay_state() {
+                event!(warn, guild_id = guild_id; "Err replaying restored player: {:?}", why);
+            }
+
+            player.validation = self.validation;
+            self.players.insert(guild_id, player);
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Removes all of the audio players on the node with the given websocket
+    /// host.
+    ///
+    /// Returns the number of players that were removed.
+    pub fn remove_on_node(&mut self, node_websocket_host: &str) -> usize {
+        self.retain(|player| player.node_websocket_host != node_websocket_host)
     }
 
     /// Removes an audio player by guild ID.
@@ -69,113 +544,3336 @@ impl AudioPlayerManager {
     /// Calls [`AudioPlayer::leave`] if it is connected.
     ///
     /// [`AudioPlayer::leave`]: struct.AudioPlayer.html#method.leave
-    pub fn remove(&mut self, guild_id: &u64) -> bool {
-        self.players.remove(guild_id).is_some()
+    pub fn remove<G: Into<GuildId>>(&mut self, guild_id: G) -> bool {
+        let guild_id = guild_id.into().0;
+
+        self.players.remove(&guild_id).is_some()
     }
 }
 
-/// A struct containing the state of a guild's audio player.
+impl Default for AudioPlayerManager {
+    fn default() -> Self {
+        Self {
+            default_volume: DEFAULT_VOLUME,
+            players: HashMap::new(),
+            stuck_policy: None,
+            validation: false,
+        }
+    }
+}
+
+/// A cloneable handle to a guild's [`AudioPlayer`] within an
+/// [`AudioPlayerManager`], which can send commands to the player without
+/// needing to look it up in the manager each time.
+///
+/// The manager is only locked for the duration of each command. Commands
+/// return [`Error::None`] if the player has since been removed.
+///
+/// A handle is returned from [`NodeManager::create_player`].
+///
+/// [`AudioPlayer`]: struct.AudioPlayer.html
+/// [`AudioPlayerManager`]: struct.AudioPlayerManager.html
+/// [`Error::None`]: ../enum.Error.html#variant.None
+/// [`NodeManager::create_player`]: ../nodes/struct.NodeManager.html#method.create_player
 #[derive(Clone, Debug)]
-pub struct AudioPlayer {
-    /// The ID of the guild that the player represents.
-    pub guild_id: u64,
-    /// Whether the player is paused.
-    pub paused: bool,
-    /// The estimated position of the player.
-    pub position: i64,
-    sender: MpscSender<OwnedMessage>,
-    /// The current time of the player.
-    pub time: i64,
-    /// The track that the player is playing.
-    pub track: Option<String>,
-    /// The volume setting, on a scale of 0 to 150.
-    pub volume: i32,
+pub struct AudioPlayerHandle {
+    guild_id: GuildId,
+    manager: Arc<Mutex<AudioPlayerManager>>,
 }
 
-impl AudioPlayer {
-    /// Creates a new audio player.
-    ///
-    /// Using [`AudioPlayerManager::create`] or [`NodeManager::create_player`]
-    /// is the preferred method of creating a new player.
-    ///
-    /// [`AudioPlayerManager::create`]: struct.AudioPlayerManager.html#method.create
-    /// [`NodeManager::create_player`]: ../nodes/struct.NodeManager.html#method.create_player
-    pub fn new(guild_id: u64, sender: MpscSender<OwnedMessage>) -> Self {
+impl AudioPlayerHandle {
+    /// Creates a new handle to the player for the guild within the manager.
+    pub fn new<G: Into<GuildId>>(guild_id: G, manager: Arc<Mutex<AudioPlayerManager>>) -> Self {
         Self {
-            paused: false,
-            position: 0,
-            time: 0,
-            track: None,
-            volume: 100,
-            guild_id,
-            sender,
+            guild_id: guild_id.into(),
+            manager,
         }
     }
 
-    /// Sends a message to Lavalink telling it to either pause or unpause the
-    /// player.
-    pub fn pause(&mut self, pause: bool) -> Result<(), Error> {
-        let msg = serde_json::to_vec(&Pause::new(
-            &self.guild_id.to_string()[..],
-            pause,
-        ))?;
+    /// Adds a track to the back of the player's queue.
+    ///
+    /// Refer to [`AudioPlayer::enqueue`] for more information.
+    ///
+    /// [`AudioPlayer::enqueue`]: struct.AudioPlayer.html#method.enqueue
+    pub fn enqueue(&self, track: &str) -> Result<bool, Error> {
+        self.with_player(|player| player.enqueue(track))
+    }
+
+    /// The ID of the guild that the player is for.
+    #[inline]
+    pub fn guild_id(&self) -> GuildId {
+        self.guild_id
+    }
 
-        self.send(OwnedMessage::Binary(msg))
+    /// Retrieves a clone of the current state of the player.
+    pub fn player(&self) -> Result<AudioPlayer, Error> {
+        self.with_player(|player| Ok(player.clone()))
     }
 
-    /// Sends a message to Lavalink telling it to play a track with optional
-    /// configuration settings.
+    /// Pauses or unpauses the player.
+    ///
+    /// Refer to [`AudioPlayer::pause`] for more information.
+    ///
+    /// [`AudioPlayer::pause`]: struct.AudioPlayer.html#method.pause
+    pub fn pause(&self, pause: bool) -> Result<(), Error> {
+        self.with_player(|player| player.pause(pause))
+    }
+
+    /// Like [`pause`], but resolves once the command has been flushed.
+    ///
+    /// Refer to [`AudioPlayer::pause_async`] for more information.
+    ///
+    /// [`AudioPlayer::pause_async`]: struct.AudioPlayer.html#method.pause_async
+    /// [`pause`]: #method.pause
+    pub fn pause_async(&self, pause: bool) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_player_async(|player| player.pause_async(pause))
+    }
+
+    /// Pauses the player once the delay has passed, such as for a sleep
+    /// timer, resolving once the pause has been flushed.
+    ///
+    /// The pause is sent by a timer on the tokio Core of the handle, which
+    /// looks the player up in the manager when it fires. Dropping the future
+    /// before the delay has passed cancels the pause.
+    pub fn pause_after(&self, delay: Duration, handle: &Handle)
+        -> Box<Future<Item = (), Error = Error>> {
+        let timer = match Timeout::new(delay, handle) {
+            Ok(timer) => timer,
+            Err(why) => return Box::new(future::err(Error::from(why).with_guild(self.guild_id))),
+        };
+        let player = self.clone();
+
+        Box::new(timer.from_err().and_then(move |_| player.pause_async(true)))
+    }
+
+    /// Plays a track on the player.
+    ///
+    /// Refer to [`AudioPlayer::play`] for more information.
+    ///
+    /// [`AudioPlayer::play`]: struct.AudioPlayer.html#method.play
     pub fn play(
-        &mut self,
+        &self,
         track: &str,
         start_time: Option<u64>,
         end_time: Option<u64>,
     ) -> Result<(), Error> {
-        let msg = serde_json::to_vec(&Play::new(
-            &self.guild_id.to_string()[..],
-            track,
-            start_time,
-            end_time,
-        ))?;
+        self.with_player(|player| player.play(track, start_time, end_time))
+    }
+
+    /// Like [`play`], but resolves once the command has been flushed.
+    ///
+    /// Refer to [`AudioPlayer::play_async`] for more information.
+    ///
+    /// [`AudioPlayer::play_async`]: struct.AudioPlayer.html#method.play_async
+    /// [`play`]: #method.play
+    pub fn play_async(
+        &self,
+        track: &str,
+        start_time: Option<u64>,
+        end_time: Option<u64>,
+    ) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_player_async(|player| player.play_async(track, start_time, end_time))
+    }
 
-        self.send(OwnedMessage::Binary(msg))
+    /// Adds a track to the front of the player's queue.
+    ///
+    /// Refer to [`AudioPlayer::play_next`] for more information.
+    ///
+    /// [`AudioPlayer::play_next`]: struct.AudioPlayer.html#method.play_next
+    pub fn play_next(&self, track: &str) -> Result<bool, Error> {
+        self.with_player(|player| player.play_next(track))
     }
 
-    /// Sends a message to Lavalink telling it to seek the player to a certain
-    /// position.
-    pub fn seek(&mut self, position: i64) -> Result<(), Error> {
-        let msg = serde_json::to_vec(&Seek::new(
-            &self.guild_id.to_string()[..],
-            position,
-        ))?;
+    /// Plays a track on the player immediately, replacing the current track.
+    ///
+    /// Refer to [`AudioPlayer::play_now`] for more information.
+    ///
+    /// [`AudioPlayer::play_now`]: struct.AudioPlayer.html#method.play_now
+    pub fn play_now(&self, track: &str) -> Result<(), Error> {
+        self.with_player(|player| player.play_now(track))
+    }
 
-        self.send(OwnedMessage::Binary(msg))
+    /// Plays a track on the player with the given options.
+    ///
+    /// Refer to [`AudioPlayer::play_with_options`] for more information.
+    ///
+    /// [`AudioPlayer::play_with_options`]: struct.AudioPlayer.html#method.play_with_options
+    pub fn play_with_options(&self, track: &str, options: &PlayOptions) -> Result<(), Error> {
+        self.with_player(|player| player.play_with_options(track, options))
     }
 
-    /// Sends a message to Lavalink telling it to stop the player.
-    pub fn stop(&mut self) -> Result<(), Error> {
-        let msg = serde_json::to_vec(&Stop::new(
-            &self.guild_id.to_string()[..],
-        ))?;
+    /// Like [`play_with_options`], but resolves once the command has been flushed.
+    ///
+    /// Refer to [`AudioPlayer::play_with_options_async`] for more information.
+    ///
+    /// [`AudioPlayer::play_with_options_async`]: struct.AudioPlayer.html#method.play_with_options_async
+    /// [`play_with_options`]: #method.play_with_options
+    pub fn play_with_options_async(&self, track: &str, options: &PlayOptions)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_player_async(|player| player.play_with_options_async(track, options))
+    }
 
-        self.send(OwnedMessage::Binary(msg))
+    /// Plays a track on the player, attaching data to the track.
+    ///
+    /// Refer to [`AudioPlayer::play_with_data`] for more information.
+    ///
+    /// [`AudioPlayer::play_with_data`]: struct.AudioPlayer.html#method.play_with_data
+    pub fn play_with_data<T: Any + Send + Sync>(&self, track: &str, data: T)
+        -> Result<(), Error> {
+        self.with_player(|player| player.play_with_data(track, data))
     }
 
-    /// Sends a message to Lavalink telling it to mutate the volume setting.
-    pub fn volume(&mut self, volume: i32) -> Result<(), Error> {
-        let msg = serde_json::to_vec(&Volume::new(
-            &self.guild_id.to_string()[..],
-            volume,
-        ))?;
+    /// Plays the track that most recently finished playing on the player
+    /// again.
+    ///
+    /// Refer to [`AudioPlayer::previous`] for more information.
+    ///
+    /// [`AudioPlayer::previous`]: struct.AudioPlayer.html#method.previous
+    pub fn previous(&self) -> Result<Option<String>, Error> {
+        self.with_player(|player| player.previous())
+    }
 
-        self.send(OwnedMessage::Binary(msg))
+    /// Seeks the player to a position.
+    ///
+    /// Refer to [`AudioPlayer::seek`] for more information.
+    ///
+    /// [`AudioPlayer::seek`]: struct.AudioPlayer.html#method.seek
+    pub fn seek(&self, position: i64) -> Result<(), Error> {
+        self.with_player(|player| player.seek(position))
     }
 
-    /// Sends a WebSocket message over the node.
+    /// Like [`seek`], but resolves once the command has been flushed.
     ///
-    /// You should prefer using one of the other methods where it makes sense.
-    #[inline]
-    pub fn send(&mut self, message: OwnedMessage) -> Result<(), Error> {
-        self.sender.start_send(message).map(|_| ()).map_err(From::from)
+    /// Refer to [`AudioPlayer::seek_async`] for more information.
+    ///
+    /// [`AudioPlayer::seek_async`]: struct.AudioPlayer.html#method.seek_async
+    /// [`seek`]: #method.seek
+    pub fn seek_async(&self, position: i64) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_player_async(|player| player.seek_async(position))
+    }
+
+    /// Applies filters to the player.
+    ///
+    /// Refer to [`AudioPlayer::set_filters`] for more information.
+    ///
+    /// [`AudioPlayer::set_filters`]: struct.AudioPlayer.html#method.set_filters
+    pub fn set_filters(&self, filters: Filters) -> Result<(), Error> {
+        self.with_player(|player| player.set_filters(filters))
+    }
+
+    /// Like [`set_filters`], but resolves once the command has been flushed.
+    ///
+    /// Refer to [`AudioPlayer::set_filters_async`] for more information.
+    ///
+    /// [`AudioPlayer::set_filters_async`]: struct.AudioPlayer.html#method.set_filters_async
+    /// [`set_filters`]: #method.set_filters
+    pub fn set_filters_async(&self, filters: Filters)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_player_async(|player| player.set_filters_async(filters))
+    }
+
+    /// Stops the player.
+    ///
+    /// Refer to [`AudioPlayer::stop`] for more information.
+    ///
+    /// [`AudioPlayer::stop`]: struct.AudioPlayer.html#method.stop
+    pub fn stop(&self) -> Result<(), Error> {
+        self.with_player(|player| player.stop())
+    }
+
+    /// Like [`stop`], but resolves once the command has been flushed.
+    ///
+    /// Refer to [`AudioPlayer::stop_async`] for more information.
+    ///
+    /// [`AudioPlayer::stop_async`]: struct.AudioPlayer.html#method.stop_async
+    /// [`stop`]: #method.stop
+    pub fn stop_async(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_player_async(|player| player.stop_async())
+    }
+
+    /// Fades the volume of the player out to silence over the duration, then
+    /// stops it, resolving once the stop has been flushed.
+    ///
+    /// The volume is lowered by a timer on the tokio Core of the handle every
+    /// [`FADE_STEP`], locking the manager for each step. Once stopped, the
+    /// volume is set back to what it was, so that the next track isn't
+    /// silent. Dropping the future cancels the rest of the fade, leaving the
+    /// volume where it was.
+    ///
+    /// [`FADE_STEP`]: constant.FADE_STEP.html
+    pub fn stop_with_fade(&self, duration: Duration, handle: &Handle)
+        -> Box<Future<Item = (), Error = Error>> {
+        let steps = (millis(duration) / millis(FADE_STEP)).max(1);
+
+        let initial = match self.with_player(|player| Ok(player.volume)) {
+            Ok(initial) => initial,
+            Err(why) => return Box::new(future::err(why)),
+        };
+        let interval = match Interval::new(FADE_STEP, handle) {
+            Ok(interval) => interval,
+            Err(why) => return Box::new(future::err(Error::from(why).with_guild(self.guild_id))),
+        };
+        let player = self.clone();
+        let player2 = self.clone();
+
+        let done = interval
+            .take(steps)
+            .from_err()
+            .fold(1, move |step, _| {
+                // The last step is silence, so the stop isn't audible.
+                let volume = (i64::from(initial) * (steps - step) as i64 / steps as i64) as i32;
+
+                player.volume(volume).map(|_| step + 1)
+            })
+            .and_then(move |_| {
+                // Commands are sent in order, so the stop has been flushed
+                // once the volume is.
+                player2.with_player_async(|player| match player.stop() {
+                    Ok(()) => player.volume_async(initial),
+                    Err(why) => Box::new(future::err(why)),
+                })
+            });
+
+        Box::new(done)
+    }
+
+    /// Sets the volume of the player.
+    ///
+    /// Refer to [`AudioPlayer::volume`] for more information.
+    ///
+    /// [`AudioPlayer::volume`]: struct.AudioPlayer.html#method.volume
+    pub fn volume(&self, volume: i32) -> Result<(), Error> {
+        self.with_player(|player| player.volume(volume))
+    }
+
+    /// Like [`volume`], but resolves once the command has been flushed.
+    ///
+    /// Refer to [`AudioPlayer::volume_async`] for more information.
+    ///
+    /// [`AudioPlayer::volume_async`]: struct.AudioPlayer.html#method.volume_async
+    /// [`volume`]: #method.volume
+    pub fn volume_async(&self, volume: i32) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.with_player_async(|player| player.volume_async(volume))
+    }
+
+    /// Changes the volume of the player by an amount.
+    ///
+    /// Refer to [`AudioPlayer::volume_relative`] for more information.
+    ///
+    /// [`AudioPlayer::volume_relative`]: struct.AudioPlayer.html#method.volume_relative
+    pub fn volume_relative(&self, delta: i32) -> Result<i32, Error> {
+        self.with_player(|player| player.volume_relative(delta))
+    }
+
+    fn with_player_async<F>(&self, f: F) -> Box<Future<Item = (), Error = Error> + Send>
+        where F: FnOnce(&mut AudioPlayer) -> Box<Future<Item = (), Error = Error> + Send> {
+        match self.with_player(|player| Ok(f(player))) {
+            Ok(future) => future,
+            Err(why) => Box::new(future::err(why)),
+        }
+    }
+
+    fn with_player<F, T>(&self, f: F) -> Result<T, Error>
+        where F: FnOnce(&mut AudioPlayer) -> Result<T, Error> {
+        let mut manager = lock_players(&self.manager);
+        let player = manager
+            .get_mut(self.guild_id)
+            .ok_or_else(|| Error::None.with_guild(self.guild_id))?;
+
+        f(player)
+    }
+}
+
+/// Audio filters applied to a player via [`AudioPlayer::set_filters`].
+///
+/// Filters that are not set are disabled. Filters are built by chaining
+/// methods, such as for a nightcore effect:
+///
+/// ```rust,no_run
+/// use lavalink_futures::player::{Filters, Timescale};
+///
+/// let filters = Filters::new().timescale(Timescale {
+///     pitch: 1.2,
+///     speed: 1.2,
+///     ..Timescale::default()
+/// });
+/// ```
+///
+/// **Note**: Filters are only supported by Lavalink 3.4 and newer.
+///
+/// [`AudioPlayer::set_filters`]: struct.AudioPlayer.html#method.set_filters
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Filters {
+    /// Mixes the left and right channels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel_mix: Option<ChannelMix>,
+    /// Distorts the audio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distortion: Option<Distortion>,
+    /// Adjusts the gain of frequency bands.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub equalizer: Vec<EqualizerBand>,
+    /// Removes vocals from the audio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub karaoke: Option<Karaoke>,
+    /// Suppresses higher frequencies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_pass: Option<LowPass>,
+    /// Rotates the audio around the stereo channels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Rotation>,
+    /// Changes the speed, pitch, and rate of the audio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timescale: Option<Timescale>,
+    /// Oscillates the volume of the audio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tremolo: Option<Tremolo>,
+    /// Oscillates the pitch of the audio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vibrato: Option<Vibrato>,
+    /// The volume multiplier, from `0.0` to `5.0`, where `1.0` is 100%.
+    ///
+    /// This is applied separately from the player's [`volume`].
+    ///
+    /// [`volume`]: struct.AudioPlayer.html#structfield.volume
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<f64>,
+}
+
+impl Filters {
+    /// Creates a new set of filters with every filter disabled.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the channel mix filter.
+    pub fn channel_mix(mut self, channel_mix: ChannelMix) -> Self {
+        self.channel_mix = Some(channel_mix);
+
+        self
+    }
+
+    /// Sets the distortion filter.
+    pub fn distortion(mut self, distortion: Distortion) -> Self {
+        self.distortion = Some(distortion);
+
+        self
+    }
+
+    /// Sets the gain of an equalizer band, replacing its previous gain.
+    pub fn equalizer(mut self, band: u8, gain: f64) -> Self {
+        self.equalizer.retain(|existing| existing.band != band);
+        self.equalizer.push(EqualizerBand {
+            band,
+            gain,
+        });
+
+        self
+    }
+
+    /// Whether every filter is disabled.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Sets the karaoke filter.
+    pub fn karaoke(mut self, karaoke: Karaoke) -> Self {
+        self.karaoke = Some(karaoke);
+
+        self
+    }
+
+    /// Sets the low pass filter.
+    pub fn low_pass(mut self, low_pass: LowPass) -> Self {
+        self.low_pass = Some(low_pass);
+
+        self
+    }
+
+    /// Sets the rotation filter.
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = Some(rotation);
+
+        self
+    }
+
+    /// Sets the timescale filter.
+    pub fn timescale(mut self, timescale: Timescale) -> Self {
+        self.timescale = Some(timescale);
+
+        self
+    }
+
+    /// Sets the tremolo filter.
+    pub fn tremolo(mut self, tremolo: Tremolo) -> Self {
+        self.tremolo = Some(tremolo);
+
+        self
+    }
+
+    /// Sets the vibrato filter.
+    pub fn vibrato(mut self, vibrato: Vibrato) -> Self {
+        self.vibrato = Some(vibrato);
+
+        self
+    }
+
+    /// Sets the volume multiplier, from `0.0` to `5.0`.
+    pub fn volume(mut self, volume: f64) -> Self {
+        self.volume = Some(volume);
+
+        self
+    }
+}
+
+/// A filter mixing the left and right channels, where each factor is from
+/// `0.0` to `1.0`.
+///
+/// The default leaves both channels unchanged.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelMix {
+    /// How much of the left channel is kept in the left channel.
+    pub left_to_left: f64,
+    /// How much of the left channel is mixed into the right channel.
+    pub left_to_right: f64,
+    /// How much of the right channel is mixed into the left channel.
+    pub right_to_left: f64,
+    /// How much of the right channel is kept in the right channel.
+    pub right_to_right: f64,
+}
+
+impl Default for ChannelMix {
+    fn default() -> Self {
+        Self {
+            left_to_left: 1.0,
+            left_to_right: 0.0,
+            right_to_left: 0.0,
+            right_to_right: 1.0,
+        }
+    }
+}
+
+/// A filter distorting the audio via sine, cosine, and tangent functions.
+///
+/// The default leaves the audio unchanged.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Distortion {
+    /// The offset of the cosine function.
+    pub cos_offset: f64,
+    /// The scale of the cosine function.
+    pub cos_scale: f64,
+    /// The overall offset.
+    pub offset: f64,
+    /// The overall scale.
+    pub scale: f64,
+    /// The offset of the sine function.
+    pub sin_offset: f64,
+    /// The scale of the sine function.
+    pub sin_scale: f64,
+    /// The offset of the tangent function.
+    pub tan_offset: f64,
+    /// The scale of the tangent function.
+    pub tan_scale: f64,
+}
+
+impl Default for Distortion {
+    fn default() -> Self {
+        Self {
+            cos_offset: 0.0,
+            cos_scale: 1.0,
+            offset: 0.0,
+            scale: 1.0,
+            sin_offset: 0.0,
+            sin_scale: 1.0,
+            tan_offset: 0.0,
+            tan_scale: 1.0,
+        }
+    }
+}
+
+/// The gain of one of the 15 bands of the equalizer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct EqualizerBand {
+    /// The band, from `0` (25 Hz) to `14` (16 kHz).
+    pub band: u8,
+    /// The gain, from `-0.25` (muted) to `1.0` (doubled). `0.0` leaves the
+    /// band unchanged.
+    pub gain: f64,
+}
+
+/// A filter removing vocals by suppressing a frequency band, usually
+/// targeting the mono channel.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Karaoke {
+    /// The frequency band to suppress, in Hz.
+    pub filter_band: f64,
+    /// The width of the suppressed band, in Hz.
+    pub filter_width: f64,
+    /// The level of the effect, from `0.0` to `1.0`.
+    pub level: f64,
+    /// The level of the effect on the mono channel, from `0.0` to `1.0`.
+    pub mono_level: f64,
+}
+
+impl Default for Karaoke {
+    fn default() -> Self {
+        Self {
+            filter_band: 220.0,
+            filter_width: 100.0,
+            level: 1.0,
+            mono_level: 1.0,
+        }
+    }
+}
+
+/// A filter suppressing higher frequencies.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LowPass {
+    /// The amount of smoothing, where values above `1.0` suppress more.
+    pub smoothing: f64,
+}
+
+impl Default for LowPass {
+    fn default() -> Self {
+        Self {
+            smoothing: 20.0,
+        }
+    }
+}
+
+/// A filter rotating the audio around the stereo channels, also known as
+/// audio panning.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Rotation {
+    /// The frequency of the rotation in Hz, such as `0.2` for 8D audio.
+    #[serde(rename = "rotationHz")]
+    pub rotation_hz: f64,
+}
+
+/// A filter changing the speed, pitch, and rate of the audio, where `1.0` is
+/// unchanged.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Timescale {
+    /// The pitch multiplier.
+    pub pitch: f64,
+    /// The rate multiplier, which changes both the speed and pitch.
+    pub rate: f64,
+    /// The speed multiplier.
+    pub speed: f64,
+}
+
+impl Default for Timescale {
+    fn default() -> Self {
+        Self {
+            pitch: 1.0,
+            rate: 1.0,
+            speed: 1.0,
+        }
+    }
+}
+
+/// A filter oscillating the volume of the audio.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Tremolo {
+    /// The depth of the oscillation, from `0.0` to `1.0`.
+    pub depth: f64,
+    /// The frequency of the oscillation in Hz, greater than `0.0`.
+    pub frequency: f64,
+}
+
+impl Default for Tremolo {
+    fn default() -> Self {
+        Self {
+            depth: 0.5,
+            frequency: 2.0,
+        }
+    }
+}
+
+/// A filter oscillating the pitch of the audio.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Vibrato {
+    /// The depth of the oscillation, from `0.0` to `1.0`.
+    pub depth: f64,
+    /// The frequency of the oscillation in Hz, from `0.0` to `14.0`.
+    pub frequency: f64,
+}
+
+impl Default for Vibrato {
+    fn default() -> Self {
+        Self {
+            depth: 0.5,
+            frequency: 2.0,
+        }
+    }
+}
+
+/// A named position in a player's track, set via [`AudioPlayer::set_marker`].
+///
+/// [`AudioPlayer::set_marker`]: struct.AudioPlayer.html#method.set_marker
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Marker {
+    /// The name of the marker, given to [`EventHandler::marker_reached`].
+    ///
+    /// [`EventHandler::marker_reached`]: ../trait.EventHandler.html#method.marker_reached
+    pub name: String,
+    /// The position of the marker in milliseconds.
+    pub position: i64,
+    /// Whether the player's position has reached the marker since it was
+    /// last re-armed.
+    pub reached: bool,
+}
+
+/// An op sent to a node, such as a command to a guild's player.
+///
+/// Every op sent by an [`AudioPlayer`] is built as one of these and sent via
+/// [`AudioPlayer::send_op`], which serializes it via [`to_json`]. This allows
+/// ops to be inspected uniformly before they are sent, such as for logging
+/// or metrics.
+///
+/// [`AudioPlayer`]: struct.AudioPlayer.html
+/// [`AudioPlayer::send_op`]: struct.AudioPlayer.html#method.send_op
+/// [`to_json`]: #method.to_json
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutboundOp {
+    /// Configures the node to keep the session for the timeout in seconds
+    /// after the connection is lost, so that it can be resumed with the key.
+    ConfigureResuming {
+        /// The key to resume the session with.
+        key: String,
+        /// How long the node keeps the session for in seconds.
+        timeout: u64,
+    },
+    /// Destroys a guild's player on the node.
+    Destroy {
+        /// The ID of the guild of the player.
+        guild_id: GuildId,
+    },
+    /// Sets the gains of bands of a guild's player's equalizer, via the
+    /// `equalizer` op of Lavalink versions older than 3.4.
+    ///
+    /// Newer versions replace this with [`Filters::equalizer`].
+    ///
+    /// [`Filters::equalizer`]: struct.Filters.html#structfield.equalizer
+    Equalizer {
+        /// The bands to set the gains of.
+        bands: Vec<EqualizerBand>,
+        /// The ID of the guild of the player.
+        guild_id: GuildId,
+    },
+    /// Applies filters to a guild's player, replacing its previous filters.
+    ///
+    /// Nodes reporting a version older than Lavalink v3.4 are sent only the
+    /// equalizer, via the `equalizer` op.
+    Filters {
+        /// The filters to apply.
+        filters: Filters,
+        /// The ID of the guild of the player.
+        guild_id: GuildId,
+    },
+    /// Pauses or unpauses a guild's player.
+    Pause {
+        /// The ID of the guild of the player.
+        guild_id: GuildId,
+        /// Whether to pause the player.
+        pause: bool,
+    },
+    /// Plays a track on a guild's player.
+    Play {
+        /// The position in milliseconds to end the track at, if any.
+        end_time: Option<u64>,
+        /// The ID of the guild of the player.
+        guild_id: GuildId,
+        /// Whether the node should ignore the track if one is playing.
+        no_replace: bool,
+        /// The position in milliseconds to start the track at, if any.
+        start_time: Option<u64>,
+        /// The base64 encoded track.
+        track: String,
+    },
+    /// A payload that is sent as-is, such as an op of a plugin.
+    Raw(Value),
+    /// Seeks a guild's player to a position in milliseconds.
+    Seek {
+        /// The ID of the guild of the player.
+        guild_id: GuildId,
+        /// The position to seek to.
+        position: i64,
+    },
+    /// Stops a guild's player.
+    Stop {
+        /// The ID of the guild of the player.
+        guild_id: GuildId,
+    },
+    /// Sends a guild's voice connection to the node.
+    VoiceUpdate {
+        /// The ID of the guild of the player.
+        guild_id: GuildId,
+        /// The information about the voice connection.
+        voice: VoiceUpdate,
+    },
+    /// Sets the volume of a guild's player.
+    Volume {
+        /// The ID of the guild of the player.
+        guild_id: GuildId,
+        /// The volume to set.
+        volume: i32,
+    },
+}
+
+impl OutboundOp {
+    /// Parses the payload of an op, such as one sent over
+    /// [`Node::user_to_node`].
+    ///
+    /// The payload is [`Raw`] if it isn't one of the other ops, or if it
+    /// can't be represented exactly by one of them - such as a `filters` op
+    /// containing the filters of a plugin - so that [serializing] the op
+    /// gives back the same payload.
+    ///
+    /// [`Node::user_to_node`]: ../nodes/struct.Node.html#structfield.user_to_node
+    /// [`Raw`]: #variant.Raw
+    /// [serializing]: #method.to_json
+    pub fn from_json(payload: Value) -> Self {
+        match parse_op(&payload) {
+            Some(ref op) if op.to_json().ok().as_ref() == Some(&payload) => op.clone(),
+            _ => OutboundOp::Raw(payload),
+        }
+    }
+
+    /// The ID of the guild of the player that the op is for, if any.
+    ///
+    /// Raw payloads are read for their `guildId`.
+    pub fn guild_id(&self) -> Option<GuildId> {
+        match *self {
+            OutboundOp::ConfigureResuming { .. } => None,
+            OutboundOp::Destroy { guild_id }
+            | OutboundOp::Equalizer { guild_id, .. }
+            | OutboundOp::Filters { guild_id, .. }
+            | OutboundOp::Pause { guild_id, .. }
+            | OutboundOp::Play { guild_id, .. }
+            | OutboundOp::Seek { guild_id, .. }
+            | OutboundOp::Stop { guild_id }
+            | OutboundOp::VoiceUpdate { guild_id, .. }
+            | OutboundOp::Volume { guild_id, .. } => Some(guild_id),
+            OutboundOp::Raw(ref payload) => GuildId::from_value(payload.get("guildId")?),
+        }
+    }
+
+    /// The name of the op as sent to the node, such as `"play"`.
+    ///
+    /// Raw payloads are read for their `op`, and are an empty string if they
+    /// have none.
+    pub fn name(&self) -> &str {
+        match *self {
+            OutboundOp::ConfigureResuming { .. } => "configureResuming",
+            OutboundOp::Destroy { .. } => "destroy",
+            OutboundOp::Equalizer { .. } => "equalizer",
+            OutboundOp::Filters { .. } => "filters",
+            OutboundOp::Pause { .. } => "pause",
+            OutboundOp::Play { .. } => "play",
+            OutboundOp::Raw(ref payload) => {
+                payload.get("op").and_then(Value::as_str).unwrap_or("")
+            },
+            OutboundOp::Seek { .. } => "seek",
+            OutboundOp::Stop { .. } => "stop",
+            OutboundOp::VoiceUpdate { .. } => "voiceUpdate",
+            OutboundOp::Volume { .. } => "volume",
+        }
+    }
+
+    /// Serializes the op into the payload sent to the node.
+    pub fn to_json(&self) -> Result<Value, Error> {
+        let payload = match *self {
+            OutboundOp::ConfigureResuming { ref key, timeout } => json!({
+                "op": "configureResuming",
+                "key": key,
+                "timeout": timeout,
+            }),
+            OutboundOp::Destroy { guild_id } => json!({
+                "op": "destroy",
+                "guildId": guild_id.to_string(),
+            }),
+            OutboundOp::Equalizer { ref bands, guild_id } => json!({
+                "op": "equalizer",
+                "guildId": guild_id.to_string(),
+                "bands": bands,
+            }),
+            OutboundOp::Filters { ref filters, guild_id } => {
+                let mut payload = serde_json::to_value(filters)?;
+                payload["op"] = json!("filters");
+                payload["guildId"] = json!(guild_id.to_string());
+
+                payload
+            },
+            OutboundOp::Pause { guild_id, pause } => json!({
+                "op": "pause",
+                "guildId": guild_id.to_string(),
+                "pause": pause,
+            }),
+            OutboundOp::Play { end_time, guild_id, no_replace, start_time, ref track } => {
+                let mut payload = json!({
+                    "op": "play",
+                    "guildId": guild_id.to_string(),
+                    "track": track,
+                    "noReplace": no_replace,
+                });
+
+                if let Some(start_time) = start_time {
+                    payload["startTime"] = json!(start_time);
+                }
+
+                if let Some(end_time) = end_time {
+                    payload["endTime"] = json!(end_time);
+                }
+
+                payload
+            },
+            OutboundOp::Raw(ref payload) => payload.clone(),
+            OutboundOp::Seek { guild_id, position } => json!({
+                "op": "seek",
+                "guildId": guild_id.to_string(),
+                "position": position,
+            }),
+            OutboundOp::Stop { guild_id } => json!({
+                "op": "stop",
+                "guildId": guild_id.to_string(),
+            }),
+            OutboundOp::VoiceUpdate { guild_id, ref voice } => json!({
+                "op": "voiceUpdate",
+                "guildId": guild_id.to_string(),
+                "sessionId": voice.session_id,
+                "event": {
+                    "endpoint": voice.endpoint,
+                    "guild_id": guild_id.to_string(),
+                    "token": voice.token,
+                },
+            }),
+            OutboundOp::Volume { guild_id, volume } => json!({
+                "op": "volume",
+                "guildId": guild_id.to_string(),
+                "volume": volume,
+            }),
+        };
+
+        Ok(payload)
+    }
+
+    /// Serializes the op into a WebSocket message to send to the node.
+    pub fn to_message(&self) -> Result<OwnedMessage, Error> {
+        self.to_json().map(|payload| OwnedMessage::Text(payload.to_string()))
+    }
+}
+
+/// A track that finished playing on a player, as recorded in its
+/// [`history`].
+///
+/// [`history`]: struct.AudioPlayer.html#structfield.history
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PlayedTrack {
+    /// The Unix timestamp in milliseconds of when the track ended.
+    pub ended_at: i64,
+    /// The Unix timestamp in milliseconds of when the track was played, if
+    /// it was played via the player.
+    pub played_at: Option<i64>,
+    /// The reason that the track ended.
+    pub reason: TrackEndReason,
+    /// The track.
+    pub track: String,
+}
+
+/// Options for playing a track, such as via [`NodeManager::play`].
+///
+/// [`NodeManager::play`]: ../nodes/struct.NodeManager.html#method.play
+#[derive(Clone, Debug, Default)]
+pub struct PlayOptions {
+    /// The position in milliseconds to stop the track at, if any.
+    pub end_time: Option<u64>,
+    /// Whether the node should ignore the play if a track is already
+    /// playing, rather than replacing it.
+    ///
+    /// Refer to [`AudioPlayer::play_status`] for whether the play was
+    /// ignored.
+    ///
+    /// [`AudioPlayer::play_status`]: struct.AudioPlayer.html#structfield.play_status
+    pub no_replace: bool,
+    /// The websocket host of the node to create the player on if it does not
+    /// exist. The best node is used if this is not present.
+    ///
+    /// This is ignored by [`AudioPlayer::play_with_options`].
+    ///
+    /// [`AudioPlayer::play_with_options`]: struct.AudioPlayer.html#method.play_with_options
+    pub node_websocket_host: Option<String>,
+    /// The position in milliseconds to start the track at, if any.
+    pub start_time: Option<u64>,
+}
+
+/// The status of the last track played on an [`AudioPlayer`].
+///
+/// [`AudioPlayer`]: struct.AudioPlayer.html
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PlayStatus {
+    /// The node accepted the track, or no track has been played.
+    Accepted,
+    /// The node ignored the track, as it was played with
+    /// [`PlayOptions::no_replace`] while another track was playing.
+    ///
+    /// [`PlayOptions::no_replace`]: struct.PlayOptions.html#structfield.no_replace
+    Ignored,
+    /// The track was played with [`PlayOptions::no_replace`] while another
+    /// track was playing, and it is not yet known whether the node accepted
+    /// it. This is resolved when the node next starts or ends a track.
+    ///
+    /// [`PlayOptions::no_replace`]: struct.PlayOptions.html#structfield.no_replace
+    Pending,
+}
+
+impl Default for PlayStatus {
+    fn default() -> Self {
+        PlayStatus::Accepted
+    }
+}
+
+/// A command to a player translated from its WebSocket op for the REST API
+/// of Lavalink v4, which removed the ops controlling players.
+///
+/// The commands of players on nodes speaking [`ProtocolVersion::V4`] are sent
+/// this way, so players are used in the same way regardless of the node's
+/// version.
+///
+/// [`ProtocolVersion::V4`]: ../nodes/enum.ProtocolVersion.html#variant.V4
+#[derive(Clone, Debug, PartialEq)]
+pub enum RestCommand {
+    /// Destroys the player, via
+    /// `DELETE /v4/sessions/{sessionId}/players/{guildId}`.
+    Destroy {
+        /// The ID of the guild that the player is for.
+        guild_id: GuildId,
+    },
+    /// Updates the player with the fields of the body, via
+    /// `PATCH /v4/sessions/{sessionId}/players/{guildId}`.
+    Update {
+        /// The fields of the player to update, such as `{"paused": true}`.
+        body: Value,
+        /// The ID of the guild that the player is for.
+        guild_id: GuildId,
+        /// Whether a track being played shouldn't replace the current track.
+        no_replace: bool,
+    },
+}
+
+impl RestCommand {
+    /// Translates a message that would be sent over the WebSocket into a
+    /// command, if it is one of a player's ops.
+    ///
+    /// The `destroy`, `equalizer`, `filters`, `pause`, `play`, `seek`,
+    /// `stop`, `voiceUpdate`, and `volume` ops are translated. Returns `None`
+    /// for other messages, which are still sent over the WebSocket.
+    ///
+    /// The `equalizer` op, which v4 removed, is sent as the equalizer of the
+    /// player's filters, so replaces its other filters.
+    pub fn from_message(message: &OwnedMessage) -> Option<Self> {
+        let mut payload = match *message {
+            OwnedMessage::Binary(ref data) => serde_json::from_slice::<Value>(data).ok()?,
+            OwnedMessage::Text(ref data) => serde_json::from_str::<Value>(data).ok()?,
+            _ => return None,
+        };
+
+        let guild_id = GuildId::from_value(payload.get("guildId")?)?;
+        let op = payload.get("op")?.as_str()?.to_owned();
+        let mut no_replace = false;
+
+        let body = match &op[..] {
+            "destroy" => return Some(RestCommand::Destroy { guild_id }),
+            "equalizer" => json!({ "filters": { "equalizer": payload.get("bands")? } }),
+            "filters" => {
+                let filters = payload.as_object_mut()?;
+                filters.remove("guildId");
+                filters.remove("op");
+
+                json!({ "filters": filters })
+            },
+            "pause" => json!({ "paused": payload.get("pause")? }),
+            "play" => {
+                no_replace = payload.get("noReplace").and_then(Value::as_bool).unwrap_or(false);
+
+                let mut body = json!({ "encodedTrack": payload.get("track")? });
+
+                if let Some(start_time) = payload.get("startTime") {
+                    body["position"] = start_time.clone();
+                }
+
+                if let Some(end_time) = payload.get("endTime") {
+                    body["endTime"] = end_time.clone();
+                }
+
+                body
+            },
+            "seek" => json!({ "position": payload.get("position")? }),
+            "stop" => json!({ "encodedTrack": null }),
+            "voiceUpdate" => {
+                let event = payload.get("event")?;
+
+                json!({
+                    "voice": {
+                        "endpoint": event.get("endpoint")?,
+                        "sessionId": payload.get("sessionId")?,
+                        "token": event.get("token")?,
+                    },
+                })
+            },
+            "volume" => json!({ "volume": payload.get("volume")? }),
+            _ => return None,
+        };
+
+        Some(RestCommand::Update {
+            body,
+            guild_id,
+            no_replace,
+        })
+    }
+
+    /// The ID of the guild that the command is for.
+    pub fn guild_id(&self) -> GuildId {
+        match *self {
+            RestCommand::Destroy { guild_id } | RestCommand::Update { guild_id, .. } => guild_id,
+        }
+    }
+}
+
+/// An action taken by a player to recover from its track being stuck, as
+/// part of a [`StuckPolicy`].
+///
+/// [`StuckPolicy`]: struct.StuckPolicy.html
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum StuckAction {
+    /// Play the track again from its estimated position, keeping its data.
+    Replay,
+    /// Play the next queued track, or stop the player if nothing is queued.
+    Skip,
+    /// Stop the player.
+    Stop,
+}
+
+/// How a player recovers from its track being stuck, as set via
+/// [`AudioPlayer::stuck_policy`].
+///
+/// [`AudioPlayer::stuck_policy`]: struct.AudioPlayer.html#structfield.stuck_policy
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct StuckPolicy {
+    /// The action taken.
+    pub action: StuckAction,
+    /// The number of times that the same track must be reported as stuck
+    /// before the action is taken, such as 1 to take it immediately.
+    ///
+    /// The action is taken on every report after that, so a track that is
+    /// replayed and gets stuck again is replayed again.
+    pub after: u32,
+}
+
+/// The information about a guild's voice connection that was last sent to the
+/// node via [`AudioPlayer::voice_update`].
+///
+/// [`AudioPlayer::voice_update`]: struct.AudioPlayer.html#method.voice_update
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct VoiceUpdate {
+    /// The endpoint of the voice server.
+    pub endpoint: String,
+    /// The session ID of the bot user's voice state.
+    pub session_id: String,
+    /// The token of the voice server.
+    pub token: String,
+}
+
+/// A serializable snapshot of the state of a guild's audio player, created
+/// via [`AudioPlayerManager::snapshot`].
+///
+/// [`AudioPlayerManager::snapshot`]: struct.AudioPlayerManager.html#method.snapshot
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlayerSnapshot {
+    /// The ID of the voice channel that the player was in, if any.
+    pub channel_id: Option<u64>,
+    /// The filters of the player.
+    #[serde(default)]
+    pub filters: Filters,
+    /// The ID of the guild that the player is for.
+    pub guild_id: u64,
+    /// The websocket host of the node that the player was on.
+    pub node_websocket_host: String,
+    /// Whether the player was paused.
+    pub paused: bool,
+    /// The position of the track, in milliseconds.
+    pub position: i64,
+    /// The tracks queued to play after the current track.
+    #[serde(default)]
+    pub queue: Vec<String>,
+    /// The track that was playing, if any.
+    pub track: Option<String>,
+    /// The last voice update that was sent to the node, if any.
+    pub voice: Option<VoiceUpdate>,
+    /// The volume of the player.
+    pub volume: i32,
+}
+
+impl<'a> From<&'a AudioPlayer> for PlayerSnapshot {
+    fn from(player: &'a AudioPlayer) -> Self {
+        Self {
+            channel_id: player.channel_id,
+            filters: player.filters.clone(),
+            guild_id: player.guild_id.0,
+            node_websocket_host: player.node_websocket_host.clone(),
+            paused: player.paused,
+            position: player.estimated_position(),
+            queue: player.queue.iter().cloned().collect(),
+            track: player.track.clone(),
+            voice: player.voice.clone(),
+            volume: player.volume,
+        }
+    }
+}
+
+/// A struct containing the state of a guild's audio player.
+#[derive(Clone)]
+pub struct AudioPlayer {
+    /// The ID of the voice channel that the player is joining or is connected
+    /// to, if any.
+    pub channel_id: Option<u64>,
+    /// Whether the player is connected to the voice channel, meaning that the
+    /// node was sent a [`voice_update`] after joining it.
+    ///
+    /// [`voice_update`]: #method.voice_update
+    pub connected: bool,
+    delivery: LastDelivery,
+    /// The filters applied to the player via [`set_filters`].
+    ///
+    /// [`set_filters`]: #method.set_filters
+    pub filters: Filters,
+    /// The ID of the guild that the player represents.
+    pub guild_id: GuildId,
+    handler: Option<Arc<Mutex<Box<EventHandler>>>>,
+    /// The tracks that finished playing on the player, from oldest to newest.
+    ///
+    /// Up to the [`history_limit`] of tracks are kept.
+    ///
+    /// [`history_limit`]: #structfield.history_limit
+    pub history: VecDeque<PlayedTrack>,
+    /// The maximum number of tracks kept in the [`history`]. Defaults to 0, so
+    /// that no history is kept.
+    ///
+    /// [`history`]: #structfield.history
+    pub history_limit: usize,
+    listeners: Vec<Arc<AudioPlayerListener>>,
+    /// The markers of the player, ordered by position.
+    ///
+    /// Refer to [`set_marker`] for more information.
+    ///
+    /// [`set_marker`]: #method.set_marker
+    pub markers: Vec<Marker>,
+    outbound: Arc<Mutex<Outbound>>,
+    /// Whether the connection to the player's node was lost, so that the
+    /// [`track`] and [`paused`] state may no longer reflect the node's.
+    ///
+    /// This is set when the node's connection closes and cleared once it is
+    /// reconnected to. The [estimated position] doesn't advance meanwhile.
+    ///
+    /// [`paused`]: #structfield.paused
+    /// [`track`]: #structfield.track
+    /// [estimated position]: #method.estimated_position
+    pub node_disconnected: bool,
+    /// The websocket host of the node that the player is on.
+    pub node_websocket_host: String,
+    /// Whether the player is paused.
+    pub paused: bool,
+    /// The track that was played with [`PlayOptions::no_replace`] while
+    /// another track was playing, if the node has not yet started it.
+    ///
+    /// [`PlayOptions::no_replace`]: struct.PlayOptions.html#structfield.no_replace
+    pub pending_track: Option<String>,
+    /// The status of the last track that was played.
+    pub play_status: PlayStatus,
+    played_at: Option<i64>,
+    /// The estimated position of the player.
+    pub position: i64,
+    /// The tracks queued to play after the current track, from first to
+    /// last.
+    ///
+    /// When the node ends a track for a reason that [may start the next],
+    /// the first queued track is played via [`play_queued`].
+    ///
+    /// [`play_queued`]: #method.play_queued
+    /// [may start the next]: ../model/enum.TrackEndReason.html#method.may_start_next
+    pub queue: VecDeque<String>,
+    // The position that the track was replayed from and when, until the
+    // first player update after the replay is reconciled against it.
+    replayed: Option<(i64, i64)>,
+    /// Whether [`play_now`] pushes the track that it interrupts to the front
+    /// of the [`queue`], so that it is played again from the start once the
+    /// new track ends. Defaults to `false`.
+    ///
+    /// [`play_now`]: #method.play_now
+    /// [`queue`]: #structfield.queue
+    pub requeue_interrupted: bool,
+    /// The session ID of the bot user's voice state in the guild, as last
+    /// received via [`voice_state_update`] or sent via [`voice_update`].
+    ///
+    /// [`voice_state_update`]: #method.voice_state_update
+    /// [`voice_update`]: #method.voice_update
+    pub session_id: Option<String>,
+    // The track that was last reported as stuck, and how many times.
+    stuck: Option<(String, u32)>,
+    /// How the player recovers from its track being stuck, if at all.
+    /// Defaults to `None`, leaving recovery to the [`EventHandler`].
+    ///
+    /// The recovery is made via [`recover_stuck`] before
+    /// [`EventHandler::track_stuck`] is called.
+    ///
+    /// [`EventHandler`]: ../trait.EventHandler.html
+    /// [`EventHandler::track_stuck`]: ../trait.EventHandler.html#tymethod.track_stuck
+    /// [`recover_stuck`]: #method.recover_stuck
+    pub stuck_policy: Option<StuckPolicy>,
+    /// The current time of the player.
+    pub time: i64,
+    /// The track that the player is playing.
+    pub track: Option<String>,
+    /// The data attached to the current [`track`] via [`play_with_data`], if
+    /// any.
+    ///
+    /// Use [`downcast_track_data`] to retrieve the data as its concrete type.
+    ///
+    /// [`downcast_track_data`]: #method.downcast_track_data
+    /// [`play_with_data`]: #method.play_with_data
+    /// [`track`]: #structfield.track
+    pub track_data: Option<TrackData>,
+    /// The length of the current [`track`] in milliseconds, if known.
+    ///
+    /// This is cleared when a track is played, and can be set from the
+    /// track's [`LoadedTrackInfo::length`] via [`set_track_info`]. Seeking
+    /// outside of the track returns [`Error::SeekOutOfBounds`].
+    ///
+    /// [`Error::SeekOutOfBounds`]: ../enum.Error.html#variant.SeekOutOfBounds
+    /// [`LoadedTrackInfo::length`]: ../rest/struct.LoadedTrackInfo.html#structfield.length
+    /// [`set_track_info`]: #method.set_track_info
+    /// [`track`]: #structfield.track
+    pub track_length: Option<i64>,
+    /// Whether the current [`track`] can be seeked, if known.
+    ///
+    /// This is cleared when a track is played, and is set via
+    /// [`set_track_info`]. Seeking a track that can not be seeked returns
+    /// [`Error::NotSeekable`].
+    ///
+    /// [`Error::NotSeekable`]: ../enum.Error.html#variant.NotSeekable
+    /// [`set_track_info`]: #method.set_track_info
+    /// [`track`]: #structfield.track
+    pub track_seekable: Option<bool>,
+    /// Whether the node has confirmed that the current [`track`] started
+    /// playing.
+    ///
+    /// [`track`]: #structfield.track
+    pub track_started: bool,
+    unacknowledged: Arc<Mutex<Vec<(String, oneshot::Sender<()>)>>>,
+    /// Whether outgoing payloads are checked before being sent, returning an
+    /// [`Error::Validation`] for those that misuse the protocol. Defaults to
+    /// `false`.
+    ///
+    /// Refer to the [`validation`] module for the checks that are made.
+    ///
+    /// [`Error::Validation`]: ../enum.Error.html#variant.Validation
+    /// [`validation`]: ../validation/index.html
+    pub validation: bool,
+    /// The information about the guild's voice connection that was last sent
+    /// to the node, if any.
+    pub voice: Option<VoiceUpdate>,
+    voice_connections: VoiceConnectionManager,
+    /// The last applied volume setting, on a scale of [`MIN_VOLUME`] to
+    /// [`MAX_VOLUME`].
+    ///
+    /// [`MAX_VOLUME`]: constant.MAX_VOLUME.html
+    /// [`MIN_VOLUME`]: constant.MIN_VOLUME.html
+    pub volume: i32,
+}
+
+impl AudioPlayer {
+    /// Creates a new audio player.
+    ///
+    /// Using [`AudioPlayerManager::create`] or [`NodeManager::create_player`]
+    /// is the preferred method of creating a new player.
+    ///
+    /// [`AudioPlayerManager::create`]: struct.AudioPlayerManager.html#method.create
+    /// [`NodeManager::create_player`]: ../nodes/struct.NodeManager.html#method.create_player
+    pub fn new<G: Into<GuildId>>(
+        guild_id: G,
+        node_websocket_host: String,
+        sender: MpscSender<OwnedMessage>,
+    ) -> Self {
+        Self {
+            channel_id: None,
+            connected: false,
+            delivery: LastDelivery::Unsent,
+            filters: Filters::default(),
+            handler: None,
+            history: VecDeque::new(),
+            history_limit: 0,
+            markers: Vec::new(),
+            node_disconnected: false,
+            paused: false,
+            pending_track: None,
+            play_status: PlayStatus::default(),
+            played_at: None,
+            position: 0,
+            queue: VecDeque::new(),
+            replayed: None,
+            requeue_interrupted: false,
+            session_id: None,
+            stuck: None,
+            stuck_policy: None,
+            time: 0,
+            track: None,
+            track_data: None,
+            track_length: None,
+            track_seekable: None,
+            track_started: false,
+            unacknowledged: Arc::new(Mutex::new(Vec::new())),
+            validation: false,
+            voice: None,
+            voice_connections: VoiceConnectionManager::new(1),
+            volume: DEFAULT_VOLUME,
+            guild_id: guild_id.into(),
+            listeners: Vec::new(),
+            node_websocket_host,
+            outbound: Arc::new(Mutex::new(Outbound {
+                draining: false,
+                queue: VecDeque::new(),
+                remote: None,
+                sender,
+            })),
+        }
+    }
+
+    /// The ID of the guild that the player represents, as a raw `u64`.
+    ///
+    /// [`guild_id`] was a `u64` before it became a [`GuildId`].
+    ///
+    /// [`GuildId`]: ../model/struct.GuildId.html
+    /// [`guild_id`]: #structfield.guild_id
+    #[deprecated(note = "use the `guild_id` field, whose `GuildId` converts into a `u64`")]
+    #[inline]
+    pub fn raw_guild_id(&self) -> u64 {
+        self.guild_id.0
+    }
+
+    /// Sets the handler used to forward voice state updates to Discord when
+    /// [joining] or [leaving] a voice channel, and the number of shards that
+    /// the bot is using.
+    ///
+    /// This is set automatically by [`NodeManager::create_player`].
+    ///
+    /// [`NodeManager::create_player`]: ../nodes/struct.NodeManager.html#method.create_player
+    /// [joining]: #method.join
+    /// [leaving]: #method.leave
+    pub fn set_handler(&mut self, handler: Arc<Mutex<Box<EventHandler>>>, num_shards: u64) {
+        self.handler = Some(handler);
+        self.voice_connections.set_num_shards(num_shards);
+    }
+
+    /// Sets the remote of the tokio Core that messages queued while the
+    /// node's sender is full are sent from once it is ready again.
+    ///
+    /// This is set automatically by [`NodeManager::create_player`]. Without
+    /// it, queued messages are only sent when another message is sent, or
+    /// when a future from [`deliver`] or [`flush`] is polled.
+    ///
+    /// [`NodeManager::create_player`]: ../nodes/struct.NodeManager.html#method.create_player
+    /// [`deliver`]: #method.deliver
+    /// [`flush`]: #method.flush
+    pub fn set_remote(&mut self, remote: Remote) {
+        match self.outbound.lock() {
+            Ok(mut outbound) => {
+                outbound.remote = Some(remote);
+                self.drain_later(&mut outbound);
+            },
+            Err(why) => {
+                event!(warn, guild_id = self.guild_id; "Err locking outbound queue: {:?}", why);
+            },
+        }
+    }
+
+    /// Joins a voice channel, forwarding the gateway `VOICE_STATE_UPDATE` to
+    /// Discord over the guild's shard via [`EventHandler::forward`].
+    ///
+    /// The player is [`connected`] once Discord responds and the node is sent
+    /// a [`voice_update`].
+    ///
+    /// Returns [`Error::None`] if no handler was [set]. The returned future
+    /// must be run for the update to be forwarded, and resolves the error of
+    /// the handler's `forward` if it failed.
+    ///
+    /// **Note**: This locks the handler, and so must not be called from within
+    /// an [`EventHandler`] tymethod.
+    ///
+    /// [`Error::None`]: ../enum.Error.html#variant.None
+    /// [`EventHandler`]: ../trait.EventHandler.html
+    /// [`EventHandler::forward`]: ../trait.EventHandler.html#tymethod.forward
+    /// [`connected`]: #structfield.connected
+    /// [`voice_update`]: #method.voice_update
+    /// [set]: #method.set_handler
+    pub fn join(&mut self, channel_id: u64)
+        -> Result<Box<Future<Item = (), Error = Error>>, Error> {
+        let done = self.forward_voice_state(Some(channel_id))?;
+
+        self.channel_id = Some(channel_id);
+        self.connected = false;
+
+        Ok(done)
+    }
+
+    /// Leaves the voice channel, forwarding the gateway `VOICE_STATE_UPDATE`
+    /// to Discord over the guild's shard via [`EventHandler::forward`].
+    ///
+    /// Refer to [`join`] for more information.
+    ///
+    /// [`EventHandler::forward`]: ../trait.EventHandler.html#tymethod.forward
+    /// [`join`]: #method.join
+    pub fn leave(&mut self) -> Result<Box<Future<Item = (), Error = Error>>, Error> {
+        let done = self.forward_voice_state(None)?;
+
+        self.channel_id = None;
+        self.connected = false;
+
+        Ok(done)
+    }
+
+    /// Whether the player is in a voice channel and the node was sent the
+    /// voice connection's information, so that it can play audio.
+    ///
+    /// This can be used to answer [`EventHandler::is_connected`] and
+    /// [`EventHandler::is_valid`] from the library's state.
+    ///
+    /// [`EventHandler::is_connected`]: ../trait.EventHandler.html#tymethod.is_connected
+    /// [`EventHandler::is_valid`]: ../trait.EventHandler.html#tymethod.is_valid
+    pub fn is_connected(&self) -> bool {
+        self.connected && self.channel_id.is_some()
+    }
+
+    fn forward_voice_state(&mut self, channel_id: Option<u64>)
+        -> Result<Box<Future<Item = (), Error = Error>>, Error> {
+        let handler = match self.handler {
+            Some(ref handler) => handler,
+            None => return Err(self.context(Error::None)),
+        };
+        let mut handler = handler.lock()?;
+        let guild_id = self.guild_id.0;
+
+        let done = match channel_id {
+            Some(channel_id) => self.voice_connections.join(&mut **handler, guild_id, channel_id),
+            None => self.voice_connections.leave(&mut **handler, guild_id),
+        };
+
+        Ok(Box::new(done.map(|_| ())))
+    }
+
+    /// Adds a listener for events of the player.
+    pub fn add_listener(&mut self, listener: Box<AudioPlayerListener>) {
+        self.listeners.push(Arc::from(listener));
+    }
+
+    /// The listeners for events of the player.
+    pub fn listeners(&self) -> &[Arc<AudioPlayerListener>] {
+        &self.listeners
+    }
+
+    /// The data attached to the current track via [`play_with_data`], if it
+    /// is of the given type.
+    ///
+    /// [`play_with_data`]: #method.play_with_data
+    pub fn downcast_track_data<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.track_data.as_ref().and_then(|data| data.downcast_ref::<T>())
+    }
+
+    /// Adds a track to the back of the [`queue`], so that it plays once the
+    /// tracks before it have ended.
+    ///
+    /// If the player is not playing a track, then the track is played
+    /// immediately instead. Returns whether the track was played.
+    ///
+    /// Queued tracks are started when the node ends a track for a reason
+    /// that [may start the next]; stopping or replacing a track does not
+    /// advance the queue.
+    ///
+    /// [`queue`]: #structfield.queue
+    /// [may start the next]: ../model/enum.TrackEndReason.html#method.may_start_next
+    pub fn enqueue(&mut self, track: &str) -> Result<bool, Error> {
+        if self.is_idle() {
+            self.play_with_options(track, &PlayOptions::default())?;
+
+            return Ok(true);
+        }
+
+        self.queue.push_back(track.to_owned());
+
+        Ok(false)
+    }
+
+    /// Estimates the current position of the player, in milliseconds.
+    ///
+    /// The [`position`] is only updated when the node sends a player update,
+    /// which is usually every 5 seconds. This interpolates between updates
+    /// using the [`time`] of the last update and the current clock, so that
+    /// progress bars are accurate in the meantime.
+    ///
+    /// The [`position`] is returned unchanged if the player is paused, is not
+    /// playing a track, has not yet received a player update, or its
+    /// [node is disconnected].
+    ///
+    /// [`position`]: #structfield.position
+    /// [`time`]: #structfield.time
+    /// [node is disconnected]: #structfield.node_disconnected
+    pub fn estimated_position(&self) -> i64 {
+        if self.paused || self.node_disconnected || self.track.is_none() || self.time == 0 {
+            return self.position;
+        }
+
+        let elapsed = now_millis().saturating_sub(self.time).max(0);
+
+        self.position.saturating_add(elapsed)
+    }
+
+    /// Marks the player as having lost the connection to its node, freezing
+    /// its [estimated position] at where it was.
+    ///
+    /// This is called automatically when the node's connection closes.
+    ///
+    /// [estimated position]: #method.estimated_position
+    pub fn mark_node_disconnected(&mut self) {
+        if self.node_disconnected {
+            return;
+        }
+
+        self.position = self.estimated_position();
+        self.node_disconnected = true;
+    }
+
+    /// Marks the player as being reconnected to its node, resuming estimating
+    /// its position from now.
+    ///
+    /// This is called automatically when the node is reconnected to.
+    pub fn mark_node_reconnected(&mut self) {
+        if !self.node_disconnected {
+            return;
+        }
+
+        self.node_disconnected = false;
+
+        if self.time != 0 {
+            self.time = now_millis();
+        }
+    }
+
+    /// Sends a message to Lavalink telling it to either pause or unpause the
+    /// player.
+    pub fn pause(&mut self, pause: bool) -> Result<(), Error> {
+        let guild_id = self.guild_id;
+
+        self.send_op(OutboundOp::Pause { guild_id, pause })?;
+
+        // Freeze the position while paused, and resume estimating from now.
+        if pause && !self.paused {
+            self.position = self.estimated_position();
+        }
+
+        if self.time != 0 {
+            self.time = now_millis();
+        }
+
+        self.paused = pause;
+
+        for listener in &self.listeners {
+            listener.pause_toggle(self, pause);
+        }
+
+        Ok(())
+    }
+
+    /// Like [`pause`], but resolves once the command has been
+    /// accepted by the node's sender, which writes commands to the WebSocket
+    /// in the order that they are accepted.
+    ///
+    /// This allows sequencing commands from within the event loop, such as
+    /// seeking only once a track has been sent to be played.
+    ///
+    /// [`pause`]: #method.pause
+    pub fn pause_async(&mut self, pause: bool)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.flushed(|player| player.pause(pause))
+    }
+
+    /// Sends a message to Lavalink telling it to play a track with optional
+    /// configuration settings.
+    pub fn play(
+        &mut self,
+        track: &str,
+        start_time: Option<u64>,
+        end_time: Option<u64>,
+    ) -> Result<(), Error> {
+        self.play_with_options(track, &PlayOptions {
+            end_time,
+            start_time,
+            ..PlayOptions::default()
+        })
+    }
+
+    /// Like [`play`], but resolves once the command has been
+    /// accepted by the node's sender, which writes commands to the WebSocket
+    /// in the order that they are accepted.
+    ///
+    /// This allows sequencing commands from within the event loop, such as
+    /// seeking only once a track has been sent to be played.
+    ///
+    /// [`play`]: #method.play
+    pub fn play_async(
+        &mut self,
+        track: &str,
+        start_time: Option<u64>,
+        end_time: Option<u64>,
+    ) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.flushed(|player| player.play(track, start_time, end_time))
+    }
+
+    /// Adds a track to the front of the [`queue`], so that it plays once the
+    /// current track ends.
+    ///
+    /// If the player is not playing a track, then the track is played
+    /// immediately instead. Returns whether the track was played.
+    ///
+    /// [`queue`]: #structfield.queue
+    pub fn play_next(&mut self, track: &str) -> Result<bool, Error> {
+        if self.is_idle() {
+            self.play_with_options(track, &PlayOptions::default())?;
+
+            return Ok(true);
+        }
+
+        self.queue.push_front(track.to_owned());
+
+        Ok(false)
+    }
+
+    /// Plays a track immediately, replacing the current track.
+    ///
+    /// The node ends the replaced track with [`TrackEndReason::Replaced`],
+    /// which does not start the next track in the [`queue`], so the queue is
+    /// left as is. If [`requeue_interrupted`] is set, then the replaced track
+    /// is pushed to the front of the queue to be played again once this
+    /// track ends.
+    ///
+    /// [`TrackEndReason::Replaced`]: ../model/enum.TrackEndReason.html#variant.Replaced
+    /// [`queue`]: #structfield.queue
+    /// [`requeue_interrupted`]: #structfield.requeue_interrupted
+    pub fn play_now(&mut self, track: &str) -> Result<(), Error> {
+        let interrupted = if self.requeue_interrupted {
+            self.track.clone()
+        } else {
+            None
+        };
+
+        self.play_with_options(track, &PlayOptions::default())?;
+
+        if let Some(interrupted) = interrupted {
+            self.queue.push_front(interrupted);
+        }
+
+        Ok(())
+    }
+
+    /// Sends a message to Lavalink telling it to play a track with the given
+    /// options.
+    ///
+    /// If [`PlayOptions::no_replace`] is set while a track is playing, then
+    /// the node may ignore the track. The track is then the
+    /// [`pending_track`] rather than the [`track`] until the node starts it,
+    /// and the [`play_status`] tells whether it was ignored.
+    ///
+    /// [`PlayOptions::no_replace`]: struct.PlayOptions.html#structfield.no_replace
+    /// [`pending_track`]: #structfield.pending_track
+    /// [`play_status`]: #structfield.play_status
+    /// [`track`]: #structfield.track
+    pub fn play_with_options(&mut self, track: &str, options: &PlayOptions)
+        -> Result<(), Error> {
+        self.send_op(OutboundOp::Play {
+            end_time: options.end_time,
+            guild_id: self.guild_id,
+            no_replace: options.no_replace,
+            start_time: options.start_time,
+            track: track.to_owned(),
+        })?;
+
+        if options.no_replace && self.track.is_some() {
+            self.pending_track = Some(track.to_owned());
+            self.play_status = PlayStatus::Pending;
+        } else {
+            self.pending_track = None;
+            self.play_status = PlayStatus::Accepted;
+            self.played_at = Some(now_millis());
+            self.replayed = None;
+            self.track = Some(track.to_owned());
+            self.track_data = None;
+            self.track_length = None;
+            self.track_seekable = None;
+            self.track_started = false;
+
+            self.rearm_markers(options.start_time.map_or(0, |start| start as i64));
+        }
+
+        Ok(())
+    }
+
+    /// Like [`play_with_options`], but resolves once the command has been
+    /// accepted by the node's sender, which writes commands to the WebSocket
+    /// in the order that they are accepted.
+    ///
+    /// This allows sequencing commands from within the event loop, such as
+    /// seeking only once a track has been sent to be played.
+    ///
+    /// [`play_with_options`]: #method.play_with_options
+    pub fn play_with_options_async(&mut self, track: &str, options: &PlayOptions)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.flushed(|player| player.play_with_options(track, options))
+    }
+
+    /// Sends a message to Lavalink telling it to play a track, attaching data
+    /// to the track.
+    ///
+    /// The data is available via [`downcast_track_data`] until the track ends,
+    /// and is passed to [`EventHandler::track_end_with_data`] and
+    /// [`EventHandler::track_exception_with_data`]. This is useful for
+    /// correlating events to application context, such as the text channel
+    /// to announce the next track in.
+    ///
+    /// [`EventHandler::track_end_with_data`]: ../trait.EventHandler.html#method.track_end_with_data
+    /// [`EventHandler::track_exception_with_data`]: ../trait.EventHandler.html#method.track_exception_with_data
+    /// [`downcast_track_data`]: #method.downcast_track_data
+    pub fn play_with_data<T: Any + Send + Sync>(&mut self, track: &str, data: T)
+        -> Result<(), Error> {
+        self.play_with_options(track, &PlayOptions::default())?;
+        self.track_data = Some(Arc::new(data));
+
+        Ok(())
+    }
+
+    /// Sends a message to Lavalink telling it to play a track with the given
+    /// options, resolving once the node starts the track.
+    ///
+    /// Lavalink silently ignores some invalid plays, such as of a malformed
+    /// track. Resolves to [`Error::CommandUnacknowledged`] if the track does
+    /// not start within the timeout, so that the play can be retried. This
+    /// includes plays made with [`PlayOptions::no_replace`] that the node
+    /// ignored.
+    ///
+    /// The handle must be to the tokio Core that the future is run on.
+    ///
+    /// [`Error::CommandUnacknowledged`]: ../enum.Error.html#variant.CommandUnacknowledged
+    /// [`PlayOptions::no_replace`]: struct.PlayOptions.html#structfield.no_replace
+    pub fn play_acknowledged(
+        &mut self,
+        track: &str,
+        options: &PlayOptions,
+        handle: &Handle,
+        timeout: Duration,
+    ) -> Box<Future<Item = (), Error = Error>> {
+        let (tx, rx) = oneshot::channel();
+
+        match self.unacknowledged.lock() {
+            Ok(mut unacknowledged) => {
+                // Forget plays whose futures were dropped or timed out.
+                unacknowledged.retain(|&(_, ref tx)| !tx.is_canceled());
+                unacknowledged.push((track.to_owned(), tx));
+            },
+            Err(why) => return Box::new(future::err(self.context(Error::from(why)))),
+        }
+
+        if let Err(why) = self.play_with_options(track, options) {
+            return Box::new(future::err(why));
+        }
+
+        let guild_id = self.guild_id;
+        let node = self.node_websocket_host.clone();
+        let started = Box::new(rx.from_err());
+
+        Box::new(rest::with_timeout(handle, Some(timeout), started).map_err(move |why| {
+            let why = match why {
+                Error::Timeout => Error::CommandUnacknowledged,
+                other => other,
+            };
+
+            why.with_guild(guild_id).with_node(node)
+        }))
+    }
+
+    /// Plays the first track in the [`queue`], removing it from the queue.
+    ///
+    /// This replaces the current track, if any. This is called when the node
+    /// ends a track for a reason that [may start the next].
+    ///
+    /// Returns the track that was played, or `None` if the queue is empty.
+    ///
+    /// [`queue`]: #structfield.queue
+    /// [may start the next]: ../model/enum.TrackEndReason.html#method.may_start_next
+    pub fn play_queued(&mut self) -> Result<Option<String>, Error> {
+        let track = match self.queue.pop_front() {
+            Some(track) => track,
+            None => return Ok(None),
+        };
+
+        if let Err(why) = self.play_with_options(&track, &PlayOptions::default()) {
+            self.queue.push_front(track);
+
+            return Err(why);
+        }
+
+        Ok(Some(track))
+    }
+
+    /// Plays the track that most recently finished playing again, removing it
+    /// from the [`history`].
+    ///
+    /// This replaces the current track, if any. Calling this repeatedly plays
+    /// tracks further back in the history.
+    ///
+    /// Returns the track that was played, or `None` if the history is empty.
+    ///
+    /// [`history`]: #structfield.history
+    pub fn previous(&mut self) -> Result<Option<String>, Error> {
+        let played = match self.history.pop_back() {
+            Some(played) => played,
+            None => return Ok(None),
+        };
+
+        if let Err(why) = self.play_with_options(&played.track, &PlayOptions::default()) {
+            self.history.push_back(played);
+
+            return Err(why);
+        }
+
+        Ok(Some(played.track))
+    }
+
+    /// Resolves the futures of plays of the track that are awaiting
+    /// acknowledgement via [`play_acknowledged`].
+    ///
+    /// This is called when the node starts the track.
+    ///
+    /// [`play_acknowledged`]: #method.play_acknowledged
+    pub fn acknowledge_start(&self, track: &str) {
+        let mut unacknowledged = match self.unacknowledged.lock() {
+            Ok(unacknowledged) => unacknowledged,
+            Err(why) => {
+                event!(
+                    warn,
+                    guild_id = self.guild_id;
+                    "Err locking unacknowledged plays: {:?}",
+                    why
+                );
+
+                return;
+            },
+        };
+
+        let mut i = 0;
+
+        while i < unacknowledged.len() {
+            if unacknowledged[i].0 == track {
+                let (_, tx) = unacknowledged.remove(i);
+                let _ = tx.send(());
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// The number of plays awaiting acknowledgement via
+    /// [`play_acknowledged`].
+    ///
+    /// [`play_acknowledged`]: #method.play_acknowledged
+    pub fn unacknowledged(&self) -> usize {
+        self.unacknowledged.lock().map(|unacknowledged| unacknowledged.len()).unwrap_or(0)
+    }
+
+    /// Records that a track finished playing in the [`history`], evicting the
+    /// oldest track if the [`history_limit`] is reached.
+    ///
+    /// This is called when the node sends a track end event.
+    ///
+    /// [`history`]: #structfield.history
+    /// [`history_limit`]: #structfield.history_limit
+    pub fn record_track_end(&mut self, track: String, reason: TrackEndReason) {
+        let played_at = self.played_at.take();
+
+        if self.history_limit == 0 {
+            return;
+        }
+
+        while self.history.len() >= self.history_limit {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(PlayedTrack {
+            ended_at: now_millis(),
+            played_at,
+            reason,
+            track,
+        });
+    }
+
+    /// Sends the player's state to the node again, so that it resumes where it
+    /// left off.
+    ///
+    /// This sends the last [`voice`] update, plays the current [`track`]
+    /// starting from the last known [`position`], and sends the volume and
+    /// pause state. Listeners are not called.
+    ///
+    /// This is useful after reconnecting to a node without resuming its
+    /// session, as the node no longer knows about the player.
+    ///
+    /// The ops are sent like any other, so ops that the node's sender isn't
+    /// ready for are queued rather than dropped. Refer to [`send`] for more
+    /// information.
+    ///
+    /// [`position`]: #structfield.position
+    /// [`send`]: #method.send
+    /// [`track`]: #structfield.track
+    /// [`voice`]: #structfield.voice
+    pub fn replay_state(&mut self) -> Result<(), Error> {
+        let guild_id = self.guild_id;
+
+        if let Some(voice) = self.voice.clone() {
+            self.send_op(OutboundOp::VoiceUpdate { guild_id, voice })?;
+        }
+
+        if let Some(track) = self.track.clone() {
+            let start_time = if self.position > 0 {
+                Some(self.position as u64)
+            } else {
+                None
+            };
+
+            self.send_op(OutboundOp::Play {
+                end_time: None,
+                guild_id,
+                no_replace: false,
+                start_time,
+                track,
+            })?;
+            self.replayed = Some((self.position.max(0), now_millis()));
+            self.track_started = false;
+        }
+
+        let volume = self.volume;
+        self.send_op(OutboundOp::Volume { guild_id, volume })?;
+
+        if !self.filters.is_empty() {
+            let filters = self.filters.clone();
+            self.send_op(OutboundOp::Filters { filters, guild_id })?;
+        }
+
+        if self.paused {
+            self.send_op(OutboundOp::Pause { guild_id, pause: true })?;
+        }
+
+        Ok(())
+    }
+
+    /// Compares the position reported by the first player update since the
+    /// player's state was [replayed] against the position it was expected to
+    /// be at, seeking to the expected position if they differ by more than
+    /// the threshold in milliseconds.
+    ///
+    /// This corrects the position diverging from where it was when a node
+    /// was reconnected to, and is called automatically according to the
+    /// node's [`NodeConfig::position_sync_threshold`]. Later calls do nothing
+    /// until the state is replayed again.
+    ///
+    /// Returns the position that was seeked to, if any.
+    ///
+    /// [`NodeConfig::position_sync_threshold`]: ../nodes/struct.NodeConfig.html#method.position_sync_threshold
+    /// [replayed]: #method.replay_state
+    pub fn reconcile_position(&mut self, position: i64, threshold: i64)
+        -> Result<Option<i64>, Error> {
+        let (replayed_position, replayed_at) = match self.replayed.take() {
+            Some(replayed) => replayed,
+            None => return Ok(None),
+        };
+
+        if self.track.is_none() {
+            return Ok(None);
+        }
+
+        let expected = if self.paused {
+            replayed_position
+        } else {
+            replayed_position.saturating_add(now_millis().saturating_sub(replayed_at).max(0))
+        };
+
+        if (expected - position).abs() <= threshold {
+            return Ok(None);
+        }
+
+        self.seek(expected)?;
+
+        Ok(Some(expected))
+    }
+
+    /// Marks the markers that the position has reached as reached, returning
+    /// the names of those that weren't already, in order of position.
+    ///
+    /// This is called automatically with the position of each player update,
+    /// and with the [estimated position] once it is expected to reach the
+    /// [next marker].
+    ///
+    /// [estimated position]: #method.estimated_position
+    /// [next marker]: #method.next_marker
+    pub fn reach_markers(&mut self, position: i64) -> Vec<String> {
+        self.markers
+            .iter_mut()
+            .filter(|marker| !marker.reached && marker.position <= position)
+            .map(|marker| {
+                marker.reached = true;
+
+                marker.name.clone()
+            })
+            .collect()
+    }
+
+    /// Re-arms the markers from a position onward, so that they are reached
+    /// again, and marks those before it as reached.
+    ///
+    /// This is called automatically when a track is played or the player
+    /// seeks.
+    pub fn rearm_markers(&mut self, position: i64) {
+        for marker in &mut self.markers {
+            marker.reached = marker.position < position;
+        }
+    }
+
+    /// The first marker that has not yet been reached, if any.
+    pub fn next_marker(&self) -> Option<&Marker> {
+        self.markers.iter().find(|marker| !marker.reached)
+    }
+
+    /// Records that the node reported the track as stuck, recovering from it
+    /// according to the [`stuck_policy`] once the track was stuck enough
+    /// times.
+    ///
+    /// This is called automatically when the node sends a
+    /// `TrackStuckEvent`. Reports for a track other than the current one are
+    /// ignored.
+    ///
+    /// Returns the action that was taken, if any.
+    ///
+    /// [`stuck_policy`]: #structfield.stuck_policy
+    pub fn recover_stuck(&mut self, track: &str) -> Result<Option<StuckAction>, Error> {
+        let current = match self.track {
+            Some(ref current) => current == track,
+            None => false,
+        };
+
+        if !current {
+            return Ok(None);
+        }
+
+        let count = match self.stuck {
+            Some((ref stuck, count)) if stuck == track => count + 1,
+            _ => 1,
+        };
+        self.stuck = Some((track.to_owned(), count));
+
+        let policy = match self.stuck_policy {
+            Some(policy) if count >= policy.after => policy,
+            _ => return Ok(None),
+        };
+
+        match policy.action {
+            StuckAction::Replay => {
+                let data = self.track_data.take();
+                let options = PlayOptions {
+                    start_time: Some(self.estimated_position().max(0) as u64),
+                    ..PlayOptions::default()
+                };
+
+                self.play_with_options(track, &options)?;
+                self.track_data = data;
+            },
+            StuckAction::Skip => {
+                if self.play_queued()?.is_none() {
+                    self.stop()?;
+                }
+            },
+            StuckAction::Stop => self.stop()?,
+        }
+
+        Ok(Some(policy.action))
+    }
+
+    /// Sends a message to Lavalink telling it to seek the player to a certain
+    /// position.
+    ///
+    /// If the current track's information was set via [`set_track_info`],
+    /// then seeks that Lavalink would reject or ignore return an error
+    /// without being sent: [`Error::NotSeekable`] if the track can not be
+    /// seeked, and [`Error::SeekOutOfBounds`] if the position is outside of
+    /// the track.
+    ///
+    /// [`Error::NotSeekable`]: ../enum.Error.html#variant.NotSeekable
+    /// [`Error::SeekOutOfBounds`]: ../enum.Error.html#variant.SeekOutOfBounds
+    /// [`set_track_info`]: #method.set_track_info
+    pub fn seek(&mut self, position: i64) -> Result<(), Error> {
+        if self.track_seekable == Some(false) {
+            return Err(self.context(Error::NotSeekable));
+        }
+
+        if let Some(length) = self.track_length {
+            if position < 0 || position > length {
+                return Err(self.context(Error::SeekOutOfBounds(position)));
+            }
+        }
+
+        let guild_id = self.guild_id;
+
+        self.send_op(OutboundOp::Seek { guild_id, position })?;
+        self.replayed = None;
+
+        if self.time != 0 {
+            self.position = position;
+            self.time = now_millis();
+        }
+
+        self.rearm_markers(position);
+
+        for listener in &self.listeners {
+            listener.seek(self, position);
+        }
+
+        Ok(())
+    }
+
+    /// Like [`seek`], but resolves once the command has been
+    /// accepted by the node's sender, which writes commands to the WebSocket
+    /// in the order that they are accepted.
+    ///
+    /// This allows sequencing commands from within the event loop, such as
+    /// seeking only once a track has been sent to be played.
+    ///
+    /// [`seek`]: #method.seek
+    pub fn seek_async(&mut self, position: i64)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.flushed(|player| player.seek(position))
+    }
+
+    /// Sends a message to Lavalink telling it to apply filters to the player,
+    /// replacing the previous filters.
+    ///
+    /// Use [`Filters::default`] to disable every filter.
+    ///
+    /// [`Filters::default`]: struct.Filters.html#impl-Default
+    pub fn set_filters(&mut self, filters: Filters) -> Result<(), Error> {
+        let guild_id = self.guild_id;
+
+        self.send_op(OutboundOp::Filters { filters: filters.clone(), guild_id })?;
+        self.filters = filters;
+
+        Ok(())
+    }
+
+    /// Like [`set_filters`], but resolves once the command has been
+    /// accepted by the node's sender, which writes commands to the WebSocket
+    /// in the order that they are accepted.
+    ///
+    /// This allows sequencing commands from within the event loop, such as
+    /// seeking only once a track has been sent to be played.
+    ///
+    /// [`set_filters`]: #method.set_filters
+    pub fn set_filters_async(&mut self, filters: Filters)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.flushed(|player| player.set_filters(filters))
+    }
+
+    /// Sets a marker at a position in milliseconds, replacing any marker with
+    /// the same name.
+    ///
+    /// [`EventHandler::marker_reached`] is called once the player's position
+    /// reaches the marker, such as to skip the intro of a track or to announce
+    /// its chapters. The position is checked with each player update from the
+    /// node, and with the [estimated position] if the marker is expected to be
+    /// reached before the next update.
+    ///
+    /// Each marker is reached once, and is re-armed when a track is played
+    /// from before it or the player seeks to before it. Markers are kept
+    /// across tracks until they are [removed].
+    ///
+    /// A marker that the [estimated position] has already passed isn't
+    /// reached until it is re-armed.
+    ///
+    /// [`EventHandler::marker_reached`]: ../trait.EventHandler.html#method.marker_reached
+    /// [estimated position]: #method.estimated_position
+    /// [removed]: #method.remove_marker
+    pub fn set_marker(&mut self, position: i64, name: &str) {
+        let reached = position < self.estimated_position();
+
+        self.markers.retain(|marker| marker.name != name);
+
+        let index = self.markers
+            .iter()
+            .position(|marker| marker.position > position)
+            .unwrap_or_else(|| self.markers.len());
+
+        self.markers.insert(index, Marker {
+            name: name.to_owned(),
+            position,
+            reached,
+        });
+    }
+
+    /// Removes a marker by name.
+    ///
+    /// Returns whether the marker existed.
+    pub fn remove_marker(&mut self, name: &str) -> bool {
+        let len = self.markers.len();
+
+        self.markers.retain(|marker| marker.name != name);
+
+        self.markers.len() != len
+    }
+
+    /// Removes all of the player's markers.
+    pub fn clear_markers(&mut self) {
+        self.markers.clear();
+    }
+
+    /// Sets the [`track_length`] and [`track_seekable`] of the current track
+    /// from its information, such as from [`NodeRest::track_info`].
+    ///
+    /// Streams have no length and can not be seeked.
+    ///
+    /// [`NodeRest::track_info`]: ../rest/struct.NodeRest.html#method.track_info
+    /// [`track_length`]: #structfield.track_length
+    /// [`track_seekable`]: #structfield.track_seekable
+    pub fn set_track_info(&mut self, info: &LoadedTrackInfo) {
+        if info.is_stream {
+            self.track_length = None;
+            self.track_seekable = Some(false);
+        } else {
+            self.track_length = Some(info.length);
+            self.track_seekable = Some(info.is_seekable);
+        }
+    }
+
+    /// Sends a message to Lavalink telling it to stop the player.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        let guild_id = self.guild_id;
+
+        self.send_op(OutboundOp::Stop { guild_id })
+    }
+
+    /// Like [`stop`], but resolves once the command has been
+    /// accepted by the node's sender, which writes commands to the WebSocket
+    /// in the order that they are accepted.
+    ///
+    /// This allows sequencing commands from within the event loop, such as
+    /// seeking only once a track has been sent to be played.
+    ///
+    /// [`stop`]: #method.stop
+    pub fn stop_async(&mut self) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.flushed(|player| player.stop())
+    }
+
+    /// Sends a message to Lavalink telling it to mutate the volume setting.
+    ///
+    /// Returns [`Error::InvalidVolume`] if the volume is not within the range
+    /// of [`MIN_VOLUME`] to [`MAX_VOLUME`], rather than letting Lavalink
+    /// silently clamp it.
+    ///
+    /// [`Error::InvalidVolume`]: ../enum.Error.html#variant.InvalidVolume
+    /// [`MAX_VOLUME`]: constant.MAX_VOLUME.html
+    /// [`MIN_VOLUME`]: constant.MIN_VOLUME.html
+    pub fn volume(&mut self, volume: i32) -> Result<(), Error> {
+        validate_volume(volume).map_err(|why| self.context(why))?;
+
+        let guild_id = self.guild_id;
+
+        self.send_op(OutboundOp::Volume { guild_id, volume })?;
+        self.volume = volume;
+
+        for listener in &self.listeners {
+            listener.volume_change(self, volume);
+        }
+
+        Ok(())
+    }
+
+    /// Like [`volume`], but resolves once the command has been
+    /// accepted by the node's sender, which writes commands to the WebSocket
+    /// in the order that they are accepted.
+    ///
+    /// This allows sequencing commands from within the event loop, such as
+    /// seeking only once a track has been sent to be played.
+    ///
+    /// [`volume`]: #method.volume
+    pub fn volume_async(&mut self, volume: i32)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        self.flushed(|player| player.volume(volume))
+    }
+
+    /// Changes the volume setting by an amount relative to the last applied
+    /// volume, such as `-10` to lower it.
+    ///
+    /// The new volume is clamped to the range of [`MIN_VOLUME`] to
+    /// [`MAX_VOLUME`].
+    ///
+    /// Returns the new volume.
+    ///
+    /// [`MAX_VOLUME`]: constant.MAX_VOLUME.html
+    /// [`MIN_VOLUME`]: constant.MIN_VOLUME.html
+    pub fn volume_relative(&mut self, delta: i32) -> Result<i32, Error> {
+        let volume = self.volume
+            .saturating_add(delta)
+            .max(MIN_VOLUME)
+            .min(MAX_VOLUME);
+
+        self.volume(volume)?;
+
+        Ok(volume)
+    }
+
+    /// Updates the player's voice state from Discord's `VOICE_STATE_UPDATE`
+    /// event for the bot user, with the ID of the voice channel that it is
+    /// in and its session ID.
+    ///
+    /// A channel of `None` means that the bot user left the voice channel or
+    /// was disconnected from it, so the player is no longer [`connected`].
+    /// A new session ID means that the voice connection has to be
+    /// re-established, so the player is not [`connected`] until it is sent
+    /// another [`voice_update`].
+    ///
+    /// Returns whether the bot user was moved from one voice channel to
+    /// another.
+    ///
+    /// [`connected`]: #structfield.connected
+    /// [`voice_update`]: #method.voice_update
+    pub fn voice_state_update(&mut self, channel_id: Option<u64>, session_id: &str) -> bool {
+        let moved = match (self.channel_id, channel_id) {
+            (Some(old), Some(new)) => old != new,
+            _ => false,
+        };
+
+        let new_session = match self.session_id {
+            Some(ref id) => id != session_id,
+            None => true,
+        };
+
+        if channel_id.is_none() {
+            self.connected = false;
+            self.session_id = None;
+        } else if new_session {
+            self.connected = false;
+            self.session_id = Some(session_id.to_owned());
+        }
+
+        self.channel_id = channel_id;
+
+        moved
+    }
+
+    /// Sends a message to Lavalink with the information about the guild's
+    /// voice connection, received from Discord's `VOICE_SERVER_UPDATE` and
+    /// `VOICE_STATE_UPDATE` events.
+    ///
+    /// The information is stored in [`voice`], so that it can be sent again
+    /// via [`replay_state`]. The player is then marked as [`connected`].
+    ///
+    /// [`connected`]: #structfield.connected
+    /// [`replay_state`]: #method.replay_state
+    /// [`voice`]: #structfield.voice
+    pub fn voice_update(
+        &mut self,
+        session_id: &str,
+        token: &str,
+        endpoint: &str,
+    ) -> Result<(), Error> {
+        let voice = VoiceUpdate {
+            endpoint: endpoint.to_owned(),
+            session_id: session_id.to_owned(),
+            token: token.to_owned(),
+        };
+        let guild_id = self.guild_id;
+
+        self.send_op(OutboundOp::VoiceUpdate { guild_id, voice: voice.clone() })?;
+        self.connected = true;
+        self.session_id = Some(voice.session_id.clone());
+        self.voice = Some(voice);
+
+        Ok(())
+    }
+
+    /// Sends a WebSocket message over the node.
+    ///
+    /// If the node's sender is full, then the message is queued and sent once
+    /// the sender is ready, by a task spawned on the player's [remote].
+    /// Queued messages are also sent in order when another message is sent,
+    /// or when a future from [`deliver`] or [`flush`] is polled.
+    ///
+    /// Returns [`Error::QueueFull`] if too many messages are already queued,
+    /// or [`Error::Closed`] if the connection to the node was closed.
+    ///
+    /// You should prefer using one of the other methods where it makes sense.
+    ///
+    /// [`Error::Closed`]: ../enum.Error.html#variant.Closed
+    /// [`Error::QueueFull`]: ../enum.Error.html#variant.QueueFull
+    /// [`deliver`]: #method.deliver
+    /// [`flush`]: #method.flush
+    /// [remote]: #method.set_remote
+    pub fn send(&mut self, message: OwnedMessage) -> Result<(), Error> {
+        let mut rx = self.enqueue_message(message).map_err(|why| self.context(why))?;
+
+        // Report the error if the message couldn't be sent immediately, and
+        // otherwise keep its delivery for the futures of `_async` commands.
+        self.delivery = match rx.try_recv() {
+            Ok(Some(Err(why))) => return Err(self.context(why)),
+            Ok(Some(Ok(()))) => LastDelivery::Delivered,
+            _ => LastDelivery::Pending(rx),
+        };
+
+        Ok(())
+    }
+
+    /// Serializes an op and sends it over the node.
+    ///
+    /// Refer to [`send`] for more information.
+    ///
+    /// [`send`]: #method.send
+    pub fn send_op(&mut self, op: OutboundOp) -> Result<(), Error> {
+        let message = op.to_message().map_err(|why| self.context(why))?;
+
+        self.send(message)
+    }
+
+    /// Sends a WebSocket message over the node, resolving once the message has
+    /// been accepted by the node's sender.
+    ///
+    /// Refer to [`send`] for more information.
+    ///
+    /// [`send`]: #method.send
+    pub fn deliver(&mut self, message: OwnedMessage)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        let rx = match self.enqueue_message(message) {
+            Ok(rx) => rx,
+            Err(why) => return Box::new(future::err(self.context(why))),
+        };
+
+        let guild_id = self.guild_id;
+        let node = self.node_websocket_host.clone();
+
+        Box::new(Delivery {
+            outbound: Arc::clone(&self.outbound),
+            rx,
+        }.map_err(move |why| why.with_guild(guild_id).with_node(node)))
+    }
+
+    /// Resolves once all of the queued messages have been accepted by the
+    /// node's sender.
+    pub fn flush(&self) -> Box<Future<Item = (), Error = Error> + Send> {
+        Box::new(Flush {
+            outbound: Arc::clone(&self.outbound),
+        })
+    }
+
+    /// The number of messages that are queued to be sent.
+    pub fn queued(&self) -> usize {
+        self.outbound.lock().map(|outbound| outbound.queue.len()).unwrap_or(0)
+    }
+
+    /// Whether the player has neither a track playing nor one that it was
+    /// told to play, so a track can be started immediately rather than
+    /// enqueued.
+    fn is_idle(&self) -> bool {
+        self.track.is_none() && self.pending_track.is_none()
+    }
+
+    /// Adds the player's guild and node to the context of an error.
+    fn context(&self, error: Error) -> Error {
+        error
+            .with_guild(self.guild_id)
+            .with_node(self.node_websocket_host.clone())
+    }
+
+    /// Runs a command, resolving once the last message that it sent has been
+    /// accepted by the node's sender, or immediately if it failed or sent
+    /// nothing.
+    ///
+    /// This waits on neither the messages queued before the command nor
+    /// those queued after it.
+    fn flushed<F>(&mut self, command: F) -> Box<Future<Item = (), Error = Error> + Send>
+        where F: FnOnce(&mut Self) -> Result<(), Error> {
+        self.delivery = LastDelivery::Unsent;
+
+        if let Err(why) = command(self) {
+            return Box::new(future::err(why));
+        }
+
+        let rx = match mem::replace(&mut self.delivery, LastDelivery::Unsent) {
+            LastDelivery::Pending(rx) => rx,
+            LastDelivery::Delivered | LastDelivery::Unsent => return Box::new(future::ok(())),
+        };
+        let guild_id = self.guild_id;
+        let node = self.node_websocket_host.clone();
+
+        Box::new(Delivery {
+            outbound: Arc::clone(&self.outbound),
+            rx,
+        }.map_err(move |why| why.with_guild(guild_id).with_node(node)))
+    }
+
+    fn enqueue_message(&mut self, message: OwnedMessage)
+        -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        if self.validation {
+            validation::validate_message(&message, &PlayerContext::from(&*self))?;
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let mut outbound = self.outbound.lock()?;
+
+        if outbound.queue.len() >= MAX_QUEUED {
+            event!(
+                warn,
+                guild_id = self.guild_id,
+                node = self.node_websocket_host;
+                "Player command queue is full"
+            );
+
+            return Err(Error::QueueFull);
+        }
+
+        event!(
+            trace,
+            guild_id = self.guild_id,
+            node = self.node_websocket_host,
+            queued = outbound.queue.len();
+            "Sending player command: {:?}",
+            message
+        );
+
+        outbound.queue.push_back((message, tx));
+        outbound.flush(false);
+        self.drain_later(&mut outbound);
+
+        Ok(rx)
+    }
+
+    /// Spawns a task on the player's remote sending the messages left queued
+    /// once the sender is ready, unless one is already running.
+    fn drain_later(&self, outbound: &mut Outbound) {
+        if outbound.draining || outbound.queue.is_empty() {
+            return;
+        }
+
+        let remote = match outbound.remote {
+            Some(ref remote) => remote.clone(),
+            None => return,
+        };
+
+        outbound.draining = true;
+
+        let drain = Drain {
+            outbound: Arc::clone(&self.outbound),
+        };
+
+        remote.spawn(move |_| drain);
+    }
+
+    /// Sets the sender to the node that the player sends over, flushing any
+    /// queued messages over it.
+    ///
+    /// This does not update the [`node_websocket_host`].
+    ///
+    /// [`node_websocket_host`]: #structfield.node_websocket_host
+    pub fn set_sender(&mut self, sender: MpscSender<OwnedMessage>) {
+        match self.outbound.lock() {
+            Ok(mut outbound) => {
+                outbound.sender = sender;
+                outbound.flush(false);
+
+                // A running drain waits for the readiness of the previous
+                // sender, so another is spawned for the new one.
+                outbound.draining = false;
+                self.drain_later(&mut outbound);
+            },
+            Err(why) => {
+                event!(warn, guild_id = self.guild_id; "Err locking outbound queue: {:?}", why);
+            },
+        }
+    }
+}
+
+impl Debug for AudioPlayer {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("AudioPlayer")
+            .field("channel_id", &self.channel_id)
+            .field("connected", &self.connected)
+            .field("filters", &self.filters)
+            .field("guild_id", &self.guild_id)
+            .field("handler", &self.handler.is_some())
+            .field("history", &self.history)
+            .field("history_limit", &self.history_limit)
+            .field("listeners", &self.listeners.len())
+            .field("markers", &self.markers)
+            .field("node_disconnected", &self.node_disconnected)
+            .field("num_shards", &self.voice_connections.num_shards())
+            .field("node_websocket_host", &self.node_websocket_host)
+            .field("paused", &self.paused)
+            .field("pending_track", &self.pending_track)
+            .field("play_status", &self.play_status)
+            .field("played_at", &self.played_at)
+            .field("position", &self.position)
+            .field("queue", &self.queue)
+            .field("queued", &self.queued())
+            .field("replayed", &self.replayed)
+            .field("requeue_interrupted", &self.requeue_interrupted)
+            .field("session_id", &self.session_id)
+            .field("stuck", &self.stuck)
+            .field("stuck_policy", &self.stuck_policy)
+            .field("time", &self.time)
+            .field("track", &self.track)
+            .field("track_data", &self.track_data.is_some())
+            .field("track_length", &self.track_length)
+            .field("track_seekable", &self.track_seekable)
+            .field("track_started", &self.track_started)
+            .field("unacknowledged", &self.unacknowledged())
+            .field("validation", &self.validation)
+            .field("voice", &self.voice)
+            .field("volume", &self.volume)
+            .finish()
+    }
+}
+
+/// The maximum number of messages that can be queued for a player.
+const MAX_QUEUED: usize = 256;
+
+/// The queue of messages waiting to be sent over a player's node, shared
+/// between clones of the player so that messages are sent in order.
+struct Outbound {
+    draining: bool,
+    queue: VecDeque<(OwnedMessage, oneshot::Sender<Result<(), Error>>)>,
+    remote: Option<Remote>,
+    sender: MpscSender<OwnedMessage>,
+}
+
+impl Outbound {
+    /// Sends as many queued messages as the sender is ready for, completing
+    /// their deliveries.
+    ///
+    /// Within a task, the task is notified once the sender is ready again.
+    /// Outside of a task, the sender can't wait for readiness.
+    fn flush(&mut self, in_task: bool) {
+        while let Some((msg, tx)) = self.queue.pop_front() {
+            let res = if in_task {
+                match self.sender.start_send(msg) {
+                    Ok(AsyncSink::Ready) => Ok(()),
+                    Ok(AsyncSink::NotReady(msg)) => {
+                        self.queue.push_front((msg, tx));
+
+                        break;
+                    },
+                    Err(_) => Err(Error::Closed),
+                }
+            } else {
+                match self.sender.try_send(msg) {
+                    Ok(()) => Ok(()),
+                    Err(why) => {
+                        if why.is_full() {
+                            self.queue.push_front((why.into_inner(), tx));
+
+                            break;
+                        }
+
+                        Err(Error::Closed)
+                    },
+                }
+            };
+
+            // The delivery may not be awaited.
+            let _ = tx.send(res);
+        }
+    }
+}
+
+/// The delivery of the last message sent by a player, taken by the futures
+/// of its `_async` commands via `AudioPlayer::flushed`.
+///
+/// Clones of the player don't share it, so a clone starts without one.
+enum LastDelivery {
+    /// The message was accepted by the sender immediately.
+    Delivered,
+    /// The message is queued until the sender is ready.
+    Pending(oneshot::Receiver<Result<(), Error>>),
+    /// Nothing was sent.
+    Unsent,
+}
+
+impl Clone for LastDelivery {
+    fn clone(&self) -> Self {
+        LastDelivery::Unsent
+    }
+}
+
+/// A future resolving once a queued message is accepted by the sender.
+struct Delivery {
+    outbound: Arc<Mutex<Outbound>>,
+    rx: oneshot::Receiver<Result<(), Error>>,
+}
+
+impl Future for Delivery {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        self.outbound.lock()?.flush(true);
+
+        match self.rx.poll()? {
+            Async::Ready(res) => res.map(Async::Ready),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// A future resolving once all queued messages are accepted by the sender.
+struct Flush {
+    outbound: Arc<Mutex<Outbound>>,
+}
+
+impl Future for Flush {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        let mut outbound = self.outbound.lock()?;
+        outbound.flush(true);
+
+        if outbound.queue.is_empty() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// A task sending the queued messages of a player as the sender becomes
+/// ready, spawned on its remote once the sender is full.
+struct Drain {
+    outbound: Arc<Mutex<Outbound>>,
+}
+
+impl Future for Drain {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let mut outbound = match self.outbound.lock() {
+            Ok(outbound) => outbound,
+            Err(why) => {
+                event!(warn; "Err locking outbound queue: {:?}", why);
+
+                return Err(());
+            },
+        };
+
+        outbound.flush(true);
+
+        // Checked under the same lock as messages are queued, so that a
+        // message queued once this resolves spawns another drain.
+        if outbound.queue.is_empty() {
+            outbound.draining = false;
+
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// Locks the player manager, recovering it if a panic poisoned the lock.
+///
+/// Every lock of the player manager within the library goes through this, so
+/// that a panic in one place - such as in a listener - doesn't make players
+/// inaccessible or drop the events of nodes everywhere else.
+pub(crate) fn lock_players(player_manager: &Arc<Mutex<AudioPlayerManager>>)
+    -> MutexGuard<AudioPlayerManager> {
+    player_manager.lock().unwrap_or_else(|poisoned| {
+        event!(warn; "Player manager was poisoned, recovering it");
+
+        poisoned.into_inner()
+    })
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_nanos() / 1_000_000)
+}
+
+fn now_millis() -> i64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    now.as_secs() as i64 * 1000 + i64::from(now.subsec_nanos() / 1_000_000)
+}
+
+fn parse_op(payload: &Value) -> Option<OutboundOp> {
+    let op = payload.get("op")?.as_str()?;
+
+    if op == "configureResuming" {
+        return Some(OutboundOp::ConfigureResuming {
+            key: payload.get("key")?.as_str()?.to_owned(),
+            timeout: payload.get("timeout")?.as_u64()?,
+        });
+    }
+
+    let guild_id = payload.get("guildId")?.as_str()?.parse().ok()?;
+
+    Some(match op {
+        "destroy" => OutboundOp::Destroy { guild_id },
+        "equalizer" => OutboundOp::Equalizer {
+            bands: serde_json::from_value(payload.get("bands")?.clone()).ok()?,
+            guild_id,
+        },
+        "filters" => {
+            let mut filters = payload.as_object()?.clone();
+            filters.remove("guildId");
+            filters.remove("op");
+
+            OutboundOp::Filters {
+                filters: serde_json::from_value(Value::Object(filters)).ok()?,
+                guild_id,
+            }
+        },
+        "pause" => OutboundOp::Pause {
+            guild_id,
+            pause: payload.get("pause")?.as_bool()?,
+        },
+        "play" => OutboundOp::Play {
+            end_time: payload.get("endTime").and_then(Value::as_u64),
+            guild_id,
+            no_replace: payload.get("noReplace").and_then(Value::as_bool).unwrap_or(false),
+            start_time: payload.get("startTime").and_then(Value::as_u64),
+            track: payload.get("track")?.as_str()?.to_owned(),
+        },
+        "seek" => OutboundOp::Seek {
+            guild_id,
+            position: payload.get("position")?.as_i64()?,
+        },
+        "stop" => OutboundOp::Stop { guild_id },
+        "voiceUpdate" => {
+            let event = payload.get("event")?;
+
+            OutboundOp::VoiceUpdate {
+                guild_id,
+                voice: VoiceUpdate {
+                    endpoint: event.get("endpoint")?.as_str()?.to_owned(),
+                    session_id: payload.get("sessionId")?.as_str()?.to_owned(),
+                    token: event.get("token")?.as_str()?.to_owned(),
+                },
+            }
+        },
+        "volume" => OutboundOp::Volume {
+            guild_id,
+            volume: payload.get("volume")?.as_i64()? as i32,
+        },
+        _ => return None,
+    })
+}
+
+fn validate_volume(volume: i32) -> Result<(), Error> {
+    if volume < MIN_VOLUME || volume > MAX_VOLUME {
+        return Err(Error::InvalidVolume(volume));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::mpsc::{self, Receiver};
+    use futures::{Future, Stream};
+    use serde_json::{self, Value};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+    use websocket::OwnedMessage;
+    use ::model::GuildId;
+    use super::{
+        AudioPlayer,
+        AudioPlayerHandle,
+        AudioPlayerManager,
+        EqualizerBand,
+        Filters,
+        OutboundOp,
+        RestCommand,
+        VoiceUpdate,
+    };
+
+    const HOST: &str = "ws://localhost:2333";
+
+    fn handle() -> (AudioPlayerHandle, Receiver<OwnedMessage>) {
+        let (sender, receiver) = mpsc::channel(16);
+        let mut manager = AudioPlayerManager::new();
+        manager.create(1, HOST.to_owned(), sender).unwrap();
+
+        (AudioPlayerHandle::new(1, Arc::new(Mutex::new(manager))), receiver)
+    }
+
+    /// The command that a payload is translated into.
+    fn command(payload: &Value) -> Option<RestCommand> {
+        RestCommand::from_message(&OwnedMessage::Text(payload.to_string()))
+    }
+
+    fn player() -> (AudioPlayer, Receiver<OwnedMessage>) {
+        let (sender, receiver) = mpsc::channel(0);
+
+        (AudioPlayer::new(1, HOST.to_owned(), sender), receiver)
+    }
+
+    /// An update of the player of guild 1 with the body.
+    fn update(body: Value, no_replace: bool) -> Option<RestCommand> {
+        Some(RestCommand::Update {
+            body,
+            guild_id: GuildId(1),
+            no_replace,
+        })
+    }
+
+    /// Takes the payloads that were sent, as pairs of their op and volume.
+    fn sent(receiver: Receiver<OwnedMessage>, count: u64) -> Vec<(String, Option<i64>)> {
+        let messages = receiver.take(count).collect().wait().unwrap();
+
+        messages.into_iter().map(|message| {
+            let payload: Value = match message {
+                OwnedMessage::Text(text) => serde_json::from_str(&text).unwrap(),
+                other => panic!("Unexpected message: {:?}", other),
+            };
+
+            (payload["op"].as_str().unwrap().to_owned(), payload["volume"].as_i64())
+        }).collect()
+    }
+
+    #[test]
+    fn test_async_command_resolves_once_sent() {
+        let (mut player, _receiver) = player();
+
+        assert!(player.pause_async(true).wait().is_ok());
+    }
+
+    #[test]
+    fn test_async_command_fails_when_dropped() {
+        let (mut player, receiver) = player();
+
+        // Fill the sender, so that the pause is queued.
+        player.volume(50).unwrap();
+
+        let paused = player.pause_async(true);
+
+        assert_eq!(player.queued(), 1);
+
+        drop(receiver);
+
+        assert!(paused.wait().is_err());
+    }
+
+    #[test]
+    fn test_pause_after_pauses_stored_player() {
+        let mut core = Core::new().unwrap();
+        let (handle, receiver) = handle();
+
+        core.run(handle.pause_after(Duration::from_millis(10), &core.handle())).unwrap();
+
+        assert!(handle.player().unwrap().paused);
+        assert_eq!(sent(receiver, 1), vec![("pause".to_owned(), None)]);
+    }
+
+    #[test]
+    fn test_stop_with_fade_restores_volume_of_stored_player() {
+        let mut core = Core::new().unwrap();
+        let (handle, receiver) = handle();
+        handle.volume(90).unwrap();
+
+        core.run(handle.stop_with_fade(Duration::from_millis(300), &core.handle())).unwrap();
+
+        assert_eq!(handle.player().unwrap().volume, 90);
+        assert_eq!(sent(receiver, 6), vec![
+            ("volume".to_owned(), Some(90)),
+            ("volume".to_owned(), Some(60)),
+            ("volume".to_owned(), Some(30)),
+            ("volume".to_owned(), Some(0)),
+            ("stop".to_owned(), None),
+            ("volume".to_owned(), Some(90)),
+        ]);
+    }
+
+    #[test]
+    fn test_rest_command_destroy() {
+        let payload = json!({ "op": "destroy", "guildId": "1" });
+
+        assert_eq!(command(&payload), Some(RestCommand::Destroy { guild_id: GuildId(1) }));
+    }
+
+    #[test]
+    fn test_rest_command_equalizer_sets_filters() {
+        let payload = json!({
+            "op": "equalizer",
+            "guildId": "1",
+            "bands": [{ "band": 0, "gain": 0.25 }],
+        });
+        let body = json!({ "filters": { "equalizer": [{ "band": 0, "gain": 0.25 }] } });
+
+        assert_eq!(command(&payload), update(body, false));
+    }
+
+    #[test]
+    fn test_rest_command_filters() {
+        let payload = json!({
+            "op": "filters",
+            "guildId": "1",
+            "timescale": { "pitch": 1.2, "rate": 1.0, "speed": 1.2 },
+        });
+        let body = json!({
+            "filters": { "timescale": { "pitch": 1.2, "rate": 1.0, "speed": 1.2 } },
+        });
+
+        assert_eq!(command(&payload), update(body, false));
+    }
+
+    #[test]
+    fn test_rest_command_pause() {
+        let payload = json!({ "op": "pause", "guildId": "1", "pause": true });
+
+        assert_eq!(command(&payload), update(json!({ "paused": true }), false));
+    }
+
+    #[test]
+    fn test_rest_command_play() {
+        let payload = json!({
+            "op": "play",
+            "guildId": "1",
+            "track": "track",
+            "startTime": 1000,
+            "endTime": 5000,
+            "noReplace": true,
+        });
+        let body = json!({ "encodedTrack": "track", "position": 1000, "endTime": 5000 });
+
+        assert_eq!(command(&payload), update(body, true));
+    }
+
+    #[test]
+    fn test_rest_command_seek() {
+        let payload = json!({ "op": "seek", "guildId": "1", "position": 1000 });
+
+        assert_eq!(command(&payload), update(json!({ "position": 1000 }), false));
+    }
+
+    #[test]
+    fn test_rest_command_stop_clears_track() {
+        let payload = json!({ "op": "stop", "guildId": "1" });
+
+        assert_eq!(command(&payload), update(json!({ "encodedTrack": null }), false));
+    }
+
+    #[test]
+    fn test_rest_command_voice_update() {
+        let payload = json!({
+            "op": "voiceUpdate",
+            "guildId": "1",
+            "sessionId": "session",
+            "event": { "endpoint": "endpoint", "guild_id": "1", "token": "token" },
+        });
+        let body = json!({
+            "voice": { "endpoint": "endpoint", "sessionId": "session", "token": "token" },
+        });
+
+        assert_eq!(command(&payload), update(body, false));
+    }
+
+    #[test]
+    fn test_rest_command_volume() {
+        let payload = json!({ "op": "volume", "guildId": "1", "volume": 50 });
+
+        assert_eq!(command(&payload), update(json!({ "volume": 50 }), false));
+    }
+
+    #[test]
+    fn test_rest_command_ignores_other_messages() {
+        let payload = json!({ "op": "configureResuming", "key": "key", "timeout": 60 });
+
+        assert_eq!(command(&payload), None);
+        assert_eq!(command(&json!({ "op": "custom", "guildId": "1" })), None);
+        assert_eq!(RestCommand::from_message(&OwnedMessage::Ping(Vec::new())), None);
+    }
+
+    #[test]
+    fn test_outbound_op_configure_resuming() {
+        let payload = json!({ "op": "configureResuming", "key": "key", "timeout": 60 });
+        let op = OutboundOp::ConfigureResuming {
+            key: "key".to_owned(),
+            timeout: 60,
+        };
+
+        assert_eq!(OutboundOp::from_json(payload), op);
+    }
+
+    #[test]
+    fn test_outbound_op_destroy() {
+        let payload = json!({ "op": "destroy", "guildId": "1" });
+
+        assert_eq!(OutboundOp::from_json(payload), OutboundOp::Destroy { guild_id: GuildId(1) });
+    }
+
+    #[test]
+    fn test_outbound_op_equalizer() {
+        let payload = json!({
+            "op": "equalizer",
+            "guildId": "1",
+            "bands": [{ "band": 0, "gain": 0.25 }],
+        });
+        let op = OutboundOp::Equalizer {
+            bands: vec![EqualizerBand { band: 0, gain: 0.25 }],
+            guild_id: GuildId(1),
+        };
+
+        assert_eq!(OutboundOp::from_json(payload), op);
+    }
+
+    #[test]
+    fn test_outbound_op_filters() {
+        let payload = json!({ "op": "filters", "guildId": "1", "volume": 0.5 });
+        let op = OutboundOp::Filters {
+            filters: Filters {
+                volume: Some(0.5),
+                ..Filters::default()
+            },
+            guild_id: GuildId(1),
+        };
+
+        assert_eq!(OutboundOp::from_json(payload), op);
+    }
+
+    #[test]
+    fn test_outbound_op_filters_of_plugin_is_raw() {
+        let payload = json!({
+            "op": "filters",
+            "guildId": "1",
+            "pluginFilters": { "echo": { "delay": 0.5 } },
+        });
+
+        assert_eq!(OutboundOp::from_json(payload.clone()), OutboundOp::Raw(payload));
+    }
+
+    #[test]
+    fn test_outbound_op_pause() {
+        let payload = json!({ "op": "pause", "guildId": "1", "pause": true });
+        let op = OutboundOp::Pause {
+            guild_id: GuildId(1),
+            pause: true,
+        };
+
+        assert_eq!(OutboundOp::from_json(payload), op);
+    }
+
+    #[test]
+    fn test_outbound_op_play() {
+        let payload = json!({
+            "op": "play",
+            "guildId": "1",
+            "track": "track",
+            "noReplace": false,
+            "startTime": 1000,
+        });
+        let op = OutboundOp::Play {
+            end_time: None,
+            guild_id: GuildId(1),
+            no_replace: false,
+            start_time: Some(1000),
+            track: "track".to_owned(),
+        };
+
+        assert_eq!(OutboundOp::from_json(payload), op);
+    }
+
+    #[test]
+    fn test_outbound_op_play_without_no_replace_is_raw() {
+        // The op would be serialized with `noReplace`, so doesn't round-trip.
+        let payload = json!({ "op": "play", "guildId": "1", "track": "track" });
+
+        assert_eq!(OutboundOp::from_json(payload.clone()), OutboundOp::Raw(payload));
+    }
+
+    #[test]
+    fn test_outbound_op_seek() {
+        let payload = json!({ "op": "seek", "guildId": "1", "position": 1000 });
+        let op = OutboundOp::Seek {
+            guild_id: GuildId(1),
+            position: 1000,
+        };
+
+        assert_eq!(OutboundOp::from_json(payload), op);
+    }
+
+    #[test]
+    fn test_outbound_op_stop() {
+        let payload = json!({ "op": "stop", "guildId": "1" });
+
+        assert_eq!(OutboundOp::from_json(payload), OutboundOp::Stop { guild_id: GuildId(1) });
+    }
+
+    #[test]
+    fn test_outbound_op_voice_update() {
+        let payload = json!({
+            "op": "voiceUpdate",
+            "guildId": "1",
+            "sessionId": "session",
+            "event": { "endpoint": "endpoint", "guild_id": "1", "token": "token" },
+        });
+        let op = OutboundOp::VoiceUpdate {
+            guild_id: GuildId(1),
+            voice: VoiceUpdate {
+                endpoint: "endpoint".to_owned(),
+                session_id: "session".to_owned(),
+                token: "token".to_owned(),
+            },
+        };
+
+        assert_eq!(OutboundOp::from_json(payload), op);
+    }
+
+    #[test]
+    fn test_outbound_op_volume() {
+        let payload = json!({ "op": "volume", "guildId": "1", "volume": 50 });
+        let op = OutboundOp::Volume {
+            guild_id: GuildId(1),
+            volume: 50,
+        };
+
+        assert_eq!(OutboundOp::from_json(payload), op);
+    }
+
+    #[test]
+    fn test_outbound_op_other_payloads_are_raw() {
+        let payloads = vec![
+            json!({ "op": "custom", "guildId": "1" }),
+            json!({ "op": "pause", "pause": true }),
+            json!({ "op": "pause", "guildId": "1", "pause": true, "extra": 1 }),
+            json!({ "guildId": "1" }),
+        ];
+
+        for payload in payloads {
+            assert_eq!(OutboundOp::from_json(payload.clone()), OutboundOp::Raw(payload));
+        }
     }
 }
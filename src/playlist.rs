@@ -0,0 +1,131 @@
+//! Playlists loaded from nodes, and enqueuing them onto players.
+//!
+//! A [`Playlist`] is created from the [`LoadTracksResponse`] of loading a
+//! playlist's URL, such as via [`NodeRest::load_tracks`]. All of its tracks
+//! can then be added to a player's [`queue`] in one call via
+//! [`Playlist::enqueue`].
+//!
+//! [`LoadTracksResponse`]: ../rest/struct.LoadTracksResponse.html
+//! [`NodeRest::load_tracks`]: ../rest/struct.NodeRest.html#method.load_tracks
+//! [`Playlist`]: struct.Playlist.html
+//! [`Playlist::enqueue`]: struct.Playlist.html#method.enqueue
+//! [`queue`]: ../player/struct.AudioPlayer.html#structfield.queue
+
+use ::player::AudioPlayer;
+use ::rest::{LoadTracksResponse, LoadedTrack};
+use ::Error;
+
+/// The default maximum length of a player's queue when enqueuing a playlist.
+pub const DEFAULT_MAX_QUEUE_LENGTH: usize = 500;
+
+/// A playlist of tracks loaded from a node.
+#[derive(Clone, Debug)]
+pub struct Playlist {
+    /// The name of the playlist, if it was loaded as a playlist.
+    pub name: Option<String>,
+    /// The index of the track that was selected in the playlist, if any.
+    ///
+    /// This is the case when loading the URL of a track within a playlist.
+    pub selected_track: Option<usize>,
+    /// The tracks of the playlist.
+    pub tracks: Vec<LoadedTrack>,
+}
+
+impl Playlist {
+    /// Adds the tracks of the playlist, starting from the selected track, to
+    /// a player's [`queue`], with up to [`DEFAULT_MAX_QUEUE_LENGTH`] tracks
+    /// in the queue.
+    ///
+    /// Refer to [`enqueue_with_limit`] for more information.
+    ///
+    /// [`DEFAULT_MAX_QUEUE_LENGTH`]: constant.DEFAULT_MAX_QUEUE_LENGTH.html
+    /// [`enqueue_with_limit`]: #method.enqueue_with_limit
+    /// [`queue`]: ../player/struct.AudioPlayer.html#structfield.queue
+    pub fn enqueue(&self, player: &mut AudioPlayer) -> Result<usize, Error> {
+        self.enqueue_with_limit(player, DEFAULT_MAX_QUEUE_LENGTH)
+    }
+
+    /// Adds the tracks of the playlist, starting from the selected track, to
+    /// a player's [`queue`], stopping once the queue has `max_length`
+    /// tracks.
+    ///
+    /// If the player is not playing a track, then the first track is played
+    /// rather than queued.
+    ///
+    /// Returns the number of tracks that were played or queued. Returns an
+    /// error if the first track could not be played, in which case no tracks
+    /// are queued.
+    ///
+    /// [`queue`]: ../player/struct.AudioPlayer.html#structfield.queue
+    pub fn enqueue_with_limit(&self, player: &mut AudioPlayer, max_length: usize)
+        -> Result<usize, Error> {
+        let mut tracks = self.tracks_from_selected().iter();
+        let mut count = 0;
+
+        if player.track.is_none() && player.pending_track.is_none() {
+            if let Some(first) = tracks.next() {
+                player.play(&first.track, None, None)?;
+                player.set_track_info(&first.info);
+
+                count += 1;
+            }
+        }
+
+        for loaded in tracks {
+            if player.queue.len() >= max_length {
+                break;
+            }
+
+            player.queue.push_back(loaded.track.clone());
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Whether the playlist has no tracks.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// The number of tracks in the playlist.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// The track that was selected in the playlist, if any.
+    pub fn selected(&self) -> Option<&LoadedTrack> {
+        self.selected_track.and_then(|index| self.tracks.get(index))
+    }
+
+    /// The tracks of the playlist starting from the selected track, or every
+    /// track if none was selected.
+    pub fn tracks_from_selected(&self) -> &[LoadedTrack] {
+        match self.selected_track {
+            Some(index) if index < self.tracks.len() => &self.tracks[index..],
+            _ => &self.tracks,
+        }
+    }
+}
+
+impl From<LoadTracksResponse> for Playlist {
+    fn from(response: LoadTracksResponse) -> Self {
+        let (name, selected_track) = match response.playlist_info {
+            Some(info) => {
+                let selected = info.selected_track
+                    .and_then(|index| if index >= 0 { Some(index as usize) } else { None });
+
+                (info.name, selected)
+            },
+            None => (None, None),
+        };
+
+        Self {
+            tracks: response.tracks,
+            name,
+            selected_track,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Models for the [LavaLyrics] plugin.
+//!
+//! Lyrics of a track are loaded via [`Node::load_lyrics`]. Once a player is
+//! subscribed via [`Node::subscribe_lyrics`], the node sends an event as the
+//! player reaches each line of its track's lyrics, which is passed to
+//! [`EventHandler::lyrics_line`].
+//!
+//! [LavaLyrics]: https://github.com/topi314/LavaLyrics
+//! [`EventHandler::lyrics_line`]: ../../trait.EventHandler.html#method.lyrics_line
+//! [`Node::load_lyrics`]: ../../nodes/struct.Node.html#method.load_lyrics
+//! [`Node::subscribe_lyrics`]: ../../nodes/struct.Node.html#method.subscribe_lyrics
+
+use serde_json::Value;
+use ::model::GuildId;
+
+/// The lyrics of a track, loaded via [`Node::load_lyrics`].
+///
+/// [`Node::load_lyrics`]: ../../nodes/struct.Node.html#method.load_lyrics
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lyrics {
+    /// The timed lines of the lyrics.
+    ///
+    /// This is empty if the provider only has the plain [`text`].
+    ///
+    /// [`text`]: #structfield.text
+    #[serde(default)]
+    pub lines: Vec<LyricsLine>,
+    /// Additional information from the plugin providing the lyrics.
+    #[serde(default)]
+    pub plugin: Value,
+    /// The name of the provider of the lyrics.
+    pub provider: String,
+    /// The name of the source that the lyrics were loaded from, such as
+    /// `"spotify"`.
+    pub source_name: String,
+    /// The plain text of the lyrics, if known.
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+/// A timed line of a track's [`Lyrics`].
+///
+/// [`Lyrics`]: struct.Lyrics.html
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LyricsLine {
+    /// How long the line lasts in milliseconds, if known.
+    #[serde(default)]
+    pub duration: Option<i64>,
+    /// The text of the line.
+    pub line: String,
+    /// Additional information from the plugin providing the lyrics.
+    #[serde(default)]
+    pub plugin: Value,
+    /// The position in the track that the line starts at in milliseconds.
+    pub timestamp: i64,
+}
+
+/// A player subscribed to lyrics reached a line of its track's lyrics.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricsLineEvent {
+    /// The ID of the guild of the player.
+    pub guild_id: GuildId,
+    /// The line that was reached.
+    pub line: LyricsLine,
+    /// The index of the line within the lyrics' [`lines`].
+    ///
+    /// [`lines`]: struct.Lyrics.html#structfield.lines
+    pub line_index: i64,
+    /// Whether the line was skipped over, such as by seeking.
+    #[serde(default)]
+    pub skipped: bool,
+}
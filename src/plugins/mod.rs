@@ -0,0 +1,21 @@
+//! Models for the payloads of popular Lavalink plugins, each behind a cargo
+//! feature of the same name.
+//!
+//! - `lyrics`: [`plugins::lyrics`], for the LavaLyrics plugin
+//! - `sponsorblock`: [`plugins::sponsorblock`], for the SponsorBlock plugin
+//!
+//! Enabling a feature also adds the plugin's REST endpoints to [`Node`] and
+//! [`NodeRest`], and dispatches the plugin's events to their own
+//! [`EventHandler`] tymethods rather than [`EventHandler::unknown_op`].
+//!
+//! [`EventHandler`]: ../trait.EventHandler.html
+//! [`EventHandler::unknown_op`]: ../trait.EventHandler.html#method.unknown_op
+//! [`Node`]: ../nodes/struct.Node.html
+//! [`NodeRest`]: ../rest/struct.NodeRest.html
+//! [`plugins::lyrics`]: lyrics/index.html
+//! [`plugins::sponsorblock`]: sponsorblock/index.html
+
+#[cfg(feature = "lyrics")]
+pub mod lyrics;
+#[cfg(feature = "sponsorblock")]
+pub mod sponsorblock;
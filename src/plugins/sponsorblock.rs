@@ -0,0 +1,33 @@
+//! Models for the [SponsorBlock plugin].
+//!
+//! The categories of segments to skip are set per player via
+//! [`Node::set_sponsorblock_categories`]. The node skips segments of those
+//! categories in YouTube tracks, sending an event for each skipped segment
+//! which is passed to [`EventHandler::segment_skipped`].
+//!
+//! [SponsorBlock plugin]: https://github.com/topi314/Sponsorblock-Plugin
+//! [`EventHandler::segment_skipped`]: ../../trait.EventHandler.html#method.segment_skipped
+//! [`Node::set_sponsorblock_categories`]: ../../nodes/struct.Node.html#method.set_sponsorblock_categories
+
+use ::model::GuildId;
+
+/// A segment of a track, such as a sponsored section of a video.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Segment {
+    /// The category of the segment, such as `"sponsor"` or `"intro"`.
+    pub category: String,
+    /// The position in the track that the segment ends at in milliseconds.
+    pub end: i64,
+    /// The position in the track that the segment starts at in milliseconds.
+    pub start: i64,
+}
+
+/// A segment of a player's track was skipped.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentSkippedEvent {
+    /// The ID of the guild of the player.
+    pub guild_id: GuildId,
+    /// The segment that was skipped.
+    pub segment: Segment,
+}
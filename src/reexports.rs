@@ -1,4 +1,18 @@
 //! A list of re-exports, so that other external crates don't need to be
 //! explicitly depended on.
+//!
+//! These are the types of other crates that appear in this crate's public
+//! API, so that downstream code can name them without depending on the
+//! versions of the crates that this crate is pinned to. Errors of the
+//! `hyper`, `native-tls` and `websocket` crates are boxed by [`Error`]
+//! rather than re-exported.
+//!
+//! [`Error`]: ../enum.Error.html
 
-pub use websocket::OwnedMessage;
+pub use futures::sync::oneshot::Canceled;
+pub use hyper::Method;
+pub use hyper::client::{Client, HttpConnector};
+pub use hyper_tls::HttpsConnector;
+pub use serde_json::{Error as JsonError, Value};
+pub use tokio_core::reactor::{Handle, Remote};
+pub use websocket::{ClientBuilder, CloseData, OwnedMessage};
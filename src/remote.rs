@@ -0,0 +1,27 @@
+use futures::sync::oneshot;
+use futures::{Future, future};
+use tokio_core::reactor::{Handle, Remote};
+use ::Error;
+
+/// Runs the future created by the given function on the tokio Core that the
+/// remote belongs to, resolving with its result.
+///
+/// This allows futures that require a `Handle` to be started from any thread.
+pub fn run<F, T>(remote: &Remote, f: F)
+    -> Box<Future<Item = T, Error = Error> + Send>
+    where F: FnOnce(&Handle) -> Box<Future<Item = T, Error = Error>> + Send + 'static,
+          T: Send + 'static {
+    let (tx, rx) = oneshot::channel();
+
+    remote.spawn(move |handle| {
+        f(handle).then(move |res| {
+            if tx.send(res).is_err() {
+                event!(debug; "Receiver of remote future dropped");
+            }
+
+            future::ok::<(), ()>(())
+        })
+    });
+
+    Box::new(rx.from_err().and_then(|res| res))
+}
@@ -0,0 +1,45 @@
+use futures::{Future, future};
+use futures_cpupool::{Builder, CpuPool};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::{Once, ONCE_INIT};
+use ::Error;
+
+/// The number of threads that lookups are shared between.
+const THREADS: usize = 2;
+
+static INIT: Once = ONCE_INIT;
+static mut POOL: Option<CpuPool> = None;
+
+/// Resolves a host into its addresses on the shared resolver pool, so that the
+/// Core isn't blocked by slow DNS lookups.
+///
+/// Hosts that are already IP addresses are returned without a lookup.
+pub fn resolve(host: &str, port: u16) -> Box<Future<Item = Vec<SocketAddr>, Error = Error>> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Box::new(future::ok(vec![SocketAddr::new(ip, port)]));
+    }
+
+    let host = host.to_owned();
+
+    let done = pool().spawn_fn(move || {
+        (&host[..], port).to_socket_addrs().map(|addrs| addrs.collect())
+    });
+
+    Box::new(done.map_err(From::from))
+}
+
+/// The pool that lookups run on, started on the first lookup and shared by
+/// every node.
+fn pool() -> &'static CpuPool {
+    // The pool is only written once, before any reads, guarded by `INIT`.
+    unsafe {
+        INIT.call_once(|| {
+            POOL = Some(Builder::new()
+                .name_prefix("lavalink-resolver-")
+                .pool_size(THREADS)
+                .create());
+        });
+
+        POOL.as_ref().expect("resolver pool initialized")
+    }
+}
@@ -0,0 +1,1159 @@
+//! Functions and models for interacting with the REST API of Lavalink nodes,
+//! such as loading and searching for tracks.
+
+use futures::future::Either;
+use futures::sync::oneshot;
+use futures::{Future, Stream, future};
+use hyper::client::{Client, HttpConnector};
+use hyper::{Method, Request, StatusCode, Uri};
+use hyper_tls::HttpsConnector;
+use percent_encoding::{QUERY_ENCODE_SET, utf8_percent_encode};
+use serde_json::{self, Value};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_core::reactor::{Remote, Timeout};
+use websocket::async::Handle;
+use ::player::RestCommand;
+#[cfg(feature = "lyrics")]
+use ::plugins::lyrics::Lyrics;
+use ::{Error, remote, tls};
+
+/// The type of HTTP client used for REST requests, which supports both `http`
+/// and `https` hosts.
+pub type RestClient = Client<HttpsConnector<HttpConnector>>;
+
+define_encode_set! {
+    /// The encode set used for encoding track identifiers in query strings.
+    ///
+    /// This encodes characters that would otherwise split the identifier into
+    /// multiple query parameters.
+    pub IDENTIFIER_ENCODE_SET = [QUERY_ENCODE_SET] | {'&', '+', '=', '?', '/'}
+}
+
+/// Information about a track loaded from a node.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadedTrackInfo {
+    /// The author of the track.
+    pub author: String,
+    /// The identifier of the track, such as a YouTube video ID.
+    pub identifier: String,
+    /// Whether the track can be seeked.
+    pub is_seekable: bool,
+    /// Whether the track is a stream.
+    pub is_stream: bool,
+    /// The length of the track in milliseconds.
+    pub length: i64,
+    /// The position of the track in milliseconds.
+    pub position: i64,
+    /// The title of the track.
+    pub title: String,
+    /// The URI of the track.
+    pub uri: String,
+}
+
+/// A track loaded from a node.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LoadedTrack {
+    /// Information about the track.
+    pub info: LoadedTrackInfo,
+    /// The base64 encoded track, which can be passed to
+    /// [`AudioPlayer::play`].
+    ///
+    /// [`AudioPlayer::play`]: ../player/struct.AudioPlayer.html#method.play
+    pub track: String,
+}
+
+/// Information about a loaded playlist.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistInfo {
+    /// The name of the playlist.
+    pub name: Option<String>,
+    /// The index of the track that was selected in the playlist, if any.
+    pub selected_track: Option<i64>,
+}
+
+/// The response from loading tracks from a node.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadTracksResponse {
+    /// The type of result that was loaded, such as `"SEARCH_RESULT"`.
+    ///
+    /// This is not sent by older versions of Lavalink.
+    #[serde(default)]
+    pub load_type: Option<String>,
+    /// Information about the playlist, if a playlist was loaded.
+    #[serde(default)]
+    pub playlist_info: Option<PlaylistInfo>,
+    /// The tracks that were loaded.
+    pub tracks: Vec<LoadedTrack>,
+}
+
+impl LoadTracksResponse {
+    /// Parses a response body from a node.
+    ///
+    /// Older versions of Lavalink respond with only an array of tracks, while
+    /// newer versions respond with an object containing the tracks. Both are
+    /// supported.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+        let value = serde_json::from_slice::<Value>(bytes)?;
+
+        if value.is_array() {
+            return Ok(Self {
+                load_type: None,
+                playlist_info: None,
+                tracks: serde_json::from_value(value)?,
+            });
+        }
+
+        serde_json::from_value(value).map_err(From::from)
+    }
+}
+
+/// Configuration for caching the responses of [`NodeRest::load_tracks`].
+///
+/// [`NodeRest::load_tracks`]: struct.NodeRest.html#method.load_tracks
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    /// The maximum number of responses to cache. The least recently used
+    /// response is evicted when the cache is full. Defaults to 256.
+    pub capacity: usize,
+    /// How long a response is cached for. Defaults to 10 minutes.
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            ttl: Duration::from_secs(600),
+        }
+    }
+}
+
+/// The number of lookups of a [`LoadCache`] that were hits or misses.
+///
+/// [`LoadCache`]: struct.LoadCache.html
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// The number of lookups that found a cached response.
+    pub hits: u64,
+    /// The number of lookups that did not find a cached response, or found
+    /// an expired one.
+    pub misses: u64,
+}
+
+/// A least-recently-used cache of [`LoadTracksResponse`]s keyed by the
+/// identifier that was loaded, such as a search query.
+///
+/// The information of every loaded or decoded track is also cached, keyed by
+/// the base64 encoded track. Track information never changes, so it does not
+/// expire, and the oldest is evicted once the capacity is reached.
+///
+/// [`LoadTracksResponse`]: struct.LoadTracksResponse.html
+#[derive(Debug)]
+pub struct LoadCache {
+    config: CacheConfig,
+    entries: HashMap<String, (Instant, LoadTracksResponse)>,
+    // Identifiers ordered from least to most recently used.
+    recency: VecDeque<String>,
+    stats: CacheStats,
+    // Encoded tracks ordered from oldest to newest.
+    track_order: VecDeque<String>,
+    tracks: HashMap<String, LoadedTrackInfo>,
+}
+
+impl LoadCache {
+    /// Creates a new empty cache.
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: VecDeque::new(),
+            stats: CacheStats::default(),
+            track_order: VecDeque::new(),
+            tracks: HashMap::new(),
+            config,
+        }
+    }
+
+    /// Removes all of the cached responses and track information. The stats
+    /// are kept.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.track_order.clear();
+        self.tracks.clear();
+    }
+
+    /// Retrieves the cached response for the identifier if it has not
+    /// expired, counting a hit or miss.
+    pub fn get(&mut self, identifier: &str) -> Option<LoadTracksResponse> {
+        let expired = match self.entries.get(identifier) {
+            Some(&(inserted, _)) => inserted.elapsed() >= self.config.ttl,
+            None => {
+                self.stats.misses += 1;
+
+                return None;
+            },
+        };
+
+        if expired {
+            self.remove(identifier);
+            self.stats.misses += 1;
+
+            return None;
+        }
+
+        self.touch(identifier);
+        self.stats.hits += 1;
+
+        self.entries.get(identifier).map(|&(_, ref response)| response.clone())
+    }
+
+    /// Caches the response for the identifier, evicting the least recently
+    /// used response if the cache is full.
+    ///
+    /// The information of the response's tracks is cached as well.
+    pub fn insert(&mut self, identifier: String, response: LoadTracksResponse) {
+        if self.config.capacity == 0 {
+            return;
+        }
+
+        for loaded in &response.tracks {
+            self.insert_track_info(loaded.track.clone(), loaded.info.clone());
+        }
+
+        if self.entries.contains_key(&identifier) {
+            self.touch(&identifier);
+        } else {
+            while self.entries.len() >= self.config.capacity {
+                match self.recency.pop_front() {
+                    Some(oldest) => {
+                        self.entries.remove(&oldest);
+                    },
+                    None => break,
+                }
+            }
+
+            self.recency.push_back(identifier.clone());
+        }
+
+        self.entries.insert(identifier, (Instant::now(), response));
+    }
+
+    /// Caches the information of the base64 encoded track, evicting the
+    /// oldest information if the cache is full.
+    pub fn insert_track_info(&mut self, track: String, info: LoadedTrackInfo) {
+        if self.config.capacity == 0 {
+            return;
+        }
+
+        if !self.tracks.contains_key(&track) {
+            while self.tracks.len() >= self.config.capacity {
+                match self.track_order.pop_front() {
+                    Some(oldest) => {
+                        self.tracks.remove(&oldest);
+                    },
+                    None => break,
+                }
+            }
+
+            self.track_order.push_back(track.clone());
+        }
+
+        self.tracks.insert(track, info);
+    }
+
+    /// Retrieves the cached information of the base64 encoded track, if any.
+    ///
+    /// Lookups of track information are not counted in the stats.
+    pub fn track_info(&self, track: &str) -> Option<LoadedTrackInfo> {
+        self.tracks.get(track).cloned()
+    }
+
+    /// The number of cached responses, including expired ones that have not
+    /// yet been removed.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no cached responses.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The number of lookups that were hits or misses.
+    #[inline]
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn remove(&mut self, identifier: &str) {
+        self.entries.remove(identifier);
+        self.recency.retain(|key| key != identifier);
+    }
+
+    fn touch(&mut self, identifier: &str) {
+        if let Some(index) = self.recency.iter().position(|key| key == identifier) {
+            if let Some(key) = self.recency.remove(index) {
+                self.recency.push_back(key);
+            }
+        }
+    }
+}
+
+/// Configuration for limiting the rate of a node's REST requests via a token
+/// bucket.
+///
+/// Each request takes a token from the bucket, which holds up to the
+/// [`burst`] and is refilled at the rate of [`per_second`]. Requests made
+/// while the bucket is empty are queued until a token is available, in the
+/// order that they were made.
+///
+/// The burst must be at least 1 and the rate must be positive, which is
+/// checked by [`new`] and when connecting to the node.
+///
+/// [`burst`]: #structfield.burst
+/// [`new`]: #method.new
+/// [`per_second`]: #structfield.per_second
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    /// The maximum number of requests that can be made at once. Defaults to
+    /// 5.
+    pub burst: u32,
+    /// The number of tokens added to the bucket per second. Defaults to 5.
+    pub per_second: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            burst: 5,
+            per_second: 5.0,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Creates a new configuration with the given burst and rate.
+    ///
+    /// Returns [`Error::InvalidConfig`] if the burst is 0 or the rate is not
+    /// positive, as no request would ever be made.
+    ///
+    /// [`Error::InvalidConfig`]: ../enum.Error.html#variant.InvalidConfig
+    pub fn new(burst: u32, per_second: f64) -> Result<Self, Error> {
+        let config = Self {
+            burst,
+            per_second,
+        };
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Checks that the burst is at least 1 and the rate is positive.
+    ///
+    /// Returns [`Error::InvalidConfig`] otherwise.
+    ///
+    /// [`Error::InvalidConfig`]: ../enum.Error.html#variant.InvalidConfig
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.burst == 0 {
+            return Err(Error::InvalidConfig("The rate limit burst must be at least 1"));
+        }
+
+        if self.per_second.is_nan() || self.per_second <= 0.0 {
+            return Err(Error::InvalidConfig("The rate limit must be positive"));
+        }
+
+        Ok(())
+    }
+}
+
+/// A token bucket limiting the rate of requests, queueing excess requests.
+///
+/// Refer to [`RateLimitConfig`] for more information.
+///
+/// [`RateLimitConfig`]: struct.RateLimitConfig.html
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    /// Creates a new limiter with a full bucket.
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                last: Instant::now(),
+                tokens: f64::from(config.burst),
+                waiting: VecDeque::new(),
+                config,
+            })),
+        }
+    }
+
+    /// Resolves once a token is taken from the bucket, which is immediate if
+    /// the bucket has one and no requests are queued.
+    ///
+    /// This must be called on the thread of the tokio Core of the handle, as
+    /// queued requests are released via timers on it.
+    pub fn acquire(&self, handle: &Handle) -> Box<Future<Item = (), Error = Error>> {
+        let mut bucket = match self.bucket.lock() {
+            Ok(bucket) => bucket,
+            Err(why) => return Box::new(future::err(From::from(why))),
+        };
+
+        bucket.refill();
+
+        if bucket.waiting.is_empty() && bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+
+            return Box::new(future::ok(()));
+        }
+
+        let (tx, rx) = oneshot::channel();
+        bucket.waiting.push_back(tx);
+
+        // Only the first queued request schedules the release, which then
+        // reschedules itself until the queue is empty.
+        if bucket.waiting.len() == 1 {
+            let delay = bucket.delay();
+
+            drop(bucket);
+            release_after(handle.clone(), Arc::clone(&self.bucket), delay);
+        }
+
+        Box::new(rx.from_err())
+    }
+
+    /// The number of requests waiting for a token.
+    pub fn queued(&self) -> usize {
+        self.bucket.lock().map(|bucket| bucket.waiting.len()).unwrap_or(0)
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    config: RateLimitConfig,
+    last: Instant,
+    tokens: f64,
+    waiting: VecDeque<oneshot::Sender<()>>,
+}
+
+impl Bucket {
+    /// How long until the bucket has a token.
+    fn delay(&self) -> Duration {
+        let missing = (1.0 - self.tokens).max(0.0);
+        let millis = (missing / self.config.per_second.max(0.001) * 1000.0).ceil();
+
+        Duration::from_millis(millis as u64)
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+
+        self.tokens = (self.tokens + elapsed * self.config.per_second)
+            .min(f64::from(self.config.burst));
+        self.last = now;
+    }
+
+    /// Releases queued requests while there are tokens, returning whether
+    /// requests are still queued.
+    fn release(&mut self) -> bool {
+        self.refill();
+
+        while self.tokens >= 1.0 {
+            let tx = match self.waiting.pop_front() {
+                Some(tx) => tx,
+                None => break,
+            };
+
+            // Requests that were dropped while queued don't take a token.
+            if tx.send(()).is_ok() {
+                self.tokens -= 1.0;
+            }
+        }
+
+        !self.waiting.is_empty()
+    }
+}
+
+/// Spawns a timer releasing the queued requests of the bucket after the
+/// delay, rescheduling itself while requests remain queued.
+fn release_after(handle: Handle, bucket: Arc<Mutex<Bucket>>, delay: Duration) {
+    let timer = match Timeout::new(delay, &handle) {
+        Ok(timer) => timer,
+        Err(why) => {
+            event!(warn; "Err creating rate limit timer: {:?}", why);
+
+            return;
+        },
+    };
+
+    let handle2 = handle.clone();
+    let done = timer.map_err(|why| {
+        event!(warn; "Err waiting for rate limit timer: {:?}", why);
+    }).map(move |_| {
+        let delay = match bucket.lock() {
+            Ok(mut locked) => if locked.release() {
+                Some(locked.delay())
+            } else {
+                None
+            },
+            Err(why) => {
+                event!(warn; "Err locking rate limit bucket: {:?}", why);
+
+                None
+            },
+        };
+
+        if let Some(delay) = delay {
+            release_after(handle2, bucket, delay);
+        }
+    });
+
+    handle.spawn(done);
+}
+
+/// Configuration for the pooled HTTP client used for a node's REST requests.
+#[derive(Clone, Debug)]
+pub struct RestConfig {
+    /// Whether to keep connections to the node alive between requests.
+    /// Defaults to `true`.
+    pub keep_alive: bool,
+    /// How long an idle connection is kept alive for, if at all. Defaults to
+    /// 90 seconds.
+    pub keep_alive_timeout: Option<Duration>,
+    /// How long to wait for a request to complete before resolving to
+    /// [`Error::Timeout`], if at all. Defaults to 10 seconds.
+    ///
+    /// [`Error::Timeout`]: ../enum.Error.html#variant.Timeout
+    pub timeout: Option<Duration>,
+    /// The configuration of the cache of loaded tracks, if any. Defaults to
+    /// `None`, so that nothing is cached.
+    pub cache: Option<CacheConfig>,
+    /// The configuration of the rate limit of requests, if any. Defaults to
+    /// `None`, so that requests are never delayed.
+    ///
+    /// Responses served from the cache are not rate limited.
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+impl Default for RestConfig {
+    fn default() -> Self {
+        Self {
+            cache: None,
+            keep_alive: true,
+            keep_alive_timeout: Some(Duration::from_secs(90)),
+            rate_limit: None,
+            timeout: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+static NEXT_POOL_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // Clients can't be sent across threads, so each pool's client is kept on
+    // the thread of the tokio Core that its requests are run on.
+    static POOLS: RefCell<HashMap<usize, RestClient>> = RefCell::new(HashMap::new());
+}
+
+/// A handle to the REST API of a single node, sharing a pool of connections
+/// between all of its requests.
+///
+/// The Authorization header is applied to every request. Requests are run on
+/// the tokio Core that the node is connected on, so the handle can be used
+/// from any thread.
+///
+/// This is retrieved via [`Node::rest`].
+///
+/// [`Node::rest`]: ../nodes/struct.Node.html#method.rest
+#[derive(Clone, Debug)]
+pub struct NodeRest {
+    cache: Option<Arc<Mutex<LoadCache>>>,
+    config: RestConfig,
+    host: String,
+    id: usize,
+    limiter: Option<RateLimiter>,
+    password: String,
+    remote: Remote,
+    root_certificates: Arc<Vec<Vec<u8>>>,
+}
+
+impl NodeRest {
+    /// Creates a new handle to the REST API of the node at the HTTP host.
+    ///
+    /// The client is created lazily when the first request is made.
+    pub fn new(
+        remote: Remote,
+        host: String,
+        password: String,
+        root_certificates: Vec<Vec<u8>>,
+        config: RestConfig,
+    ) -> Self {
+        Self {
+            cache: config.cache.clone().map(|cache| {
+                Arc::new(Mutex::new(LoadCache::new(cache)))
+            }),
+            id: NEXT_POOL_ID.fetch_add(1, Ordering::Relaxed),
+            limiter: config.rate_limit.clone().map(RateLimiter::new),
+            root_certificates: Arc::new(root_certificates),
+            config,
+            host,
+            password,
+            remote,
+        }
+    }
+
+    /// The HTTP host of the node.
+    #[inline]
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Sends a request to the node at the path, such as `"/loadtracks"`,
+    /// resolving to the body of the response.
+    ///
+    /// If a body is given, it is sent as JSON. If a rate limit is
+    /// [configured], the request waits in a queue while the limit is
+    /// exceeded. The timeout only starts once the request is sent.
+    ///
+    /// Resolves to [`Error::HttpStatus`] if the node responds with an
+    /// unsuccessful status.
+    ///
+    /// [`Error::HttpStatus`]: ../enum.Error.html#variant.HttpStatus
+    /// [configured]: struct.RestConfig.html#structfield.rate_limit
+    pub fn send(&self, method: Method, path: &str, body: Option<Vec<u8>>)
+        -> Box<Future<Item = Vec<u8>, Error = Error> + Send> {
+        let config = self.config.clone();
+        let id = self.id;
+        let limiter = self.limiter.clone();
+        let password = self.password.clone();
+        let root_certificates = Arc::clone(&self.root_certificates);
+        let uri = format!("{}{}", self.host, path);
+
+        remote::run(&self.remote, move |handle| {
+            let uri = match uri.parse::<Uri>() {
+                Ok(uri) => uri,
+                Err(why) => return Box::new(future::err(From::from(why))),
+            };
+
+            let client = match pooled_client(handle, id, &root_certificates, &config) {
+                Ok(client) => client,
+                Err(why) => return Box::new(future::err(why)),
+            };
+
+            let mut request = Request::new(method, uri);
+            request.headers_mut().set_raw("Authorization", password);
+
+            if let Some(body) = body {
+                request.headers_mut().set_raw("Content-Type", "application/json");
+                request.set_body(body);
+            }
+
+            let acquired: Box<Future<Item = (), Error = Error>> = match limiter {
+                Some(limiter) => limiter.acquire(handle),
+                None => Box::new(future::ok(())),
+            };
+
+            let handle = handle.clone();
+            let done = acquired.and_then(move |_| {
+                let done = client.request(request)
+                    .and_then(|res| {
+                        let status = res.status();
+
+                        res.body().concat2().map(move |body| (status, body))
+                    })
+                    .from_err()
+                    .and_then(|(status, body)| checked(status, body.to_vec()));
+
+                with_timeout(&handle, config.timeout, Box::new(done))
+            });
+
+            Box::new(done)
+        })
+    }
+
+    /// The number of requests waiting for the rate limit, if one is
+    /// [configured].
+    ///
+    /// [configured]: struct.RestConfig.html#structfield.rate_limit
+    pub fn queued_requests(&self) -> usize {
+        self.limiter.as_ref().map_or(0, RateLimiter::queued)
+    }
+
+    /// The number of lookups of the cache of loaded tracks that were hits or
+    /// misses, if caching is [configured].
+    ///
+    /// [configured]: struct.RestConfig.html#structfield.cache
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        let cache = self.cache.as_ref()?;
+
+        cache.lock().ok().map(|cache| cache.stats())
+    }
+
+    /// Removes all of the responses from the cache of loaded tracks, if
+    /// caching is [configured].
+    ///
+    /// [configured]: struct.RestConfig.html#structfield.cache
+    pub fn clear_cache(&self) -> Result<(), Error> {
+        if let Some(ref cache) = self.cache {
+            cache.lock()?.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Decodes a base64 encoded track into its information via the node.
+    ///
+    /// If caching is [configured], the cached information of the track is
+    /// used if there is one, such as from a previous [`load_tracks`] that
+    /// loaded the track.
+    ///
+    /// [`load_tracks`]: #method.load_tracks
+    /// [configured]: struct.RestConfig.html#structfield.cache
+    pub fn decode_track(&self, track: &str)
+        -> Box<Future<Item = LoadedTrackInfo, Error = Error> + Send> {
+        if let Some(info) = self.track_info(track) {
+            return Box::new(future::ok(info));
+        }
+
+        let cache = self.cache.clone();
+        let key = track.to_owned();
+        let track = utf8_percent_encode(track, IDENTIFIER_ENCODE_SET);
+        let path = format!("/decodetrack?track={}", track);
+
+        let done = self.send(Method::Get, &path, None)
+            .and_then(|body| serde_json::from_slice::<LoadedTrackInfo>(&body).map_err(From::from))
+            .map(move |info| {
+                if let Some(cache) = cache {
+                    if let Ok(mut cache) = cache.lock() {
+                        cache.insert_track_info(key, info.clone());
+                    }
+                }
+
+                info
+            });
+
+        Box::new(done)
+    }
+
+    /// The cached information of a base64 encoded track, if caching is
+    /// [configured] and the track was loaded or decoded via the handle.
+    ///
+    /// This can be passed to [`AudioPlayer::set_track_info`] so that seeks
+    /// are checked against the track.
+    ///
+    /// [`AudioPlayer::set_track_info`]: ../player/struct.AudioPlayer.html#method.set_track_info
+    /// [configured]: struct.RestConfig.html#structfield.cache
+    pub fn track_info(&self, track: &str) -> Option<LoadedTrackInfo> {
+        let cache = self.cache.as_ref()?;
+
+        match cache.lock() {
+            Ok(cache) => cache.track_info(track),
+            Err(why) => {
+                event!(warn; "Err locking load cache: {:?}", why);
+
+                None
+            },
+        }
+    }
+
+    /// Loads tracks from the node by identifier.
+    ///
+    /// If caching is [configured], a cached response is used if there is
+    /// one. Responses of tracks that failed to load are not cached.
+    ///
+    /// Refer to [`load_tracks`] for more information.
+    ///
+    /// [`load_tracks`]: fn.load_tracks.html
+    /// [configured]: struct.RestConfig.html#structfield.cache
+    pub fn load_tracks(&self, identifier: &str)
+        -> Box<Future<Item = LoadTracksResponse, Error = Error> + Send> {
+        if let Some(ref cache) = self.cache {
+            match cache.lock() {
+                Ok(mut cache) => if let Some(response) = cache.get(identifier) {
+                    return Box::new(future::ok(response));
+                },
+                Err(why) => event!(warn; "Err locking load cache: {:?}", why),
+            }
+        }
+
+        let cache = self.cache.clone();
+        let key = identifier.to_owned();
+        let identifier = utf8_percent_encode(identifier, IDENTIFIER_ENCODE_SET);
+        let path = format!("/loadtracks?identifier={}", identifier);
+
+        let done = self.send(Method::Get, &path, None)
+            .and_then(|body| LoadTracksResponse::from_slice(&body))
+            .map(move |response| {
+                let failed = response.load_type.as_ref().map_or(false, |kind| {
+                    kind == "LOAD_FAILED"
+                });
+
+                if let (Some(cache), false) = (cache, failed) {
+                    if let Ok(mut cache) = cache.lock() {
+                        cache.insert(key, response.clone());
+                    }
+                }
+
+                response
+            });
+
+        Box::new(done)
+    }
+
+    /// Searches YouTube for tracks matching the query.
+    ///
+    /// Refer to [`search_youtube`] for more information.
+    ///
+    /// [`search_youtube`]: fn.search_youtube.html
+    pub fn search_youtube(&self, query: &str)
+        -> Box<Future<Item = Vec<LoadedTrack>, Error = Error> + Send> {
+        let identifier = format!("ytsearch:{}", query);
+
+        Box::new(self.load_tracks(&identifier).map(|res| res.tracks))
+    }
+
+    /// Searches SoundCloud for tracks matching the query.
+    ///
+    /// Refer to [`search_soundcloud`] for more information.
+    ///
+    /// [`search_soundcloud`]: fn.search_soundcloud.html
+    pub fn search_soundcloud(&self, query: &str)
+        -> Box<Future<Item = Vec<LoadedTrack>, Error = Error> + Send> {
+        let identifier = format!("scsearch:{}", query);
+
+        Box::new(self.load_tracks(&identifier).map(|res| res.tracks))
+    }
+
+    /// Sends a command to a player on a Lavalink v4 node, with the ID of the
+    /// session of the node's WebSocket connection.
+    ///
+    /// Refer to [`RestCommand`] for the requests that are made.
+    ///
+    /// [`RestCommand`]: ../player/enum.RestCommand.html
+    pub fn player_command(&self, session_id: &str, command: &RestCommand)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        let path = format!("/v4/sessions/{}/players/{}", session_id, command.guild_id());
+
+        let done = match *command {
+            RestCommand::Destroy { .. } => self.send(Method::Delete, &path, None),
+            RestCommand::Update { ref body, no_replace, .. } => {
+                let body = match serde_json::to_vec(body) {
+                    Ok(body) => body,
+                    Err(why) => return Box::new(future::err(From::from(why))),
+                };
+                let path = format!("{}?noReplace={}", path, no_replace);
+
+                self.send(Method::Patch, &path, Some(body))
+            },
+        };
+
+        Box::new(done.map(|_| ()))
+    }
+
+    /// Loads the lyrics of a base64 encoded track via the LavaLyrics plugin.
+    ///
+    /// Resolves to `None` if no lyrics were found. If `skip_track_source` is
+    /// true, then the lyrics are only loaded from lyrics providers rather
+    /// than the track's own source, such as Spotify.
+    ///
+    /// This requires the `lyrics` feature.
+    #[cfg(feature = "lyrics")]
+    pub fn load_lyrics(&self, track: &str, skip_track_source: bool)
+        -> Box<Future<Item = Option<Lyrics>, Error = Error> + Send> {
+        let track = utf8_percent_encode(track, IDENTIFIER_ENCODE_SET);
+        let path = format!("/v4/lyrics?track={}&skipTrackSource={}", track, skip_track_source);
+
+        let done = self.send(Method::Get, &path, None).and_then(|body| {
+            // The node responds without a body if no lyrics were found.
+            if body.is_empty() {
+                return Ok(None);
+            }
+
+            serde_json::from_slice(&body).map(Some).map_err(From::from)
+        });
+
+        Box::new(done)
+    }
+
+    /// Subscribes a player on a Lavalink v4 node to the lyrics of its tracks
+    /// via the LavaLyrics plugin, with the ID of the session of the node's
+    /// WebSocket connection.
+    ///
+    /// The node then sends an event as the player reaches each line of the
+    /// lyrics. Refer to [`load_lyrics`] for `skip_track_source`.
+    ///
+    /// This requires the `lyrics` feature.
+    ///
+    /// [`load_lyrics`]: #method.load_lyrics
+    #[cfg(feature = "lyrics")]
+    pub fn subscribe_lyrics(&self, session_id: &str, guild_id: u64, skip_track_source: bool)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        let path = format!(
+            "/v4/sessions/{}/players/{}/lyrics/subscribe?skipTrackSource={}",
+            session_id,
+            guild_id,
+            skip_track_source,
+        );
+
+        Box::new(self.send(Method::Post, &path, None).map(|_| ()))
+    }
+
+    /// Unsubscribes a player on a Lavalink v4 node from the lyrics of its
+    /// tracks, with the ID of the session of the node's WebSocket connection.
+    ///
+    /// This requires the `lyrics` feature.
+    #[cfg(feature = "lyrics")]
+    pub fn unsubscribe_lyrics(&self, session_id: &str, guild_id: u64)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        let path = format!("/v4/sessions/{}/players/{}/lyrics/subscribe", session_id, guild_id);
+
+        Box::new(self.send(Method::Delete, &path, None).map(|_| ()))
+    }
+
+    /// Retrieves the categories of segments that are skipped for a player on
+    /// a Lavalink v4 node via the SponsorBlock plugin, with the ID of the
+    /// session of the node's WebSocket connection.
+    ///
+    /// This requires the `sponsorblock` feature.
+    #[cfg(feature = "sponsorblock")]
+    pub fn sponsorblock_categories(&self, session_id: &str, guild_id: u64)
+        -> Box<Future<Item = Vec<String>, Error = Error> + Send> {
+        let path = sponsorblock_path(session_id, guild_id);
+
+        let done = self.send(Method::Get, &path, None)
+            .and_then(|body| serde_json::from_slice(&body).map_err(From::from));
+
+        Box::new(done)
+    }
+
+    /// Sets the categories of segments that are skipped for a player on a
+    /// Lavalink v4 node via the SponsorBlock plugin, such as `"sponsor"` and
+    /// `"selfpromo"`, with the ID of the session of the node's WebSocket
+    /// connection.
+    ///
+    /// This requires the `sponsorblock` feature.
+    #[cfg(feature = "sponsorblock")]
+    pub fn set_sponsorblock_categories(&self, session_id: &str, guild_id: u64, categories: &[&str])
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        let body = match serde_json::to_vec(categories) {
+            Ok(body) => body,
+            Err(why) => return Box::new(future::err(From::from(why))),
+        };
+        let path = sponsorblock_path(session_id, guild_id);
+
+        Box::new(self.send(Method::Put, &path, Some(body)).map(|_| ()))
+    }
+
+    /// Stops skipping segments for a player on a Lavalink v4 node, with the
+    /// ID of the session of the node's WebSocket connection.
+    ///
+    /// This requires the `sponsorblock` feature.
+    #[cfg(feature = "sponsorblock")]
+    pub fn clear_sponsorblock_categories(&self, session_id: &str, guild_id: u64)
+        -> Box<Future<Item = (), Error = Error> + Send> {
+        let path = sponsorblock_path(session_id, guild_id);
+
+        Box::new(self.send(Method::Delete, &path, None).map(|_| ()))
+    }
+
+    /// Removes the pooled client of the handle from the tokio Core, closing
+    /// its idle connections.
+    ///
+    /// The client is created again if another request is made.
+    pub fn close(&self) {
+        let id = self.id;
+
+        self.remote.spawn(move |_| {
+            POOLS.with(|pools| pools.borrow_mut().remove(&id));
+
+            Ok::<(), ()>(())
+        });
+    }
+}
+
+/// Retrieves the client of the pool with the given ID, creating it if it does
+/// not yet exist on this thread.
+fn pooled_client(
+    handle: &Handle,
+    id: usize,
+    root_certificates: &[Vec<u8>],
+    config: &RestConfig,
+) -> Result<RestClient, Error> {
+    POOLS.with(|pools| {
+        let mut pools = pools.borrow_mut();
+
+        if let Some(client) = pools.get(&id) {
+            return Ok(client.clone());
+        }
+
+        let client = build_client(handle, root_certificates, config)?;
+        pools.insert(id, client.clone());
+
+        Ok(client)
+    })
+}
+
+/// Resolves to the body of a response, or to [`Error::HttpStatus`] if the
+/// status of the response is unsuccessful.
+///
+/// [`Error::HttpStatus`]: ../enum.Error.html#variant.HttpStatus
+fn checked<T>(status: StatusCode, body: T) -> Result<T, Error> {
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(Error::HttpStatus(status.as_u16()))
+    }
+}
+
+/// The path of the SponsorBlock plugin's categories of a player.
+#[cfg(feature = "sponsorblock")]
+fn sponsorblock_path(session_id: &str, guild_id: u64) -> String {
+    format!("/v4/sessions/{}/players/{}/sponsorblock/categories", session_id, guild_id)
+}
+
+/// Resolves to [`Error::Timeout`] if the future does not resolve within the
+/// timeout.
+///
+/// [`Error::Timeout`]: ../enum.Error.html#variant.Timeout
+pub fn with_timeout<T: 'static>(
+    handle: &Handle,
+    timeout: Option<Duration>,
+    future: Box<Future<Item = T, Error = Error>>,
+) -> Box<Future<Item = T, Error = Error>> {
+    let timer = match timeout.map(|timeout| Timeout::new(timeout, handle)) {
+        Some(Ok(timer)) => timer,
+        Some(Err(why)) => {
+            event!(warn; "Err creating request timeout: {:?}", why);
+
+            return future;
+        },
+        None => return future,
+    };
+
+    let done = future.select2(timer).then(|res| match res {
+        Ok(Either::A((item, _))) => Ok(item),
+        Err(Either::A((why, _))) => Err(why),
+        Ok(Either::B(_)) | Err(Either::B(_)) => Err(Error::Timeout),
+    });
+
+    Box::new(done)
+}
+
+/// Creates a new HTTP client for REST requests.
+///
+/// The client trusts the system's root certificates in addition to the given
+/// DER-encoded root certificates, such as those from
+/// [`NodeConfig::tls_root_certificates`].
+///
+/// [`NodeConfig::tls_root_certificates`]: ../nodes/struct.NodeConfig.html#method.tls_root_certificates
+pub fn client(handle: &Handle, root_certificates: &[Vec<u8>])
+    -> Result<RestClient, Error> {
+    build_client(handle, root_certificates, &RestConfig::default())
+}
+
+fn build_client(handle: &Handle, root_certificates: &[Vec<u8>], config: &RestConfig)
+    -> Result<RestClient, Error> {
+    let mut http = HttpConnector::new(4, handle);
+    http.enforce_http(false);
+
+    let tls = tls::connector(root_certificates)?;
+    let connector = HttpsConnector::from((http, tls));
+
+    let client = Client::configure()
+        .connector(connector)
+        .keep_alive(config.keep_alive)
+        .keep_alive_timeout(config.keep_alive_timeout)
+        .build(handle);
+
+    Ok(client)
+}
+
+/// Loads tracks from a node by identifier.
+///
+/// The `host` is the HTTP host of the node, such as
+/// `"http://127.0.0.1:2333"`. The identifier is encoded for use in the query
+/// string.
+///
+/// Resolves to [`Error::HttpStatus`] if the node responds with an
+/// unsuccessful status, such as when the password is wrong.
+///
+/// It may be preferable to use [`Node::load_tracks`].
+///
+/// [`Error::HttpStatus`]: ../enum.Error.html#variant.HttpStatus
+/// [`Node::load_tracks`]: ../nodes/struct.Node.html#method.load_tracks
+pub fn load_tracks(
+    client: &RestClient,
+    host: &str,
+    password: &str,
+    identifier: &str,
+) -> Box<Future<Item = LoadTracksResponse, Error = Error>> {
+    let identifier = utf8_percent_encode(identifier, IDENTIFIER_ENCODE_SET);
+    let uri = format!("{}/loadtracks?identifier={}", host, identifier);
+
+    let uri = match uri.parse::<Uri>() {
+        Ok(uri) => uri,
+        Err(why) => return Box::new(future::err(From::from(why))),
+    };
+
+    let mut request = Request::new(Method::Get, uri);
+    request.headers_mut().set_raw("Authorization", password.to_owned());
+
+    let done = client.request(request)
+        .and_then(|res| {
+            let status = res.status();
+
+            res.body().concat2().map(move |body| (status, body))
+        })
+        .from_err()
+        .and_then(|(status, body)| checked(status, body))
+        .and_then(|body| LoadTracksResponse::from_slice(&body));
+
+    Box::new(done)
+}
+
+/// Searches YouTube for tracks matching the query.
+///
+/// This is a shortcut for [`load_tracks`] with the query prefixed by
+/// `ytsearch:`.
+///
+/// [`load_tracks`]: fn.load_tracks.html
+pub fn search_youtube(
+    client: &RestClient,
+    host: &str,
+    password: &str,
+    query: &str,
+) -> Box<Future<Item = Vec<LoadedTrack>, Error = Error>> {
+    let identifier = format!("ytsearch:{}", query);
+
+    Box::new(load_tracks(client, host, password, &identifier).map(|res| res.tracks))
+}
+
+/// Searches SoundCloud for tracks matching the query.
+///
+/// This is a shortcut for [`load_tracks`] with the query prefixed by
+/// `scsearch:`.
+///
+/// [`load_tracks`]: fn.load_tracks.html
+pub fn search_soundcloud(
+    client: &RestClient,
+    host: &str,
+    password: &str,
+    query: &str,
+) -> Box<Future<Item = Vec<LoadedTrack>, Error = Error>> {
+    let identifier = format!("scsearch:{}", query);
+
+    Box::new(load_tracks(client, host, password, &identifier).map(|res| res.tracks))
+}
@@ -0,0 +1,339 @@
+//! Utilities for testing bots without a real Lavalink server, enabled via the
+//! `testing` feature.
+//!
+//! [`MockNode`] speaks Lavalink's WebSocket protocol in-process, recording the
+//! payloads that it receives and emitting scripted events to the clients
+//! connected to it.
+//!
+//! # Examples
+//!
+//! Add a mock node to a [`NodeManager`] and end a track:
+//!
+//! ```rust,ignore
+//! let mut core = Core::new()?;
+//! let mock = MockNode::bind(&core.handle())?;
+//! let config = mock.config_builder().build()?;
+//!
+//! let (manager, result) = core.run(manager.add_node(config)).unwrap();
+//! result?;
+//!
+//! mock.emit_track_end(381880193251409931, "track", "FINISHED");
+//! ```
+//!
+//! [`MockNode`]: struct.MockNode.html
+//! [`NodeManager`]: ../nodes/struct.NodeManager.html
+
+use futures::sync::mpsc::{self, UnboundedSender};
+use futures::{Future, Sink, Stream};
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio_core::reactor::Handle;
+use websocket::async::Server;
+use websocket::OwnedMessage;
+use ::nodes::NodeConfigBuilder;
+use ::Error;
+
+/// The password that [`MockNode::config_builder`] is configured with.
+///
+/// [`MockNode::config_builder`]: struct.MockNode.html#method.config_builder
+pub const PASSWORD: &str = "youshallnotpass";
+/// The user ID that [`MockNode::config_builder`] is configured with.
+///
+/// [`MockNode::config_builder`]: struct.MockNode.html#method.config_builder
+pub const USER_ID: &str = "1";
+
+/// An in-process Lavalink node listening on a local port.
+///
+/// The node accepts any number of WebSocket connections, replies to pings,
+/// and sends an empty stats payload to each client when it connects. The
+/// node does not serve REST requests, which are rejected without affecting
+/// other connections.
+///
+/// The server runs on the core of the handle that it was [bound] with, so the
+/// core must be running for clients to connect.
+///
+/// [bound]: #method.bind
+#[derive(Clone, Debug)]
+pub struct MockNode {
+    addr: SocketAddr,
+    clients: Arc<Clients>,
+    received: Arc<Mutex<Vec<Value>>>,
+}
+
+/// The senders to the clients connected to a [`MockNode`], keyed by the order
+/// that they connected in.
+///
+/// [`MockNode`]: struct.MockNode.html
+#[derive(Debug, Default)]
+struct Clients {
+    next_id: AtomicUsize,
+    senders: Mutex<HashMap<usize, UnboundedSender<OwnedMessage>>>,
+}
+
+impl Clients {
+    fn insert(&self, tx: UnboundedSender<OwnedMessage>) -> usize {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        match self.senders.lock() {
+            Ok(mut senders) => {
+                senders.insert(id, tx);
+            },
+            Err(why) => event!(warn; "Err locking mock node clients: {:?}", why),
+        }
+
+        id
+    }
+
+    fn remove(&self, id: usize) {
+        match self.senders.lock() {
+            Ok(mut senders) => {
+                senders.remove(&id);
+            },
+            Err(why) => event!(warn; "Err locking mock node clients: {:?}", why),
+        }
+    }
+}
+
+impl MockNode {
+    /// Binds a new mock node to a random local port, accepting connections
+    /// on the core of the given handle.
+    pub fn bind(handle: &Handle) -> Result<Self, Error> {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().expect("valid address");
+        let server = Server::bind(addr, handle)?;
+        let addr = server.local_addr()?;
+
+        let clients = Arc::new(Clients::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let handle2 = handle.clone();
+        let clients2 = Arc::clone(&clients);
+        let received2 = Arc::clone(&received);
+
+        // Errors are handled per connection, so that a request which isn't a
+        // WebSocket handshake - such as a probe of the REST API - doesn't end
+        // the accept loop.
+        let done = server.incoming().then(|res| {
+            if let Err(ref why) = res {
+                event!(warn; "Err accepting mock node connection: {:?}", why.error);
+            }
+
+            Ok::<_, ()>(res.ok())
+        }).filter_map(|upgrade| upgrade).for_each(move |(upgrade, _)| {
+            let clients = Arc::clone(&clients2);
+            let received = Arc::clone(&received2);
+            let handle = handle2.clone();
+
+            let accepted = upgrade.accept().map(move |(client, _)| {
+                accept(&handle, client, &clients, received);
+            }).map_err(|why| {
+                event!(warn; "Err upgrading mock node connection: {:?}", why);
+            });
+
+            handle2.spawn(accepted);
+
+            Ok(())
+        });
+
+        handle.spawn(done);
+
+        Ok(Self {
+            addr,
+            clients,
+            received,
+        })
+    }
+
+    /// The address that the node is listening on.
+    #[inline]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Forgets all of the payloads received so far.
+    pub fn clear_received(&self) {
+        if let Ok(mut received) = self.received.lock() {
+            received.clear();
+        }
+    }
+
+    /// Creates a builder for the configuration of a node connecting to this
+    /// node, using the [`PASSWORD`] and [`USER_ID`].
+    ///
+    /// [`PASSWORD`]: constant.PASSWORD.html
+    /// [`USER_ID`]: constant.USER_ID.html
+    pub fn config_builder(&self) -> NodeConfigBuilder {
+        NodeConfigBuilder::new()
+            .address(self.addr.ip().to_string())
+            .http_port(self.addr.port())
+            .password(PASSWORD)
+            .user_id(USER_ID)
+            .websocket_port(self.addr.port())
+    }
+
+    /// The number of clients currently connected.
+    pub fn connections(&self) -> usize {
+        self.clients.senders.lock().map(|senders| senders.len()).unwrap_or(0)
+    }
+
+    /// Sends a payload to every connected client.
+    ///
+    /// Returns the number of clients that the payload was sent to.
+    pub fn emit(&self, payload: &Value) -> usize {
+        let message = OwnedMessage::Text(payload.to_string());
+
+        let mut senders = match self.clients.senders.lock() {
+            Ok(senders) => senders,
+            Err(why) => {
+                event!(warn; "Err locking mock node clients: {:?}", why);
+
+                return 0;
+            },
+        };
+
+        senders.retain(|_, tx| tx.unbounded_send(message.clone()).is_ok());
+
+        senders.len()
+    }
+
+    /// Sends a `playerUpdate` for a guild to every connected client.
+    pub fn emit_player_update(&self, guild_id: u64, time: i64, position: i64) -> usize {
+        self.emit(&json!({
+            "op": "playerUpdate",
+            "guildId": guild_id.to_string(),
+            "state": {
+                "position": position,
+                "time": time,
+            },
+        }))
+    }
+
+    /// Sends a `ready` op with a session ID to every connected client, as
+    /// Lavalink v3.7 and newer do once connected to.
+    pub fn emit_ready(&self, resumed: bool, session_id: &str) -> usize {
+        self.emit(&json!({
+            "op": "ready",
+            "resumed": resumed,
+            "sessionId": session_id,
+        }))
+    }
+
+    /// Sends `stats` to every connected client.
+    pub fn emit_stats(&self, players: i32, playing_players: i32) -> usize {
+        self.emit(&stats(players, playing_players))
+    }
+
+    /// Sends a `TrackEndEvent` for a guild to every connected client.
+    ///
+    /// The `reason` is the raw reason, such as `"FINISHED"`.
+    pub fn emit_track_end(&self, guild_id: u64, track: &str, reason: &str) -> usize {
+        self.emit(&json!({
+            "op": "event",
+            "type": "TrackEndEvent",
+            "guildId": guild_id.to_string(),
+            "reason": reason,
+            "track": track,
+        }))
+    }
+
+    /// The payloads received from clients, in the order that they were
+    /// received.
+    pub fn received(&self) -> Vec<Value> {
+        self.received.lock().map(|received| received.clone()).unwrap_or_default()
+    }
+
+    /// The ops of the payloads received from clients, such as `"play"`, in the
+    /// order that they were received.
+    pub fn received_ops(&self) -> Vec<String> {
+        self.received()
+            .iter()
+            .filter_map(|payload| payload.get("op").and_then(Value::as_str))
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    /// The WebSocket host of the node, such as `ws://127.0.0.1:12345`.
+    pub fn websocket_host(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+}
+
+fn accept<C>(
+    handle: &Handle,
+    client: C,
+    clients: &Arc<Clients>,
+    received: Arc<Mutex<Vec<Value>>>,
+) where C: Sink<SinkItem = OwnedMessage> + Stream<Item = OwnedMessage> + 'static {
+    let (sink, stream) = client.split();
+    let (tx, rx) = mpsc::unbounded();
+
+    let _ = tx.unbounded_send(OwnedMessage::Text(stats(0, 0).to_string()));
+
+    let id = clients.insert(tx.clone());
+    let writer_clients = Arc::clone(clients);
+    let reader_clients = Arc::clone(clients);
+
+    // The client is removed once either half of its connection ends, so that
+    // it isn't counted as connected.
+    let writer = rx
+        .forward(sink.sink_map_err(|_| ()))
+        .then(move |_| {
+            writer_clients.remove(id);
+
+            Ok(())
+        });
+
+    let reader = stream.map_err(|_| ()).for_each(move |message| {
+        let payload = match message {
+            OwnedMessage::Binary(bytes) => serde_json::from_slice(&bytes),
+            OwnedMessage::Text(text) => serde_json::from_str(&text),
+            OwnedMessage::Ping(data) => {
+                let _ = tx.unbounded_send(OwnedMessage::Pong(data));
+
+                return Ok(());
+            },
+            OwnedMessage::Close(_) => return Err(()),
+            OwnedMessage::Pong(_) => return Ok(()),
+        };
+
+        match payload {
+            Ok(payload) => {
+                if let Ok(mut received) = received.lock() {
+                    received.push(payload);
+                }
+            },
+            Err(why) => event!(warn; "Err parsing payload sent to mock node: {:?}", why),
+        }
+
+        Ok(())
+    }).then(move |_| {
+        reader_clients.remove(id);
+
+        Ok(())
+    });
+
+    handle.spawn(writer);
+    handle.spawn(reader);
+}
+
+fn stats(players: i32, playing_players: i32) -> Value {
+    json!({
+        "op": "stats",
+        "cpu": {
+            "cores": 1,
+            "lavalinkLoad": 0.0,
+            "systemLoad": 0.0,
+        },
+        "memory": {
+            "allocated": 0,
+            "free": 0,
+            "reservable": 0,
+            "used": 0,
+        },
+        "players": players,
+        "playingPlayers": playing_players,
+        "uptime": 0,
+    })
+}
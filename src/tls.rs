@@ -0,0 +1,14 @@
+use native_tls::{Certificate, TlsConnector};
+use ::Error;
+
+/// Builds a TLS connector trusting the system's root certificates in addition
+/// to the given DER-encoded root certificates.
+pub fn connector(root_certificates: &[Vec<u8>]) -> Result<TlsConnector, Error> {
+    let mut builder = TlsConnector::builder()?;
+
+    for der in root_certificates {
+        builder.add_root_certificate(Certificate::from_der(der)?)?;
+    }
+
+    builder.build().map_err(From::from)
+}
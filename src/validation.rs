@@ -0,0 +1,152 @@
+//! Opt-in validation of outgoing payloads, catching protocol misuse before it
+//! reaches a node.
+//!
+//! Lavalink silently clamps or ignores many invalid payloads, such as a
+//! volume out of range or a play on a player that was never sent a voice
+//! update. Validation turns these into [`Error::Validation`]s describing what
+//! was wrong.
+//!
+//! Validation is enabled for an audio player by setting its [`validation`]
+//! field, or for every player of a manager via
+//! [`AudioPlayerManager::set_validation`].
+//!
+//! [`AudioPlayerManager::set_validation`]: ../player/struct.AudioPlayerManager.html#method.set_validation
+//! [`Error::Validation`]: ../enum.Error.html#variant.Validation
+//! [`validation`]: ../player/struct.AudioPlayer.html#structfield.validation
+
+use serde_json::{self, Value};
+use websocket::OwnedMessage;
+use ::player::{AudioPlayer, MAX_VOLUME, MIN_VOLUME};
+use ::Error;
+
+/// The maximum volume multiplier of the `volume` filter.
+pub const MAX_FILTER_VOLUME: f64 = 5.0;
+
+/// What is known about a player when validating a payload sent for it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PlayerContext {
+    /// Whether the player was sent a voice update.
+    pub connected: bool,
+    /// The length of the player's current track in milliseconds, if known.
+    pub track_length: Option<i64>,
+}
+
+impl<'a> From<&'a AudioPlayer> for PlayerContext {
+    fn from(player: &'a AudioPlayer) -> Self {
+        Self {
+            connected: player.voice.is_some(),
+            track_length: player.track_length,
+        }
+    }
+}
+
+/// Validates a WebSocket message sent for a player.
+///
+/// Messages that aren't JSON payloads, such as pings, are always valid.
+pub fn validate_message(message: &OwnedMessage, context: &PlayerContext)
+    -> Result<(), Error> {
+    let payload = match *message {
+        OwnedMessage::Binary(ref bytes) => serde_json::from_slice::<Value>(bytes)?,
+        OwnedMessage::Text(ref text) => serde_json::from_str::<Value>(text)?,
+        _ => return Ok(()),
+    };
+
+    validate(&payload, context)
+}
+
+/// Validates a payload sent for a player.
+///
+/// The checked ops are:
+///
+/// - `filters`: the volume multiplier is from `0.0` to [`MAX_FILTER_VOLUME`]
+/// - `play`: the player was sent a voice update, and the end time is after
+/// the start time
+/// - `seek`: the position is not negative, and is within the track when its
+/// length is known
+/// - `volume`: the volume is from [`MIN_VOLUME`] to [`MAX_VOLUME`]
+///
+/// Other ops are always valid.
+///
+/// [`MAX_FILTER_VOLUME`]: constant.MAX_FILTER_VOLUME.html
+/// [`MAX_VOLUME`]: ../player/constant.MAX_VOLUME.html
+/// [`MIN_VOLUME`]: ../player/constant.MIN_VOLUME.html
+pub fn validate(payload: &Value, context: &PlayerContext) -> Result<(), Error> {
+    match payload.get("op").and_then(Value::as_str) {
+        Some("filters") => validate_filters(payload),
+        Some("play") => validate_play(payload, context),
+        Some("seek") => validate_seek(payload, context),
+        Some("volume") => validate_volume(payload),
+        _ => Ok(()),
+    }
+}
+
+fn validate_filters(payload: &Value) -> Result<(), Error> {
+    match payload.get("volume").and_then(Value::as_f64) {
+        Some(volume) if volume < 0.0 || volume > MAX_FILTER_VOLUME => {
+            Err(Error::Validation(format!(
+                "Filter volume {} is not between 0 and {}",
+                volume,
+                MAX_FILTER_VOLUME,
+            )))
+        },
+        _ => Ok(()),
+    }
+}
+
+fn validate_play(payload: &Value, context: &PlayerContext) -> Result<(), Error> {
+    if !context.connected {
+        return Err(Error::Validation(
+            "Played a track on a player that was not sent a voice update".to_owned(),
+        ));
+    }
+
+    let start_time = payload.get("startTime").and_then(Value::as_i64);
+    let end_time = payload.get("endTime").and_then(Value::as_i64);
+
+    if let (Some(start_time), Some(end_time)) = (start_time, end_time) {
+        if end_time <= start_time {
+            return Err(Error::Validation(format!(
+                "End time {} is not after start time {}",
+                end_time,
+                start_time,
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_seek(payload: &Value, context: &PlayerContext) -> Result<(), Error> {
+    let position = match payload.get("position").and_then(Value::as_i64) {
+        Some(position) => position,
+        None => return Err(Error::Validation("Seek has no position".to_owned())),
+    };
+
+    if position < 0 {
+        return Err(Error::Validation(format!("Seek position {} is negative", position)));
+    }
+
+    match context.track_length {
+        Some(length) if position > length => Err(Error::Validation(format!(
+            "Seek position {} is past the end of the track at {}",
+            position,
+            length,
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn validate_volume(payload: &Value) -> Result<(), Error> {
+    match payload.get("volume").and_then(Value::as_i64) {
+        Some(volume) if volume >= i64::from(MIN_VOLUME) && volume <= i64::from(MAX_VOLUME) => {
+            Ok(())
+        },
+        Some(volume) => Err(Error::Validation(format!(
+            "Volume {} is not between {} and {}",
+            volume,
+            MIN_VOLUME,
+            MAX_VOLUME,
+        ))),
+        None => Err(Error::Validation("Volume has no volume".to_owned())),
+    }
+}
@@ -0,0 +1,122 @@
+//! Functions and structs for managing the voice connections of guilds via
+//! Discord's gateway, such as joining and leaving voice channels.
+//!
+//! Payloads are sent to Discord over the correct shard via
+//! [`EventHandler::forward`].
+//!
+//! [`EventHandler::forward`]: ../trait.EventHandler.html#tymethod.forward
+
+use futures::Future;
+use std::collections::HashMap;
+use websocket::OwnedMessage;
+use ::{Error, EventHandler};
+
+/// Calculates the ID of the shard that a guild is on.
+///
+/// This is calculated as `(guild_id >> 22) % num_shards`, as documented by
+/// Discord. If there are no shards, then `0` is returned.
+pub fn shard_id(guild_id: u64, num_shards: u64) -> u64 {
+    if num_shards == 0 {
+        return 0;
+    }
+
+    (guild_id >> 22) % num_shards
+}
+
+/// Creates the payload of a gateway `VOICE_STATE_UPDATE` for the bot user.
+///
+/// A `channel_id` of `None` disconnects from the guild's voice channel.
+pub fn voice_state_update(
+    guild_id: u64,
+    channel_id: Option<u64>,
+    self_mute: bool,
+    self_deaf: bool,
+) -> String {
+    json!({
+        "op": 4,
+        "d": {
+            "channel_id": channel_id.map(|id| id.to_string()),
+            "guild_id": guild_id.to_string(),
+            "self_deaf": self_deaf,
+            "self_mute": self_mute,
+        },
+    }).to_string()
+}
+
+/// A manager of the voice channels that the bot user is connected to in each
+/// guild, which forwards voice state updates to Discord over the correct
+/// shard.
+#[derive(Clone, Debug, Default)]
+pub struct VoiceConnectionManager {
+    channels: HashMap<u64, u64>,
+    num_shards: u64,
+}
+
+impl VoiceConnectionManager {
+    /// Creates a new manager for a bot using the given number of shards.
+    pub fn new(num_shards: u64) -> Self {
+        Self {
+            channels: HashMap::new(),
+            num_shards,
+        }
+    }
+
+    /// The ID of the voice channel that the bot user is joining or is
+    /// connected to in the guild, if any.
+    pub fn channel_id(&self, guild_id: u64) -> Option<u64> {
+        self.channels.get(&guild_id).cloned()
+    }
+
+    /// The number of shards that the bot is using.
+    #[inline]
+    pub fn num_shards(&self) -> u64 {
+        self.num_shards
+    }
+
+    /// Calculates the ID of the shard that a guild is on.
+    ///
+    /// Refer to [`shard_id`] for more information.
+    ///
+    /// [`shard_id`]: fn.shard_id.html
+    #[inline]
+    pub fn shard_id(&self, guild_id: u64) -> u64 {
+        shard_id(guild_id, self.num_shards)
+    }
+
+    /// Joins a voice channel in a guild, forwarding the voice state update to
+    /// Discord via the handler.
+    ///
+    /// Lavalink must then be sent a voice update once Discord responds, such
+    /// as via [`AudioPlayer::voice_update`].
+    ///
+    /// [`AudioPlayer::voice_update`]: ../player/struct.AudioPlayer.html#method.voice_update
+    pub fn join(
+        &mut self,
+        handler: &mut EventHandler,
+        guild_id: u64,
+        channel_id: u64,
+    ) -> Box<Future<Item = Option<OwnedMessage>, Error = Error>> {
+        self.channels.insert(guild_id, channel_id);
+
+        let payload = voice_state_update(guild_id, Some(channel_id), false, false);
+
+        handler.forward(self.shard_id(guild_id), &payload)
+    }
+
+    /// Leaves the voice channel in a guild, forwarding the voice state update
+    /// to Discord via the handler.
+    pub fn leave(&mut self, handler: &mut EventHandler, guild_id: u64)
+        -> Box<Future<Item = Option<OwnedMessage>, Error = Error>> {
+        self.channels.remove(&guild_id);
+
+        let payload = voice_state_update(guild_id, None, false, false);
+
+        handler.forward(self.shard_id(guild_id), &payload)
+    }
+
+    /// Updates the number of shards that the bot is using, such as after
+    /// resharding.
+    pub fn set_num_shards(&mut self, num_shards: u64) {
+        self.num_shards = num_shards;
+    }
+}
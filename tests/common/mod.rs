@@ -0,0 +1,41 @@
+//! Utilities shared between the test suites.
+
+use futures::{Future, future};
+use lavalink_futures::model::{TrackEndReason, TrackException};
+use lavalink_futures::reexports::OwnedMessage;
+use lavalink_futures::{Error, EventHandler};
+
+/// A handler doing nothing for events, which considers every shard connected
+/// and every guild valid.
+pub struct Handler;
+
+impl EventHandler for Handler {
+    fn forward(&mut self, _: u64, _: &str)
+        -> Box<Future<Item = Option<OwnedMessage>, Error = Error>> {
+        Box::new(future::ok(None))
+    }
+
+    fn is_connected(&mut self, _: u64) -> Box<Future<Item = bool, Error = Error>> {
+        Box::new(future::ok(true))
+    }
+
+    fn is_valid(&mut self, _: &str, _: Option<String>)
+        -> Box<Future<Item = bool, Error = Error>> {
+        Box::new(future::ok(true))
+    }
+
+    fn track_end(&mut self, _: String, _: TrackEndReason)
+        -> Box<Future<Item = (), Error = Error>> {
+        Box::new(future::ok(()))
+    }
+
+    fn track_exception(&mut self, _: String, _: TrackException)
+        -> Box<Future<Item = (), Error = Error>> {
+        Box::new(future::ok(()))
+    }
+
+    fn track_stuck(&mut self, _: String, _: i64)
+        -> Box<Future<Item = (), Error = Error>> {
+        Box::new(future::ok(()))
+    }
+}
@@ -0,0 +1,134 @@
+//! Tests against a real Lavalink node, enabled via the `integration-tests`
+//! feature.
+//!
+//! The node is configured via environment variables:
+//!
+//! - `LAVALINK_ADDRESS`: the address of the node, such as `127.0.0.1:2333`.
+//!   This is required.
+//! - `LAVALINK_PASSWORD`: the password of the node. Defaults to
+//!   `youshallnotpass`.
+//! - `LAVALINK_TRACK`: the identifier of a track to load and play. Defaults to
+//!   a YouTube video, so the node must be able to reach YouTube otherwise.
+//!
+//! A node can be started with Docker:
+//!
+//! ```sh
+//! docker run -d -p 2333:2333 fredboat/lavalink:master
+//! LAVALINK_ADDRESS=127.0.0.1:2333 cargo test --features integration-tests
+//! ```
+//!
+//! No voice connection is made, so tracks are sent to the node but are not
+//! heard.
+
+extern crate futures;
+extern crate lavalink_futures;
+extern crate tokio_core;
+
+mod common;
+
+use common::Handler;
+use lavalink_futures::nodes::{NodeConfig, NodeConfigBuilder, NodeManager, NodeStatus};
+use lavalink_futures::EventHandler;
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_core::reactor::Core;
+
+const DEFAULT_PASSWORD: &str = "youshallnotpass";
+const DEFAULT_TRACK: &str = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+const GUILD_ID: u64 = 381880193251409931;
+const USER_ID: &str = "381880193700069377";
+
+fn config() -> NodeConfig {
+    let address = env::var("LAVALINK_ADDRESS")
+        .expect("LAVALINK_ADDRESS must be set to run integration tests");
+    let password = env::var("LAVALINK_PASSWORD")
+        .unwrap_or_else(|_| DEFAULT_PASSWORD.to_owned());
+
+    NodeConfigBuilder::new()
+        .address(address)
+        .num_shards(1)
+        .password(password)
+        .ready_timeout(Some(Duration::from_secs(10)))
+        .user_id(USER_ID)
+        .build()
+        .expect("Invalid node config")
+}
+
+fn connect(core: &mut Core) -> (NodeManager, String) {
+    let config = config();
+    let host = config.websocket_host.clone();
+    let handler: Box<EventHandler> = Box::new(Handler);
+    let manager = NodeManager::new(core.handle(), Mutex::new(handler));
+
+    let (manager, result) = core.run(manager.add_node(config)).unwrap();
+
+    result.expect("Err connecting to node");
+
+    (manager, host)
+}
+
+fn track() -> String {
+    env::var("LAVALINK_TRACK").unwrap_or_else(|_| DEFAULT_TRACK.to_owned())
+}
+
+#[test]
+fn test_connect() {
+    let mut core = Core::new().unwrap();
+    let (manager, host) = connect(&mut core);
+
+    let node = manager.get_node(&host).expect("Node missing from manager");
+
+    assert_eq!(node.status(), NodeStatus::Connected);
+}
+
+#[test]
+fn test_stats() {
+    let mut core = Core::new().unwrap();
+    let (manager, host) = connect(&mut core);
+
+    // Nodes are ready once they send their first stats.
+    let stats = manager.get_node(&host).and_then(|node| node.stats());
+
+    assert!(stats.is_some());
+    assert!(manager.all_stats().contains_key(&host));
+}
+
+#[test]
+fn test_play_and_seek() {
+    let mut core = Core::new().unwrap();
+    let (mut manager, host) = connect(&mut core);
+
+    let loaded = {
+        let node = manager.get_node(&host).expect("Node missing from manager");
+
+        core.run(node.load_tracks(&track())).expect("Err loading track")
+    };
+    let track = loaded.tracks.first().expect("No tracks were loaded").track.clone();
+
+    let player = manager.create_player(GUILD_ID, Some(&host)).expect("Err creating player");
+
+    core.run(player.play_async(&track, None, None)).expect("Err playing track");
+    core.run(player.seek_async(1000)).expect("Err seeking track");
+
+    let state = player.player().expect("Player missing from manager");
+
+    assert_eq!(state.track, Some(track));
+    assert_eq!(state.queued(), 0);
+
+    core.run(player.stop_async()).expect("Err stopping track");
+}
+
+#[test]
+fn test_reconnect() {
+    let mut core = Core::new().unwrap();
+    let (manager, host) = connect(&mut core);
+
+    let (manager, result) = core.run(manager.reconnect(&host)).unwrap();
+
+    result.expect("Err reconnecting to node");
+
+    let node = manager.get_node(&host).expect("Node missing from manager");
+
+    assert_eq!(node.status(), NodeStatus::Connected);
+}
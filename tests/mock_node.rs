@@ -0,0 +1,170 @@
+//! Tests against an in-process [`MockNode`], enabled via the `testing`
+//! feature.
+//!
+//! ```sh
+//! cargo test --features testing
+//! ```
+//!
+//! [`MockNode`]: ../lavalink_futures/testing/struct.MockNode.html
+
+extern crate futures;
+extern crate lavalink_futures;
+extern crate tokio_core;
+
+mod common;
+
+use common::Handler;
+use lavalink_futures::nodes::{NodeManager, NodeStatus};
+use lavalink_futures::testing::MockNode;
+use lavalink_futures::EventHandler;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_core::reactor::Core;
+
+const GUILD_ID: u64 = 381880193251409931;
+
+fn connect(core: &mut Core, mock: &MockNode) -> NodeManager {
+    let config = mock.config_builder()
+        .num_shards(1)
+        .ready_timeout(Some(Duration::from_secs(5)))
+        .build()
+        .expect("Invalid node config");
+    let handler: Box<EventHandler> = Box::new(Handler);
+    let manager = NodeManager::new(core.handle(), Mutex::new(handler));
+
+    let (manager, result) = core.run(manager.add_node(config)).unwrap();
+
+    result.expect("Err connecting to mock node");
+
+    manager
+}
+
+/// Turns the core until the condition is met, giving up after a second.
+fn await_until<F: FnMut() -> bool>(core: &mut Core, mut condition: F) -> bool {
+    for _ in 0..100 {
+        if condition() {
+            return true;
+        }
+
+        core.turn(Some(Duration::from_millis(10)));
+    }
+
+    false
+}
+
+/// Turns the core until the mock node received an op, giving up after a
+/// second.
+fn await_op(core: &mut Core, mock: &MockNode, op: &str) -> bool {
+    await_until(core, || mock.received_ops().iter().any(|received| received == op))
+}
+
+#[test]
+fn test_connect() {
+    let mut core = Core::new().unwrap();
+    let mock = MockNode::bind(&core.handle()).expect("Err binding mock node");
+    let manager = connect(&mut core, &mock);
+
+    let node = manager.get_node(&mock.websocket_host()).expect("Node missing from manager");
+
+    assert_eq!(node.status(), NodeStatus::Connected);
+    assert_eq!(mock.connections(), 1);
+}
+
+#[test]
+fn test_player_ops_reach_node() {
+    let mut core = Core::new().unwrap();
+    let mock = MockNode::bind(&core.handle()).expect("Err binding mock node");
+    let mut manager = connect(&mut core, &mock);
+    let host = mock.websocket_host();
+
+    let player = manager.create_player(GUILD_ID, Some(&host)).expect("Err creating player");
+
+    core.run(player.play_async("track", None, None)).expect("Err playing track");
+
+    assert!(await_op(&mut core, &mock, "play"));
+}
+
+#[test]
+fn test_disconnected_client_is_pruned() {
+    let mut core = Core::new().unwrap();
+    let mock = MockNode::bind(&core.handle()).expect("Err binding mock node");
+    let mut manager = connect(&mut core, &mock);
+
+    manager.remove_node(&mock.websocket_host()).expect("Err removing node");
+
+    assert!(await_until(&mut core, || mock.connections() == 0));
+}
+
+#[test]
+fn test_ready_sets_session_id() {
+    let mut core = Core::new().unwrap();
+    let mock = MockNode::bind(&core.handle()).expect("Err binding mock node");
+    let manager = connect(&mut core, &mock);
+    let host = mock.websocket_host();
+
+    assert_eq!(mock.emit_ready(false, "session"), 1);
+
+    assert!(await_until(&mut core, || {
+        manager.get_node(&host).and_then(|node| node.session_id()) == Some("session".to_owned())
+    }));
+}
+
+#[test]
+fn test_player_update_sets_position() {
+    let mut core = Core::new().unwrap();
+    let mock = MockNode::bind(&core.handle()).expect("Err binding mock node");
+    let mut manager = connect(&mut core, &mock);
+    let host = mock.websocket_host();
+
+    let player = manager.create_player(GUILD_ID, Some(&host)).expect("Err creating player");
+
+    core.run(player.play_async("track", None, None)).expect("Err playing track");
+    assert_eq!(mock.emit_player_update(GUILD_ID, 1, 1500), 1);
+
+    assert!(await_until(&mut core, || {
+        player.player().map(|player| player.position == 1500).unwrap_or(false)
+    }));
+}
+
+#[test]
+fn test_stats_update_cluster_stats() {
+    let mut core = Core::new().unwrap();
+    let mock = MockNode::bind(&core.handle()).expect("Err binding mock node");
+    let manager = connect(&mut core, &mock);
+
+    assert_eq!(mock.emit_stats(3, 2), 1);
+
+    assert!(await_until(&mut core, || {
+        manager.stats_aggregate().map(|stats| stats.players == 3).unwrap_or(false)
+    }));
+
+    let stats = manager.stats_aggregate().expect("Err aggregating stats");
+
+    assert_eq!(stats.nodes, 1);
+    assert_eq!(stats.playing_players, 2);
+}
+
+#[test]
+fn test_track_end_plays_queued_track() {
+    let mut core = Core::new().unwrap();
+    let mock = MockNode::bind(&core.handle()).expect("Err binding mock node");
+    let mut manager = connect(&mut core, &mock);
+    let host = mock.websocket_host();
+
+    let player = manager.create_player(GUILD_ID, Some(&host)).expect("Err creating player");
+
+    assert!(player.enqueue("first").expect("Err playing first track"));
+    assert!(!player.enqueue("second").expect("Err queueing second track"));
+    assert!(await_op(&mut core, &mock, "play"));
+
+    mock.clear_received();
+    assert_eq!(mock.emit_track_end(GUILD_ID, "first", "FINISHED"), 1);
+
+    assert!(await_op(&mut core, &mock, "play"));
+
+    let played = mock.received();
+    let play = played.iter().find(|payload| payload["op"] == "play").unwrap();
+
+    assert_eq!(play["track"], "second");
+    assert!(player.player().expect("Player missing").queue.is_empty());
+}